@@ -0,0 +1,43 @@
+//! Tauri commands for exporting budget data
+
+use std::path::Path;
+
+use super::ledger::{self, LedgerExportResult, LedgerOptions};
+use super::template::{self, TemplateColumn};
+use super::transactions::{self, ExportOptions, ExportResult};
+use crate::ynab4::commands::with_budget;
+
+/// Write the budget's transactions, filtered, to a CSV or XLSX file
+#[tauri::command]
+pub fn export_transactions(
+    budget_path: String,
+    path: String,
+    options: ExportOptions,
+) -> Result<ExportResult, String> {
+    with_budget(&budget_path, |budget| {
+        transactions::export_transactions(&budget.data, Path::new(&path), &options)
+    })
+}
+
+/// Write the whole budget as a ledger, hledger or beancount journal
+#[tauri::command]
+pub fn export_ledger(
+    budget_path: String,
+    path: String,
+    options: LedgerOptions,
+) -> Result<LedgerExportResult, String> {
+    with_budget(&budget_path, |budget| {
+        ledger::export_ledger(&budget.data, Path::new(&path), &options)
+    })
+}
+
+/// Write the import template workbook with the given columns, sample rows and instructions
+#[tauri::command]
+pub fn write_import_template(
+    path: String,
+    columns: Vec<TemplateColumn>,
+    rows: Vec<Vec<String>>,
+    instructions: Vec<String>,
+) -> Result<(), String> {
+    template::write_template(Path::new(&path), &columns, &rows, &instructions)
+}
@@ -0,0 +1,1010 @@
+//! Plain-text accounting export for ledger, hledger and beancount.
+//!
+//! Budget accounts become `Assets:` or `Liabilities:` accounts, categories
+//! `Expenses:<master>:<sub>` and income `Income:<payee>`. A transfer is
+//! written once, from the split when one of its lines is the transfer.
+//!
+//! Balance assertions go after the last reconciliation of each account and
+//! after every reconciliation adjustment. They assert the balance the journal
+//! itself reaches, so the checkers pass; when that is not the statement
+//! balance YNAB4 recorded, a comment gives the recorded one and the export
+//! counts the mismatch.
+
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use crate::import::transaction::category_path;
+use crate::ynab4::entities::*;
+use crate::ynab4::reconcile::ADJUSTMENT_PAYEE_NAME;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LedgerDialect {
+    Ledger,
+    Hledger,
+    #[default]
+    Beancount,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LedgerOptions {
+    pub dialect: LedgerDialect,
+    /// Currency code; taken from the budget's currency locale when not given
+    pub commodity: Option<String>,
+    /// Add each month's budgeted amounts: Fava `custom "budget"` entries for
+    /// beancount, periodic transactions for ledger and hledger
+    pub include_budget: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerExportResult {
+    pub path: String,
+    pub transaction_count: usize,
+    pub account_count: usize,
+    pub assertion_count: usize,
+    /// Assertions whose statement balance the journal does not reach
+    pub mismatch_count: usize,
+}
+
+/// Account types that hold debt
+const LIABILITY_TYPES: [&str; 4] = ["CreditCard", "LineOfCredit", "Mortgage", "OtherLiability"];
+
+/// Currency of the country in a locale such as "es_MX"
+const CURRENCIES: [(&str, &str); 19] = [
+    ("US", "USD"),
+    ("MX", "MXN"),
+    ("CA", "CAD"),
+    ("GB", "GBP"),
+    ("AU", "AUD"),
+    ("NZ", "NZD"),
+    ("JP", "JPY"),
+    ("CH", "CHF"),
+    ("IN", "INR"),
+    ("BR", "BRL"),
+    ("AR", "ARS"),
+    ("CL", "CLP"),
+    ("CO", "COP"),
+    ("PE", "PEN"),
+    ("ZA", "ZAR"),
+    ("SE", "SEK"),
+    ("NO", "NOK"),
+    ("DK", "DKK"),
+    ("PL", "PLN"),
+];
+
+/// Countries that pay in euros
+const EURO_COUNTRIES: [&str; 14] = [
+    "DE", "FR", "ES", "IT", "NL", "PT", "AT", "BE", "FI", "IE", "GR", "SK", "SI", "LU",
+];
+
+struct Posting {
+    account: String,
+    cents: i64,
+    memo: String,
+}
+
+struct Entry {
+    date: String,
+    cleared: bool,
+    payee: String,
+    memo: String,
+    postings: Vec<Posting>,
+}
+
+struct Assertion {
+    /// Day the balance is reached, after its transactions
+    date: String,
+    account: String,
+    /// Balance the entries reach
+    cents: i64,
+    /// Statement balance YNAB4 recorded at the last reconciliation
+    statement_cents: Option<i64>,
+}
+
+impl Assertion {
+    /// The journal does not reach the statement balance YNAB4 recorded
+    fn mismatch(&self) -> bool {
+        self.statement_cents.is_some_and(|s| s != self.cents)
+    }
+}
+
+struct BudgetLine {
+    /// First day of the month
+    month: String,
+    account: String,
+    cents: i64,
+}
+
+/// Account names, unique after cleaning, keyed by what they stand for
+#[derive(Default)]
+struct Names {
+    by_key: BTreeMap<String, String>,
+    used: BTreeSet<String>,
+}
+
+impl Names {
+    fn get(&mut self, key: &str, root: &str, parts: &[&str]) -> String {
+        if let Some(name) = self.by_key.get(key) {
+            return name.clone();
+        }
+        let base: Vec<String> = std::iter::once(root.to_string())
+            .chain(parts.iter().map(|p| component(p)))
+            .collect();
+        let base = base.join(":");
+        let mut name = base.clone();
+        let mut n = 2;
+        while self.used.contains(&name) {
+            name = format!("{}-{}", base, n);
+            n += 1;
+        }
+        self.used.insert(name.clone());
+        self.by_key.insert(key.to_string(), name.clone());
+        name
+    }
+
+    fn account(&mut self, account: &Account) -> String {
+        let root = match LIABILITY_TYPES.contains(&account.account_type.as_str()) {
+            true => "Liabilities",
+            false => "Assets",
+        };
+        self.get(&account.entity_id, root, &[&account.account_name])
+    }
+
+    fn income(&mut self, payee: &str) -> String {
+        match payee.trim() {
+            "" => self.get("income", "Income", &["Uncategorized"]),
+            payee => self.get(
+                &format!("payee:{}", payee.to_lowercase()),
+                "Income",
+                &[payee],
+            ),
+        }
+    }
+
+    /// Where money taken from (negative `cents`) or put in an account goes
+    fn counterpart(
+        &mut self,
+        data: &BudgetData,
+        category_id: Option<&str>,
+        payee: &str,
+        cents: i64,
+    ) -> String {
+        match category_id {
+            Some(IMMEDIATE_INCOME_CATEGORY_ID | DEFERRED_INCOME_CATEGORY_ID) => {
+                return self.income(payee)
+            }
+            Some(id) => {
+                if let Some((master, sub)) = category_path(data, id)
+                    .as_deref()
+                    .and_then(|p| p.split_once(':'))
+                {
+                    return self.get(id, "Expenses", &[master, sub]);
+                }
+            }
+            None => {}
+        }
+        match cents > 0 {
+            true => self.get("income", "Income", &["Uncategorized"]),
+            false => self.get("expenses", "Expenses", &["Uncategorized"]),
+        }
+    }
+}
+
+/// One part of an account name: letters, digits and dashes, starting with a
+/// capital or a digit as beancount requires
+fn component(name: &str) -> String {
+    let mut out = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            out.push(c);
+        } else if !out.is_empty() && !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    let out = out.trim_end_matches('-');
+    let mut chars = out.chars();
+    match chars.next() {
+        None => "Unnamed".to_string(),
+        Some(first) if first.is_ascii_digit() || first.to_uppercase().all(char::is_uppercase) => {
+            first.to_uppercase().chain(chars).collect()
+        }
+        Some(_) => format!("X{}", out),
+    }
+}
+
+fn cents(amount: f64) -> i64 {
+    (amount * 100.0).round() as i64
+}
+
+fn amount_text(cents: i64, commodity: &str) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!(
+        "{}{}.{:02} {}",
+        sign,
+        cents.abs() / 100,
+        cents.abs() % 100,
+        commodity
+    )
+}
+
+/// Currency of the budget's locale, USD when unknown
+fn budget_commodity(data: &BudgetData) -> String {
+    let locale = data
+        .budget_meta_data
+        .get("currencyLocale")
+        .and_then(Value::as_str)
+        .unwrap_or("en_US");
+    let country = locale
+        .split_once(['_', '-'])
+        .map_or("", |(_, country)| country);
+    if EURO_COUNTRIES.contains(&country) {
+        return "EUR".to_string();
+    }
+    CURRENCIES
+        .iter()
+        .find(|(c, _)| *c == country)
+        .map_or("USD", |(_, currency)| currency)
+        .to_string()
+}
+
+/// Commodity name as beancount accepts it: capitals and digits, starting with a letter
+fn commodity_name(code: &str) -> Option<String> {
+    let code: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_uppercase();
+    (code.len() >= 2 && code.starts_with(|c: char| c.is_ascii_alphabetic())).then_some(code)
+}
+
+fn day(date: &str) -> &str {
+    date.get(..10).unwrap_or(date)
+}
+
+fn payee_name(data: &BudgetData, payee_id: Option<&str>) -> String {
+    payee_id
+        .and_then(|id| data.payee(id))
+        .map(|p| p.name.clone())
+        .unwrap_or_default()
+}
+
+/// Balanced entries for the budget's transactions, by date
+fn entries(data: &BudgetData, names: &mut Names) -> Vec<Entry> {
+    let mut transactions: Vec<&Transaction> = data
+        .transactions
+        .iter()
+        .filter(|t| !t.is_tombstone && data.account(&t.account_id).is_some_and(|a| !a.is_tombstone))
+        .collect();
+    // Split lines of written transactions only; a line in a deleted account
+    // leaves its transfer counterpart to stand on its own
+    let sub_ids: BTreeSet<&str> = transactions
+        .iter()
+        .flat_map(|t| t.live_sub_transactions())
+        .map(|s| s.entity_id.as_str())
+        .collect();
+    transactions.sort_by(|a, b| day(&a.date).cmp(day(&b.date)));
+
+    let mut written: BTreeSet<&str> = BTreeSet::new();
+    let mut entries = Vec::new();
+    for transaction in transactions {
+        if let Some(other) = transaction.transfer_transaction_id.as_deref() {
+            if sub_ids.contains(other) || written.contains(other) {
+                continue;
+            }
+        }
+        written.insert(&transaction.entity_id);
+        let Some(account) = data.account(&transaction.account_id) else {
+            continue;
+        };
+        let payee = payee_name(data, transaction.payee_id.as_deref());
+        let total = cents(transaction.amount);
+        let mut postings = vec![Posting {
+            account: names.account(account),
+            cents: total,
+            memo: String::new(),
+        }];
+
+        let lines: Vec<_> = transaction.live_sub_transactions().collect();
+        if transaction.is_split() && !lines.is_empty() {
+            for line in &lines {
+                let line_cents = cents(line.amount);
+                let transfer = line.transfer_transaction_id.as_ref().and(
+                    line.target_account_id.as_deref().or_else(|| {
+                        line.payee_id
+                            .as_deref()?
+                            .strip_prefix(TRANSFER_PAYEE_PREFIX)
+                    }),
+                );
+                let account = match transfer.and_then(|id| data.account(id)) {
+                    Some(other) => names.account(other),
+                    None => {
+                        names.counterpart(data, line.category_id.as_deref(), &payee, line_cents)
+                    }
+                };
+                postings.push(Posting {
+                    account,
+                    cents: -line_cents,
+                    memo: line.memo.clone().unwrap_or_default(),
+                });
+            }
+        } else {
+            let account = match transaction
+                .transfer_account_id()
+                .and_then(|id| data.account(id))
+            {
+                Some(other) => names.account(other),
+                None => names.counterpart(data, transaction.category_id.as_deref(), &payee, total),
+            };
+            postings.push(Posting {
+                account,
+                cents: -total,
+                memo: String::new(),
+            });
+        }
+        let remainder: i64 = postings.iter().map(|p| p.cents).sum();
+        if remainder != 0 {
+            postings.push(Posting {
+                account: names.counterpart(data, None, &payee, remainder),
+                cents: -remainder,
+                memo: "Split lines don't add up to the total".to_string(),
+            });
+        }
+
+        entries.push(Entry {
+            date: day(&transaction.date).to_string(),
+            cleared: transaction.cleared != UNCLEARED,
+            payee,
+            memo: transaction.memo.clone().unwrap_or_default(),
+            postings,
+        });
+    }
+    entries
+}
+
+/// Assertions at each account's reconciliation points, with the balance the
+/// entries reach at the end of that day
+fn assertions(data: &BudgetData, names: &mut Names, entries: &[Entry]) -> Vec<Assertion> {
+    let mut points: BTreeMap<(String, String), Option<i64>> = BTreeMap::new();
+    for transaction in data.transactions.iter().filter(|t| !t.is_tombstone) {
+        if payee_name(data, transaction.payee_id.as_deref()) == ADJUSTMENT_PAYEE_NAME {
+            points.insert(
+                (
+                    transaction.account_id.clone(),
+                    day(&transaction.date).to_string(),
+                ),
+                None,
+            );
+        }
+    }
+    for account in data.accounts.iter().filter(|a| !a.is_tombstone) {
+        if let Some(date) = &account.last_reconciled_date {
+            let statement = account.last_reconciled_balance.map(cents);
+            points.insert(
+                (account.entity_id.clone(), day(date).to_string()),
+                statement,
+            );
+        }
+    }
+
+    let mut assertions = Vec::new();
+    for ((account_id, date), statement_cents) in points {
+        let Some(account) = data.account(&account_id).filter(|a| !a.is_tombstone) else {
+            continue;
+        };
+        let account = names.account(account);
+        let balance = entries
+            .iter()
+            .filter(|e| e.date <= date)
+            .flat_map(|e| &e.postings)
+            .filter(|p| p.account == account)
+            .map(|p| p.cents)
+            .sum();
+        assertions.push(Assertion {
+            date,
+            account,
+            cents: balance,
+            statement_cents,
+        });
+    }
+    assertions
+}
+
+/// Budgeted amounts of each month. A category budgeted the month before and
+/// not now gets a zero line, since a Fava budget entry lasts until the next one.
+fn budget_lines(data: &BudgetData, names: &mut Names) -> Vec<BudgetLine> {
+    let mut months: Vec<&MonthlyBudget> = data
+        .monthly_budgets
+        .iter()
+        .filter(|m| !m.is_tombstone)
+        .collect();
+    months.sort_by(|a, b| a.month.cmp(&b.month));
+    let mut previous: BTreeMap<String, i64> = BTreeMap::new();
+    let mut lines = Vec::new();
+    for month in months {
+        let mut amounts: Vec<(String, i64)> = month
+            .monthly_sub_category_budgets
+            .iter()
+            .filter(|b| !b.is_tombstone)
+            .map(|b| (b.category_id.clone(), cents(b.budgeted)))
+            .collect();
+        // A month without an entry for a category budgets nothing for it
+        for id in previous.keys() {
+            if !amounts.iter().any(|(other, _)| other == id) {
+                amounts.push((id.clone(), 0));
+            }
+        }
+        for (category_id, amount) in amounts {
+            let path = category_path(data, &category_id);
+            let Some((master, sub)) = path.as_deref().and_then(|p| p.split_once(':')) else {
+                continue;
+            };
+            if master == "Income" {
+                continue;
+            }
+            let was_budgeted = previous.contains_key(&category_id);
+            if amount == 0 && !was_budgeted {
+                continue;
+            }
+            lines.push(BudgetLine {
+                month: format!("{}-01", month.month_key()),
+                account: names.get(&category_id, "Expenses", &[master, sub]),
+                cents: amount,
+            });
+            if amount == 0 {
+                previous.remove(&category_id);
+            } else {
+                previous.insert(category_id, amount);
+            }
+        }
+    }
+    lines
+}
+
+/// Text on one line, for payees and comments
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn quoted(text: &str) -> String {
+    format!(
+        "\"{}\"",
+        one_line(text).replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+fn posting_line(account: &str, amount: &str) -> String {
+    let pad = 48usize.saturating_sub(account.chars().count()).max(2);
+    format!("  {}{}{}", account, " ".repeat(pad), amount)
+}
+
+fn next_day(date: &str) -> String {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|d| (d + Duration::days(1)).format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|_| date.to_string())
+}
+
+struct Journal {
+    dialect: LedgerDialect,
+    commodity: String,
+    entries: Vec<Entry>,
+    assertions: Vec<Assertion>,
+    budget: Vec<BudgetLine>,
+    accounts: Vec<String>,
+}
+
+impl Journal {
+    fn date(&self, date: &str) -> String {
+        match self.dialect {
+            LedgerDialect::Ledger => date.replace('-', "/"),
+            _ => date.to_string(),
+        }
+    }
+
+    fn beancount_entry(&self, entry: &Entry) -> String {
+        let flag = if entry.cleared { "*" } else { "!" };
+        let mut text = match entry.payee.trim() {
+            "" => format!("{} {} {}\n", entry.date, flag, quoted(&entry.memo)),
+            payee => format!(
+                "{} {} {} {}\n",
+                entry.date,
+                flag,
+                quoted(payee),
+                quoted(&entry.memo)
+            ),
+        };
+        for posting in &entry.postings {
+            text.push_str(&posting_line(
+                &posting.account,
+                &amount_text(posting.cents, &self.commodity),
+            ));
+            text.push('\n');
+            if !posting.memo.trim().is_empty() {
+                text.push_str(&format!("    memo: {}\n", quoted(&posting.memo)));
+            }
+        }
+        text
+    }
+
+    fn ledger_entry(&self, entry: &Entry) -> String {
+        let flag = if entry.cleared { "*" } else { "!" };
+        let payee = one_line(&entry.payee).replace(';', ",");
+        let mut text = format!("{} {} {}", self.date(&entry.date), flag, payee)
+            .trim_end()
+            .to_string();
+        if !entry.memo.trim().is_empty() {
+            text.push_str(&format!("  ; {}", one_line(&entry.memo)));
+        }
+        text.push('\n');
+        for posting in &entry.postings {
+            text.push_str(&posting_line(
+                &posting.account,
+                &amount_text(posting.cents, &self.commodity),
+            ));
+            if !posting.memo.trim().is_empty() {
+                text.push_str(&format!("  ; {}", one_line(&posting.memo)));
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    fn assertion(&self, assertion: &Assertion) -> String {
+        let mut text = String::new();
+        if let Some(statement) = assertion.statement_cents.filter(|_| assertion.mismatch()) {
+            text.push_str(&format!(
+                "; YNAB4 recorded a statement balance of {}\n",
+                amount_text(statement, &self.commodity)
+            ));
+        }
+        let balance = amount_text(assertion.cents, &self.commodity);
+        match self.dialect {
+            // Beancount checks the balance at the start of the day
+            LedgerDialect::Beancount => text.push_str(&format!(
+                "{} balance {}  {}\n",
+                next_day(&assertion.date),
+                assertion.account,
+                balance
+            )),
+            _ => {
+                let zero = format!("{} = {}", amount_text(0, &self.commodity), balance);
+                text.push_str(&format!(
+                    "{} * Reconciliation\n",
+                    self.date(&assertion.date)
+                ));
+                text.push_str(&posting_line(&assertion.account, &zero));
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    fn budget_months(&self) -> Vec<String> {
+        let mut text = Vec::new();
+        // Periodic transactions end with their month, so ledger needs no zero lines
+        let budget: Vec<&BudgetLine> = self
+            .budget
+            .iter()
+            .filter(|b| self.dialect == LedgerDialect::Beancount || b.cents != 0)
+            .collect();
+        let mut months: Vec<&str> = budget.iter().map(|b| b.month.as_str()).collect();
+        months.dedup();
+        for month in months {
+            let lines = budget.iter().filter(|b| b.month == month);
+            match self.dialect {
+                LedgerDialect::Beancount => {
+                    for line in lines {
+                        text.push(format!(
+                            "{} custom \"budget\" {} \"monthly\" {}",
+                            month,
+                            line.account,
+                            amount_text(line.cents, &self.commodity)
+                        ));
+                    }
+                }
+                _ => {
+                    let end = NaiveDate::parse_from_str(month, "%Y-%m-%d")
+                        .ok()
+                        .and_then(|d| d.checked_add_months(chrono::Months::new(1)))
+                        .map_or(month.to_string(), |d| d.format("%Y-%m-%d").to_string());
+                    let mut entry = format!(
+                        "~ monthly from {} to {}\n",
+                        self.date(month),
+                        self.date(&end)
+                    );
+                    for line in lines {
+                        entry.push_str(&posting_line(
+                            &line.account,
+                            &amount_text(line.cents, &self.commodity),
+                        ));
+                        entry.push('\n');
+                    }
+                    entry.push_str("  Assets\n");
+                    text.push(entry.trim_end().to_string());
+                }
+            }
+        }
+        text
+    }
+
+    fn render(&self) -> String {
+        let first = self
+            .entries
+            .iter()
+            .map(|e| e.date.as_str())
+            .chain(self.assertions.iter().map(|a| a.date.as_str()))
+            .chain(self.budget.iter().map(|b| b.month.as_str()))
+            .min()
+            .unwrap_or("1970-01-01");
+
+        let mut out = String::new();
+        match self.dialect {
+            LedgerDialect::Beancount => {
+                out.push_str(&format!(
+                    "option \"operating_currency\" \"{}\"\n\n",
+                    self.commodity
+                ));
+                for account in &self.accounts {
+                    out.push_str(&format!("{} open {} {}\n", first, account, self.commodity));
+                }
+            }
+            _ => {
+                for account in &self.accounts {
+                    out.push_str(&format!("account {}\n", account));
+                }
+            }
+        }
+
+        let budget = self.budget_months();
+        if !budget.is_empty() {
+            out.push('\n');
+            let separator = if self.dialect == LedgerDialect::Beancount {
+                "\n"
+            } else {
+                "\n\n"
+            };
+            out.push_str(&budget.join(separator));
+            out.push('\n');
+        }
+
+        // Assertions come after the transactions of their day
+        let mut blocks: Vec<(&str, u8, String)> = self
+            .entries
+            .iter()
+            .map(|e| {
+                let text = match self.dialect {
+                    LedgerDialect::Beancount => self.beancount_entry(e),
+                    _ => self.ledger_entry(e),
+                };
+                (e.date.as_str(), 0, text)
+            })
+            .chain(
+                self.assertions
+                    .iter()
+                    .map(|a| (a.date.as_str(), 1, self.assertion(a))),
+            )
+            .collect();
+        blocks.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+        for (_, _, block) in blocks {
+            out.push('\n');
+            out.push_str(&block);
+        }
+        out
+    }
+}
+
+/// Journal text for the whole budget
+pub fn journal_text(data: &BudgetData, options: &LedgerOptions) -> (String, LedgerExportResult) {
+    let commodity = options
+        .commodity
+        .as_deref()
+        .and_then(commodity_name)
+        .unwrap_or_else(|| budget_commodity(data));
+    let mut names = Names::default();
+    let entries = entries(data, &mut names);
+    let assertions = assertions(data, &mut names, &entries);
+    let budget = match options.include_budget {
+        true => budget_lines(data, &mut names),
+        false => Vec::new(),
+    };
+    let journal = Journal {
+        dialect: options.dialect,
+        commodity,
+        accounts: names.used.iter().cloned().collect(),
+        entries,
+        assertions,
+        budget,
+    };
+    let result = LedgerExportResult {
+        path: String::new(),
+        transaction_count: journal.entries.len(),
+        account_count: journal.accounts.len(),
+        assertion_count: journal.assertions.len(),
+        mismatch_count: journal.assertions.iter().filter(|a| a.mismatch()).count(),
+    };
+    (journal.render(), result)
+}
+
+/// Write the budget as a ledger, hledger or beancount journal
+pub fn export_ledger(
+    data: &BudgetData,
+    path: &Path,
+    options: &LedgerOptions,
+) -> Result<LedgerExportResult, String> {
+    let (text, result) = journal_text(data, options);
+    fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(LedgerExportResult {
+        path: path.display().to_string(),
+        ..result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::budget::Budget;
+    use crate::ynab4::fixtures;
+
+    fn parsed_cents(amount: &str) -> i64 {
+        (amount.parse::<f64>().unwrap() * 100.0).round() as i64
+    }
+
+    /// What bean-check verifies that the export can get wrong: opened accounts,
+    /// balanced transactions and balance directives
+    fn check_beancount(text: &str) -> BTreeMap<String, i64> {
+        let mut open = BTreeSet::new();
+        let mut postings: Vec<(String, String, i64)> = Vec::new();
+        let mut balances = Vec::new();
+        let mut current: Option<(String, i64)> = None;
+        let close = |current: &mut Option<(String, i64)>| {
+            if let Some((header, sum)) = current.take() {
+                assert_eq!(sum, 0, "unbalanced: {}", header);
+            }
+        };
+        for line in text.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if line.starts_with("  ") && !line.starts_with("    ") {
+                let (header, sum) = current.as_mut().expect("posting outside a transaction");
+                let account = words[0].to_string();
+                assert!(open.contains(&account), "not opened: {}", account);
+                let amount = parsed_cents(words[1]);
+                *sum += amount;
+                let date = header[..10].to_string();
+                postings.push((date, account, amount));
+                continue;
+            }
+            if line.starts_with("    ") {
+                assert!(line.trim_start().starts_with("memo: \""));
+                continue;
+            }
+            close(&mut current);
+            match words.get(1) {
+                Some(&"open") => {
+                    open.insert(words[2].to_string());
+                }
+                Some(&"*") | Some(&"!") => current = Some((line.to_string(), 0)),
+                Some(&"balance") => balances.push((
+                    words[0].to_string(),
+                    words[2].to_string(),
+                    parsed_cents(words[3]),
+                )),
+                _ => {}
+            }
+        }
+        close(&mut current);
+
+        let mut asserted = BTreeMap::new();
+        for (date, account, amount) in balances {
+            let reached: i64 = postings
+                .iter()
+                .filter(|p| p.0 < date && p.1 == account)
+                .map(|p| p.2)
+                .sum();
+            assert_eq!(reached, amount, "balance of {} on {}", account, date);
+            asserted.insert(format!("{} {}", date, account), amount);
+        }
+        asserted
+    }
+
+    fn budget_with_reconciliations(path: &Path) -> BudgetData {
+        let mut data = Budget::load(path).unwrap().data;
+        let checking = data
+            .accounts
+            .iter_mut()
+            .find(|a| a.entity_id == "ACC-CHK")
+            .unwrap();
+        checking.last_reconciled_date = Some("2024-01-10".to_string());
+        checking.last_reconciled_balance = Some(650.0);
+        let visa = data
+            .accounts
+            .iter_mut()
+            .find(|a| a.entity_id == "ACC-CC")
+            .unwrap();
+        visa.last_reconciled_date = Some("2024-01-06".to_string());
+        visa.last_reconciled_balance = Some(-80.0);
+
+        // The food line of the split becomes a transfer to the card
+        let split = data
+            .transactions
+            .iter_mut()
+            .find(|t| t.entity_id == "T-7")
+            .unwrap();
+        let line = &mut split.sub_transactions.as_mut().unwrap()[1];
+        line.category_id = None;
+        line.target_account_id = Some("ACC-CC".to_string());
+        line.transfer_transaction_id = Some("T-8".to_string());
+        line.memo = Some("card \"payment\"".to_string());
+        data.transactions.push(Transaction {
+            entity_id: "T-8".to_string(),
+            account_id: "ACC-CC".to_string(),
+            date: "2024-02-03".to_string(),
+            amount: 100.0,
+            payee_id: Some("Payee/Transfer:ACC-CHK".to_string()),
+            transfer_transaction_id: Some("S-2".to_string()),
+            cleared: "Uncleared".to_string(),
+            accepted: true,
+            ..Default::default()
+        });
+        data
+    }
+
+    #[test]
+    fn writes_a_balanced_beancount_journal() {
+        let path = fixtures::budget_folder("ledger-beancount");
+        let data = budget_with_reconciliations(&path);
+        let options = LedgerOptions {
+            include_budget: true,
+            ..Default::default()
+        };
+        let (text, result) = journal_text(&data, &options);
+        assert!(
+            text.starts_with("option \"operating_currency\" \"MXN\"\n"),
+            "{}",
+            text
+        );
+        // T-6 and T-8 are the other sides of transfers already written
+        assert_eq!(result.transaction_count, 6);
+        assert_eq!(result.assertion_count, 2);
+        assert_eq!(result.mismatch_count, 0);
+
+        let asserted = check_beancount(&text);
+        assert_eq!(asserted["2024-01-11 Assets:Checking"], 65000);
+        assert_eq!(asserted["2024-01-07 Liabilities:Visa"], -8000);
+        assert!(!text.contains("; YNAB4 recorded"));
+
+        assert!(text.contains("2024-01-01 * \"Employer\" \"\"\n  Assets:Checking"));
+        assert!(text.contains("  Income:Employer"));
+        assert!(text.contains("2024-01-05 ! \"OXXO\" \"\""));
+        assert!(text.contains("  Expenses:Everyday:Rent"));
+        assert!(text.contains("    memo: \"card \\\"payment\\\"\""));
+        assert!(text.contains(
+            "2024-01-01 custom \"budget\" Expenses:Everyday:Food \"monthly\" 100.00 MXN"
+        ));
+        assert!(text.contains(
+            "2024-03-01 custom \"budget\" Expenses:Everyday:Fun \"monthly\" 2000.00 MXN"
+        ));
+        assert!(text.contains("2024-01-01 open Liabilities:Visa MXN"));
+
+        let plain = journal_text(&data, &LedgerOptions::default()).0;
+        assert!(!plain.contains("custom"));
+    }
+
+    #[test]
+    fn writes_ledger_and_hledger_journals() {
+        let path = fixtures::budget_folder("ledger-hledger");
+        let data = budget_with_reconciliations(&path);
+        let options = LedgerOptions {
+            dialect: LedgerDialect::Hledger,
+            commodity: Some("usd".to_string()),
+            include_budget: true,
+        };
+        let out = path.join("budget.journal");
+        let result = export_ledger(&data, &out, &options).unwrap();
+        assert_eq!(result.path, out.display().to_string());
+        let text = std::fs::read_to_string(&out).unwrap();
+        assert!(text.starts_with("account "));
+        assert!(text.contains("2024-01-10 * Reconciliation\n  Assets:Checking"));
+        assert!(text.contains("0.00 USD = 650.00 USD\n"));
+        assert!(text.contains("~ monthly from 2024-01-01 to 2024-02-01\n"));
+        assert!(text.contains("  ; card \"payment\"\n"));
+
+        // The reconciliation comes after the other transactions of its day
+        let transfer = text.find("2024-01-10 * Transfer : Visa").unwrap();
+        let reconciliation = text.find("2024-01-10 * Reconciliation").unwrap();
+        assert!(transfer < reconciliation);
+
+        let options = LedgerOptions {
+            dialect: LedgerDialect::Ledger,
+            ..Default::default()
+        };
+        let (text, _) = journal_text(&data, &options);
+        assert!(text.contains("2024/01/05 ! OXXO\n"));
+        assert!(text.contains("2024/01/10 * Reconciliation\n"));
+    }
+
+    #[test]
+    fn drifted_statement_balances_are_noted_but_not_asserted() {
+        let path = fixtures::budget_folder("ledger-mismatch");
+        let mut data = budget_with_reconciliations(&path);
+        // The card drifted from its statement without an adjustment
+        let visa = data
+            .accounts
+            .iter_mut()
+            .find(|a| a.entity_id == "ACC-CC")
+            .unwrap();
+        visa.last_reconciled_balance = Some(-75.0);
+        let (text, result) = journal_text(&data, &LedgerOptions::default());
+        assert_eq!(result.mismatch_count, 1);
+        assert!(text.contains(
+            "; YNAB4 recorded a statement balance of -75.00 MXN\n\
+             2024-01-07 balance Liabilities:Visa  -80.00 MXN\n"
+        ));
+        let asserted = check_beancount(&text);
+        assert_eq!(asserted["2024-01-07 Liabilities:Visa"], -8000);
+    }
+
+    #[test]
+    fn beancount_budgets_drop_to_zero() {
+        let path = fixtures::budget_folder("ledger-budget-zero");
+        let mut data = budget_with_reconciliations(&path);
+        // Food goes from 100 to an explicit 0; Fun and Rent have no February entry
+        let february = data
+            .monthly_budgets
+            .iter_mut()
+            .find(|m| m.entity_id == "MB/2024-02")
+            .unwrap();
+        february.monthly_sub_category_budgets[0].budgeted = 0.0;
+        let options = LedgerOptions {
+            include_budget: true,
+            ..Default::default()
+        };
+        let (text, _) = journal_text(&data, &options);
+        for account in ["Food", "Fun", "Rent"] {
+            let line = format!(
+                "2024-02-01 custom \"budget\" Expenses:Everyday:{} \"monthly\" 0.00 MXN",
+                account
+            );
+            assert!(text.contains(&line), "{}", text);
+        }
+        assert!(text.contains(
+            "2024-03-01 custom \"budget\" Expenses:Everyday:Fun \"monthly\" 2000.00 MXN"
+        ));
+        // Already zero, so March repeats nothing
+        assert!(!text.contains("2024-03-01 custom \"budget\" Expenses:Everyday:Food"));
+        assert!(!text.contains("2024-03-01 custom \"budget\" Expenses:Everyday:Rent"));
+
+        let options = LedgerOptions {
+            dialect: LedgerDialect::Hledger,
+            include_budget: true,
+            ..Default::default()
+        };
+        let (text, _) = journal_text(&data, &options);
+        assert!(!text.contains("~ monthly from 2024-02-01"), "{}", text);
+        assert!(text.contains("~ monthly from 2024-03-01 to 2024-04-01\n"));
+    }
+
+    #[test]
+    fn transfers_out_of_deleted_accounts_stand_on_their_own() {
+        let path = fixtures::budget_folder("ledger-deleted");
+        let mut data = budget_with_reconciliations(&path);
+        // T-7 and its transfer line to the card go with the checking account
+        let checking = data
+            .accounts
+            .iter_mut()
+            .find(|a| a.entity_id == "ACC-CHK")
+            .unwrap();
+        checking.is_tombstone = true;
+        let (text, _) = journal_text(&data, &LedgerOptions::default());
+        check_beancount(&text);
+        assert!(
+            text.contains("2024-02-03 ! \"Transfer : Checking\""),
+            "{}",
+            text
+        );
+    }
+}
@@ -0,0 +1,6 @@
+//! Writing budget data out to other files and programs
+
+pub mod commands;
+pub mod ledger;
+pub mod template;
+pub mod transactions;
@@ -0,0 +1,99 @@
+//! The import template workbook: a sheet of sample rows under the template's
+//! headers and a sheet of instructions. What goes in it comes from the
+//! frontend, which also writes the sample CSV from the same columns.
+
+use rust_xlsxwriter::{Workbook, XlsxError};
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateColumn {
+    pub header: String,
+    /// Width in characters
+    pub width: f64,
+}
+
+fn write_workbook(
+    path: &Path,
+    columns: &[TemplateColumn],
+    rows: &[Vec<String>],
+    instructions: &[String],
+) -> Result<(), XlsxError> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Transacciones")?;
+    for (col, column) in columns.iter().enumerate() {
+        sheet.write_string(0, col as u16, &column.header)?;
+        sheet.set_column_width(col as u16, column.width)?;
+    }
+    for (i, row) in rows.iter().enumerate() {
+        for (col, value) in row.iter().enumerate().filter(|(_, v)| !v.is_empty()) {
+            sheet.write_string(i as u32 + 1, col as u16, value)?;
+        }
+    }
+
+    let notes = workbook.add_worksheet();
+    notes.set_name("Instrucciones")?;
+    notes.set_column_width(0, 80)?;
+    for (i, line) in instructions
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| !l.is_empty())
+    {
+        notes.write_string(i as u32, 0, line)?;
+    }
+    workbook.save(path)
+}
+
+/// Write the import template to `path`
+pub fn write_template(
+    path: &Path,
+    columns: &[TemplateColumn],
+    rows: &[Vec<String>],
+    instructions: &[String],
+) -> Result<(), String> {
+    write_workbook(path, columns, rows, instructions)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::spreadsheet;
+
+    #[test]
+    fn template_reads_back_as_an_import() {
+        let dir = std::env::temp_dir().join("ynab4-client-tests");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("import-template.xlsx");
+        let columns = vec![
+            TemplateColumn {
+                header: "Date".to_string(),
+                width: 12.0,
+            },
+            TemplateColumn {
+                header: "Outflow".to_string(),
+                width: 15.0,
+            },
+        ];
+        let rows = vec![vec!["15/01/2025".to_string(), "5000.00".to_string()]];
+        let instructions = vec![
+            "INSTRUCTIONS".to_string(),
+            String::new(),
+            "COLUMNS:".to_string(),
+        ];
+        write_template(&path, &columns, &rows, &instructions).unwrap();
+
+        assert_eq!(
+            spreadsheet::sheet_names(&path).unwrap(),
+            vec!["Transacciones", "Instrucciones"]
+        );
+        let sheet = spreadsheet::read_sheet(&path, 0, 0, 0, None).unwrap();
+        assert_eq!(sheet.headers, vec!["Date", "Outflow"]);
+        assert_eq!(sheet.rows[1], vec!["15/01/2025", "5000.00"]);
+        let notes = spreadsheet::read_sheet(&path, 1, 0, 0, None).unwrap();
+        assert_eq!(notes.headers, vec!["INSTRUCTIONS"]);
+        assert_eq!(notes.rows[1], vec!["COLUMNS:"]);
+    }
+}
@@ -0,0 +1,715 @@
+//! Exporting the register to CSV or XLSX.
+//!
+//! Rows are built for every transaction first, so running balances count the
+//! whole account history, and only then filtered. With `ynab4_order` the file
+//! has the columns and values of YNAB4's own register export, which
+//! `import::ynab4_csv` reads back.
+
+use chrono::{Datelike, NaiveDate};
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook, XlsxError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::import::formats::{self, DateFormat, NumberFormat};
+use crate::import::transaction::category_path;
+use crate::ynab4::entities::{
+    Account, BudgetData, Transaction, CLEARED, RECONCILED, TRANSFER_PAYEE_PREFIX,
+};
+
+/// Category shown for a split written as one row
+const SPLIT_CATEGORY_NAME: &str = "Split (Multiple Categories)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Xlsx,
+}
+
+/// A column of the exported file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportColumn {
+    Account,
+    Flag,
+    CheckNumber,
+    Date,
+    Payee,
+    /// "Master: Sub"
+    Category,
+    MasterCategory,
+    SubCategory,
+    Memo,
+    Outflow,
+    Inflow,
+    /// Signed amount, negative for outflows
+    Amount,
+    Cleared,
+    RunningBalance,
+}
+
+impl ExportColumn {
+    /// Header text, as YNAB4 writes it
+    pub fn header(self) -> &'static str {
+        match self {
+            ExportColumn::Account => "Account",
+            ExportColumn::Flag => "Flag",
+            ExportColumn::CheckNumber => "Check Number",
+            ExportColumn::Date => "Date",
+            ExportColumn::Payee => "Payee",
+            ExportColumn::Category => "Category",
+            ExportColumn::MasterCategory => "Master Category",
+            ExportColumn::SubCategory => "Sub Category",
+            ExportColumn::Memo => "Memo",
+            ExportColumn::Outflow => "Outflow",
+            ExportColumn::Inflow => "Inflow",
+            ExportColumn::Amount => "Amount",
+            ExportColumn::Cleared => "Cleared",
+            ExportColumn::RunningBalance => "Running Balance",
+        }
+    }
+}
+
+/// Columns of YNAB4's register export, in its order
+pub const YNAB4_COLUMNS: [ExportColumn; 13] = [
+    ExportColumn::Account,
+    ExportColumn::Flag,
+    ExportColumn::CheckNumber,
+    ExportColumn::Date,
+    ExportColumn::Payee,
+    ExportColumn::Category,
+    ExportColumn::MasterCategory,
+    ExportColumn::SubCategory,
+    ExportColumn::Memo,
+    ExportColumn::Outflow,
+    ExportColumn::Inflow,
+    ExportColumn::Cleared,
+    ExportColumn::RunningBalance,
+];
+
+/// Columns written when none are chosen
+pub const DEFAULT_COLUMNS: [ExportColumn; 8] = [
+    ExportColumn::Date,
+    ExportColumn::Account,
+    ExportColumn::Payee,
+    ExportColumn::Category,
+    ExportColumn::Memo,
+    ExportColumn::Amount,
+    ExportColumn::Cleared,
+    ExportColumn::RunningBalance,
+];
+
+/// How split transactions are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SplitMode {
+    /// One row with the whole amount
+    #[default]
+    Combined,
+    /// One row per split line
+    Expanded,
+}
+
+/// Which transactions to write. Empty lists don't filter.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportFilter {
+    pub account_ids: Vec<String>,
+    /// First day included, "YYYY-MM-DD"
+    pub from: Option<String>,
+    /// Last day included, "YYYY-MM-DD"
+    pub to: Option<String>,
+    pub category_ids: Vec<String>,
+    pub payee_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    pub filter: ExportFilter,
+    /// Empty for `DEFAULT_COLUMNS`
+    pub columns: Vec<ExportColumn>,
+    /// Write YNAB4's register export instead: its columns, splits expanded,
+    /// `(Split i/n)` memos and U/C/R in the cleared column
+    pub ynab4_order: bool,
+    pub split_mode: SplitMode,
+    /// Taken from the budget's locale when not given
+    pub number_format: Option<NumberFormat>,
+    /// Taken from the budget's locale when not given
+    pub date_format: Option<DateFormat>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportResult {
+    pub path: String,
+    pub row_count: usize,
+    pub transaction_count: usize,
+}
+
+/// One row of the register: a transaction or one of its split lines
+#[derive(Debug, Clone, Default)]
+pub struct ExportRow {
+    pub transaction_id: String,
+    pub account_id: String,
+    pub account: String,
+    pub flag: String,
+    pub check_number: String,
+    /// "YYYY-MM-DD"
+    pub date: String,
+    pub payee: String,
+    pub category: String,
+    pub master_category: String,
+    pub sub_category: String,
+    pub memo: String,
+    pub amount: f64,
+    pub cleared: String,
+    /// Account balance after this row
+    pub running_balance: f64,
+    payee_id: Option<String>,
+    category_ids: Vec<String>,
+}
+
+enum Cell<'a> {
+    Text(&'a str),
+    Date(&'a str),
+    Amount(f64),
+}
+
+/// Languages that write "1,234.56"
+const DOT_DECIMAL_LANGUAGES: [&str; 8] = ["en", "ja", "zh", "ko", "he", "th", "hi", "ms"];
+
+/// Spanish-speaking countries that write "1,234.56"
+const DOT_DECIMAL_SPANISH: [&str; 10] =
+    ["MX", "US", "GT", "HN", "NI", "PA", "PE", "DO", "SV", "PR"];
+
+/// Languages that group thousands with a space: "1 234,56"
+const SPACE_THOUSANDS_LANGUAGES: [&str; 10] =
+    ["fr", "sv", "nb", "nn", "fi", "ru", "pl", "cs", "sk", "uk"];
+
+/// Languages that write dates year first
+const YMD_LANGUAGES: [&str; 6] = ["ja", "zh", "ko", "hu", "lt", "sv"];
+
+fn locale_parts(locale: &str) -> (&str, &str) {
+    locale.split_once(['_', '-']).unwrap_or((locale, ""))
+}
+
+/// Number format of a locale such as "es_MX" or "de_DE"
+pub fn locale_number_format(locale: &str) -> NumberFormat {
+    let (language, country) = locale_parts(locale);
+    if DOT_DECIMAL_LANGUAGES.contains(&language)
+        || (language == "es" && DOT_DECIMAL_SPANISH.contains(&country))
+    {
+        return NumberFormat::default();
+    }
+    let thousands = if SPACE_THOUSANDS_LANGUAGES.contains(&language) {
+        ' '
+    } else {
+        '.'
+    };
+    NumberFormat {
+        decimal: ',',
+        thousands: Some(thousands),
+    }
+}
+
+/// Date format of a locale such as "en_US" or "es_MX"
+pub fn locale_date_format(locale: &str) -> DateFormat {
+    match locale_parts(locale) {
+        ("en", "US" | "") => DateFormat::Mdy,
+        ("en", "CA") => DateFormat::Ymd,
+        (language, _) if YMD_LANGUAGES.contains(&language) => DateFormat::Ymd,
+        _ => DateFormat::Dmy,
+    }
+}
+
+/// Number and date formats of the budget's currency and date locales
+pub fn budget_formats(data: &BudgetData) -> (NumberFormat, DateFormat) {
+    let locale = |key: &str| {
+        data.budget_meta_data
+            .get(key)
+            .and_then(Value::as_str)
+            .unwrap_or("en_US")
+    };
+    (
+        locale_number_format(locale("currencyLocale")),
+        locale_date_format(locale("dateLocale")),
+    )
+}
+
+/// "Transfer : <account>" for transfers, or the payee's name
+fn payee_name(
+    data: &BudgetData,
+    transfer_account_id: Option<&str>,
+    payee_id: Option<&str>,
+) -> String {
+    if let Some(account) = transfer_account_id.and_then(|id| data.account(id)) {
+        return format!("Transfer : {}", account.account_name);
+    }
+    payee_id
+        .and_then(|id| data.payee(id))
+        .map(|p| p.name.clone())
+        .unwrap_or_default()
+}
+
+fn with_category(data: &BudgetData, mut row: ExportRow, category_id: Option<&str>) -> ExportRow {
+    let Some(id) = category_id else {
+        return row;
+    };
+    if let Some((master, sub)) = category_path(data, id)
+        .as_deref()
+        .and_then(|p| p.split_once(':'))
+    {
+        row.category = format!("{}: {}", master, sub);
+        row.master_category = master.to_string();
+        row.sub_category = sub.to_string();
+    }
+    row.category_ids = vec![id.to_string()];
+    row
+}
+
+/// Rows for one transaction, without running balances
+fn transaction_rows(
+    data: &BudgetData,
+    account: &Account,
+    transaction: &Transaction,
+    expand: bool,
+    ynab4: bool,
+) -> Vec<ExportRow> {
+    let cleared = match (ynab4, transaction.cleared.as_str()) {
+        (true, RECONCILED) => "R",
+        (true, CLEARED) => "C",
+        (true, _) => "U",
+        (false, cleared) => cleared,
+    };
+    let base = ExportRow {
+        transaction_id: transaction.entity_id.clone(),
+        account_id: account.entity_id.clone(),
+        account: account.account_name.clone(),
+        flag: transaction.flag.clone().unwrap_or_default(),
+        check_number: transaction.check_number.clone().unwrap_or_default(),
+        date: transaction
+            .date
+            .get(..10)
+            .unwrap_or(&transaction.date)
+            .to_string(),
+        payee: payee_name(
+            data,
+            transaction.transfer_account_id(),
+            transaction.payee_id.as_deref(),
+        ),
+        memo: transaction.memo.clone().unwrap_or_default(),
+        amount: transaction.amount,
+        cleared: cleared.to_string(),
+        payee_id: transaction.payee_id.clone(),
+        ..Default::default()
+    };
+
+    let lines: Vec<_> = transaction.live_sub_transactions().collect();
+    if !transaction.is_split() || lines.is_empty() {
+        return vec![with_category(
+            data,
+            base,
+            transaction.category_id.as_deref(),
+        )];
+    }
+    if !expand {
+        return vec![ExportRow {
+            category: SPLIT_CATEGORY_NAME.to_string(),
+            category_ids: lines.iter().filter_map(|l| l.category_id.clone()).collect(),
+            ..base
+        }];
+    }
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let transfer = line.transfer_transaction_id.as_ref().and(
+                line.target_account_id.as_deref().or_else(|| {
+                    line.payee_id
+                        .as_deref()?
+                        .strip_prefix(TRANSFER_PAYEE_PREFIX)
+                }),
+            );
+            let memo = line.memo.clone().unwrap_or_default();
+            let row = ExportRow {
+                payee: match transfer {
+                    Some(_) => payee_name(data, transfer, None),
+                    None => base.payee.clone(),
+                },
+                payee_id: line.payee_id.clone().or_else(|| base.payee_id.clone()),
+                memo: match ynab4 {
+                    true => format!("(Split {}/{}) {}", i + 1, lines.len(), memo)
+                        .trim_end()
+                        .to_string(),
+                    false => memo,
+                },
+                amount: line.amount,
+                ..base.clone()
+            };
+            with_category(data, row, line.category_id.as_deref())
+        })
+        .collect()
+}
+
+/// Every row of the register, by account and date, with running balances
+fn register_rows(data: &BudgetData, expand: bool, ynab4: bool) -> Vec<ExportRow> {
+    let mut accounts: Vec<&Account> = data.accounts.iter().filter(|a| !a.is_tombstone).collect();
+    accounts.sort_by(|a, b| {
+        a.sortable_index
+            .unwrap_or(0.0)
+            .total_cmp(&b.sortable_index.unwrap_or(0.0))
+    });
+
+    let mut rows = Vec::new();
+    for account in accounts {
+        let mut transactions: Vec<&Transaction> = data
+            .transactions
+            .iter()
+            .filter(|t| !t.is_tombstone && t.account_id == account.entity_id)
+            .collect();
+        transactions.sort_by(|a, b| {
+            a.date
+                .cmp(&b.date)
+                .then_with(|| a.entity_id.cmp(&b.entity_id))
+        });
+        let mut balance = 0.0;
+        for transaction in transactions {
+            for mut row in transaction_rows(data, account, transaction, expand, ynab4) {
+                balance += row.amount;
+                row.running_balance = (balance * 100.0_f64).round() / 100.0;
+                rows.push(row);
+            }
+        }
+    }
+    rows
+}
+
+impl ExportFilter {
+    fn matches(&self, row: &ExportRow) -> bool {
+        (self.account_ids.is_empty() || self.account_ids.contains(&row.account_id))
+            && self.from.as_ref().map_or(true, |from| row.date >= *from)
+            && self.to.as_ref().map_or(true, |to| row.date <= *to)
+            && (self.category_ids.is_empty()
+                || row
+                    .category_ids
+                    .iter()
+                    .any(|id| self.category_ids.contains(id)))
+            && (self.payee_ids.is_empty()
+                || row
+                    .payee_id
+                    .as_ref()
+                    .is_some_and(|id| self.payee_ids.contains(id)))
+    }
+}
+
+impl ExportOptions {
+    /// Columns to write, in order
+    pub fn resolved_columns(&self) -> Vec<ExportColumn> {
+        if self.ynab4_order {
+            YNAB4_COLUMNS.to_vec()
+        } else if self.columns.is_empty() {
+            DEFAULT_COLUMNS.to_vec()
+        } else {
+            self.columns.clone()
+        }
+    }
+}
+
+/// Register rows that pass the options' filter
+pub fn export_rows(data: &BudgetData, options: &ExportOptions) -> Vec<ExportRow> {
+    let expand = options.ynab4_order || options.split_mode == SplitMode::Expanded;
+    let mut rows = register_rows(data, expand, options.ynab4_order);
+    rows.retain(|row| options.filter.matches(row));
+    rows
+}
+
+fn cell(row: &ExportRow, column: ExportColumn) -> Cell<'_> {
+    match column {
+        ExportColumn::Account => Cell::Text(&row.account),
+        ExportColumn::Flag => Cell::Text(&row.flag),
+        ExportColumn::CheckNumber => Cell::Text(&row.check_number),
+        ExportColumn::Date => Cell::Date(&row.date),
+        ExportColumn::Payee => Cell::Text(&row.payee),
+        ExportColumn::Category => Cell::Text(&row.category),
+        ExportColumn::MasterCategory => Cell::Text(&row.master_category),
+        ExportColumn::SubCategory => Cell::Text(&row.sub_category),
+        ExportColumn::Memo => Cell::Text(&row.memo),
+        ExportColumn::Outflow => Cell::Amount(if row.amount < 0.0 { -row.amount } else { 0.0 }),
+        ExportColumn::Inflow => Cell::Amount(if row.amount > 0.0 { row.amount } else { 0.0 }),
+        ExportColumn::Amount => Cell::Amount(row.amount),
+        ExportColumn::Cleared => Cell::Text(&row.cleared),
+        ExportColumn::RunningBalance => Cell::Amount(row.running_balance),
+    }
+}
+
+fn parse_iso_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+/// CSV text for the rows: UTF-8 with a byte order mark so Excel reads accents,
+/// every field quoted, and `;` between fields when the decimal is a comma
+pub fn csv_text(
+    rows: &[ExportRow],
+    columns: &[ExportColumn],
+    number: &NumberFormat,
+    date: DateFormat,
+) -> String {
+    let delimiter = if number.decimal == ',' { ";" } else { "," };
+    let line = |fields: Vec<String>| {
+        let quoted: Vec<String> = fields
+            .iter()
+            .map(|f| format!("\"{}\"", f.replace('"', "\"\"")))
+            .collect();
+        quoted.join(delimiter) + "\r\n"
+    };
+
+    let mut text = String::from("\u{feff}");
+    text.push_str(&line(
+        columns.iter().map(|c| c.header().to_string()).collect(),
+    ));
+    for row in rows {
+        let fields = columns
+            .iter()
+            .map(|column| match cell(row, *column) {
+                Cell::Text(value) => value.to_string(),
+                Cell::Date(value) => parse_iso_date(value)
+                    .map_or(value.to_string(), |d| formats::format_date(d, date)),
+                Cell::Amount(value) => formats::format_amount(value, number),
+            })
+            .collect();
+        text.push_str(&line(fields));
+    }
+    text
+}
+
+/// Excel number format for dates
+fn excel_date_format(format: DateFormat) -> &'static str {
+    match format {
+        DateFormat::Ymd => "yyyy-mm-dd",
+        DateFormat::Dmy => "dd/mm/yyyy",
+        DateFormat::Mdy => "mm/dd/yyyy",
+        DateFormat::DayMonthName => "dd-mmm-yyyy",
+        DateFormat::MonthNameDay => "mmm dd, yyyy",
+    }
+}
+
+/// Workbook with one sheet of rows. Amounts and dates are real numbers and
+/// dates, so Excel shows them in the reader's own locale.
+fn write_xlsx(
+    rows: &[ExportRow],
+    columns: &[ExportColumn],
+    date: DateFormat,
+    path: &Path,
+) -> Result<(), XlsxError> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Transactions")?;
+    let bold = Format::new().set_bold();
+    let money = Format::new().set_num_format("#,##0.00");
+    let day = Format::new().set_num_format(excel_date_format(date));
+
+    for (col, column) in columns.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, column.header(), &bold)?;
+    }
+    for (i, row) in rows.iter().enumerate() {
+        let r = i as u32 + 1;
+        for (col, column) in columns.iter().enumerate() {
+            let col = col as u16;
+            match cell(row, *column) {
+                Cell::Text("") => {}
+                Cell::Text(value) => {
+                    sheet.write_string(r, col, value)?;
+                }
+                Cell::Date(value) => match parse_iso_date(value) {
+                    Some(d) => {
+                        let excel = ExcelDateTime::from_ymd(
+                            d.year() as u16,
+                            d.month() as u8,
+                            d.day() as u8,
+                        )?;
+                        sheet.write_datetime_with_format(r, col, &excel, &day)?;
+                    }
+                    None => {
+                        sheet.write_string(r, col, value)?;
+                    }
+                },
+                Cell::Amount(value) => {
+                    sheet.write_number_with_format(r, col, value, &money)?;
+                }
+            }
+        }
+    }
+    sheet.set_freeze_panes(1, 0)?;
+    sheet.autofit();
+    workbook.save(path)
+}
+
+/// Write the filtered register to `path` as CSV or XLSX
+pub fn export_transactions(
+    data: &BudgetData,
+    path: &Path,
+    options: &ExportOptions,
+) -> Result<ExportResult, String> {
+    let rows = export_rows(data, options);
+    let columns = options.resolved_columns();
+    let (locale_number, locale_date) = budget_formats(data);
+    let number = options.number_format.unwrap_or(locale_number);
+    let date = options.date_format.unwrap_or(locale_date);
+
+    match options.format {
+        ExportFormat::Csv => fs::write(path, csv_text(&rows, &columns, &number, date))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?,
+        ExportFormat::Xlsx => write_xlsx(&rows, &columns, date, path)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?,
+    }
+
+    let transactions: BTreeSet<&str> = rows.iter().map(|r| r.transaction_id.as_str()).collect();
+    Ok(ExportResult {
+        path: path.display().to_string(),
+        row_count: rows.len(),
+        transaction_count: transactions.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::statement::parse_statement;
+    use crate::import::ynab4_csv::{self, Ynab4ExportFile};
+    use crate::ynab4::budget::Budget;
+    use crate::ynab4::fixtures;
+
+    #[test]
+    fn exports_filtered_rows_with_whole_account_balances() {
+        let path = fixtures::budget_folder("export-csv");
+        let budget = Budget::load(&path).unwrap();
+        let out = path.parent().unwrap().join("export.csv");
+        let options = ExportOptions {
+            filter: ExportFilter {
+                account_ids: vec!["ACC-CHK".to_string()],
+                from: Some("2024-01-05".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = export_transactions(&budget.data, &out, &options).unwrap();
+        assert_eq!((result.row_count, result.transaction_count), (4, 4));
+        let text = fs::read_to_string(&out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            "\u{feff}\"Date\",\"Account\",\"Payee\",\"Category\",\"Memo\",\"Amount\",\"Cleared\",\"Running Balance\""
+        );
+        // es_MX: day first, dot decimal. The balance counts T-1 before the range.
+        assert_eq!(
+            lines[1],
+            "\"05/01/2024\",\"Checking\",\"OXXO\",\"Everyday: Food\",\"\",\"-250.00\",\"Uncleared\",\"750.00\""
+        );
+        assert!(lines[4].contains("\"Split (Multiple Categories)\",\"\",\"-300.00\""));
+
+        // Expanded splits keep only the lines in the category
+        let options = ExportOptions {
+            filter: ExportFilter {
+                category_ids: vec!["CAT-FOOD".to_string()],
+                ..Default::default()
+            },
+            split_mode: SplitMode::Expanded,
+            columns: vec![
+                ExportColumn::Date,
+                ExportColumn::SubCategory,
+                ExportColumn::Outflow,
+                ExportColumn::RunningBalance,
+            ],
+            number_format: Some(NumberFormat {
+                decimal: ',',
+                thousands: Some('.'),
+            }),
+            date_format: Some(DateFormat::Ymd),
+            ..Default::default()
+        };
+        let rows = export_rows(&budget.data, &options);
+        let ids: Vec<&str> = rows.iter().map(|r| r.transaction_id.as_str()).collect();
+        assert_eq!(ids, vec!["T-2", "T-7"]);
+        assert_eq!((rows[1].amount, rows[1].running_balance), (-100.0, 850.0));
+        export_transactions(&budget.data, &out, &options).unwrap();
+        let text = fs::read_to_string(&out).unwrap();
+        assert!(
+            text.contains("\"2024-02-03\";\"Food\";\"100,00\";\"850,00\""),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn same_day_rows_come_in_transaction_id_order() {
+        let path = fixtures::budget_folder("export-same-day");
+        let mut budget = Budget::load(&path).unwrap();
+        let template = budget.data.transaction("T-2").unwrap().clone();
+        for (id, amount) in [("T-9", -9.0), ("T-8", -8.0)] {
+            budget.data.transactions.insert(
+                0,
+                Transaction {
+                    entity_id: id.to_string(),
+                    amount,
+                    ..template.clone()
+                },
+            );
+        }
+        let options = ExportOptions {
+            filter: ExportFilter {
+                account_ids: vec!["ACC-CHK".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let rows = export_rows(&budget.data, &options);
+        let same_day: Vec<(&str, f64)> = rows
+            .iter()
+            .filter(|r| r.date == "2024-01-05")
+            .map(|r| (r.transaction_id.as_str(), r.running_balance))
+            .collect();
+        assert_eq!(
+            same_day,
+            vec![("T-2", 750.0), ("T-8", 742.0), ("T-9", 733.0)]
+        );
+    }
+
+    #[test]
+    fn ynab4_order_reads_back_as_a_register_export() {
+        let path = fixtures::budget_folder("export-ynab4");
+        let budget = Budget::load(&path).unwrap();
+        let out = path.parent().unwrap().join("register.csv");
+        let options = ExportOptions {
+            ynab4_order: true,
+            columns: vec![ExportColumn::Amount],
+            ..Default::default()
+        };
+        export_transactions(&budget.data, &out, &options).unwrap();
+
+        let parsed = parse_statement(&fs::read(&out).unwrap()).unwrap();
+        assert_eq!(parsed.headers.len(), 13);
+        let Some(Ynab4ExportFile::Register(export)) =
+            ynab4_csv::read_export(&parsed, Some(&budget.data)).unwrap()
+        else {
+            panic!("not read as a register");
+        };
+        assert!(export.errors.is_empty(), "{:?}", export.errors);
+        assert!(export.missing_accounts.is_empty() && export.missing_categories.is_empty());
+        assert_eq!(export.paired_transfers, 1);
+        let split = export
+            .transactions
+            .iter()
+            .find(|t| t.splits.len() == 2)
+            .unwrap();
+        assert_eq!(split.amount, -300.0);
+        assert_eq!(split.splits[0].category_id.as_deref(), Some("CAT-RENT"));
+        assert_eq!(export.transactions.len(), 6);
+        assert!(export.transactions.iter().all(|t| t.status == "ready"));
+    }
+}
@@ -0,0 +1,266 @@
+//! ISO 20022 camt.053 bank-to-customer statements (camt.052 reports read the same way).
+//!
+//! Each `Ntry` is a booking; a batch booking lists its parts as `TxDtls`,
+//! and those become one row each. The counterparty is the creditor for
+//! debits and the debtor for credits.
+
+use super::markup::{self, Element};
+use super::transaction::{self, BankStatement, ImportTransaction, StatementBalance};
+use crate::ynab4::entities::BudgetData;
+
+/// Signed amount of an element with `Amt` and `CdtDbtInd`
+fn signed_amount(element: &Element, indicator: Option<&str>) -> Option<f64> {
+    let amount: f64 = element
+        .text_of("Amt")
+        .or_else(|| element.text_at(&["AmtDtls", "TxAmt", "Amt"]))?
+        .parse()
+        .ok()?;
+    let indicator = element
+        .text_of("CdtDbtInd")
+        .or(indicator.map(str::to_string));
+    Some(if indicator.as_deref() == Some("DBIT") {
+        -amount
+    } else {
+        amount
+    })
+}
+
+/// `Dt` or the date part of `DtTm`
+fn date_of(element: Option<&Element>) -> Option<String> {
+    let element = element?;
+    element
+        .text_of("Dt")
+        .or_else(|| element.text_of("DtTm"))
+        .and_then(|d| d.get(..10).map(str::to_string))
+}
+
+fn balance(stmt: &Element, codes: &[&str]) -> Option<StatementBalance> {
+    codes.iter().find_map(|code| {
+        let bal = stmt
+            .children_named("Bal")
+            .find(|b| b.text_at(&["Tp", "CdOrPrtry", "Cd"]).as_deref() == Some(*code))?;
+        Some(StatementBalance {
+            amount: signed_amount(bal, None)?,
+            date: date_of(bal.child("Dt")),
+        })
+    })
+}
+
+/// Name and IBAN of the other party
+fn counterparty(details: &Element, debit: bool) -> (Option<String>, Option<String>) {
+    let (party, account) = if debit {
+        ("Cdtr", "CdtrAcct")
+    } else {
+        ("Dbtr", "DbtrAcct")
+    };
+    let Some(parties) = details.child("RltdPties") else {
+        return (None, None);
+    };
+    let name = parties
+        .text_at(&[party, "Nm"])
+        .or_else(|| parties.text_at(&[party, "Pty", "Nm"]))
+        .or_else(|| parties.text_at(&[if debit { "UltmtCdtr" } else { "UltmtDbtr" }, "Nm"]));
+    let iban = parties.text_at(&[account, "Id", "IBAN"]);
+    (name, iban)
+}
+
+/// Unstructured remittance lines, or the structured creditor reference
+fn remittance(details: &Element) -> Option<String> {
+    let info = details.child("RmtInf")?;
+    let lines: Vec<String> = info
+        .children_named("Ustrd")
+        .filter_map(Element::text)
+        .collect();
+    if !lines.is_empty() {
+        return Some(lines.join(" "));
+    }
+    info.children_named("Strd")
+        .find_map(|s| s.text_at(&["CdtrRefInf", "Ref"]))
+}
+
+/// Bank reference of a row. Parts of a batch booking share the entry's
+/// reference, so they use their own, or the entry's with the part number.
+fn reference(entry: &Element, details: Option<&Element>, part: Option<usize>) -> String {
+    let refs = details.and_then(|d| d.child("Refs"));
+    let own = refs.and_then(|r| r.text_of("AcctSvcrRef")).or_else(|| {
+        refs.and_then(|r| r.text_of("EndToEndId"))
+            .filter(|id| id != "NOTPROVIDED")
+    });
+    let Some(part) = part else {
+        return entry.text_of("AcctSvcrRef").or(own).unwrap_or_default();
+    };
+    own.or_else(|| {
+        entry
+            .text_of("AcctSvcrRef")
+            .map(|r| format!("{}/{}", r, part + 1))
+    })
+    .unwrap_or_default()
+}
+
+fn read_entry(
+    entry: &Element,
+    batch: i64,
+    index: &mut usize,
+) -> Result<Vec<ImportTransaction>, String> {
+    let label = entry
+        .text_of("NtryRef")
+        .or_else(|| entry.text_of("AcctSvcrRef"))
+        .unwrap_or_else(|| format!("#{}", *index + 1));
+    let entry_amount =
+        signed_amount(entry, None).ok_or_else(|| format!("Entry {}: invalid amount", label))?;
+    let booked = date_of(entry.child("BookgDt"))
+        .or_else(|| date_of(entry.child("ValDt")))
+        .ok_or_else(|| format!("Entry {}: missing booking date", label))?;
+    let value_date = date_of(entry.child("ValDt"));
+    let indicator = entry.text_of("CdtDbtInd");
+
+    let details: Vec<&Element> = entry
+        .children_named("NtryDtls")
+        .flat_map(|d| d.children_named("TxDtls"))
+        .collect();
+    // A single booking, or one row per part of a batch
+    let batched = details.len() > 1;
+    let parts: Vec<(Option<&Element>, f64)> = if batched {
+        details
+            .iter()
+            .map(|d| {
+                signed_amount(d, indicator.as_deref())
+                    .map(|amount| (Some(*d), amount))
+                    .ok_or_else(|| format!("Entry {}: a batch part has no amount", label))
+            })
+            .collect::<Result<_, _>>()?
+    } else {
+        vec![(details.first().copied(), entry_amount)]
+    };
+
+    let mut rows = Vec::new();
+    for (part, (details, amount)) in parts.into_iter().enumerate() {
+        let (name, iban) = details
+            .map(|d| counterparty(d, amount < 0.0))
+            .unwrap_or_default();
+        let memo = details
+            .and_then(remittance)
+            .or_else(|| details.and_then(|d| d.text_of("AddtlTxInf")))
+            .or_else(|| entry.text_of("AddtlNtryInf"))
+            .unwrap_or_default();
+        let description = name.unwrap_or_else(|| memo.clone());
+
+        let mut row = ImportTransaction::new(batch, *index, booked.clone(), description, amount);
+        row.original_memo = memo.clone();
+        row.memo = memo;
+        row.reference = reference(entry, details, batched.then_some(part));
+        row.value_date = value_date.clone().filter(|d| *d != booked);
+        row.counterparty_iban = iban;
+        rows.push(row);
+        *index += 1;
+    }
+    Ok(rows)
+}
+
+/// Parse every statement in a camt.053 (or camt.052) document
+pub fn parse_camt(text: &str, data: Option<&BudgetData>) -> Result<Vec<BankStatement>, String> {
+    let root = markup::parse(text);
+    let mut statements = Vec::new();
+    root.descendants("Stmt", &mut statements);
+    root.descendants("Rpt", &mut statements);
+    if statements.is_empty() {
+        return Err("Not a camt.053 file: no statements".to_string());
+    }
+
+    let batch = transaction::batch_id();
+    let mut index = 0;
+    let mut result = Vec::new();
+    for stmt in statements {
+        let mut statement = BankStatement {
+            statement_id: stmt.text_of("Id").unwrap_or_default(),
+            account: stmt
+                .text_at(&["Acct", "Id", "IBAN"])
+                .or_else(|| stmt.text_at(&["Acct", "Id", "Othr", "Id"]))
+                .unwrap_or_default(),
+            currency: stmt.text_at(&["Acct", "Ccy"]),
+            opening_balance: balance(stmt, &["OPBD", "PRCD"]),
+            closing_balance: balance(stmt, &["CLBD"]),
+            ..Default::default()
+        };
+        let mut pending = 0;
+        for entry in stmt.children_named("Ntry") {
+            let status = entry
+                .text_of("Sts")
+                .or_else(|| entry.text_at(&["Sts", "Cd"]));
+            if matches!(status.as_deref(), Some("PDNG" | "INFO")) {
+                pending += 1;
+                continue;
+            }
+            match read_entry(entry, batch, &mut index) {
+                Ok(rows) => statement.transactions.extend(rows),
+                Err(e) => statement.errors.push(e),
+            }
+        }
+        if pending > 0 {
+            statement
+                .errors
+                .push(format!("Skipped {} pending entries", pending));
+        }
+        statement.assign_account(data);
+        statement.check_balances();
+        result.push(statement);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+<BkToCstmrStmt><GrpHdr><MsgId>M1</MsgId></GrpHdr>
+<Stmt><Id>STMT-1</Id>
+<Acct><Id><IBAN>DE89370400440532013000</IBAN></Id><Ccy>EUR</Ccy></Acct>
+<Bal><Tp><CdOrPrtry><Cd>OPBD</Cd></CdOrPrtry></Tp><Amt Ccy="EUR">1000.00</Amt><CdtDbtInd>CRDT</CdtDbtInd><Dt><Dt>2024-01-01</Dt></Dt></Bal>
+<Bal><Tp><CdOrPrtry><Cd>CLBD</Cd></CdOrPrtry></Tp><Amt Ccy="EUR">1837.50</Amt><CdtDbtInd>CRDT</CdtDbtInd><Dt><Dt>2024-01-31</Dt></Dt></Bal>
+<Ntry><Amt Ccy="EUR">12.50</Amt><CdtDbtInd>DBIT</CdtDbtInd><Sts>BOOK</Sts>
+<BookgDt><Dt>2024-01-05</Dt></BookgDt><ValDt><Dt>2024-01-04</Dt></ValDt><AcctSvcrRef>BANK-1</AcctSvcrRef>
+<NtryDtls><TxDtls><Refs><EndToEndId>E2E</EndToEndId></Refs>
+<RltdPties><Cdtr><Nm>Caf&#233; Central</Nm></Cdtr><CdtrAcct><Id><IBAN>AT611904300234573201</IBAN></Id></CdtrAcct></RltdPties>
+<RmtInf><Ustrd>Invoice 42</Ustrd><Ustrd>thanks</Ustrd></RmtInf></TxDtls></NtryDtls></Ntry>
+<Ntry><Amt Ccy="EUR">850.00</Amt><CdtDbtInd>CRDT</CdtDbtInd><Sts><Cd>BOOK</Cd></Sts>
+<BookgDt><DtTm>2024-01-10T10:00:00</DtTm></BookgDt><AcctSvcrRef>BANK-2</AcctSvcrRef>
+<NtryDtls>
+<TxDtls><Amt Ccy="EUR">800.00</Amt><RltdPties><Dbtr><Pty><Nm>Employer</Nm></Pty></Dbtr></RltdPties><RmtInf><Strd><CdtrRefInf><Ref>RF18</Ref></CdtrRefInf></Strd></RmtInf></TxDtls>
+<TxDtls><Refs><EndToEndId>E2E-FRIEND</EndToEndId></Refs><AmtDtls><TxAmt><Amt Ccy="EUR">50.00</Amt></TxAmt></AmtDtls><RltdPties><Dbtr><Nm>Friend</Nm></Dbtr></RltdPties></TxDtls>
+</NtryDtls></Ntry>
+<Ntry><Amt Ccy="EUR">5.00</Amt><CdtDbtInd>DBIT</CdtDbtInd><Sts>PDNG</Sts><BookgDt><Dt>2024-01-31</Dt></BookgDt></Ntry>
+</Stmt></BkToCstmrStmt></Document>"#;
+
+    #[test]
+    fn reads_a_statement_with_a_batch_booking() {
+        let statements = parse_camt(CAMT, None).unwrap();
+        let s = &statements[0];
+        assert_eq!(s.statement_id, "STMT-1");
+        assert_eq!(s.account, "DE89370400440532013000");
+        assert_eq!(s.currency.as_deref(), Some("EUR"));
+        assert_eq!(s.opening_balance.as_ref().unwrap().amount, 1000.0);
+        assert_eq!(
+            s.closing_balance.as_ref().unwrap().date.as_deref(),
+            Some("2024-01-31")
+        );
+        assert_eq!(s.transactions.len(), 3);
+        let t = &s.transactions[0];
+        assert_eq!(t.amount, -12.5);
+        assert_eq!(t.date, "2024-01-05");
+        assert_eq!(t.value_date.as_deref(), Some("2024-01-04"));
+        assert_eq!(t.description, "Café Central");
+        assert_eq!(t.counterparty_iban.as_deref(), Some("AT611904300234573201"));
+        assert_eq!(t.memo, "Invoice 42 thanks");
+        assert_eq!(t.reference, "BANK-1");
+        assert_eq!(s.transactions[1].description, "Employer");
+        assert_eq!(s.transactions[1].memo, "RF18");
+        assert_eq!(s.transactions[1].date, "2024-01-10");
+        assert_eq!(s.transactions[2].amount, 50.0);
+        // The batch parts share the entry's reference, so each gets its own
+        assert_eq!(s.transactions[1].reference, "BANK-2/1");
+        assert_eq!(s.transactions[2].reference, "E2E-FRIEND");
+        assert_eq!(s.errors, vec!["Skipped 1 pending entries".to_string()]);
+    }
+}
@@ -0,0 +1,378 @@
+//! CFDI 3.3/4.0 electronic invoices from the SAT.
+//!
+//! An invoice is a `cfdi:Comprobante` with the issuer (`Emisor`), the line
+//! concepts and, once stamped, a `tfd:TimbreFiscalDigital` complement with
+//! the UUID. Invoices are matched to the budget's transactions by amount and
+//! date, and attached ones are kept in a sidecar file in the budget folder
+//! because YNAB4 has nowhere to store them.
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::markup::{self, Element};
+use super::transaction::{self, ImportTransaction};
+use crate::ynab4::entities::BudgetData;
+use crate::ynab4::local;
+
+/// Sidecar with the attached invoices, next to `Budget.ymeta`
+pub const INVOICES_SIDECAR_FILE: &str = "invoices.ynab4client.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CfdiConcept {
+    pub description: String,
+    pub quantity: f64,
+    pub unit_price: f64,
+    pub amount: f64,
+    /// SAT product or service key
+    pub product_code: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Cfdi {
+    /// Folio fiscal from the stamp; empty for an unstamped invoice
+    pub uuid: String,
+    pub version: String,
+    /// I (income), E (credit note), P (payment) or T (transfer)
+    pub invoice_type: String,
+    pub issuer_rfc: String,
+    pub issuer_name: String,
+    pub receiver_rfc: String,
+    pub receiver_name: String,
+    /// YYYY-MM-DD
+    pub date: String,
+    pub subtotal: f64,
+    pub total: f64,
+    pub currency: String,
+    /// Pesos per unit of `currency`, for invoices not in MXN
+    pub exchange_rate: Option<f64>,
+    /// PUE (paid at once) or PPD (paid in parts)
+    pub payment_method: Option<String>,
+    /// SAT payment form code: 01 cash, 03 transfer, 04 credit card...
+    pub payment_form: Option<String>,
+    pub series: Option<String>,
+    pub folio: Option<String>,
+    pub concepts: Vec<CfdiConcept>,
+}
+
+/// A transaction that could be the payment of an invoice
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CfdiMatch {
+    pub transaction_id: String,
+    pub account_id: String,
+    pub date: String,
+    pub amount: f64,
+    pub payee_name: Option<String>,
+    pub memo: Option<String>,
+    pub days_apart: i64,
+    /// 0 to 1; higher is a better match
+    pub score: f64,
+}
+
+/// An invoice read from a file, with its import row and likely transactions
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CfdiImport {
+    pub invoice: Cfdi,
+    pub row: ImportTransaction,
+    pub matches: Vec<CfdiMatch>,
+}
+
+/// An invoice attached to a transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachedInvoice {
+    pub transaction_id: String,
+    pub invoice: Cfdi,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoicesSidecar {
+    #[serde(default)]
+    pub invoices: Vec<AttachedInvoice>,
+}
+
+fn number(element: &Element, name: &str) -> Option<f64> {
+    element.attr(name)?.trim().parse().ok()
+}
+
+fn text_attr(element: &Element, name: &str) -> Option<String> {
+    element
+        .attr(name)
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+/// Parse a CFDI document
+pub fn parse_cfdi(text: &str) -> Result<Cfdi, String> {
+    let root = markup::parse(text);
+    let comprobante = root
+        .child("Comprobante")
+        .ok_or("Not a CFDI file: no Comprobante element")?;
+    let version = text_attr(comprobante, "Version").unwrap_or_default();
+    let fecha = text_attr(comprobante, "Fecha").ok_or("The invoice has no date")?;
+    let date = fecha
+        .get(..10)
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+        .ok_or_else(|| format!("Invalid invoice date '{}'", fecha))?;
+    let invoice_type =
+        text_attr(comprobante, "TipoDeComprobante").unwrap_or_else(|| "I".to_string());
+
+    let concepts = comprobante
+        .child("Conceptos")
+        .map(|c| {
+            c.children_named("Concepto")
+                .map(|concept| CfdiConcept {
+                    description: text_attr(concept, "Descripcion").unwrap_or_default(),
+                    quantity: number(concept, "Cantidad").unwrap_or(1.0),
+                    unit_price: number(concept, "ValorUnitario").unwrap_or_default(),
+                    amount: number(concept, "Importe").unwrap_or_default(),
+                    product_code: text_attr(concept, "ClaveProdServ"),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut stamps = Vec::new();
+    comprobante.descendants("TimbreFiscalDigital", &mut stamps);
+    let uuid = stamps
+        .first()
+        .and_then(|t| text_attr(t, "UUID"))
+        .map(|u| u.to_uppercase())
+        .unwrap_or_default();
+
+    // A payment receipt has a zero total; the money is in its Pago elements
+    let mut total = number(comprobante, "Total").ok_or("The invoice has no total")?;
+    if invoice_type == "P" && total == 0.0 {
+        let mut payments = Vec::new();
+        comprobante.descendants("Pago", &mut payments);
+        total = payments.iter().filter_map(|p| number(p, "Monto")).sum();
+    }
+
+    let issuer = comprobante.child("Emisor");
+    let receiver = comprobante.child("Receptor");
+    Ok(Cfdi {
+        uuid,
+        version,
+        invoice_type,
+        issuer_rfc: issuer.and_then(|e| text_attr(e, "Rfc")).unwrap_or_default(),
+        issuer_name: issuer
+            .and_then(|e| text_attr(e, "Nombre"))
+            .unwrap_or_default(),
+        receiver_rfc: receiver
+            .and_then(|e| text_attr(e, "Rfc"))
+            .unwrap_or_default(),
+        receiver_name: receiver
+            .and_then(|e| text_attr(e, "Nombre"))
+            .unwrap_or_default(),
+        date: date.format("%Y-%m-%d").to_string(),
+        subtotal: number(comprobante, "SubTotal").unwrap_or(total),
+        total,
+        currency: text_attr(comprobante, "Moneda").unwrap_or_else(|| "MXN".to_string()),
+        exchange_rate: number(comprobante, "TipoCambio").filter(|rate| *rate != 1.0),
+        payment_method: text_attr(comprobante, "MetodoPago"),
+        payment_form: text_attr(comprobante, "FormaPago"),
+        series: text_attr(comprobante, "Serie"),
+        folio: text_attr(comprobante, "Folio"),
+        concepts,
+    })
+}
+
+impl Cfdi {
+    /// Amount the invoice should have moved in the budget, in pesos.
+    /// Credit notes are money back.
+    pub fn budget_amount(&self) -> f64 {
+        let pesos = self.total * self.exchange_rate.unwrap_or(1.0);
+        let pesos = (pesos * 100.0).round() / 100.0;
+        if self.invoice_type == "E" {
+            pesos
+        } else {
+            -pesos
+        }
+    }
+
+    /// Text added to the memo of the matching transaction
+    pub fn memo_note(&self) -> String {
+        let mut note = format!("CFDI {}", self.uuid);
+        if let Some(first) = self.concepts.first() {
+            note.push_str(&format!(" · {}", first.description));
+            if self.concepts.len() > 1 {
+                note.push_str(&format!(" (+{})", self.concepts.len() - 1));
+            }
+        }
+        note
+    }
+
+    /// An import row for an invoice with no transaction yet
+    pub fn to_import_row(&self) -> ImportTransaction {
+        let name = if self.issuer_name.is_empty() {
+            &self.issuer_rfc
+        } else {
+            &self.issuer_name
+        };
+        let amount = self.budget_amount();
+        let mut row = ImportTransaction::new(
+            transaction::batch_id(),
+            0,
+            self.date.clone(),
+            name.clone(),
+            amount,
+        );
+        row.original_memo = self
+            .concepts
+            .iter()
+            .map(|c| c.description.as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+        row.memo = self.memo_note();
+        row.reference = self.uuid.clone();
+        row
+    }
+}
+
+/// Transactions within `days` of the invoice date for the invoice's amount,
+/// best first. A foreign-currency invoice may be off by 2% after conversion.
+pub fn suggest_matches(data: &BudgetData, invoice: &Cfdi, days: i64) -> Vec<CfdiMatch> {
+    let Ok(invoice_date) = NaiveDate::parse_from_str(&invoice.date, "%Y-%m-%d") else {
+        return Vec::new();
+    };
+    let amount = invoice.budget_amount();
+    let tolerance = if invoice.exchange_rate.is_some() {
+        amount.abs() * 0.02
+    } else {
+        0.005
+    };
+    let mut matches: Vec<CfdiMatch> = data
+        .transactions
+        .iter()
+        .filter(|t| !t.is_tombstone && (t.amount - amount).abs() <= tolerance)
+        // Already attached to an invoice
+        .filter(|t| {
+            !t.memo
+                .as_deref()
+                .is_some_and(|m| !invoice.uuid.is_empty() && m.contains(&invoice.uuid))
+        })
+        .filter_map(|t| {
+            let date = NaiveDate::parse_from_str(t.date.get(..10)?, "%Y-%m-%d").ok()?;
+            // Card charges post after the invoice is issued, rarely before
+            let days_apart = (date - invoice_date).num_days();
+            if days_apart < -1 || days_apart > days {
+                return None;
+            }
+            let payee_name = t
+                .payee_id
+                .as_deref()
+                .and_then(|id| data.payee(id))
+                .map(|p| p.name.clone());
+            let similarity = payee_name.as_deref().map_or(0.0, |name| {
+                transaction::name_similarity(name, &invoice.issuer_name)
+            });
+            let closeness = 1.0 - days_apart.abs() as f64 / (days + 2) as f64;
+            Some(CfdiMatch {
+                transaction_id: t.entity_id.clone(),
+                account_id: t.account_id.clone(),
+                date: t.date.clone(),
+                amount: t.amount,
+                payee_name,
+                memo: t.memo.clone(),
+                days_apart,
+                score: ((0.6 * closeness + 0.4 * similarity) * 100.0).round() / 100.0,
+            })
+        })
+        .collect();
+    matches.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.days_apart.abs().cmp(&b.days_apart.abs()))
+    });
+    matches
+}
+
+pub fn load_sidecar(budget_path: &Path) -> Result<InvoicesSidecar, String> {
+    local::read_or_default(&budget_path.join(INVOICES_SIDECAR_FILE))
+}
+
+/// Record an invoice against a transaction, replacing an earlier attachment
+/// of the same invoice
+pub fn attach_to_sidecar(
+    budget_path: &Path,
+    transaction_id: &str,
+    invoice: &Cfdi,
+) -> Result<(), String> {
+    let mut sidecar = load_sidecar(budget_path)?;
+    sidecar
+        .invoices
+        .retain(|a| invoice.uuid.is_empty() || a.invoice.uuid != invoice.uuid);
+    sidecar.invoices.push(AttachedInvoice {
+        transaction_id: transaction_id.to_string(),
+        invoice: invoice.clone(),
+    });
+    local::write_json(&budget_path.join(INVOICES_SIDECAR_FILE), &sidecar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::budget::Budget;
+    use crate::ynab4::fixtures;
+
+    const CFDI: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<cfdi:Comprobante xmlns:cfdi="http://www.sat.gob.mx/cfd/4" xmlns:tfd="http://www.sat.gob.mx/TimbreFiscalDigital"
+  Version="4.0" Serie="A" Folio="123" Fecha="2024-01-04T18:30:00" SubTotal="215.52" Total="250.00" Moneda="MXN"
+  TipoDeComprobante="I" MetodoPago="PUE" FormaPago="04">
+  <cfdi:Emisor Rfc="CCO8605231N4" Nombre="CADENA COMERCIAL OXXO" RegimenFiscal="601"/>
+  <cfdi:Receptor Rfc="XAXX010101000" Nombre="JUAN P&#201;REZ"/>
+  <cfdi:Conceptos>
+    <cfdi:Concepto ClaveProdServ="50202306" Cantidad="2" ValorUnitario="50.00" Importe="100.00" Descripcion="Refresco"/>
+    <cfdi:Concepto ClaveProdServ="50181900" Cantidad="1" ValorUnitario="115.52" Importe="115.52" Descripcion="Pan &amp; galletas"/>
+  </cfdi:Conceptos>
+  <cfdi:Complemento>
+    <tfd:TimbreFiscalDigital Version="1.1" UUID="ad662d33-6934-459c-a128-bdf0393e0f44" FechaTimbrado="2024-01-04T18:31:00"/>
+  </cfdi:Complemento>
+</cfdi:Comprobante>"#;
+
+    #[test]
+    fn reads_a_stamped_invoice() {
+        let invoice = parse_cfdi(CFDI).unwrap();
+        assert_eq!(invoice.version, "4.0");
+        assert_eq!(invoice.uuid, "AD662D33-6934-459C-A128-BDF0393E0F44");
+        assert_eq!(invoice.issuer_rfc, "CCO8605231N4");
+        assert_eq!(invoice.receiver_name, "JUAN PÉREZ");
+        assert_eq!(invoice.date, "2024-01-04");
+        assert_eq!(invoice.total, 250.0);
+        assert_eq!(invoice.concepts.len(), 2);
+        assert_eq!(invoice.concepts[1].description, "Pan & galletas");
+        assert_eq!(invoice.budget_amount(), -250.0);
+        let row = invoice.to_import_row();
+        assert_eq!(row.amount, -250.0);
+        assert_eq!(row.reference, invoice.uuid);
+
+        let note = CFDI.replace("TipoDeComprobante=\"I\"", "TipoDeComprobante=\"E\"");
+        assert_eq!(parse_cfdi(&note).unwrap().budget_amount(), 250.0);
+        assert!(parse_cfdi("<Document/>").is_err());
+    }
+
+    #[test]
+    fn matches_and_attaches_an_invoice_once() {
+        let path = fixtures::budget_folder("cfdi-attach");
+        let budget = Budget::load(&path).unwrap();
+        let invoice = parse_cfdi(CFDI).unwrap();
+        let matches = suggest_matches(&budget.data, &invoice, 5);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(
+            (matches[0].transaction_id.as_str(), matches[0].days_apart),
+            ("T-2", 1)
+        );
+
+        attach_to_sidecar(&path, "T-2", &invoice).unwrap();
+        attach_to_sidecar(&path, "T-3", &invoice).unwrap();
+        let sidecar = load_sidecar(&path).unwrap();
+        assert_eq!(sidecar.invoices.len(), 1);
+        assert_eq!(sidecar.invoices[0].transaction_id, "T-3");
+    }
+}
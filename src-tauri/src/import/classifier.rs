@@ -0,0 +1,399 @@
+//! Payee and category suggestions learned from the budget's own register.
+//!
+//! Two naive Bayes models share the same features: the words of the payee
+//! name and memo, a bucket for the amount and the day of the month. One
+//! predicts the category, the other the payee. The model keeps every
+//! transaction it learned from with its entity version, so retraining only
+//! adds, removes or replaces the transactions that changed since. It is
+//! stored in the app's local data, never leaves the machine and needs no
+//! network.
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use super::transaction::{self, ImportTransaction};
+use crate::ynab4::entities::{BudgetData, Transaction, SPLIT_CATEGORY_ID, TRANSFER_PAYEE_PREFIX};
+use crate::ynab4::local;
+
+const MODEL_FILE: &str = "classifier.json";
+
+/// Bumped when features change, so older models are rebuilt
+const MODEL_VERSION: u32 = 1;
+
+/// Upper bounds of the amount buckets
+const AMOUNT_BUCKETS: [f64; 8] = [10.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 5000.0, 20000.0];
+
+/// A transaction the model learned from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Example {
+    version: Option<String>,
+    payee_id: Option<String>,
+    category_id: Option<String>,
+    features: Vec<String>,
+}
+
+/// Per-label counts of one naive Bayes model
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Counts {
+    /// Examples per label
+    labels: HashMap<String, u32>,
+    /// Feature occurrences per label
+    features: HashMap<String, HashMap<String, u32>>,
+    /// All feature occurrences per label
+    totals: HashMap<String, u32>,
+}
+
+impl Counts {
+    fn add(&mut self, label: &str, features: &[String], delta: i64) {
+        let apply = |count: &mut u32, by: i64| *count = (*count as i64 + by).max(0) as u32;
+        apply(self.labels.entry(label.to_string()).or_default(), delta);
+        apply(
+            self.totals.entry(label.to_string()).or_default(),
+            delta * features.len() as i64,
+        );
+        let per_label = self.features.entry(label.to_string()).or_default();
+        for feature in features {
+            apply(per_label.entry(feature.clone()).or_default(), delta);
+        }
+        per_label.retain(|_, count| *count > 0);
+        if self.labels.get(label) == Some(&0) {
+            self.labels.remove(label);
+            self.totals.remove(label);
+            self.features.remove(label);
+        }
+    }
+
+    /// Best label with its probability among all labels
+    fn predict(&self, features: &[String], vocabulary: usize) -> Option<(String, f64)> {
+        let examples: u32 = self.labels.values().sum();
+        if examples == 0 {
+            return None;
+        }
+        let vocabulary = vocabulary.max(1) as f64;
+        let mut scores: Vec<(&String, f64)> = self
+            .labels
+            .iter()
+            .filter_map(|(label, count)| {
+                // A damaged classifier.json may lack a label's counts
+                let per_label = self.features.get(label)?;
+                let total = *self.totals.get(label)? as f64;
+                let prior = (*count as f64 / examples as f64).ln();
+                let likelihood: f64 = features
+                    .iter()
+                    .map(|f| {
+                        ((per_label.get(f).copied().unwrap_or(0) as f64 + 1.0)
+                            / (total + vocabulary))
+                            .ln()
+                    })
+                    .sum();
+                Some((label, prior + likelihood))
+            })
+            .collect();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        // Softmax over the log scores
+        let best = scores.first()?.1;
+        let sum: f64 = scores.iter().map(|(_, s)| (s - best).exp()).sum();
+        Some((scores[0].0.clone(), 1.0 / sum))
+    }
+}
+
+/// A suggestion for one import row
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Prediction {
+    pub payee_id: Option<String>,
+    pub payee_name: Option<String>,
+    pub payee_confidence: f64,
+    pub category_id: Option<String>,
+    pub category_name: Option<String>,
+    pub category_confidence: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrainingReport {
+    pub examples: usize,
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Classifier {
+    #[serde(default)]
+    model_version: u32,
+    examples: BTreeMap<String, Example>,
+    categories: Counts,
+    payees: Counts,
+    /// Word features seen, for smoothing
+    vocabulary: BTreeMap<String, u32>,
+}
+
+fn model_path(budget_path: &str) -> Result<PathBuf, String> {
+    Ok(local::budget_dir(budget_path)?.join(MODEL_FILE))
+}
+
+/// Lowercase words of at least two letters; numbers such as references and
+/// branch codes are left out
+pub fn tokens(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| t.chars().count() >= 2 && !t.chars().all(|c| c.is_ascii_digit()))
+        .map(|t| format!("w:{}", t))
+        .collect()
+}
+
+fn amount_feature(amount: f64) -> String {
+    let sign = if amount < 0.0 { "out" } else { "in" };
+    let bucket = AMOUNT_BUCKETS
+        .iter()
+        .position(|limit| amount.abs() < *limit)
+        .unwrap_or(AMOUNT_BUCKETS.len());
+    format!("amount:{}{}", sign, bucket)
+}
+
+/// Features of a text, amount and date
+pub fn features(text: &str, amount: f64, date: &str) -> Vec<String> {
+    let mut features = tokens(text);
+    features.sort();
+    features.dedup();
+    features.push(amount_feature(amount));
+    if let Some(day) = date
+        .get(..10)
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+    {
+        features.push(format!("day:{}", day.day()));
+    }
+    features
+}
+
+/// Whether a transaction says something about how payees are categorized
+fn learnable(t: &Transaction) -> bool {
+    !t.is_tombstone
+        && t.category_id
+            .as_deref()
+            .is_some_and(|c| c != SPLIT_CATEGORY_ID)
+        && !t
+            .payee_id
+            .as_deref()
+            .is_some_and(|p| p.starts_with(TRANSFER_PAYEE_PREFIX))
+}
+
+impl Classifier {
+    pub fn load(budget_path: &str) -> Result<Classifier, String> {
+        let model: Classifier = local::read_or_default(&model_path(budget_path)?)?;
+        Ok(if model.model_version == MODEL_VERSION {
+            model
+        } else {
+            Classifier::default()
+        })
+    }
+
+    pub fn save(&self, budget_path: &str) -> Result<(), String> {
+        local::write_json(&model_path(budget_path)?, self)
+    }
+
+    fn learn(&mut self, example: &Example, delta: i64) {
+        if let Some(category) = &example.category_id {
+            self.categories.add(category, &example.features, delta);
+        }
+        if let Some(payee) = &example.payee_id {
+            self.payees.add(payee, &example.features, delta);
+        }
+        for feature in example.features.iter().filter(|f| f.starts_with("w:")) {
+            let count = self.vocabulary.entry(feature.clone()).or_default();
+            *count = (*count as i64 + delta).max(0) as u32;
+            if *count == 0 {
+                self.vocabulary.remove(feature);
+            }
+        }
+    }
+
+    /// Bring the model up to date with the register, touching only the
+    /// transactions added, changed or deleted since the last training
+    pub fn train(&mut self, data: &BudgetData) -> TrainingReport {
+        self.model_version = MODEL_VERSION;
+        let mut report = TrainingReport::default();
+        let mut live = std::collections::HashSet::new();
+        for t in data.transactions.iter().filter(|t| learnable(t)) {
+            live.insert(t.entity_id.as_str());
+            let previous = self.examples.get(&t.entity_id);
+            if previous.is_some_and(|e| e.version == t.entity_version) {
+                continue;
+            }
+            let payee = t.payee_id.as_deref().and_then(|id| data.payee(id));
+            let text = format!(
+                "{} {}",
+                payee.map_or("", |p| p.name.as_str()),
+                t.memo.as_deref().unwrap_or_default()
+            );
+            let example = Example {
+                version: t.entity_version.clone(),
+                payee_id: payee.map(|p| p.entity_id.clone()),
+                category_id: t.category_id.clone(),
+                features: features(&text, t.amount, &t.date),
+            };
+            match self.examples.insert(t.entity_id.clone(), example.clone()) {
+                Some(old) => {
+                    self.learn(&old, -1);
+                    report.updated += 1;
+                }
+                None => report.added += 1,
+            }
+            self.learn(&example, 1);
+        }
+        let gone: Vec<String> = self
+            .examples
+            .keys()
+            .filter(|id| !live.contains(id.as_str()))
+            .cloned()
+            .collect();
+        for id in gone {
+            if let Some(old) = self.examples.remove(&id) {
+                self.learn(&old, -1);
+                report.removed += 1;
+            }
+        }
+        report.examples = self.examples.len();
+        report
+    }
+
+    /// Payee and category for a row, when its words were seen before
+    pub fn predict(&self, data: &BudgetData, row: &ImportTransaction) -> Option<Prediction> {
+        let text = format!(
+            "{} {} {}",
+            row.description, row.payee_name, row.original_memo
+        );
+        let features = features(&text, row.amount, &row.date);
+        // Amount and day alone would put every row in the biggest category
+        if !features.iter().any(|f| self.vocabulary.contains_key(f)) {
+            return None;
+        }
+        let vocabulary = self.vocabulary.len() + AMOUNT_BUCKETS.len() * 2 + 31;
+        let payee = self
+            .payees
+            .predict(&features, vocabulary)
+            .and_then(|(id, confidence)| {
+                Some((data.payee(&id).filter(|p| !p.is_tombstone)?, confidence))
+            });
+        let categories = data.live_categories();
+        let category = self
+            .categories
+            .predict(&features, vocabulary)
+            .map(|(id, confidence)| {
+                let name = categories
+                    .iter()
+                    .find(|c| c.entity_id == id)
+                    .map(|c| c.name.clone())
+                    .or_else(|| transaction::category_path(data, &id));
+                (id, name, confidence)
+            });
+        let round = |c: f64| (c * 100.0).round() / 100.0;
+        Some(Prediction {
+            payee_id: payee.map(|(p, _)| p.entity_id.clone()),
+            payee_name: payee.map(|(p, _)| p.name.clone()),
+            payee_confidence: payee.map_or(0.0, |(_, c)| round(c)),
+            category_id: category.as_ref().map(|(id, _, _)| id.clone()),
+            category_name: category.as_ref().and_then(|(_, name, _)| name.clone()),
+            category_confidence: category.map_or(0.0, |(_, _, c)| round(c)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::budget::Budget;
+    use crate::ynab4::entities::IMMEDIATE_INCOME_CATEGORY_ID;
+    use crate::ynab4::fixtures;
+
+    fn row(description: &str, amount: f64, date: &str) -> ImportTransaction {
+        ImportTransaction::new(1, 0, date.to_string(), description.to_string(), amount)
+    }
+
+    #[test]
+    fn learns_from_the_register_and_retrains_incrementally() {
+        let mut budget = Budget::load(&fixtures::budget_folder("classifier-train")).unwrap();
+        let template = budget.data.transaction("T-2").unwrap().clone();
+        let history = [
+            ("P-1", "CAT-FOOD", -85.0, "snacks"),
+            ("P-1", "CAT-FOOD", -120.0, ""),
+            ("P-2", "CAT-FUN", -199.0, "movie night"),
+            ("P-2", "CAT-FUN", -199.0, "movie night"),
+            ("P-EMP", IMMEDIATE_INCOME_CATEGORY_ID, 15000.0, "nomina"),
+        ];
+        for (idx, (payee, category, amount, memo)) in history.into_iter().enumerate() {
+            let mut transaction = template.clone();
+            transaction.entity_id = format!("L-{}", idx);
+            transaction.payee_id = Some(payee.to_string());
+            transaction.category_id = Some(category.to_string());
+            transaction.amount = amount;
+            transaction.memo = Some(memo.to_string());
+            budget.data.transactions.push(transaction);
+        }
+
+        let mut classifier = Classifier::default();
+        // T-5/T-6 are transfers and T-7 a split
+        assert_eq!(classifier.train(&budget.data).added, 9);
+        assert_eq!(classifier.train(&budget.data).added, 0);
+
+        let prediction = classifier
+            .predict(&budget.data, &row("OXXO SUC 1234 MTY", -95.0, "2024-03-02"))
+            .unwrap();
+        assert_eq!(prediction.payee_id.as_deref(), Some("P-1"));
+        assert_eq!(prediction.category_id.as_deref(), Some("CAT-FOOD"));
+        assert!(prediction.category_confidence > 0.5, "{:?}", prediction);
+        let prediction = classifier
+            .predict(
+                &budget.data,
+                &row("PAGO NOMINA EMPLOYER", 15000.0, "2024-03-15"),
+            )
+            .unwrap();
+        assert_eq!(
+            prediction.category_name.as_deref(),
+            Some("Income:Available this month")
+        );
+        assert!(classifier
+            .predict(&budget.data, &row("zzz 123", -5.0, "2024-03-01"))
+            .is_none());
+
+        // Recategorize one, delete another
+        let transaction = budget
+            .data
+            .transactions
+            .iter_mut()
+            .find(|t| t.entity_id == "L-0")
+            .unwrap();
+        transaction.category_id = Some("CAT-FUN".to_string());
+        transaction.entity_version = Some("A-99".to_string());
+        budget.data.transactions.retain(|t| t.entity_id != "L-4");
+        let report = classifier.train(&budget.data);
+        assert_eq!((report.added, report.updated, report.removed), (0, 1, 1));
+
+        // The same counts as learning from scratch
+        let mut fresh = Classifier::default();
+        fresh.train(&budget.data);
+        assert_eq!(
+            serde_json::to_value(&fresh).unwrap()["categories"],
+            serde_json::to_value(&classifier).unwrap()["categories"]
+        );
+    }
+
+    #[test]
+    fn labels_without_counts_are_skipped() {
+        let mut counts = Counts::default();
+        let features = vec!["w:oxxo".to_string()];
+        counts.add("P-1", &features, 1);
+        counts.labels.insert("P-GONE".to_string(), 5);
+        let (label, confidence) = counts.predict(&features, 1).unwrap();
+        assert_eq!((label.as_str(), confidence), ("P-1", 1.0));
+
+        counts.features.clear();
+        assert!(counts.predict(&features, 1).is_none());
+    }
+}
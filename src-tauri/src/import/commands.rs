@@ -0,0 +1,482 @@
+//! Tauri commands for reading files to import
+
+use std::fs;
+use std::path::Path;
+
+use super::camt;
+use super::cfdi::{self, Cfdi, CfdiImport, InvoicesSidecar};
+use super::classifier::{Classifier, Prediction, TrainingReport};
+use super::commit::{self, CommitReport};
+use super::drafts::{self, DraftSummary, ImportDraft};
+use super::formats::DateFormat;
+use super::matching::{self, ImportedReferences, MatchKind, MatchOptions};
+use super::mt940;
+use super::notifications::{self, NotificationParse, NotificationTemplate};
+use super::ofx::{self, OfxStatement};
+use super::profiles::{self, ImportProfile};
+use super::qif::{self, QifFile};
+use super::spreadsheet::{self, SheetRows};
+use super::statement::{self, ParsedStatement, StatementOptions};
+use super::transaction::{BankStatement, ImportTransaction};
+use super::ynab4_csv::{self, BudgetRestoreResult, Ynab4ExportFile};
+use crate::ynab4::commands::{with_budget, with_budget_edit};
+use crate::ynab4::transactions::{self, TransactionChange, TransactionInput};
+
+/// Read a delimited bank statement, detecting its encoding and layout.
+/// `options` come from an import profile and replace what they cover.
+#[tauri::command]
+pub fn parse_statement_file(
+    path: String,
+    options: Option<StatementOptions>,
+) -> Result<ParsedStatement, String> {
+    let bytes = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    statement::parse_statement_with(&bytes, &options.unwrap_or_default())
+}
+
+/// Read an OFX/QFX file. With a budget path, statements are matched to
+/// budget accounts by account number.
+#[tauri::command]
+pub fn parse_ofx_file(
+    path: String,
+    budget_path: Option<String>,
+) -> Result<Vec<OfxStatement>, String> {
+    let bytes = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let text = statement::decode_text(&bytes);
+    match budget_path {
+        Some(budget_path) => with_budget(&budget_path, |budget| {
+            ofx::parse_ofx(&text, Some(&budget.data))
+        }),
+        None => ofx::parse_ofx(&text, None),
+    }
+}
+
+/// Read a QIF file. Without a date format it is inferred from the file;
+/// with a budget path, categories are matched to the budget's.
+#[tauri::command]
+pub fn parse_qif_file(
+    path: String,
+    date_format: Option<DateFormat>,
+    budget_path: Option<String>,
+) -> Result<QifFile, String> {
+    let bytes = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let text = statement::decode_text(&bytes);
+    match budget_path {
+        Some(budget_path) => with_budget(&budget_path, |budget| {
+            qif::parse_qif(&text, date_format, Some(&budget.data))
+        }),
+        None => qif::parse_qif(&text, date_format, None),
+    }
+}
+
+/// Write an account's register to a QIF file; returns the number of transactions
+#[tauri::command]
+pub fn export_account_qif(
+    budget_path: String,
+    account_id: String,
+    path: String,
+    date_format: Option<DateFormat>,
+) -> Result<usize, String> {
+    let (text, count) = with_budget(&budget_path, |budget| {
+        qif::write_qif(
+            &budget.data,
+            &account_id,
+            date_format.unwrap_or(DateFormat::Mdy),
+        )
+    })?;
+    fs::write(&path, text).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    Ok(count)
+}
+
+/// Read a camt.053 XML statement; with a budget path, statements are matched
+/// to budget accounts
+#[tauri::command]
+pub fn parse_camt_file(
+    path: String,
+    budget_path: Option<String>,
+) -> Result<Vec<BankStatement>, String> {
+    let bytes = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let text = statement::decode_text(&bytes);
+    match budget_path {
+        Some(budget_path) => with_budget(&budget_path, |budget| {
+            camt::parse_camt(&text, Some(&budget.data))
+        }),
+        None => camt::parse_camt(&text, None),
+    }
+}
+
+/// Read an MT940 statement; with a budget path, statements are matched to
+/// budget accounts
+#[tauri::command]
+pub fn parse_mt940_file(
+    path: String,
+    budget_path: Option<String>,
+) -> Result<Vec<BankStatement>, String> {
+    let bytes = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let text = statement::decode_text(&bytes);
+    match budget_path {
+        Some(budget_path) => with_budget(&budget_path, |budget| {
+            mt940::parse_mt940(&text, Some(&budget.data))
+        }),
+        None => mt940::parse_mt940(&text, None),
+    }
+}
+
+/// Read a CFDI invoice; with a budget path, also suggest the transactions
+/// that paid it (within `days` after the invoice date, 5 by default)
+#[tauri::command]
+pub fn parse_cfdi_file(
+    path: String,
+    budget_path: Option<String>,
+    days: Option<i64>,
+) -> Result<CfdiImport, String> {
+    let bytes = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let invoice = cfdi::parse_cfdi(&statement::decode_text(&bytes))?;
+    let matches = match budget_path {
+        Some(budget_path) => with_budget(&budget_path, |budget| {
+            Ok(cfdi::suggest_matches(
+                &budget.data,
+                &invoice,
+                days.unwrap_or(5),
+            ))
+        })?,
+        None => Vec::new(),
+    };
+    Ok(CfdiImport {
+        row: invoice.to_import_row(),
+        invoice,
+        matches,
+    })
+}
+
+/// Attach an invoice to a transaction in the invoices sidecar, and with
+/// `update_memo` add its UUID and first concept to the memo
+#[tauri::command]
+pub fn attach_cfdi(
+    budget_path: String,
+    transaction_id: String,
+    invoice: Cfdi,
+    update_memo: bool,
+) -> Result<Option<TransactionChange>, String> {
+    let change = if update_memo {
+        with_budget_edit(&budget_path, "Attach invoice", |budget| {
+            let transaction = budget
+                .data
+                .transaction(&transaction_id)
+                .filter(|t| !t.is_tombstone)
+                .ok_or_else(|| format!("Transaction not found: {}", transaction_id))?;
+            if transaction
+                .memo
+                .as_deref()
+                .is_some_and(|m| m.contains(&invoice.uuid))
+            {
+                return Ok(None);
+            }
+            let mut input = TransactionInput::from_transaction(transaction);
+            let note = invoice.memo_note();
+            input.memo = Some(match input.memo.as_deref() {
+                Some(memo) if !memo.is_empty() => format!("{} {}", memo, note),
+                _ => note,
+            });
+            transactions::update_transaction(budget, &transaction_id, input).map(Some)
+        })?
+    } else {
+        None
+    };
+    cfdi::attach_to_sidecar(Path::new(&budget_path), &transaction_id, &invoice)?;
+    Ok(change)
+}
+
+/// Invoices attached to the budget's transactions
+#[tauri::command]
+pub fn cfdi_invoices(budget_path: String) -> Result<InvoicesSidecar, String> {
+    cfdi::load_sidecar(Path::new(&budget_path))
+}
+
+/// Sheet names of an XLSX, XLS or ODS workbook
+#[tauri::command]
+pub fn list_spreadsheet_sheets(path: String) -> Result<Vec<String>, String> {
+    spreadsheet::sheet_names(Path::new(&path))
+}
+
+/// A page of rows from a workbook sheet (the first sheet by default). Ask
+/// again with `nextOffset` for the next page.
+#[tauri::command]
+pub fn read_spreadsheet(
+    path: String,
+    sheet_index: Option<usize>,
+    skip_rows: Option<usize>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<SheetRows, String> {
+    spreadsheet::read_sheet(
+        Path::new(&path),
+        sheet_index.unwrap_or(0),
+        skip_rows.unwrap_or(0),
+        offset.unwrap_or(0),
+        limit,
+    )
+}
+
+/// Compare import rows with the register. Each row gets a `match`; exact
+/// duplicates are set to skipped. Probable matches are left for the user and
+/// point at the transaction to clear instead of importing the row.
+#[tauri::command]
+pub fn match_import_rows(
+    budget_path: String,
+    rows: Vec<ImportTransaction>,
+    options: Option<MatchOptions>,
+) -> Result<Vec<ImportTransaction>, String> {
+    let references = ImportedReferences::load(&budget_path)?;
+    let options = options.unwrap_or_default();
+    let matches = with_budget(&budget_path, |budget| {
+        Ok(matching::match_rows(
+            &budget.data,
+            &rows,
+            &references,
+            &options,
+        ))
+    })?;
+    Ok(rows
+        .into_iter()
+        .zip(matches)
+        .map(|(mut row, found)| {
+            if found.kind == MatchKind::Duplicate && row.status != "imported" {
+                row.status = "skipped".to_string();
+            }
+            row.import_match = Some(found);
+            row
+        })
+        .collect())
+}
+
+/// Payee and category learned from the register for each row. The model is
+/// brought up to date with the budget first.
+#[tauri::command]
+pub fn classifier_suggest(
+    budget_path: String,
+    rows: Vec<ImportTransaction>,
+) -> Result<Vec<Option<Prediction>>, String> {
+    let mut classifier = Classifier::load(&budget_path)?;
+    let (report, predictions) = with_budget(&budget_path, |budget| {
+        let report = classifier.train(&budget.data);
+        Ok((
+            report,
+            rows.iter()
+                .map(|row| classifier.predict(&budget.data, row))
+                .collect(),
+        ))
+    })?;
+    if report.added + report.updated + report.removed > 0 {
+        classifier.save(&budget_path)?;
+    }
+    Ok(predictions)
+}
+
+/// Update the model with the register's changes, or with `full` learn it
+/// again from scratch
+#[tauri::command]
+pub fn classifier_retrain(budget_path: String, full: bool) -> Result<TrainingReport, String> {
+    let mut classifier = if full {
+        Classifier::default()
+    } else {
+        Classifier::load(&budget_path)?
+    };
+    let report = with_budget(&budget_path, |budget| Ok(classifier.train(&budget.data)))?;
+    classifier.save(&budget_path)?;
+    Ok(report)
+}
+
+/// Import profiles saved beside the budget
+#[tauri::command]
+pub fn list_import_profiles(budget_path: String) -> Result<Vec<ImportProfile>, String> {
+    Ok(profiles::load_sidecar(Path::new(&budget_path))?.profiles)
+}
+
+/// Add or replace an import profile. With `headers`, the profile is keyed by
+/// the fingerprint of that header row.
+#[tauri::command]
+pub fn save_import_profile(
+    budget_path: String,
+    profile: ImportProfile,
+    headers: Option<Vec<String>>,
+) -> Result<ImportProfile, String> {
+    profiles::save_profile(Path::new(&budget_path), profile, headers.as_deref())
+}
+
+#[tauri::command]
+pub fn delete_import_profile(budget_path: String, profile_id: String) -> Result<(), String> {
+    profiles::delete_profile(Path::new(&budget_path), &profile_id)
+}
+
+/// Profile for a file, by its name and, when already read, its header row
+#[tauri::command]
+pub fn find_import_profile(
+    budget_path: String,
+    file_name: String,
+    headers: Option<Vec<String>>,
+) -> Result<Option<ImportProfile>, String> {
+    let sidecar = profiles::load_sidecar(Path::new(&budget_path))?;
+    Ok(profiles::find_profile(&sidecar.profiles, &file_name, headers.as_deref()).cloned())
+}
+
+/// Apply a profile's sign convention, account and payee cleanup to rows read
+/// with its mapping, and remember it was used
+#[tauri::command]
+pub fn apply_import_profile(
+    budget_path: String,
+    profile_id: String,
+    mut rows: Vec<ImportTransaction>,
+) -> Result<Vec<ImportTransaction>, String> {
+    let profile = profiles::touch_profile(Path::new(&budget_path), &profile_id)?;
+    with_budget(&budget_path, |budget| {
+        profile.apply(Some(&budget.data), &mut rows)
+    })?;
+    Ok(rows)
+}
+
+/// Import drafts of a budget, without their rows
+#[tauri::command]
+pub fn list_import_drafts(budget_path: String) -> Result<Vec<DraftSummary>, String> {
+    drafts::list_drafts(&budget_path)
+}
+
+#[tauri::command]
+pub fn get_import_draft(
+    budget_path: String,
+    draft_id: String,
+) -> Result<Option<ImportDraft>, String> {
+    drafts::get_draft(&budget_path, &draft_id)
+}
+
+/// Add or replace a draft. Returns it with its counts and `updatedAt` refreshed.
+#[tauri::command]
+pub fn save_import_draft(budget_path: String, draft: ImportDraft) -> Result<ImportDraft, String> {
+    drafts::save_draft(&budget_path, draft)
+}
+
+#[tauri::command]
+pub fn delete_import_draft(budget_path: String, draft_id: String) -> Result<(), String> {
+    drafts::delete_draft(&budget_path, &draft_id)
+}
+
+/// Move the drafts the webview kept in `localStorage` into the store
+#[tauri::command]
+pub fn migrate_import_drafts(
+    budget_path: String,
+    drafts: Vec<ImportDraft>,
+) -> Result<usize, String> {
+    drafts::migrate_drafts(&budget_path, drafts)
+}
+
+/// Read a `.ynab-import.json` file into a new draft
+#[tauri::command]
+pub fn import_draft_file(
+    budget_path: String,
+    path: String,
+    name: Option<String>,
+) -> Result<ImportDraft, String> {
+    let text = fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let mut draft = ImportDraft::from_export(&text)?;
+    if let Some(name) = name {
+        draft.summary.name = name;
+    }
+    drafts::save_draft(&budget_path, draft)
+}
+
+/// Create the draft's ready rows in the budget as one ydiff, then mark them
+/// imported. Their bank references are remembered and the classifier learns
+/// from them; once the ydiff is written those two cannot fail the import.
+#[tauri::command]
+pub fn commit_import(budget_path: String, draft_id: String) -> Result<CommitReport, String> {
+    let mut draft = drafts::get_draft(&budget_path, &draft_id)?
+        .ok_or_else(|| format!("Import draft not found: {}", draft_id))?;
+    let mut references = ImportedReferences::load(&budget_path)?;
+    let mut classifier = Classifier::load(&budget_path)?;
+    let report = with_budget_edit(&budget_path, "Import transactions", |budget| {
+        let report =
+            commit::commit_rows(budget, &draft_id, &mut draft.transactions, &mut references)?;
+        classifier.train(&budget.data);
+        Ok(report)
+    })?;
+    // The rows are in the budget now; the draft must say so before anything else
+    drafts::save_draft(&budget_path, draft)?;
+    if let Err(e) = references.save(&budget_path) {
+        log::warn!("Failed to remember imported references: {}", e);
+    }
+    if let Err(e) = classifier.save(&budget_path) {
+        log::warn!("Failed to save the import classifier: {}", e);
+    }
+    Ok(report)
+}
+
+/// Read YNAB4's own `Register.csv` or `Budget.csv`. Returns None for any other
+/// file. With a budget path, register accounts and categories are matched.
+#[tauri::command]
+pub fn parse_ynab4_export(
+    path: String,
+    budget_path: Option<String>,
+) -> Result<Option<Ynab4ExportFile>, String> {
+    let bytes = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let parsed = statement::parse_statement(&bytes)?;
+    match budget_path {
+        Some(budget_path) => with_budget(&budget_path, |budget| {
+            ynab4_csv::read_export(&parsed, Some(&budget.data))
+        }),
+        None => ynab4_csv::read_export(&parsed, None),
+    }
+}
+
+/// Put the budgeted amounts of a YNAB4 `Budget.csv` back into the budget
+#[tauri::command]
+pub fn restore_ynab4_budget(
+    budget_path: String,
+    path: String,
+) -> Result<BudgetRestoreResult, String> {
+    let bytes = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let export = ynab4_csv::parse_budget(&statement::parse_statement(&bytes)?)?;
+    with_budget_edit(&budget_path, "Restore budgeted amounts", |budget| {
+        ynab4_csv::restore_budgeted(budget, &export)
+    })
+}
+
+/// Read bank notifications (SMS or email text) into draft rows. With a budget
+/// path, the user's templates are tried first and cards are matched to
+/// accounts.
+#[tauri::command]
+pub fn parse_notification_text(
+    text: String,
+    budget_path: Option<String>,
+) -> Result<NotificationParse, String> {
+    let today = chrono::Local::now().date_naive();
+    let templates = notifications::all_templates(budget_path.as_deref().map(Path::new))?;
+    match budget_path {
+        Some(budget_path) => with_budget(&budget_path, |budget| {
+            notifications::parse_notifications(&text, &templates, Some(&budget.data), today)
+        }),
+        None => notifications::parse_notifications(&text, &templates, None, today),
+    }
+}
+
+/// The budget's notification templates followed by the built-in ones
+#[tauri::command]
+pub fn list_notification_templates(
+    budget_path: String,
+) -> Result<Vec<NotificationTemplate>, String> {
+    notifications::all_templates(Some(Path::new(&budget_path)))
+}
+
+#[tauri::command]
+pub fn save_notification_template(
+    budget_path: String,
+    template: NotificationTemplate,
+) -> Result<NotificationTemplate, String> {
+    notifications::save_template(Path::new(&budget_path), template)
+}
+
+#[tauri::command]
+pub fn delete_notification_template(
+    budget_path: String,
+    template_id: String,
+) -> Result<(), String> {
+    notifications::delete_template(Path::new(&budget_path), &template_id)
+}
@@ -0,0 +1,565 @@
+//! Turning the ready rows of an import draft into budget transactions.
+//!
+//! Every row is checked and written before anything reaches the disk; one bad
+//! row leaves the budget as it was. Payees that don't exist yet are created,
+//! a row matched to an uncleared transaction clears it instead of adding the
+//! row again, and a purchase in months without interest (MSI) becomes the
+//! same plan the MSI dialog builds: the purchase, a counter-entry that takes
+//! it back out of the category and one payment per month. Everything goes to
+//! a single ydiff.
+
+use chrono::{Datelike, Months, NaiveDate};
+use serde::Serialize;
+
+use super::matching::{ImportedReferences, MatchKind};
+use super::transaction::{self, ImportTransaction};
+use crate::ynab4::budget::{new_entity_id, Budget};
+use crate::ynab4::entities::*;
+use crate::ynab4::transactions::{self, SubTransactionInput, TransactionInput};
+
+/// Flag YNAB4 shows on every transaction of an MSI plan
+const MSI_FLAG: &str = "Orange";
+
+/// What became of one row
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommittedRow {
+    pub row_id: String,
+    /// Transactions created for the row: one, or the whole plan for MSI
+    pub transaction_ids: Vec<String>,
+    /// Uncleared transaction the row was matched to, cleared instead of
+    /// creating a new one
+    pub cleared_transaction_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitReport {
+    pub draft_id: String,
+    pub rows: Vec<CommittedRow>,
+    /// Every transaction created, in row order
+    pub transaction_ids: Vec<String>,
+    pub payee_ids: Vec<String>,
+    /// Every entity written, including transfer counterparts and split lines
+    pub changed_ids: Vec<String>,
+    pub ydiff_path: Option<String>,
+}
+
+fn live_account<'a>(data: &'a BudgetData, id: &str) -> Option<&'a Account> {
+    data.account(id).filter(|a| !a.is_tombstone)
+}
+
+/// Account by id, or by name when the row only has a name
+fn account_for(data: &BudgetData, id: Option<&str>, name: &str) -> Option<String> {
+    if let Some(account) = id.and_then(|id| live_account(data, id)) {
+        return Some(account.entity_id.clone());
+    }
+    let name = name.trim();
+    data.accounts
+        .iter()
+        .find(|a| {
+            !a.is_tombstone && !name.is_empty() && a.account_name.trim().eq_ignore_ascii_case(name)
+        })
+        .map(|a| a.entity_id.clone())
+}
+
+fn category_for(data: &BudgetData, id: Option<&str>, names: &[&str]) -> Option<String> {
+    let known = |id: &str| {
+        id == IMMEDIATE_INCOME_CATEGORY_ID
+            || id == DEFERRED_INCOME_CATEGORY_ID
+            || data.live_categories().iter().any(|c| c.entity_id == id)
+    };
+    if let Some(id) = id.filter(|id| known(id)) {
+        return Some(id.to_string());
+    }
+    names
+        .iter()
+        .filter(|n| !n.trim().is_empty())
+        .find_map(|n| transaction::find_category(data, n))
+}
+
+/// Payee for a row: the one it points at, one with the same name, or a new one
+fn payee_for(
+    budget: &mut Budget,
+    row: &ImportTransaction,
+    created: &mut Vec<String>,
+) -> Option<String> {
+    let live = |p: &&Payee| !p.is_tombstone && !p.entity_id.starts_with(TRANSFER_PAYEE_PREFIX);
+    if let Some(payee) = row
+        .payee_id
+        .as_deref()
+        .and_then(|id| budget.data.payee(id))
+        .filter(live)
+    {
+        return Some(payee.entity_id.clone());
+    }
+    let name = [&row.payee_name, &row.suggested_payee, &row.description]
+        .into_iter()
+        .map(|n| n.trim())
+        .find(|n| !n.is_empty())?
+        .to_string();
+    if let Some(payee) = budget
+        .data
+        .payees
+        .iter()
+        .filter(live)
+        .find(|p| p.name.trim().eq_ignore_ascii_case(&name))
+    {
+        return Some(payee.entity_id.clone());
+    }
+    let payee = Payee {
+        entity_id: new_entity_id(),
+        entity_type: "payee".to_string(),
+        name,
+        enabled: true,
+        ..Default::default()
+    };
+    let id = payee.entity_id.clone();
+    budget.data.payees.push(payee);
+    budget.mark_dirty(id.clone());
+    created.push(id.clone());
+    Some(id)
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Transaction input for a row, creating its payee when needed
+fn row_input(
+    budget: &mut Budget,
+    row: &ImportTransaction,
+    account_id: &str,
+    created_payees: &mut Vec<String>,
+) -> Result<TransactionInput, String> {
+    let data = &budget.data;
+    let transfer_account_id = match row.transfer_account_name.as_deref() {
+        Some(name) => Some(
+            account_for(data, None, name).ok_or_else(|| format!("Account not found: {}", name))?,
+        ),
+        None => None,
+    };
+    let mut category_id = category_for(
+        data,
+        row.category_id.as_deref(),
+        &[&row.category_name, &row.suggested_category],
+    );
+    if let Some(other) = transfer_account_id.as_deref() {
+        // Only a transfer that leaves or enters the budget keeps a category
+        let on_budget = |id: &str| live_account(data, id).is_some_and(|a| a.on_budget);
+        if on_budget(account_id) == on_budget(other) {
+            category_id = None;
+        }
+    }
+    let sub_transactions = if row.splits.is_empty() {
+        None
+    } else {
+        let mut subs = Vec::new();
+        for split in &row.splits {
+            let transfer_account_id = match split.transfer_account_name.as_deref() {
+                Some(name) => Some(
+                    account_for(data, None, name)
+                        .ok_or_else(|| format!("Account not found: {}", name))?,
+                ),
+                None => None,
+            };
+            subs.push(SubTransactionInput {
+                amount: split.amount,
+                category_id: category_for(
+                    data,
+                    split.category_id.as_deref(),
+                    &[&split.category_name],
+                ),
+                memo: non_empty(&split.memo),
+                transfer_account_id,
+                ..Default::default()
+            });
+        }
+        category_id = None;
+        Some(subs)
+    };
+    let payee_id = if transfer_account_id.is_some() {
+        None
+    } else {
+        payee_for(budget, row, created_payees)
+    };
+    Ok(TransactionInput {
+        account_id: account_id.to_string(),
+        date: row.date.clone(),
+        amount: row.amount,
+        payee_id,
+        category_id,
+        memo: non_empty(&row.memo).or_else(|| non_empty(&row.original_memo)),
+        cleared: Some(
+            match (row.reconciled, row.cleared) {
+                (true, _) => RECONCILED,
+                (_, true) => CLEARED,
+                _ => UNCLEARED,
+            }
+            .to_string(),
+        ),
+        flag: row.flag.clone().filter(|f| !f.is_empty()),
+        check_number: row.check_number.clone().filter(|n| !n.is_empty()),
+        transfer_account_id,
+        sub_transactions,
+    })
+}
+
+/// The purchase, its counter-entry and one payment a month from the month
+/// after the purchase; the first payment takes the rounding difference
+fn msi_plan(
+    purchase: TransactionInput,
+    months: u32,
+    payee_name: &str,
+) -> Result<Vec<TransactionInput>, String> {
+    let date = NaiveDate::parse_from_str(purchase.date.get(..10).unwrap_or_default(), "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date '{}'", purchase.date))?;
+    let first = NaiveDate::from_ymd_opt(date.year(), date.month(), 1)
+        .and_then(|d| d.checked_add_months(Months::new(1)))
+        .ok_or_else(|| format!("Invalid date '{}'", purchase.date))?;
+    let total = purchase.amount.abs();
+    let monthly = (total / months as f64 * 100.0).round() / 100.0;
+    let rounding = ((total - monthly * months as f64) * 100.0).round() / 100.0;
+
+    let mut plan = vec![
+        purchase.clone(),
+        TransactionInput {
+            amount: total,
+            memo: Some(format!(
+                "MSI {} meses - Contrapartida de {}",
+                months, payee_name
+            )),
+            cleared: Some(UNCLEARED.to_string()),
+            flag: Some(MSI_FLAG.to_string()),
+            check_number: None,
+            ..purchase.clone()
+        },
+    ];
+    for i in 0..months {
+        let amount = if i == 0 { monthly + rounding } else { monthly };
+        plan.push(TransactionInput {
+            date: (first + Months::new(i)).format("%Y-%m-%d").to_string(),
+            amount: -((amount * 100.0).round() / 100.0),
+            memo: Some(format!("MSI {} meses - Pago {}/{}", months, i + 1, months)),
+            cleared: Some(UNCLEARED.to_string()),
+            flag: Some(MSI_FLAG.to_string()),
+            check_number: None,
+            ..purchase.clone()
+        });
+    }
+    Ok(plan)
+}
+
+/// An MSI purchase still to be turned into a plan. Rows the MSI dialogs
+/// already built (counter-entry and payments) keep the whole purchase in
+/// `msi_original_amount` and are imported as they are.
+fn needs_msi_plan(row: &ImportTransaction) -> bool {
+    let unexpanded =
+        row.msi_original_amount == 0.0 || (row.msi_original_amount - row.amount).abs() < 0.005;
+    row.is_msi && row.msi_months > 1 && row.amount < 0.0 && unexpanded
+}
+
+/// Transaction of the row's account that duplicate detection matched and
+/// the user confirmed, usually an uncleared one
+fn probable_match(data: &BudgetData, row: &ImportTransaction, account_id: &str) -> Option<String> {
+    let found = row
+        .import_match
+        .as_ref()
+        .filter(|m| m.kind == MatchKind::Probable)?;
+    let id = found.transaction_id.as_deref()?;
+    data.transaction(id)
+        .filter(|t| !t.is_tombstone && t.account_id == account_id && t.cleared != RECONCILED)
+        .map(|t| t.entity_id.clone())
+}
+
+/// What committing the rows produced, before anything is kept
+struct Committed {
+    report: CommitReport,
+    /// (account, bank reference, transaction) to remember
+    references: Vec<(String, String, String)>,
+}
+
+fn commit_ready(budget: &mut Budget, rows: &[ImportTransaction]) -> Result<Committed, String> {
+    let mut report = CommitReport::default();
+    let mut references = Vec::new();
+    let mut changed: Vec<String> = Vec::new();
+
+    for (index, row) in rows.iter().enumerate().filter(|(_, r)| r.status == "ready") {
+        let row_error = |e: String| {
+            format!(
+                "Row {} ({} {}): {}",
+                index + 1,
+                row.date,
+                row.description,
+                e
+            )
+        };
+        let account_id = account_for(&budget.data, row.account_id.as_deref(), &row.account_name)
+            .ok_or_else(|| row_error("no account to import into".to_string()))?;
+
+        if let Some(id) = probable_match(&budget.data, row, &account_id) {
+            if let Some(t) = budget
+                .data
+                .transactions
+                .iter_mut()
+                .find(|t| t.entity_id == id)
+            {
+                t.cleared = CLEARED.to_string();
+            }
+            budget.mark_dirty(id.clone());
+            changed.push(id.clone());
+            references.push((account_id, row.reference.clone(), id.clone()));
+            report.rows.push(CommittedRow {
+                row_id: row.id.clone(),
+                transaction_ids: Vec::new(),
+                cleared_transaction_id: Some(id),
+            });
+            continue;
+        }
+
+        let input =
+            row_input(budget, row, &account_id, &mut report.payee_ids).map_err(row_error)?;
+        let inputs = if needs_msi_plan(row) && input.sub_transactions.is_none() {
+            let payee_name = input
+                .payee_id
+                .as_deref()
+                .and_then(|id| budget.data.payee(id))
+                .map_or_else(|| row.description.clone(), |p| p.name.clone());
+            msi_plan(input, row.msi_months, &payee_name).map_err(row_error)?
+        } else {
+            vec![input]
+        };
+
+        let mut ids = Vec::new();
+        for input in inputs {
+            let change = transactions::create_transaction(budget, input).map_err(row_error)?;
+            changed.extend(change.changed_ids);
+            ids.push(change.transaction_id);
+        }
+        references.push((account_id, row.reference.clone(), ids[0].clone()));
+        report.transaction_ids.extend(ids.iter().cloned());
+        report.rows.push(CommittedRow {
+            row_id: row.id.clone(),
+            transaction_ids: ids,
+            cleared_transaction_id: None,
+        });
+    }
+    if report.rows.is_empty() {
+        return Err("No rows are ready to import".to_string());
+    }
+    changed.extend(report.payee_ids.iter().cloned());
+    changed.sort();
+    changed.dedup();
+    report.changed_ids = changed;
+    Ok(Committed { report, references })
+}
+
+/// Import the draft's ready rows and write them as one ydiff. On success the
+/// rows are marked imported and their bank references remembered; on failure
+/// nothing changes.
+pub fn commit_rows(
+    budget: &mut Budget,
+    draft_id: &str,
+    rows: &mut [ImportTransaction],
+    references: &mut ImportedReferences,
+) -> Result<CommitReport, String> {
+    let snapshot = budget.data.clone();
+    let dirty_before = budget.dirty.clone();
+    let committed = match commit_ready(budget, rows) {
+        Ok(committed) => committed,
+        Err(e) => {
+            budget.data = snapshot;
+            budget.dirty = dirty_before;
+            return Err(e);
+        }
+    };
+    let ydiff_path = budget.save_entities(&committed.report.changed_ids)?;
+
+    let mut report = committed.report;
+    report.draft_id = draft_id.to_string();
+    report.ydiff_path = ydiff_path.map(|p| p.to_string_lossy().to_string());
+    for (account_id, reference, transaction_id) in &committed.references {
+        references.insert(account_id, reference, transaction_id);
+    }
+    for row in rows.iter_mut() {
+        if report.rows.iter().any(|r| r.row_id == row.id) {
+            row.status = "imported".to_string();
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::matching::ImportMatch;
+    use crate::import::transaction::ImportSplit;
+    use crate::ynab4::fixtures;
+
+    fn row(index: usize, date: &str, name: &str, amount: f64) -> ImportTransaction {
+        let mut row = ImportTransaction::new(1, index, date.to_string(), name.to_string(), amount);
+        row.account_id = Some("ACC-CHK".to_string());
+        row.status = "ready".to_string();
+        row
+    }
+
+    fn msi_row(index: usize, date: &str, amount: f64, purchase: f64) -> ImportTransaction {
+        let mut row = row(index, date, "LIVERPOOL", amount);
+        row.account_id = Some("ACC-CC".to_string());
+        row.category_id = Some("CAT-FUN".to_string());
+        row.is_msi = true;
+        row.msi_months = 3;
+        row.msi_original_amount = purchase;
+        row
+    }
+
+    #[test]
+    fn commits_ready_rows_in_one_ydiff() {
+        let path = fixtures::budget_folder("commit-rows");
+        let mut budget = Budget::load(&path).unwrap();
+
+        let mut new_payee = row(0, "2024-02-10", "CAFE LUNA 123", -45.5);
+        new_payee.payee_name = "Cafe Luna".to_string();
+        new_payee.category_name = "Everyday:Fun".to_string();
+        new_payee.cleared = true;
+
+        let mut existing = row(1, "2024-02-11", "OXXO SUC 9", -30.0);
+        existing.suggested_payee = "oxxo".to_string();
+        existing.category_id = Some("CAT-FOOD".to_string());
+
+        let mut probable = row(2, "2024-01-05", "OXXO", -250.0);
+        probable.reference = "REF-2".to_string();
+        probable.import_match = Some(ImportMatch {
+            kind: MatchKind::Probable,
+            transaction_id: Some("T-2".to_string()),
+            confidence: 0.95,
+            reason: String::new(),
+        });
+
+        let mut transfer = row(3, "2024-02-12", "TRASPASO", -200.0);
+        transfer.transfer_account_name = Some("Visa".to_string());
+
+        let mut split = row(4, "2024-02-13", "WALMART", -120.0);
+        split.splits = vec![
+            ImportSplit {
+                amount: -100.0,
+                category_name: "Food".to_string(),
+                ..Default::default()
+            },
+            ImportSplit {
+                amount: -20.0,
+                category_id: Some("CAT-FUN".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        let mut skipped = row(5, "2024-02-14", "SKIP", -1.0);
+        skipped.status = "skipped".to_string();
+
+        budget.mark_dirty("T-1");
+        let mut rows = vec![new_payee, existing, probable, transfer, split, skipped];
+        let mut references = ImportedReferences::default();
+        let report = commit_rows(&mut budget, "D-1", &mut rows, &mut references).unwrap();
+        assert_eq!(report.rows.len(), 5);
+        assert_eq!(
+            report.rows[2].cleared_transaction_id.as_deref(),
+            Some("T-2")
+        );
+        assert!(rows[..5].iter().all(|r| r.status == "imported"));
+        assert_eq!(rows[5].status, "skipped");
+        assert_eq!(
+            references.get("ACC-CHK", "REF-2").map(String::as_str),
+            Some("T-2")
+        );
+        assert!(budget.dirty.contains("T-1"));
+
+        let reloaded = Budget::load(&path).unwrap();
+        assert_eq!(reloaded.data.transaction("T-2").unwrap().cleared, CLEARED);
+        let first = reloaded
+            .data
+            .transaction(&report.rows[0].transaction_ids[0])
+            .unwrap();
+        assert_eq!(
+            reloaded
+                .data
+                .payee(first.payee_id.as_deref().unwrap())
+                .unwrap()
+                .name,
+            "Cafe Luna"
+        );
+        assert_eq!(first.category_id.as_deref(), Some("CAT-FUN"));
+        let second = reloaded
+            .data
+            .transaction(&report.rows[1].transaction_ids[0])
+            .unwrap();
+        assert_eq!(second.payee_id.as_deref(), Some("P-1"));
+        let transfer = reloaded
+            .data
+            .transaction(&report.rows[3].transaction_ids[0])
+            .unwrap();
+        assert!(transfer.transfer_transaction_id.is_some());
+        let split = reloaded
+            .data
+            .transaction(&report.rows[4].transaction_ids[0])
+            .unwrap();
+        assert_eq!(split.sub_transactions.as_ref().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn msi_purchases_are_expanded_once() {
+        let path = fixtures::budget_folder("commit-msi");
+        let mut budget = Budget::load(&path).unwrap();
+        // A purchase still flagged as MSI, then one the MSI dialog already expanded
+        let mut rows = vec![
+            msi_row(0, "2024-02-15", -1000.0, -1000.0),
+            msi_row(1, "2024-02-20", 600.0, -600.0),
+            msi_row(2, "2024-03-01", -200.0, -600.0),
+            msi_row(3, "2024-04-01", -200.0, -600.0),
+            msi_row(4, "2024-05-01", -200.0, -600.0),
+        ];
+        let mut references = ImportedReferences::default();
+        let report = commit_rows(&mut budget, "D-1", &mut rows, &mut references).unwrap();
+        assert_eq!(report.transaction_ids.len(), 5 + 4);
+        assert!(report.rows[1..]
+            .iter()
+            .all(|r| r.transaction_ids.len() == 1));
+
+        let reloaded = Budget::load(&path).unwrap();
+        let plan: Vec<_> = report.rows[0]
+            .transaction_ids
+            .iter()
+            .map(|id| reloaded.data.transaction(id).unwrap())
+            .collect();
+        let amounts: Vec<f64> = plan.iter().map(|t| t.amount).collect();
+        assert_eq!(amounts, vec![-1000.0, 1000.0, -333.34, -333.33, -333.33]);
+        assert_eq!(
+            plan[1].memo.as_deref(),
+            Some("MSI 3 meses - Contrapartida de LIVERPOOL")
+        );
+        assert_eq!(
+            (plan[2].date.as_str(), plan[4].date.as_str()),
+            ("2024-03-01", "2024-05-01")
+        );
+        assert!(plan[1..]
+            .iter()
+            .all(|t| t.flag.as_deref() == Some(MSI_FLAG)));
+    }
+
+    #[test]
+    fn a_bad_row_leaves_the_budget_untouched() {
+        let path = fixtures::budget_folder("commit-error");
+        let mut budget = Budget::load(&path).unwrap();
+        let before = serde_json::to_value(&budget.data).unwrap();
+        let mut bad = row(1, "2024-02-11", "Nowhere", -10.0);
+        bad.account_id = None;
+        bad.account_name = "Savings".to_string();
+        let mut rows = vec![row(0, "2024-02-10", "Cafe Luna", -45.5), bad];
+        let mut references = ImportedReferences::default();
+        let err = commit_rows(&mut budget, "D-2", &mut rows, &mut references).unwrap_err();
+        assert!(err.contains("Row 2"), "{}", err);
+        assert_eq!(serde_json::to_value(&budget.data).unwrap(), before);
+        assert!(budget.dirty.is_empty());
+        assert!(rows.iter().all(|r| r.status == "ready"));
+    }
+}
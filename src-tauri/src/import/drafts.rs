@@ -0,0 +1,220 @@
+//! Import drafts: the import files the user is still reviewing.
+//!
+//! Each draft is one JSON file in the budget's local folder, in the same
+//! shape as the `.ynab-import.json` files the UI exports, so a draft can be
+//! written out and read back as is. They used to live in the webview's
+//! `localStorage`, which is small and not shared between windows.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::transaction::ImportTransaction;
+use crate::ynab4::budget::{new_entity_id, read_json};
+use crate::ynab4::local;
+
+const DRAFTS_DIR: &str = "importDrafts";
+
+/// What the draft list shows, without the rows
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DraftSummary {
+    pub id: String,
+    pub name: String,
+    pub source_file: String,
+    pub created_at: String,
+    pub updated_at: String,
+    pub transaction_count: usize,
+    pub ready_count: usize,
+    pub pending_count: usize,
+    /// Sum of the rows that are not skipped
+    pub net_amount: f64,
+    /// Account names of the rows, "Sin cuenta" for rows without one
+    pub account_groups: Vec<String>,
+}
+
+/// A draft with its rows, as `ImportFileWithTransactions` in the UI
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportDraft {
+    #[serde(flatten)]
+    pub summary: DraftSummary,
+    #[serde(default)]
+    pub transactions: Vec<ImportTransaction>,
+}
+
+impl ImportDraft {
+    /// Recompute the counts from the rows
+    pub fn refresh_summary(&mut self) {
+        let rows = &self.transactions;
+        let summary = &mut self.summary;
+        summary.transaction_count = rows.len();
+        summary.ready_count = rows.iter().filter(|t| t.status == "ready").count();
+        summary.pending_count = rows.iter().filter(|t| t.status == "pending").count();
+        let net: f64 = rows
+            .iter()
+            .filter(|t| t.status != "skipped")
+            .map(|t| t.amount)
+            .sum();
+        summary.net_amount = (net * 100.0).round() / 100.0;
+        summary.account_groups = Vec::new();
+        for row in rows {
+            let name = if row.account_name.is_empty() {
+                "Sin cuenta"
+            } else {
+                row.account_name.as_str()
+            };
+            if !summary.account_groups.iter().any(|g| g == name) {
+                summary.account_groups.push(name.to_string());
+            }
+        }
+    }
+
+    /// Read an exported draft, giving it a new id so it never replaces one
+    /// already in the store
+    pub fn from_export(json: &str) -> Result<ImportDraft, String> {
+        let value: serde_json::Value =
+            serde_json::from_str(json).map_err(|e| format!("Invalid import file: {}", e))?;
+        if !value.get("transactions").is_some_and(|t| t.is_array()) {
+            return Err("Invalid import file format: no transactions".to_string());
+        }
+        let mut draft: ImportDraft =
+            serde_json::from_value(value).map_err(|e| format!("Invalid import file: {}", e))?;
+        let now = chrono::Utc::now().to_rfc3339();
+        draft.summary.id = new_entity_id();
+        draft.summary.created_at = now.clone();
+        draft.summary.updated_at = now;
+        draft.refresh_summary();
+        Ok(draft)
+    }
+}
+
+fn drafts_dir(budget_path: &str) -> Result<PathBuf, String> {
+    let dir = local::budget_dir(budget_path)?.join(DRAFTS_DIR);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    Ok(dir)
+}
+
+/// File of a draft; ids are GUIDs, anything else could point outside the folder
+fn draft_path(dir: &Path, draft_id: &str) -> Result<PathBuf, String> {
+    if draft_id.is_empty()
+        || !draft_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!("Invalid import draft id: {}", draft_id));
+    }
+    Ok(dir.join(format!("{}.json", draft_id)))
+}
+
+/// Drafts of a budget, oldest first
+pub fn list_drafts(budget_path: &str) -> Result<Vec<DraftSummary>, String> {
+    let dir = drafts_dir(budget_path)?;
+    let entries =
+        fs::read_dir(&dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    let mut drafts = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        // The counts are kept up to date on save, so the rows need not be read
+        match read_json::<DraftSummary>(&path) {
+            Ok(summary) => drafts.push(summary),
+            Err(e) => log::warn!("Skipping unreadable import draft: {}", e),
+        }
+    }
+    drafts.sort_by(|a, b| {
+        a.created_at
+            .cmp(&b.created_at)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    Ok(drafts)
+}
+
+pub fn get_draft(budget_path: &str, draft_id: &str) -> Result<Option<ImportDraft>, String> {
+    let path = draft_path(&drafts_dir(budget_path)?, draft_id)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    read_json(&path).map(Some)
+}
+
+/// Write a draft, stamping `updatedAt` and recomputing its counts
+pub fn save_draft(budget_path: &str, mut draft: ImportDraft) -> Result<ImportDraft, String> {
+    if draft.summary.id.is_empty() {
+        draft.summary.id = new_entity_id();
+    }
+    let path = draft_path(&drafts_dir(budget_path)?, &draft.summary.id)?;
+    let now = chrono::Utc::now().to_rfc3339();
+    if draft.summary.created_at.is_empty() {
+        draft.summary.created_at = now.clone();
+    }
+    draft.summary.updated_at = now;
+    draft.refresh_summary();
+    local::write_json(&path, &draft)?;
+    Ok(draft)
+}
+
+pub fn delete_draft(budget_path: &str, draft_id: &str) -> Result<(), String> {
+    let path = draft_path(&drafts_dir(budget_path)?, draft_id)?;
+    if path.exists() {
+        fs::remove_file(&path)
+            .map_err(|e| format!("Failed to delete {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Move drafts kept by the webview into the store. Drafts already there are
+/// left alone; their timestamps are kept. Returns how many were added.
+pub fn migrate_drafts(budget_path: &str, drafts: Vec<ImportDraft>) -> Result<usize, String> {
+    let dir = drafts_dir(budget_path)?;
+    let mut added = 0;
+    for mut draft in drafts {
+        if draft.summary.id.is_empty() {
+            draft.summary.id = new_entity_id();
+        }
+        let path = draft_path(&dir, &draft.summary.id)?;
+        if path.exists() {
+            continue;
+        }
+        draft.refresh_summary();
+        local::write_json(&path, &draft)?;
+        added += 1;
+    }
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_exported_drafts_with_fresh_ids_and_counts() {
+        // A draft as the webview kept it in localStorage
+        let json = r#"{"id":"b7f1c9a2-1111-4d4d-8888-000000000001","name":"enero","sourceFile":"enero.csv",
+          "createdAt":"2024-01-31T10:00:00.000Z","updatedAt":"2024-01-31T10:05:00.000Z","transactionCount":9,
+          "transactions":[
+            {"id":"import-1-0","date":"2024-01-03","description":"OXXO","accountName":"Visa","amount":-80,"status":"ready"},
+            {"id":"import-1-1","date":"2024-01-04","description":"PAGO","amount":500,"status":"skipped"},
+            {"id":"import-1-2","date":"2024-01-05","description":"CAFE","accountName":"Visa","amount":-4.5,"status":"pending"}]}"#;
+        let draft = ImportDraft::from_export(json).unwrap();
+        assert_ne!(draft.summary.id, "b7f1c9a2-1111-4d4d-8888-000000000001");
+        assert_ne!(draft.summary.created_at, "2024-01-31T10:00:00.000Z");
+        assert_eq!(draft.summary.source_file, "enero.csv");
+        assert_eq!(draft.summary.transaction_count, 3);
+        assert_eq!(
+            (draft.summary.ready_count, draft.summary.pending_count),
+            (1, 1)
+        );
+        assert_eq!(draft.summary.net_amount, -84.5);
+        assert_eq!(
+            draft.summary.account_groups,
+            vec!["Visa".to_string(), "Sin cuenta".to_string()]
+        );
+
+        assert!(ImportDraft::from_export(r#"{"id":"x"}"#).is_err());
+        assert!(draft_path(Path::new("drafts"), "../../etc/passwd").is_err());
+        assert!(draft_path(Path::new("drafts"), &draft.summary.id).is_ok());
+    }
+}
@@ -0,0 +1,587 @@
+//! Date and amount formats, inferred per column.
+//!
+//! A single cell like `03/04/2024` or `1,234` is ambiguous, but a whole column
+//! rarely is: one row with a day above 12, or one amount with both separators,
+//! settles the format for every row. Cells that don't fit the chosen format
+//! are reported instead of being guessed one by one.
+
+use chrono::NaiveDate;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// Order of the parts in a date
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DateFormat {
+    /// 2024-01-31
+    Ymd,
+    /// 31/01/2024
+    Dmy,
+    /// 01/31/2024
+    Mdy,
+    /// 31-ene-24, 31 Jan 2024
+    DayMonthName,
+    /// Jan 31, 2024
+    MonthNameDay,
+}
+
+/// Tried in this order; earlier formats win ties, so day-first beats month-first
+const DATE_FORMATS: [DateFormat; 5] = [
+    DateFormat::Ymd,
+    DateFormat::Dmy,
+    DateFormat::Mdy,
+    DateFormat::DayMonthName,
+    DateFormat::MonthNameDay,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NumberFormat {
+    pub decimal: char,
+    pub thousands: Option<char>,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat {
+            decimal: '.',
+            thousands: Some(','),
+        }
+    }
+}
+
+impl NumberFormat {
+    /// Example such as "1,234.56"
+    pub fn pattern(&self) -> String {
+        match self.thousands {
+            Some(thousands) => format!("1{}234{}56", thousands, self.decimal),
+            None => format!("1234{}56", self.decimal),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ColumnKind {
+    Empty,
+    Text,
+    Date { format: DateFormat },
+    Number { format: NumberFormat },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnFormat {
+    pub index: usize,
+    #[serde(flatten)]
+    pub kind: ColumnKind,
+    /// Format as shown to the user, e.g. "dd-MMM-yy" or "1.234,56"
+    pub pattern: Option<String>,
+    /// Non-empty cells that fit the format, out of `total`
+    pub matched: usize,
+    pub total: usize,
+    pub evidence: String,
+}
+
+const MONTH_NAMES: [[&str; 3]; 12] = [
+    ["january", "enero", ""],
+    ["february", "febrero", ""],
+    ["march", "marzo", ""],
+    ["april", "abril", ""],
+    ["may", "mayo", ""],
+    ["june", "junio", ""],
+    ["july", "julio", ""],
+    ["august", "agosto", ""],
+    ["september", "septiembre", "setiembre"],
+    ["october", "octubre", ""],
+    ["november", "noviembre", ""],
+    ["december", "diciembre", ""],
+];
+
+/// Month number for an English or Spanish month name or abbreviation (ene, Jan., sept)
+pub fn month_from_name(name: &str) -> Option<u32> {
+    let name = name.trim_end_matches('.').to_lowercase();
+    if name.chars().count() < 3 {
+        return None;
+    }
+    MONTH_NAMES
+        .iter()
+        .position(|names| {
+            names
+                .iter()
+                .any(|full| !full.is_empty() && full.starts_with(&name))
+        })
+        .map(|idx| idx as u32 + 1)
+}
+
+/// Two-digit years are read as 1970-2069
+fn full_year(part: &str) -> Option<i32> {
+    let year: i32 = part.parse().ok()?;
+    match part.len() {
+        2 if year < 70 => Some(2000 + year),
+        2 => Some(1900 + year),
+        4 => Some(year),
+        _ => None,
+    }
+}
+
+fn time_suffix() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?i)([ T]\d{1,2}:\d{2}(:\d{2}(\.\d+)?)?\s*([ap]\.?\s?m\.?|z|[+-]\d{2}:?\d{2})?)$",
+        )
+        .unwrap()
+    })
+}
+
+/// Separator and the three parts of a date, dropping any time of day
+fn date_parts(cell: &str) -> Option<(char, [&str; 3])> {
+    let trimmed = cell.trim();
+    let date = match time_suffix().find(trimmed) {
+        Some(m) => &trimmed[..m.start()],
+        None => trimmed,
+    };
+    let sep = date.chars().find(|c| matches!(c, '-' | '/' | '.' | ' '))?;
+    let mut parts = date
+        .split(['-', '/', '.', ' ', ','])
+        .filter(|p| !p.is_empty());
+    let result = [parts.next()?, parts.next()?, parts.next()?];
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((sep, result))
+}
+
+/// Parse a date in the given format
+pub fn parse_date(cell: &str, format: DateFormat) -> Option<NaiveDate> {
+    let (_, [a, b, c]) = date_parts(cell)?;
+    let number = |part: &str| -> Option<u32> {
+        if part.len() <= 2 && part.chars().all(|c| c.is_ascii_digit()) {
+            part.parse().ok()
+        } else {
+            None
+        }
+    };
+    let (year, month, day) = match format {
+        DateFormat::Ymd if a.len() == 4 => (full_year(a)?, number(b)?, number(c)?),
+        DateFormat::Dmy => (full_year(c)?, number(b)?, number(a)?),
+        DateFormat::Mdy => (full_year(c)?, number(a)?, number(b)?),
+        DateFormat::DayMonthName => (full_year(c)?, month_from_name(b)?, number(a)?),
+        DateFormat::MonthNameDay => (full_year(c)?, month_from_name(a)?, number(b)?),
+        _ => return None,
+    };
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// Write a date in the given format, with `/` between numeric parts
+pub fn format_date(date: NaiveDate, format: DateFormat) -> String {
+    let pattern = match format {
+        DateFormat::Ymd => "%Y-%m-%d",
+        DateFormat::Dmy => "%d/%m/%Y",
+        DateFormat::Mdy => "%m/%d/%Y",
+        DateFormat::DayMonthName => "%d-%b-%Y",
+        DateFormat::MonthNameDay => "%b %d, %Y",
+    };
+    date.format(pattern).to_string()
+}
+
+/// Display pattern for a date format, using the separator and year width of a sample
+fn date_pattern(format: DateFormat, sample: &str) -> String {
+    let (sep, parts) = date_parts(sample).unwrap_or(('/', ["", "", "yyyy"]));
+    let year = if parts[2].len() == 2 { "yy" } else { "yyyy" };
+    match format {
+        DateFormat::Ymd => format!("yyyy{}MM{}dd", sep, sep),
+        DateFormat::Dmy => format!("dd{}MM{}{}", sep, sep, year),
+        DateFormat::Mdy => format!("MM{}dd{}{}", sep, sep, year),
+        DateFormat::DayMonthName => format!("dd{}MMM{}{}", sep, sep, year),
+        DateFormat::MonthNameDay => format!("MMM{}dd{}{}", sep, sep, year),
+    }
+}
+
+/// Digits and separators of an amount, with its sign. A trailing "CR" marks a
+/// credit and "DR" a debit, as in bank statements that print no minus sign.
+fn amount_body(cell: &str) -> Option<(bool, String)> {
+    let cell = cell.trim();
+    let upper = cell.to_ascii_uppercase();
+    let (cell, debit) = match (upper.strip_suffix("CR"), upper.strip_suffix("DR")) {
+        (Some(rest), _) => (&cell[..rest.len()], Some(false)),
+        (_, Some(rest)) => (&cell[..rest.len()], Some(true)),
+        _ => (cell, None),
+    };
+    let letters: String = cell.chars().filter(|c| c.is_ascii_alphabetic()).collect();
+    if !(letters.is_empty()
+        || matches!(
+            letters.to_uppercase().as_str(),
+            "MXN" | "USD" | "EUR" | "MN"
+        ))
+    {
+        return None;
+    }
+    let mut text = cell
+        .trim_matches(|c: char| c.is_ascii_alphabetic() || c.is_whitespace())
+        .to_string();
+    let mut negative = false;
+    if text.starts_with('(') && text.ends_with(')') {
+        negative = true;
+        text = text[1..text.len() - 1].to_string();
+    }
+    let mut body = String::new();
+    for c in text.chars() {
+        match c {
+            '0'..='9' | '.' | ',' => body.push(c),
+            '-' | '−' => negative = true,
+            '+' | '$' | '€' | '£' | ' ' | '\u{a0}' => {}
+            _ if c.is_ascii_alphabetic() => {}
+            _ => return None,
+        }
+    }
+    if !body.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((debit.unwrap_or(negative), body))
+}
+
+/// Parse an amount in the given format
+pub fn parse_amount(cell: &str, format: &NumberFormat) -> Option<f64> {
+    let (negative, body) = amount_body(cell)?;
+    let (int_part, fraction) = match body.rfind(format.decimal) {
+        Some(idx) => (&body[..idx], Some(&body[idx + 1..])),
+        None => (body.as_str(), None),
+    };
+    if fraction.is_some_and(|f| f.is_empty() || !f.chars().all(|c| c.is_ascii_digit())) {
+        return None;
+    }
+    let digits: String = match format.thousands {
+        Some(thousands) if int_part.contains(thousands) => {
+            let groups: Vec<&str> = int_part.split(thousands).collect();
+            let valid = !groups[0].is_empty()
+                && groups[0].len() <= 3
+                && groups[1..].iter().all(|g| g.len() == 3)
+                && groups.iter().all(|g| g.chars().all(|c| c.is_ascii_digit()));
+            if !valid {
+                return None;
+            }
+            groups.concat()
+        }
+        _ if int_part.chars().all(|c| c.is_ascii_digit()) => int_part.to_string(),
+        _ => return None,
+    };
+    let number: f64 = format!(
+        "{}.{}",
+        if digits.is_empty() { "0" } else { &digits },
+        fraction.unwrap_or("0")
+    )
+    .parse()
+    .ok()?;
+    Some(if negative { -number } else { number })
+}
+
+/// Write an amount with two decimals in the given format, such as "-1,234.56"
+pub fn format_amount(amount: f64, format: &NumberFormat) -> String {
+    let cents = (amount.abs() * 100.0).round() as u64;
+    let digits = (cents / 100).to_string();
+    let mut int_part = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            if let Some(thousands) = format.thousands {
+                int_part.push(thousands);
+            }
+        }
+        int_part.push(c);
+    }
+    let sign = if cents > 0 && amount < 0.0 { "-" } else { "" };
+    format!("{}{}{}{:02}", sign, int_part, format.decimal, cents % 100)
+}
+
+/// Whether a cell reads as a date or an amount in any format
+pub fn is_value(cell: &str) -> bool {
+    let comma = NumberFormat {
+        decimal: ',',
+        thousands: Some('.'),
+    };
+    DATE_FORMATS
+        .iter()
+        .any(|format| parse_date(cell, *format).is_some())
+        || parse_amount(cell, &NumberFormat::default()).is_some()
+        || parse_amount(cell, &comma).is_some()
+}
+
+/// The decimal separator a single amount implies, if it implies one
+fn decimal_vote(body: &str) -> Option<char> {
+    let last_dot = body.rfind('.');
+    let last_comma = body.rfind(',');
+    match (last_dot, last_comma) {
+        (Some(dot), Some(comma)) => Some(if dot > comma { '.' } else { ',' }),
+        (Some(_), None) | (None, Some(_)) => {
+            let sep = if last_dot.is_some() { '.' } else { ',' };
+            let other = if sep == '.' { ',' } else { '.' };
+            let count = body.matches(sep).count();
+            let after = body.len() - body.rfind(sep)? - 1;
+            if count > 1 {
+                Some(other)
+            } else if after == 3 {
+                // 1,234 or 1.234: could be either
+                None
+            } else {
+                Some(sep)
+            }
+        }
+        (None, None) => None,
+    }
+}
+
+/// Pick the format for one column from all of its cells
+pub fn infer_column(index: usize, cells: &[&str]) -> ColumnFormat {
+    let values: Vec<&str> = cells
+        .iter()
+        .map(|c| c.trim())
+        .filter(|c| !c.is_empty())
+        .collect();
+    let total = values.len();
+    let mut column = ColumnFormat {
+        index,
+        kind: ColumnKind::Empty,
+        pattern: None,
+        matched: 0,
+        total,
+        evidence: "no values".to_string(),
+    };
+    if total == 0 {
+        return column;
+    }
+
+    let counts: Vec<(DateFormat, usize)> = DATE_FORMATS
+        .iter()
+        .map(|format| {
+            (
+                *format,
+                values
+                    .iter()
+                    .filter(|v| parse_date(v, *format).is_some())
+                    .count(),
+            )
+        })
+        .collect();
+    let mut best = counts[0];
+    for count in &counts[1..] {
+        if count.1 > best.1 {
+            best = *count;
+        }
+    }
+    if best.1 > 0 && best.1 * 2 >= total {
+        let (format, matched) = best;
+        let sample = values
+            .iter()
+            .find(|v| parse_date(v, format).is_some())
+            .unwrap_or(&values[0]);
+        let pattern = date_pattern(format, sample);
+        let rivals: Vec<String> = counts
+            .iter()
+            .filter(|(f, n)| *f != format && *n > 0)
+            .map(|(f, n)| format!("{} fits {}", date_pattern(*f, sample), n))
+            .collect();
+        let mut evidence = format!("{} fits {} of {} values", pattern, matched, total);
+        if !rivals.is_empty() {
+            evidence.push_str(&format!("; {}", rivals.join(", ")));
+        }
+        if format == DateFormat::Dmy
+            && counts
+                .iter()
+                .any(|(f, n)| *f == DateFormat::Mdy && *n == matched)
+        {
+            evidence.push_str(" (no day above 12, so day first is assumed)");
+        }
+        column.kind = ColumnKind::Date { format };
+        column.pattern = Some(pattern);
+        column.matched = matched;
+        column.evidence = evidence;
+        return column;
+    }
+
+    let bodies: Vec<String> = values
+        .iter()
+        .filter_map(|v| amount_body(v))
+        .map(|(_, b)| b)
+        .collect();
+    if bodies.len() * 2 >= total {
+        let dot_votes = bodies
+            .iter()
+            .filter(|b| decimal_vote(b) == Some('.'))
+            .count();
+        let comma_votes = bodies
+            .iter()
+            .filter(|b| decimal_vote(b) == Some(','))
+            .count();
+        let format = if comma_votes > dot_votes {
+            NumberFormat {
+                decimal: ',',
+                thousands: Some('.'),
+            }
+        } else {
+            NumberFormat::default()
+        };
+        let matched = values
+            .iter()
+            .filter(|v| parse_amount(v, &format).is_some())
+            .count();
+        if matched * 2 >= total {
+            column.evidence = if dot_votes + comma_votes == 0 {
+                format!(
+                    "{} of {} values are numbers; no decimal separator seen, assuming '.'",
+                    matched, total
+                )
+            } else {
+                format!(
+                    "'{}' is the decimal separator in {} values ('{}' in {}); {} of {} values fit",
+                    format.decimal,
+                    dot_votes.max(comma_votes),
+                    if format.decimal == '.' { ',' } else { '.' },
+                    dot_votes.min(comma_votes),
+                    matched,
+                    total
+                )
+            };
+            column.pattern = Some(format.pattern());
+            column.kind = ColumnKind::Number { format };
+            column.matched = matched;
+            return column;
+        }
+    }
+
+    column.kind = ColumnKind::Text;
+    column.matched = total;
+    column.evidence = "neither dates nor numbers".to_string();
+    column
+}
+
+/// Use a saved date or amount format instead of the one inferred for a column
+pub fn override_format(
+    column: &mut ColumnFormat,
+    cells: &[&str],
+    date_format: Option<DateFormat>,
+    number_format: Option<NumberFormat>,
+) {
+    let values: Vec<&str> = cells
+        .iter()
+        .map(|c| c.trim())
+        .filter(|c| !c.is_empty())
+        .collect();
+    let (kind, pattern, matched) = match (&column.kind, date_format, number_format) {
+        (ColumnKind::Date { .. }, Some(format), _) => {
+            let matched = values
+                .iter()
+                .filter(|v| parse_date(v, format).is_some())
+                .count();
+            let sample = values
+                .iter()
+                .find(|v| parse_date(v, format).is_some())
+                .copied()
+                .unwrap_or_default();
+            (
+                ColumnKind::Date { format },
+                date_pattern(format, sample),
+                matched,
+            )
+        }
+        (ColumnKind::Number { .. }, _, Some(format)) => {
+            let matched = values
+                .iter()
+                .filter(|v| parse_amount(v, &format).is_some())
+                .count();
+            (ColumnKind::Number { format }, format.pattern(), matched)
+        }
+        _ => return,
+    };
+    column.evidence = format!(
+        "{} from the import profile; {} of {} values fit",
+        pattern,
+        matched,
+        values.len()
+    );
+    column.kind = kind;
+    column.pattern = Some(pattern);
+    column.matched = matched;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const US: NumberFormat = NumberFormat {
+        decimal: '.',
+        thousands: Some(','),
+    };
+
+    fn date_format(cells: &[&str]) -> Option<DateFormat> {
+        match infer_column(0, cells).kind {
+            ColumnKind::Date { format } => Some(format),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn ambiguous_dates_are_read_day_first() {
+        let column = infer_column(0, &["03/04/2024", "05/06/2024", ""]);
+        assert_eq!(
+            column.kind,
+            ColumnKind::Date {
+                format: DateFormat::Dmy
+            }
+        );
+        assert_eq!(column.total, 2);
+        assert!(
+            column.evidence.contains("day first is assumed"),
+            "{}",
+            column.evidence
+        );
+    }
+
+    #[test]
+    fn one_unambiguous_date_settles_the_column() {
+        assert_eq!(
+            date_format(&["03/04/2024", "01/31/2024"]),
+            Some(DateFormat::Mdy)
+        );
+        assert_eq!(
+            date_format(&["03/04/2024", "31/01/2024"]),
+            Some(DateFormat::Dmy)
+        );
+        assert_eq!(
+            date_format(&["2024-03-04", "2024-01-31"]),
+            Some(DateFormat::Ymd)
+        );
+        // Half the column is enough; the cells that don't fit are reported
+        let column = infer_column(0, &["03/04/2024", "01/31/2024", "31/01/2024"]);
+        assert_eq!(
+            column.kind,
+            ColumnKind::Date {
+                format: DateFormat::Dmy
+            }
+        );
+        assert_eq!(column.matched, 2);
+    }
+
+    #[test]
+    fn ambiguous_amounts_use_a_decimal_point() {
+        let column = infer_column(0, &["1,234", "5,678"]);
+        assert_eq!(column.kind, ColumnKind::Number { format: US });
+        let column = infer_column(0, &["1,234", "12,50", "1.234,56"]);
+        let comma = NumberFormat {
+            decimal: ',',
+            thousands: Some('.'),
+        };
+        assert_eq!(column.kind, ColumnKind::Number { format: comma });
+        assert_eq!(parse_amount("1.234,56", &comma), Some(1234.56));
+    }
+
+    #[test]
+    fn credit_and_debit_markers_give_the_sign() {
+        assert_eq!(parse_amount("1,234.50 CR", &US), Some(1234.5));
+        assert_eq!(parse_amount("1,234.50 DR", &US), Some(-1234.5));
+        assert_eq!(parse_amount("$80.00dr", &US), Some(-80.0));
+        assert_eq!(parse_amount("MXN 80.00 Cr", &US), Some(80.0));
+        assert_eq!(parse_amount("80.00 XR", &US), None);
+    }
+}
@@ -0,0 +1,262 @@
+//! A small reader for the tag-based formats banks and the SAT send.
+//!
+//! Handles plain XML (camt.053, CFDI, OFX 2.x) and the SGML of OFX 1.x, where
+//! leaf elements such as `<TRNAMT>-12.50` are never closed: a leaf ends as
+//! soon as the next tag starts. Names are matched without their namespace
+//! prefix and without regard to case.
+
+/// OFX 1.x elements that never have children, so they end at the next tag
+/// even when empty (`<MEMO>` followed by `<NAME>`)
+const SGML_LEAVES: &[&str] = &[
+    "ACCTID", "ACCTTYPE", "BALAMT", "BANKID", "BRANCHID", "CHECKNUM", "CODE", "CURDEF", "DTASOF",
+    "DTAVAIL", "DTEND", "DTPOSTED", "DTSERVER", "DTSTART", "DTUSER", "FID", "FITID", "LANGUAGE",
+    "MEMO", "MESSAGE", "NAME", "ORG", "PAYEEID", "REFNUM", "SEVERITY", "SIC", "SRVRTID", "TRNAMT",
+    "TRNTYPE", "TRNUID",
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct Element {
+    /// Tag name without its namespace prefix
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub text: Option<String>,
+    pub children: Vec<Element>,
+}
+
+impl Element {
+    pub fn is(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.is(name))
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |c| c.is(name))
+    }
+
+    /// Follow a path of child names, e.g. `&["Acct", "Id", "IBAN"]`
+    pub fn path(&self, names: &[&str]) -> Option<&Element> {
+        names
+            .iter()
+            .try_fold(self, |element, name| element.child(name))
+    }
+
+    /// Trimmed text of this element, if not empty
+    pub fn text(&self) -> Option<String> {
+        self.text
+            .as_deref()
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::to_string)
+    }
+
+    /// Trimmed text of a direct child, if present and not empty
+    pub fn text_of(&self, name: &str) -> Option<String> {
+        self.child(name).and_then(Element::text)
+    }
+
+    /// Trimmed text at the end of a path of child names
+    pub fn text_at(&self, names: &[&str]) -> Option<String> {
+        self.path(names).and_then(Element::text)
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Every descendant with this name, in document order
+    pub fn descendants<'a>(&'a self, name: &str, found: &mut Vec<&'a Element>) {
+        for child in &self.children {
+            if child.is(name) {
+                found.push(child);
+            }
+            child.descendants(name, found);
+        }
+    }
+}
+
+pub fn unescape(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(idx) = rest.find('&') {
+        out.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        let Some(end) = rest.find(';').filter(|end| *end <= 10) else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16)
+                .ok()
+                .and_then(char::from_u32),
+            _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Name without its namespace prefix
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+/// `key="value"` pairs of a start tag
+fn read_attributes(mut rest: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].trim();
+        let after = rest[eq + 1..].trim_start();
+        let Some(quote) = after.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            break;
+        };
+        let Some(end) = after[1..].find(quote) else {
+            break;
+        };
+        attributes.push((local_name(key).to_string(), unescape(&after[1..end + 1])));
+        rest = &after[end + 2..];
+    }
+    attributes
+}
+
+/// Pop the top element into its parent
+fn close_top(stack: &mut Vec<Element>) {
+    if stack.len() > 1 {
+        let element = stack.pop().unwrap();
+        stack.last_mut().unwrap().children.push(element);
+    }
+}
+
+/// Read a document into a tree. The returned root is an unnamed element
+/// holding the top-level elements.
+pub fn parse(body: &str) -> Element {
+    let mut stack = vec![Element::default()];
+    let mut rest = body;
+    while let Some(start) = rest.find('<') {
+        let text = rest[..start].trim();
+        if !text.is_empty() && stack.len() > 1 {
+            let top = stack.last_mut().unwrap();
+            top.text
+                .get_or_insert_with(String::new)
+                .push_str(&unescape(text));
+        }
+        rest = &rest[start..];
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+            continue;
+        }
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata.find("]]>").unwrap_or(cdata.len());
+            if stack.len() > 1 {
+                let top = stack.last_mut().unwrap();
+                top.text
+                    .get_or_insert_with(String::new)
+                    .push_str(&cdata[..end]);
+            }
+            rest = cdata.get(end + 3..).unwrap_or("");
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = rest[1..end].trim();
+        rest = &rest[end + 1..];
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+
+        // An SGML leaf ends where the next tag begins
+        let leaf_open = stack.len() > 1 && {
+            let top = stack.last().unwrap();
+            top.text.is_some()
+                || (top.children.is_empty() && SGML_LEAVES.iter().any(|leaf| top.is(leaf)))
+        };
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = local_name(name.trim());
+            if leaf_open && !stack.last().unwrap().is(name) {
+                close_top(&mut stack);
+            }
+            if let Some(pos) = stack.iter().skip(1).rposition(|e| e.is(name)) {
+                while stack.len() > pos + 1 {
+                    close_top(&mut stack);
+                }
+            }
+        } else {
+            if leaf_open {
+                close_top(&mut stack);
+            }
+            let self_closing = tag.ends_with('/');
+            let tag = tag.trim_end_matches('/');
+            let (name, attributes) = match tag.find(char::is_whitespace) {
+                Some(idx) => (&tag[..idx], read_attributes(&tag[idx..])),
+                None => (tag, Vec::new()),
+            };
+            stack.push(Element {
+                name: local_name(name).to_string(),
+                attributes,
+                ..Default::default()
+            });
+            if self_closing {
+                close_top(&mut stack);
+            }
+        }
+    }
+    while stack.len() > 1 {
+        close_top(&mut stack);
+    }
+    stack.pop().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sgml_leaves_end_at_the_next_tag_even_when_empty() {
+        let root = parse("<STMTTRN><TRNTYPE>DEBIT<MEMO><NAME>OXXO<TRNAMT>-12.50</STMTTRN>");
+        let trn = root.child("STMTTRN").unwrap();
+        assert_eq!(trn.children.len(), 4);
+        assert_eq!(trn.child("MEMO").unwrap().text(), None);
+        assert_eq!(trn.text_of("NAME").as_deref(), Some("OXXO"));
+        assert_eq!(trn.text_of("TRNAMT").as_deref(), Some("-12.50"));
+    }
+
+    #[test]
+    fn reads_xml_with_namespaces_and_attributes() {
+        let root = parse(
+            r#"<?xml version="1.0"?><cfdi:Emisor Rfc="ABC" Nombre="P&amp;G"/><!-- x --><a><b><![CDATA[<1>]]></b></a>"#,
+        );
+        let issuer = root.child("Emisor").unwrap();
+        assert_eq!(
+            (issuer.attr("Rfc"), issuer.attr("Nombre")),
+            (Some("ABC"), Some("P&G"))
+        );
+        assert_eq!(root.text_at(&["a", "b"]).as_deref(), Some("<1>"));
+    }
+}
@@ -0,0 +1,355 @@
+//! Finding import rows that are already in the register.
+//!
+//! A row with a bank reference (FITID, camt/MT940 reference, CFDI UUID) is a
+//! duplicate when that reference was imported before, or shows up in a
+//! transaction's check number or memo. Other rows are compared by amount,
+//! date and payee with the account's transactions. A close match with a
+//! similar name to a cleared transaction is a duplicate; any other match is a
+//! probable one for the user to confirm, which the import clears instead of
+//! adding the row again.
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+
+use super::transaction::{self, ImportTransaction};
+use crate::ynab4::entities::{BudgetData, Transaction, UNCLEARED};
+use crate::ynab4::local;
+
+const REFERENCES_FILE: &str = "importedReferences.json";
+
+/// Score and name similarity a match needs to be a duplicate without a reference
+const DUPLICATE_SCORE: f64 = 0.75;
+const DUPLICATE_SIMILARITY: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchKind {
+    /// Not in the register
+    New,
+    /// Already in the register
+    Duplicate,
+    /// Probably a transaction entered by hand, usually uncleared; left for
+    /// the user to confirm
+    Probable,
+}
+
+/// What the matcher found for a row
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportMatch {
+    pub kind: MatchKind,
+    pub transaction_id: Option<String>,
+    /// 0 to 1: how sure the matcher is of `kind`
+    pub confidence: f64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MatchOptions {
+    /// Days a bank date may differ from the register date
+    pub date_window_days: i64,
+    /// Score below which a similar transaction is not a match
+    pub min_confidence: f64,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        MatchOptions {
+            date_window_days: 4,
+            min_confidence: 0.5,
+        }
+    }
+}
+
+/// Bank references imported before, per account, with the transaction each became
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedReferences {
+    /// `<account id>:<reference>` to transaction id
+    #[serde(default)]
+    pub references: BTreeMap<String, String>,
+}
+
+fn references_path(budget_path: &str) -> Result<PathBuf, String> {
+    Ok(local::budget_dir(budget_path)?.join(REFERENCES_FILE))
+}
+
+impl ImportedReferences {
+    pub fn load(budget_path: &str) -> Result<ImportedReferences, String> {
+        local::read_or_default(&references_path(budget_path)?)
+    }
+
+    pub fn save(&self, budget_path: &str) -> Result<(), String> {
+        local::write_json(&references_path(budget_path)?, self)
+    }
+
+    fn key(account_id: &str, reference: &str) -> String {
+        format!("{}:{}", account_id, reference.trim())
+    }
+
+    pub fn get(&self, account_id: &str, reference: &str) -> Option<&String> {
+        self.references.get(&Self::key(account_id, reference))
+    }
+
+    pub fn insert(&mut self, account_id: &str, reference: &str, transaction_id: &str) {
+        if !reference.trim().is_empty() {
+            self.references
+                .insert(Self::key(account_id, reference), transaction_id.to_string());
+        }
+    }
+}
+
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d").ok()
+}
+
+fn cents(amount: f64) -> i64 {
+    (amount * 100.0).round() as i64
+}
+
+/// Name the bank gave the row
+fn row_name(row: &ImportTransaction) -> &str {
+    [&row.payee_name, &row.suggested_payee, &row.description]
+        .into_iter()
+        .find(|n| !n.trim().is_empty())
+        .map_or("", String::as_str)
+}
+
+/// Transaction of the account that carries the row's reference
+fn by_reference<'a>(
+    row: &ImportTransaction,
+    account_id: &str,
+    candidates: &[&'a Transaction],
+    references: &ImportedReferences,
+) -> Option<(&'a Transaction, &'static str)> {
+    let reference = row.reference.trim();
+    if reference.is_empty() {
+        return None;
+    }
+    if let Some(id) = references.get(account_id, reference) {
+        if let Some(t) = candidates.iter().find(|t| &t.entity_id == id) {
+            return Some((t, "bank reference imported before"));
+        }
+    }
+    // Short references such as "1" or "NONREF" say nothing
+    if reference.len() < 6 {
+        return None;
+    }
+    candidates
+        .iter()
+        .find(|t| {
+            cents(t.amount) == cents(row.amount)
+                && (t.check_number.as_deref() == Some(reference)
+                    || t.memo.as_deref().is_some_and(|m| m.contains(reference)))
+        })
+        .map(|t| (*t, "same bank reference in the register"))
+}
+
+/// How likely `t` is the row, from 0 to 1, when the amounts are equal, and
+/// how similar the names are
+fn similarity_score(
+    data: &BudgetData,
+    row: &ImportTransaction,
+    row_date: NaiveDate,
+    t: &Transaction,
+    window: i64,
+) -> Option<(f64, f64)> {
+    if cents(t.amount) != cents(row.amount) {
+        return None;
+    }
+    let days = (parse_date(&t.date)? - row_date).num_days().abs();
+    if days > window {
+        return None;
+    }
+    let payee = t
+        .payee_id
+        .as_deref()
+        .and_then(|id| data.payee(id))
+        .map_or("", |p| p.name.as_str());
+    let memo = t.memo.as_deref().unwrap_or_default();
+    let name = row_name(row);
+    let similarity =
+        transaction::name_similarity(name, payee).max(transaction::name_similarity(name, memo));
+    let closeness = 1.0 - days as f64 / (window + 1) as f64;
+    // An equal amount on the same day is already a strong hint
+    Some((0.3 + 0.35 * closeness + 0.35 * similarity, similarity))
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Classify each row against the transactions of its account. A transaction
+/// matches one row at most; references are matched first, then the closest
+/// pairs. Rows without an account are new.
+pub fn match_rows(
+    data: &BudgetData,
+    rows: &[ImportTransaction],
+    references: &ImportedReferences,
+    options: &MatchOptions,
+) -> Vec<ImportMatch> {
+    let new = |confidence: f64, reason: &str| ImportMatch {
+        kind: MatchKind::New,
+        transaction_id: None,
+        confidence: round2(confidence),
+        reason: reason.to_string(),
+    };
+    let mut results: Vec<Option<ImportMatch>> = vec![None; rows.len()];
+    let mut taken: HashSet<&str> = HashSet::new();
+
+    let candidates_for = |account_id: &str| -> Vec<&Transaction> {
+        data.transactions
+            .iter()
+            .filter(|t| !t.is_tombstone && t.account_id == account_id)
+            .collect()
+    };
+
+    // Bank references first
+    for (idx, row) in rows.iter().enumerate() {
+        let Some(account_id) = row.account_id.as_deref() else {
+            results[idx] = Some(new(1.0, "no account to compare with"));
+            continue;
+        };
+        let candidates = candidates_for(account_id);
+        if let Some((t, reason)) = by_reference(row, account_id, &candidates, references) {
+            if taken.insert(&t.entity_id) {
+                results[idx] = Some(ImportMatch {
+                    kind: MatchKind::Duplicate,
+                    transaction_id: Some(t.entity_id.clone()),
+                    confidence: 1.0,
+                    reason: reason.to_string(),
+                });
+            }
+        }
+    }
+
+    // Then every (row, transaction) pair by score, best first
+    let mut pairs: Vec<(f64, f64, usize, &Transaction)> = Vec::new();
+    let mut best_seen: Vec<f64> = vec![0.0; rows.len()];
+    for (idx, row) in rows.iter().enumerate() {
+        if results[idx].is_some() {
+            continue;
+        }
+        let (Some(account_id), Some(row_date)) = (row.account_id.as_deref(), parse_date(&row.date))
+        else {
+            continue;
+        };
+        for t in candidates_for(account_id) {
+            if let Some((score, similarity)) =
+                similarity_score(data, row, row_date, t, options.date_window_days)
+            {
+                best_seen[idx] = best_seen[idx].max(score);
+                if score >= options.min_confidence {
+                    pairs.push((score, similarity, idx, t));
+                }
+            }
+        }
+    }
+    pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+    for (score, similarity, idx, t) in pairs {
+        if results[idx].is_some() || taken.contains(t.entity_id.as_str()) {
+            continue;
+        }
+        taken.insert(&t.entity_id);
+        let uncleared = t.cleared == UNCLEARED || t.cleared.is_empty();
+        let duplicate =
+            !uncleared && score >= DUPLICATE_SCORE && similarity >= DUPLICATE_SIMILARITY;
+        results[idx] = Some(ImportMatch {
+            kind: if duplicate {
+                MatchKind::Duplicate
+            } else {
+                MatchKind::Probable
+            },
+            transaction_id: Some(t.entity_id.clone()),
+            confidence: round2(score),
+            reason: if uncleared {
+                format!("uncleared transaction of {} on {}", t.amount, t.date)
+            } else {
+                format!(
+                    "{} transaction of {} on {}",
+                    t.cleared.to_lowercase(),
+                    t.amount,
+                    t.date
+                )
+            },
+        });
+    }
+
+    results
+        .into_iter()
+        .enumerate()
+        .map(|(idx, result)| {
+            result.unwrap_or_else(|| match best_seen[idx] {
+                score if score > 0.0 => new(
+                    1.0 - score,
+                    "similar transactions are taken or too different",
+                ),
+                _ => new(1.0, "no transaction with this amount nearby"),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::budget::Budget;
+    use crate::ynab4::fixtures;
+
+    fn row(index: usize, date: &str, name: &str, amount: f64) -> ImportTransaction {
+        let mut row = ImportTransaction::new(1, index, date.to_string(), name.to_string(), amount);
+        row.account_id = Some("ACC-CHK".to_string());
+        row
+    }
+
+    #[test]
+    fn classifies_rows_against_the_register() {
+        let budget = Budget::load(&fixtures::budget_folder("matching-rows")).unwrap();
+        let mut references = ImportedReferences::default();
+        references.insert("ACC-CHK", "FIT123456", "T-4");
+        let mut with_reference = row(3, "2024-01-22", "Deposit", 500.0);
+        with_reference.reference = "FIT123456".to_string();
+        let mut no_account = row(5, "2024-01-05", "OXXO", -250.0);
+        no_account.account_id = None;
+        let rows = vec![
+            row(0, "2024-01-06", "OXXO SUC 123", -250.0),
+            row(1, "2024-01-02", "EMPLOYER PAYROLL", 1000.0),
+            row(2, "2024-01-07", "Cafe", -42.0),
+            with_reference,
+            row(4, "2024-01-05", "OXXO", -250.0),
+            no_account,
+        ];
+        let found = match_rows(&budget.data, &rows, &references, &MatchOptions::default());
+
+        // Both OXXO rows fit T-2; the one on the same day gets it
+        assert_eq!(found[4].kind, MatchKind::Probable);
+        assert_eq!(found[4].transaction_id.as_deref(), Some("T-2"));
+        assert_eq!(found[0].kind, MatchKind::New);
+        assert_eq!(found[1].kind, MatchKind::Duplicate);
+        assert_eq!(found[1].transaction_id.as_deref(), Some("T-1"));
+        assert_eq!(found[2].kind, MatchKind::New);
+        assert_eq!(
+            (found[3].kind, found[3].confidence),
+            (MatchKind::Duplicate, 1.0)
+        );
+        assert_eq!(found[5].kind, MatchKind::New);
+    }
+
+    #[test]
+    fn an_equal_amount_alone_is_not_a_duplicate() {
+        let budget = Budget::load(&fixtures::budget_folder("matching-amount")).unwrap();
+        // T-1 is cleared, on the same day, but paid by someone else entirely
+        let rows = vec![row(0, "2024-01-01", "ZAPATERIA", 1000.0)];
+        let found = match_rows(
+            &budget.data,
+            &rows,
+            &ImportedReferences::default(),
+            &MatchOptions::default(),
+        );
+        assert_eq!(found[0].kind, MatchKind::Probable);
+        assert_eq!(found[0].transaction_id.as_deref(), Some("T-1"));
+    }
+}
@@ -0,0 +1,20 @@
+//! Reading bank statements and other files into import rows
+
+pub mod camt;
+pub mod cfdi;
+pub mod classifier;
+pub mod commands;
+pub mod commit;
+pub mod drafts;
+pub mod formats;
+pub mod markup;
+pub mod matching;
+pub mod mt940;
+pub mod notifications;
+pub mod ofx;
+pub mod profiles;
+pub mod qif;
+pub mod spreadsheet;
+pub mod statement;
+pub mod transaction;
+pub mod ynab4_csv;
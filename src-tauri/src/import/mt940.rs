@@ -0,0 +1,356 @@
+//! SWIFT MT940 customer statements.
+//!
+//! A statement is a list of `:TAG:` fields: `:25:` the account, `:60F:` and
+//! `:62F:` the opening and closing balances, and a `:61:` line per booking,
+//! usually followed by an `:86:` field with the counterparty and remittance
+//! information. German banks structure `:86:` with `?NN` subfields and many
+//! others with `/KEYWORD/` pairs; both are read, anything else is kept as text.
+
+use chrono::{Datelike, NaiveDate};
+use regex::Regex;
+use std::sync::OnceLock;
+
+use super::transaction::{self, BankStatement, ImportTransaction, StatementBalance};
+use crate::ynab4::entities::BudgetData;
+
+/// Date from YYMMDD
+fn short_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.get(..6)?, "%y%m%d").ok()
+}
+
+/// MT940 amounts use `,` as the decimal separator
+fn amount(value: &str) -> Option<f64> {
+    value.replace(',', ".").parse().ok()
+}
+
+/// `:60F:`/`:62F:` value such as C240131EUR1234,56
+fn balance(value: &str) -> Option<(StatementBalance, String)> {
+    let sign = match value.get(..1)? {
+        "C" => 1.0,
+        "D" => -1.0,
+        _ => return None,
+    };
+    let date = short_date(value.get(1..7)?)?;
+    let currency = value.get(7..10)?.to_string();
+    Some((
+        StatementBalance {
+            amount: sign * amount(value.get(10..)?.trim())?,
+            date: Some(date.format("%Y-%m-%d").to_string()),
+        },
+        currency,
+    ))
+}
+
+fn booking_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"^(\d{6})(\d{4})?(RC|RD|C|D)([A-Z])?(\d+,\d*)([NSF][A-Z0-9]{3})([^/]*)(?://(.*))?$",
+        )
+        .unwrap()
+    })
+}
+
+/// What an `:86:` field says about a booking
+#[derive(Default)]
+struct Details {
+    name: Option<String>,
+    iban: Option<String>,
+    memo: Option<String>,
+}
+
+/// `?20SVWZ+Rent?21March?32ACME GmbH?31DE02...`
+fn question_mark_details(text: &str) -> Details {
+    let mut details = Details::default();
+    let mut purpose = Vec::new();
+    let mut name = Vec::new();
+    for part in text.split('?').skip(1) {
+        let (Some(code), value) = (part.get(..2), part.get(2..).unwrap_or_default()) else {
+            continue;
+        };
+        match code {
+            "20" | "21" | "22" | "23" | "24" | "25" | "26" | "27" | "28" | "29" | "60" | "61"
+            | "62" | "63" => purpose.push(value),
+            "32" | "33" => name.push(value),
+            "31" => details.iban = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+    let purpose = purpose.concat();
+    // SEPA purpose text is prefixed with SVWZ+, after EREF+/KREF+/MREF+ parts
+    let memo = match purpose.find("SVWZ+") {
+        Some(idx) => purpose[idx + 5..].to_string(),
+        None => purpose,
+    };
+    details.memo = Some(memo.trim().to_string()).filter(|m| !m.is_empty());
+    details.name = Some(name.concat().trim().to_string()).filter(|n| !n.is_empty());
+    details
+}
+
+/// `/NAME/ACME/REMI/Invoice 12/IBAN/NL91.../`
+fn keyword_details(text: &str) -> Details {
+    const KEYWORDS: [&str; 8] = [
+        "NAME", "REMI", "IBAN", "BIC", "EREF", "TRTP", "CSID", "ORDP",
+    ];
+    let parts: Vec<&str> = text.split('/').collect();
+    let mut details = Details::default();
+    let mut idx = 0;
+    while idx < parts.len() {
+        let key = parts[idx];
+        if !KEYWORDS.contains(&key) {
+            idx += 1;
+            continue;
+        }
+        // A value runs until the next keyword, so it may itself contain slashes
+        let mut end = idx + 1;
+        while end < parts.len() && !KEYWORDS.contains(&parts[end]) {
+            end += 1;
+        }
+        let value = parts[idx + 1..end]
+            .join("/")
+            .trim()
+            .trim_end_matches('/')
+            .to_string();
+        match key {
+            "NAME" => details.name = Some(value),
+            "REMI" => details.memo = Some(value.trim_start_matches("USTD//").to_string()),
+            "IBAN" => details.iban = Some(value),
+            _ => {}
+        }
+        idx = end;
+    }
+    details
+}
+
+fn read_details(text: &str) -> Details {
+    if text.starts_with('?') || text.get(3..4) == Some("?") {
+        question_mark_details(text)
+    } else if text.contains("/NAME/") || text.contains("/REMI/") {
+        keyword_details(text)
+    } else {
+        Details {
+            memo: Some(text.trim().to_string()).filter(|m| !m.is_empty()),
+            ..Default::default()
+        }
+    }
+}
+
+fn read_booking(
+    line: &str,
+    supplementary: &str,
+    info: Option<&str>,
+    batch: i64,
+    index: usize,
+) -> Result<ImportTransaction, String> {
+    let caps = booking_regex()
+        .captures(line)
+        .ok_or_else(|| format!("Unreadable :61: line '{}'", line))?;
+    let value_date =
+        short_date(&caps[1]).ok_or_else(|| format!("Invalid date in :61: line '{}'", line))?;
+    // The booking date has no year; take the value date's, across a year end if needed
+    let booked = match caps.get(2) {
+        Some(mmdd) => {
+            let month: u32 = mmdd.as_str()[..2].parse().unwrap_or(0);
+            let day: u32 = mmdd.as_str()[2..].parse().unwrap_or(0);
+            let year = match (value_date.month(), month) {
+                (1, 12) => value_date.year() - 1,
+                (12, 1) => value_date.year() + 1,
+                _ => value_date.year(),
+            };
+            NaiveDate::from_ymd_opt(year, month, day).unwrap_or(value_date)
+        }
+        None => value_date,
+    };
+    let raw = amount(&caps[5]).ok_or_else(|| format!("Invalid amount in :61: line '{}'", line))?;
+    let signed = match &caps[3] {
+        "D" | "RC" => -raw,
+        _ => raw,
+    };
+    let owner_reference = caps[7].trim();
+    let bank_reference = caps.get(8).map(|m| m.as_str().trim()).unwrap_or_default();
+
+    let details = info.map(read_details).unwrap_or_default();
+    let supplementary = supplementary.trim();
+    let memo = details.memo.clone().unwrap_or_default();
+    let description = details
+        .name
+        .clone()
+        .or_else(|| Some(supplementary.to_string()).filter(|s| !s.is_empty()))
+        .unwrap_or_else(|| memo.clone());
+
+    let mut row = ImportTransaction::new(
+        batch,
+        index,
+        booked.format("%Y-%m-%d").to_string(),
+        description,
+        signed,
+    );
+    row.original_memo = memo.clone();
+    row.memo = memo;
+    row.reference = if !bank_reference.is_empty() {
+        bank_reference.to_string()
+    } else if owner_reference != "NONREF" {
+        owner_reference.to_string()
+    } else {
+        String::new()
+    };
+    row.value_date = Some(value_date.format("%Y-%m-%d").to_string()).filter(|d| *d != row.date);
+    row.counterparty_iban = details.iban;
+    Ok(row)
+}
+
+/// Split the text into `(tag, value)` fields, joining continuation lines
+fn fields(text: &str) -> Vec<(String, String)> {
+    // Drop the SWIFT envelope ({1:...}{2:...}{4:) if present
+    let body = text.find("{4:").map_or(text, |idx| &text[idx + 3..]);
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in body.lines() {
+        let line = line.trim_end();
+        if line.starts_with("-}") || line == "-" {
+            continue;
+        }
+        let tag = line
+            .strip_prefix(':')
+            .and_then(|rest| rest.split_once(':'))
+            .filter(|(tag, _)| {
+                tag.len() <= 3 && tag.chars().next().is_some_and(|c| c.is_ascii_digit())
+            });
+        match tag {
+            Some((tag, value)) => fields.push((tag.to_string(), value.to_string())),
+            None => {
+                if let Some((_, value)) = fields.last_mut() {
+                    value.push('\n');
+                    value.push_str(line);
+                }
+            }
+        }
+    }
+    fields
+}
+
+/// Parse every statement in an MT940 file
+pub fn parse_mt940(text: &str, data: Option<&BudgetData>) -> Result<Vec<BankStatement>, String> {
+    let fields = fields(text);
+    if !fields
+        .iter()
+        .any(|(tag, _)| tag == "61" || tag.starts_with("60"))
+    {
+        return Err("Not an MT940 file: no balances or bookings".to_string());
+    }
+
+    let batch = transaction::batch_id();
+    let mut index = 0;
+    let mut statements: Vec<BankStatement> = Vec::new();
+    let mut idx = 0;
+    while idx < fields.len() {
+        let (tag, value) = (&fields[idx].0, &fields[idx].1);
+        // :20: starts a statement; files without one still get one
+        if tag == "20" || statements.is_empty() {
+            statements.push(BankStatement {
+                statement_id: if tag == "20" {
+                    value.trim().to_string()
+                } else {
+                    String::new()
+                },
+                ..Default::default()
+            });
+        }
+        let statement = statements.last_mut().unwrap();
+        match tag.as_str() {
+            "25" => statement.account = value.trim().to_string(),
+            // Only the first opening balance counts; 60M repeats it on continuation pages
+            "60F" | "60M" if statement.opening_balance.is_none() => {
+                if let Some((opening, currency)) = balance(value.trim()) {
+                    statement.opening_balance = Some(opening);
+                    statement.currency = Some(currency);
+                }
+            }
+            "62F" | "62M" => statement.closing_balance = balance(value.trim()).map(|(b, _)| b),
+            "61" => {
+                let (line, supplementary) = value.split_once('\n').unwrap_or((value, ""));
+                let info = fields
+                    .get(idx + 1)
+                    .filter(|(next, _)| next == "86")
+                    .map(|(_, v)| v.replace('\n', ""));
+                match read_booking(line.trim(), supplementary, info.as_deref(), batch, index) {
+                    Ok(row) => statement.transactions.push(row),
+                    Err(e) => statement.errors.push(e),
+                }
+                index += 1;
+            }
+            _ => {}
+        }
+        idx += 1;
+    }
+
+    for statement in statements.iter_mut() {
+        statement.assign_account(data);
+        statement.check_balances();
+    }
+    Ok(statements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MT940: &str = "{1:F01BANKDEFFAXXX0000000000}{2:I940BANKDEFFXXXXN}{4:\r
+:20:STARTUMS\r
+:25:37040044/0532013000\r
+:28C:00001/001\r
+:60F:C231229EUR1000,00\r
+:61:2312291229D12,50NTRFNONREF//B-1\r
+:86:166?00SEPA-UEBERWEISUNG?20EREF+123?21SVWZ+Miete Dez?32Vermieter ?33GmbH?31DE0237040044\r
+0532013001\r
+:61:2401020101C850,NTRFREF2\r
+Salary\r
+:86:/TRTP/SEPA/NAME/ACME BV/REMI/USTD//Salary/Jan/IBAN/NL91ABNA0417164300/\r
+:61:240103D1,00NCHGNONREF\r
+:62F:C240103EUR1836,50\r
+-}";
+
+    #[test]
+    fn reads_a_statement_with_structured_details() {
+        let statements = parse_mt940(MT940, None).unwrap();
+        assert_eq!(statements.len(), 1);
+        let s = &statements[0];
+        assert_eq!(s.statement_id, "STARTUMS");
+        assert_eq!(s.account, "37040044/0532013000");
+        assert_eq!(
+            s.opening_balance.as_ref().unwrap().date.as_deref(),
+            Some("2023-12-29")
+        );
+        assert_eq!(s.closing_balance.as_ref().unwrap().amount, 1836.5);
+        assert!(s.errors.is_empty(), "{:?}", s.errors);
+        let t = &s.transactions[0];
+        assert_eq!(t.amount, -12.5);
+        assert_eq!(t.description, "Vermieter GmbH");
+        assert_eq!(t.memo, "Miete Dez");
+        assert_eq!(t.reference, "B-1");
+        assert_eq!(
+            t.counterparty_iban.as_deref(),
+            Some("DE02370400440532013001")
+        );
+        let t = &s.transactions[1];
+        // Booked on Jan 1st, the day before its value date
+        assert_eq!(t.date, "2024-01-01");
+        assert_eq!(t.value_date.as_deref(), Some("2024-01-02"));
+        assert_eq!(t.amount, 850.0);
+        assert_eq!(t.description, "ACME BV");
+        assert_eq!(t.memo, "Salary/Jan");
+        assert_eq!(t.reference, "REF2");
+        assert_eq!(t.counterparty_iban.as_deref(), Some("NL91ABNA0417164300"));
+        assert_eq!(s.transactions[2].reference, "");
+
+        // Value date Dec 29th, booked on Jan 2nd of the next year
+        let crossing = MT940.replace(":61:2401020101C850", ":61:2312290102C850");
+        let t = &parse_mt940(&crossing, None).unwrap()[0].transactions[1];
+        assert_eq!(t.date, "2024-01-02");
+        assert_eq!(t.value_date.as_deref(), Some("2023-12-29"));
+
+        let broken = MT940.replace(":62F:C240103EUR1836,50", ":62F:C240103EUR1800,00");
+        let s = &parse_mt940(&broken, None).unwrap()[0];
+        assert_eq!(s.errors.len(), 1);
+        assert!(parse_mt940("hello", None).is_err());
+    }
+}
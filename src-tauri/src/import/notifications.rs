@@ -0,0 +1,613 @@
+//! Purchase notifications sent by banks by SMS or email.
+//!
+//! Mexican banks send a short text for every card charge, such as "Compra por
+//! $1,234.56 en OXXO con tarjeta terminación 1234". Templates are regular
+//! expressions with named groups: `amount` (required), `merchant`, `card`
+//! (last digits of the card), `date` and `reference`. Templates the user
+//! writes are tried before the built-in ones and live in a sidecar file in
+//! the budget folder. Pasted text can hold several notifications separated
+//! by blank lines.
+
+use chrono::{Datelike, NaiveDate};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::formats::{self, DateFormat, NumberFormat};
+use super::transaction::{self, ImportTransaction};
+use crate::ynab4::budget::new_entity_id;
+use crate::ynab4::entities::BudgetData;
+use crate::ynab4::local;
+
+/// Sidecar with the user's notification templates, next to `Budget.ymeta`
+pub const TEMPLATES_SIDECAR_FILE: &str = "notificationTemplates.ynab4client.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NotificationTemplate {
+    pub id: String,
+    pub name: String,
+    /// Regex, compared without case; see the module docs for the groups
+    pub pattern: String,
+    /// Money coming in (deposits, refunds); charges otherwise
+    pub inflow: bool,
+    /// How `date` is written; day first is assumed otherwise
+    pub date_format: Option<DateFormat>,
+    /// Account for templates of a single account, when the text has no card
+    pub account_id: Option<String>,
+    /// Shipped with the app; cannot be edited or deleted
+    #[serde(skip_deserializing)]
+    pub built_in: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplatesSidecar {
+    #[serde(default)]
+    pub templates: Vec<NotificationTemplate>,
+}
+
+/// A notification that a template recognised
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationMatch {
+    pub template_id: String,
+    pub template_name: String,
+    /// Last digits of the card, as written in the text
+    pub card: Option<String>,
+    pub transaction: ImportTransaction,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationParse {
+    pub matches: Vec<NotificationMatch>,
+    /// Pieces of text no template recognised
+    pub unmatched: Vec<String>,
+}
+
+// Pieces shared by the built-in patterns
+macro_rules! amount {
+    () => {
+        r"(?P<amount>\$?\s?[\d,]+(?:\.\d{1,2})?)\s*(?:MXN|M\.?N\.?)?"
+    };
+}
+macro_rules! card {
+    () => {
+        r"tarjeta\s+(?:con\s+)?(?:terminaci[oó]n\s+|\*+|no\.?\s*)?(?P<card>\d{4})"
+    };
+}
+macro_rules! date {
+    () => {
+        r"(?P<date>\d{1,2}[/-](?:\d{1,2}|[a-z]{3})(?:[/-]\d{2,4})?)"
+    };
+}
+
+/// (id, name, pattern, inflow)
+const BUILT_IN: [(&str, &str, &str, bool); 8] = [
+    (
+        "builtin-bbva",
+        "BBVA",
+        concat!(
+            r"BBVA.*?(?:compra|cargo)\s+(?:aprobad[ao]\s+)?por\s+",
+            amount!(),
+            r"\s+en\s+(?P<merchant>.+?)\s+con\s+(?:tu\s+)?",
+            card!(),
+            r"(?:.*?\b",
+            date!(),
+            ")?"
+        ),
+        false,
+    ),
+    (
+        "builtin-santander",
+        "Santander",
+        concat!(
+            r"Santander.*?(?:cargo|compra)\s+por\s+",
+            amount!(),
+            r"\s+en\s+(?P<merchant>.+?)\s+con\s+(?:tu\s+)?",
+            card!(),
+            r"(?:.*?\b",
+            date!(),
+            ")?"
+        ),
+        false,
+    ),
+    (
+        "builtin-banorte",
+        "Banorte",
+        concat!(
+            r"Banorte.*?compra\s+con\s+(?:tu\s+)?",
+            card!(),
+            r"\s+por\s+",
+            amount!(),
+            r"\s+en\s+(?P<merchant>.+?)(?:\s+el\s+",
+            date!(),
+            r"|[.\n]|$)"
+        ),
+        false,
+    ),
+    (
+        "builtin-banamex",
+        "Citibanamex",
+        concat!(
+            r"(?:Citi)?banamex.*?",
+            card!(),
+            r".*?compra\s+por\s+",
+            amount!(),
+            r"\s+en\s+(?P<merchant>.+?)(?:\s+el\s+",
+            date!(),
+            r"|[.\n]|$)"
+        ),
+        false,
+    ),
+    (
+        "builtin-hsbc",
+        "HSBC",
+        concat!(
+            r"HSBC.*?(?:cargo|compra)\s+por\s+",
+            amount!(),
+            r"\s+en\s+(?P<merchant>.+?)\s*[,.]?\s+",
+            card!()
+        ),
+        false,
+    ),
+    (
+        "builtin-nu",
+        "Nu",
+        concat!(
+            r"\bNu\b.*?compraste\s+",
+            amount!(),
+            r"\s+en\s+(?P<merchant>.+?)\s+con\s+(?:tu\s+)?",
+            card!()
+        ),
+        false,
+    ),
+    (
+        "builtin-spei",
+        "Transferencia SPEI recibida",
+        concat!(
+            r"(?:recibiste|abono|dep[oó]sito).*?(?:SPEI|transferencia).*?por\s+",
+            amount!(),
+            r"(?:\s+de\s+(?P<merchant>.+?))?",
+            r"(?:\s+(?:clave\s+de\s+rastreo|ref(?:erencia)?)[:\s]+(?P<reference>\w+))?(?:[.\n]|$)"
+        ),
+        true,
+    ),
+    (
+        "builtin-generic",
+        "Compra con tarjeta",
+        concat!(
+            r"(?:compra|cargo)\s+(?:aprobad[ao]\s+)?(?:de|por)\s+",
+            amount!(),
+            r"\s+en\s+(?P<merchant>.+?)\s+con\s+(?:tu\s+)?",
+            card!(),
+            r"(?:.*?\b",
+            date!(),
+            ")?"
+        ),
+        false,
+    ),
+];
+
+/// Templates that come with the app, tried after the user's
+pub fn built_in_templates() -> Vec<NotificationTemplate> {
+    BUILT_IN
+        .iter()
+        .map(|(id, name, pattern, inflow)| NotificationTemplate {
+            id: id.to_string(),
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+            inflow: *inflow,
+            built_in: true,
+            ..Default::default()
+        })
+        .collect()
+}
+
+pub fn load_sidecar(budget_path: &Path) -> Result<TemplatesSidecar, String> {
+    local::read_or_default(&budget_path.join(TEMPLATES_SIDECAR_FILE))
+}
+
+/// The user's templates followed by the built-in ones
+pub fn all_templates(budget_path: Option<&Path>) -> Result<Vec<NotificationTemplate>, String> {
+    let mut templates = match budget_path {
+        Some(path) => load_sidecar(path)?.templates,
+        None => Vec::new(),
+    };
+    templates.extend(built_in_templates());
+    Ok(templates)
+}
+
+impl NotificationTemplate {
+    fn compile(&self) -> Result<Regex, String> {
+        let regex = RegexBuilder::new(&self.pattern)
+            .case_insensitive(true)
+            .dot_matches_new_line(true)
+            .build()
+            .map_err(|e| {
+                format!(
+                    "Invalid regular expression in template {}: {}",
+                    self.name, e
+                )
+            })?;
+        if !regex.capture_names().any(|name| name == Some("amount")) {
+            return Err(format!(
+                "Template {} needs an (?P<amount>...) group",
+                self.name
+            ));
+        }
+        Ok(regex)
+    }
+
+    /// Row for a text this template recognises
+    fn read(
+        &self,
+        regex: &Regex,
+        text: &str,
+        data: Option<&BudgetData>,
+        today: NaiveDate,
+    ) -> Option<(ImportTransaction, Option<String>)> {
+        let caps = regex.captures(text)?;
+        let group = |name: &str| {
+            caps.name(name)
+                .map(|m| m.as_str().trim())
+                .filter(|s| !s.is_empty())
+        };
+        let amount = read_amount(group("amount")?)?;
+        let amount = if self.inflow {
+            amount.abs()
+        } else {
+            -amount.abs()
+        };
+        let merchant = group("merchant").map(clean_merchant).unwrap_or_default();
+        let date = group("date")
+            .and_then(|d| read_date(d, self.date_format, today))
+            .unwrap_or(today);
+        let card = group("card").map(str::to_string);
+
+        let mut row = ImportTransaction::new(
+            transaction::batch_id(),
+            0,
+            date.format("%Y-%m-%d").to_string(),
+            merchant.clone(),
+            amount,
+        );
+        row.payee_name = merchant;
+        row.original_memo = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        row.reference = group("reference").unwrap_or_default().to_string();
+        let account_id = card
+            .as_deref()
+            .and_then(|card| data.and_then(|d| transaction::match_account(d, card)))
+            .or_else(|| self.account_id.clone());
+        if let Some(account) =
+            account_id.and_then(|id| data?.account(&id).filter(|a| !a.is_tombstone))
+        {
+            row.account_id = Some(account.entity_id.clone());
+            row.account_name = account.account_name.clone();
+        }
+        Some((row, card))
+    }
+}
+
+/// Amounts in notifications use a dot for decimals: "$1,234.56", "350 MXN"
+fn read_amount(text: &str) -> Option<f64> {
+    let digits: String = text
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '.' | ','))
+        .collect();
+    formats::parse_amount(&digits, &NumberFormat::default())
+}
+
+/// Merchant without the padding and trailing punctuation of the message
+fn clean_merchant(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(['.', ',', ';', ':'])
+        .to_string()
+}
+
+/// Date of a notification; a date without a year is the latest one not after today
+fn read_date(text: &str, format: Option<DateFormat>, today: NaiveDate) -> Option<NaiveDate> {
+    let text = text.to_lowercase().replace(" de ", " ");
+    let parse = |text: &str| match format {
+        Some(format) => formats::parse_date(text, format),
+        None => [DateFormat::Dmy, DateFormat::DayMonthName, DateFormat::Ymd]
+            .into_iter()
+            .find_map(|f| formats::parse_date(text, f)),
+    };
+    if let Some(date) = parse(&text) {
+        return Some(date);
+    }
+    let sep = text.chars().find(|c| matches!(c, '/' | '-' | ' '))?;
+    let date = parse(&format!("{}{}{}", text, sep, today.year()))?;
+    if date > today {
+        parse(&format!("{}{}{}", text, sep, today.year() - 1))
+    } else {
+        Some(date)
+    }
+}
+
+/// Pieces of pasted text: paragraphs, or single lines when a paragraph has
+/// several notifications one per line
+fn pieces(text: &str) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = Vec::new();
+    for line in text.lines().chain(std::iter::once("")) {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                pieces.push(current.join("\n"));
+                current.clear();
+            }
+        } else {
+            current.push(line.trim());
+        }
+    }
+    pieces
+}
+
+/// Read every notification in `text` with the first template that fits it
+pub fn parse_notifications(
+    text: &str,
+    templates: &[NotificationTemplate],
+    data: Option<&BudgetData>,
+    today: NaiveDate,
+) -> Result<NotificationParse, String> {
+    let compiled: Vec<(&NotificationTemplate, Regex)> = templates
+        .iter()
+        .map(|t| t.compile().map(|regex| (t, regex)))
+        .collect::<Result<_, _>>()?;
+    let read = |piece: &str| {
+        compiled.iter().find_map(|(template, regex)| {
+            let (transaction, card) = template.read(regex, piece, data, today)?;
+            Some(NotificationMatch {
+                template_id: template.id.clone(),
+                template_name: template.name.clone(),
+                card,
+                transaction,
+            })
+        })
+    };
+
+    let mut result = NotificationParse::default();
+    for piece in pieces(text) {
+        let lines: Vec<&str> = piece.lines().collect();
+        let per_line: Vec<Option<NotificationMatch>> =
+            lines.iter().map(|line| read(line)).collect();
+        if lines.len() > 1 && per_line.iter().filter(|m| m.is_some()).count() > 1 {
+            for (line, found) in lines.iter().zip(per_line) {
+                match found {
+                    Some(found) => result.matches.push(found),
+                    None => result.unmatched.push(line.to_string()),
+                }
+            }
+            continue;
+        }
+        match read(&piece) {
+            Some(found) => result.matches.push(found),
+            None => result.unmatched.push(piece),
+        }
+    }
+    let batch = transaction::batch_id();
+    for (index, found) in result.matches.iter_mut().enumerate() {
+        found.transaction.id = format!("import-{}-{}", batch, index);
+    }
+    Ok(result)
+}
+
+/// Add or replace one of the user's templates. Returns the saved template.
+pub fn save_template(
+    budget_path: &Path,
+    mut template: NotificationTemplate,
+) -> Result<NotificationTemplate, String> {
+    if template.name.trim().is_empty() {
+        return Err("The notification template needs a name".to_string());
+    }
+    template.compile()?;
+    if template.id.is_empty() || template.id.starts_with("builtin-") {
+        template.id = new_entity_id();
+    }
+    template.built_in = false;
+    let mut sidecar = load_sidecar(budget_path)?;
+    match sidecar.templates.iter_mut().find(|t| t.id == template.id) {
+        Some(existing) => *existing = template.clone(),
+        None => sidecar.templates.push(template.clone()),
+    }
+    local::write_json(&budget_path.join(TEMPLATES_SIDECAR_FILE), &sidecar)?;
+    Ok(template)
+}
+
+pub fn delete_template(budget_path: &Path, template_id: &str) -> Result<(), String> {
+    let mut sidecar = load_sidecar(budget_path)?;
+    let before = sidecar.templates.len();
+    sidecar.templates.retain(|t| t.id != template_id);
+    if sidecar.templates.len() == before {
+        return Err(format!("Notification template not found: {}", template_id));
+    }
+    local::write_json(&budget_path.join(TEMPLATES_SIDECAR_FILE), &sidecar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::budget::Budget;
+    use crate::ynab4::fixtures;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 20).unwrap()
+    }
+
+    #[test]
+    fn each_built_in_template_reads_its_bank() {
+        // (text, template, amount, merchant, card, date)
+        let samples = [
+            (
+                "BBVA: Compra aprobada por $1,234.56 en OXXO SUC CENTRO con tu tarjeta terminación 1234 el 15/03/2024.",
+                "builtin-bbva",
+                -1234.56,
+                "OXXO SUC CENTRO",
+                Some("1234"),
+                "2024-03-15",
+            ),
+            (
+                "Santander: Cargo por $89.90 en NETFLIX con tarjeta terminación 5678 el 10/03/2024",
+                "builtin-santander",
+                -89.9,
+                "NETFLIX",
+                Some("5678"),
+                "2024-03-10",
+            ),
+            (
+                "Banorte: Compra con tarjeta 9876 por $350.00 MXN en WALMART el 01/02/24",
+                "builtin-banorte",
+                -350.0,
+                "WALMART",
+                Some("9876"),
+                "2024-02-01",
+            ),
+            (
+                "Citibanamex: Tu tarjeta *4321 registró una compra por $75.00 en STARBUCKS el 18/03/2024.",
+                "builtin-banamex",
+                -75.0,
+                "STARBUCKS",
+                Some("4321"),
+                "2024-03-18",
+            ),
+            (
+                "HSBC: Cargo por $200.00 en LIVERPOOL, tarjeta terminación 1111",
+                "builtin-hsbc",
+                -200.0,
+                "LIVERPOOL",
+                Some("1111"),
+                "2024-03-20",
+            ),
+            (
+                "Nu: Compraste $120.50 en UBER EATS con tu tarjeta ****1234",
+                "builtin-nu",
+                -120.5,
+                "UBER EATS",
+                Some("1234"),
+                "2024-03-20",
+            ),
+            (
+                "Recibiste una transferencia SPEI por $5,000.00 de JUAN PEREZ clave de rastreo ABC123",
+                "builtin-spei",
+                5000.0,
+                "JUAN PEREZ",
+                None,
+                "2024-03-20",
+            ),
+            (
+                "Compra por $1,234.56 en OXXO con tarjeta terminación 1234",
+                "builtin-generic",
+                -1234.56,
+                "OXXO",
+                Some("1234"),
+                "2024-03-20",
+            ),
+        ];
+        assert_eq!(samples.len(), BUILT_IN.len());
+        let templates = built_in_templates();
+        for (text, template_id, amount, merchant, card, date) in samples {
+            let parsed = parse_notifications(text, &templates, None, today()).unwrap();
+            assert_eq!(parsed.matches.len(), 1, "{}", text);
+            let found = &parsed.matches[0];
+            assert_eq!(found.template_id, template_id, "{}", text);
+            assert_eq!(found.card.as_deref(), card, "{}", text);
+            let row = &found.transaction;
+            assert_eq!(
+                (row.amount, row.payee_name.as_str(), row.date.as_str()),
+                (amount, merchant, date),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn pasted_notifications_are_split_and_matched_to_cards() {
+        let path = fixtures::budget_folder("notifications-cards");
+        let mut budget = Budget::load(&path).unwrap();
+        let card = budget
+            .data
+            .accounts
+            .iter_mut()
+            .find(|a| a.entity_id == "ACC-CC")
+            .unwrap();
+        card.note = Some("Tarjeta 1234".to_string());
+        let text = "BBVA: Compra aprobada por $1,234.56 en OXXO con tu tarjeta terminación 1234 el 15/03/2024.\n\
+            Banorte: Compra con tarjeta 9876 por $350.00 MXN en WALMART el 01/02/24\n\n\
+            Hola, tu estado de cuenta está listo";
+        let parsed =
+            parse_notifications(text, &built_in_templates(), Some(&budget.data), today()).unwrap();
+        assert_eq!(parsed.matches.len(), 2);
+        assert_eq!(
+            parsed.unmatched,
+            vec!["Hola, tu estado de cuenta está listo"]
+        );
+        assert_eq!(
+            parsed.matches[0].transaction.account_id.as_deref(),
+            Some("ACC-CC")
+        );
+        assert!(
+            parsed.matches[1].transaction.account_id.is_none(),
+            "no account ends in 9876"
+        );
+        assert_ne!(
+            parsed.matches[0].transaction.id,
+            parsed.matches[1].transaction.id
+        );
+    }
+
+    #[test]
+    fn dates_without_a_year_are_never_in_the_future() {
+        let date = |text: &str| read_date(text, None, today()).map(|d| d.to_string());
+        assert_eq!(date("15/03").as_deref(), Some("2024-03-15"));
+        assert_eq!(date("20/03").as_deref(), Some("2024-03-20"));
+        // December 25 has not come yet this year
+        assert_eq!(date("25/12").as_deref(), Some("2023-12-25"));
+        assert_eq!(date("01/02/24").as_deref(), Some("2024-02-01"));
+        assert_eq!(date("junk"), None);
+    }
+
+    #[test]
+    fn user_templates_come_first() {
+        let path = fixtures::budget_folder("notifications-user");
+        let budget = Budget::load(&path).unwrap();
+        let template = NotificationTemplate {
+            name: "Mi banco".to_string(),
+            pattern:
+                r"Cargo de (?P<amount>[\d,.]+) en (?P<merchant>.+?) el (?P<date>\d{1,2}/\d{1,2})$"
+                    .to_string(),
+            account_id: Some("ACC-CHK".to_string()),
+            ..Default::default()
+        };
+        let saved = save_template(&path, template).unwrap();
+        let templates = all_templates(Some(&path)).unwrap();
+        assert_eq!(templates[0].id, saved.id);
+        assert!(templates[1..].iter().all(|t| t.built_in));
+        let text = "Cargo de 89.90 en Cinepolis el 25/12";
+        let parsed = parse_notifications(text, &templates, Some(&budget.data), today()).unwrap();
+        assert_eq!(parsed.matches[0].template_id, saved.id);
+        assert_eq!(
+            parsed.matches[0].transaction.account_id.as_deref(),
+            Some("ACC-CHK")
+        );
+
+        let without_amount = NotificationTemplate {
+            name: "Sin monto".to_string(),
+            pattern: r"Cargo en (?P<merchant>.+)".to_string(),
+            ..Default::default()
+        };
+        assert!(save_template(&path, without_amount)
+            .unwrap_err()
+            .contains("amount"));
+        delete_template(&path, &saved.id).unwrap();
+        assert!(delete_template(&path, &saved.id).is_err());
+        assert!(all_templates(Some(&path))
+            .unwrap()
+            .iter()
+            .all(|t| t.built_in));
+    }
+}
@@ -0,0 +1,237 @@
+//! OFX/QFX statements.
+//!
+//! OFX 1.x is SGML and OFX 2.x is plain XML; `markup` reads both, so one code
+//! path handles either version.
+
+use serde::Serialize;
+
+use super::markup::{self, Element};
+use super::transaction::{self, BankStatement, ImportTransaction, StatementBalance};
+use crate::ynab4::entities::BudgetData;
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OfxAccount {
+    pub bank_id: Option<String>,
+    pub account_id: String,
+    /// CHECKING, SAVINGS, CREDITLINE... or CREDITCARD for card statements
+    pub account_type: String,
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OfxStatement {
+    pub account: OfxAccount,
+    /// Budget account that looks like this one, when a budget was given
+    pub matched_account_id: Option<String>,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    /// Balance to reconcile against
+    pub ledger_balance: Option<StatementBalance>,
+    pub available_balance: Option<StatementBalance>,
+    pub transactions: Vec<ImportTransaction>,
+    /// Transactions that could not be read
+    pub errors: Vec<String>,
+}
+
+/// YYYY-MM-DD from an OFX date such as 20240115120000.000[-6:CST]
+fn ofx_date(value: &str) -> Option<String> {
+    let digits = value.get(..8)?;
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let date = format!("{}-{}-{}", &digits[..4], &digits[4..6], &digits[6..8]);
+    chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok()?;
+    Some(date)
+}
+
+/// OFX amounts use `.`, but some banks send `,` as the decimal separator
+fn ofx_amount(value: &str) -> Option<f64> {
+    value.trim().replace(',', ".").replace('+', "").parse().ok()
+}
+
+fn balance(element: Option<&Element>) -> Option<StatementBalance> {
+    let element = element?;
+    Some(StatementBalance {
+        amount: ofx_amount(&element.text_of("BALAMT")?)?,
+        date: element.text_of("DTASOF").and_then(|d| ofx_date(&d)),
+    })
+}
+
+fn read_transaction(trn: &Element, batch: i64, index: usize) -> Result<ImportTransaction, String> {
+    let fitid = trn.text_of("FITID").unwrap_or_default();
+    let label = if fitid.is_empty() {
+        format!("Transaction {}", index + 1)
+    } else {
+        format!("Transaction {}", fitid)
+    };
+    let posted = trn
+        .text_of("DTPOSTED")
+        .ok_or_else(|| format!("{}: missing DTPOSTED", label))?;
+    let date = ofx_date(&posted).ok_or_else(|| format!("{}: invalid date '{}'", label, posted))?;
+    let raw_amount = trn
+        .text_of("TRNAMT")
+        .ok_or_else(|| format!("{}: missing TRNAMT", label))?;
+    let amount = ofx_amount(&raw_amount)
+        .ok_or_else(|| format!("{}: invalid amount '{}'", label, raw_amount))?;
+
+    let name = trn
+        .text_of("NAME")
+        .or_else(|| trn.child("PAYEE").and_then(|p| p.text_of("NAME")));
+    let memo = trn.text_of("MEMO").unwrap_or_default();
+    let description = name.unwrap_or_else(|| memo.clone());
+
+    let mut row = ImportTransaction::new(batch, index, date, description, amount);
+    row.original_memo = memo.clone();
+    row.memo = memo;
+    row.reference = fitid;
+    row.check_number = trn.text_of("CHECKNUM");
+    row.value_date = trn.text_of("DTAVAIL").and_then(|d| ofx_date(&d));
+    Ok(row)
+}
+
+/// Parse every bank and credit card statement in an OFX file.
+/// With budget data, each statement is matched to a budget account by the
+/// last digits of its account number.
+pub fn parse_ofx(text: &str, data: Option<&BudgetData>) -> Result<Vec<OfxStatement>, String> {
+    let start = text
+        .to_ascii_uppercase()
+        .find("<OFX>")
+        .ok_or_else(|| "Not an OFX file: no <OFX> element".to_string())?;
+    let root = markup::parse(&text[start..]);
+
+    let mut responses = Vec::new();
+    root.descendants("STMTRS", &mut responses);
+    root.descendants("CCSTMTRS", &mut responses);
+    if responses.is_empty() {
+        return Err("The OFX file has no bank or credit card statements".to_string());
+    }
+
+    let batch = transaction::batch_id();
+    let mut index = 0;
+    let mut statements = Vec::new();
+    for response in responses {
+        let mut statement = OfxStatement::default();
+        if let Some(from) = response.child("BANKACCTFROM") {
+            statement.account = OfxAccount {
+                bank_id: from.text_of("BANKID"),
+                account_id: from.text_of("ACCTID").unwrap_or_default(),
+                account_type: from.text_of("ACCTTYPE").unwrap_or_default(),
+                currency: None,
+            };
+        } else if let Some(from) = response.child("CCACCTFROM") {
+            statement.account.account_id = from.text_of("ACCTID").unwrap_or_default();
+            statement.account.account_type = "CREDITCARD".to_string();
+        }
+        statement.account.currency = response.text_of("CURDEF");
+        statement.ledger_balance = balance(response.child("LEDGERBAL"));
+        statement.available_balance = balance(response.child("AVAILBAL"));
+
+        // The rows go through a BankStatement to be matched to a budget account
+        let mut rows = BankStatement {
+            account: statement.account.account_id.clone(),
+            ..Default::default()
+        };
+        if let Some(list) = response.child("BANKTRANLIST") {
+            statement.start_date = list.text_of("DTSTART").and_then(|d| ofx_date(&d));
+            statement.end_date = list.text_of("DTEND").and_then(|d| ofx_date(&d));
+            for trn in list.children_named("STMTTRN") {
+                match read_transaction(trn, batch, index) {
+                    Ok(row) => rows.transactions.push(row),
+                    Err(e) => statement.errors.push(e),
+                }
+                index += 1;
+            }
+        }
+        rows.assign_account(data);
+        statement.matched_account_id = rows.matched_account_id;
+        statement.transactions = rows.transactions;
+        statements.push(statement);
+    }
+    Ok(statements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::budget::Budget;
+    use crate::ynab4::fixtures;
+
+    const SGML: &str = "OFXHEADER:100\r\nDATA:OFXSGML\r\nVERSION:102\r\nCHARSET:1252\r\n\r\n<OFX>\r\n<SIGNONMSGSRSV1><SONRS><STATUS><CODE>0<SEVERITY>INFO</STATUS><DTSERVER>20240131</SONRS></SIGNONMSGSRSV1>\r\n<BANKMSGSRSV1><STMTTRNRS><TRNUID>1<STMTRS><CURDEF>MXN\r\n<BANKACCTFROM><BANKID>012<ACCTID>001234567890<ACCTTYPE>CHECKING</BANKACCTFROM>\r\n<BANKTRANLIST><DTSTART>20240101<DTEND>20240131\r\n<STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20240115120000.000[-6:CST]<TRNAMT>-1234.50<FITID>F1<CHECKNUM>101<NAME>OXXO &amp; CO<MEMO>Compra tarjeta</STMTTRN>\r\n<STMTTRN><TRNTYPE>CREDIT<DTPOSTED>20240120<TRNAMT>5000,00<FITID>F2<MEMO>Nomina</STMTTRN>\r\n<STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20240125<MEMO>\r\n<NAME>OXXO<TRNAMT>-45.00<FITID>F4</STMTTRN>\r\n<STMTTRN><TRNTYPE>DEBIT<DTPOSTED>2024xx<TRNAMT>1<FITID>F3</STMTTRN>\r\n</BANKTRANLIST>\r\n<LEDGERBAL><BALAMT>10500.25<DTASOF>20240131</LEDGERBAL><AVAILBAL><BALAMT>10000<DTASOF>20240131</AVAILBAL>\r\n</STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>\r\n";
+
+    const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<?OFX OFXHEADER="200" VERSION="220"?>
+<OFX><CREDITCARDMSGSRSV1><CCSTMTTRNRS><CCSTMTRS>
+<CURDEF>USD</CURDEF>
+<CCACCTFROM><ACCTID>4111111111114321</ACCTID></CCACCTFROM>
+<BANKTRANLIST>
+<STMTTRN><TRNTYPE>DEBIT</TRNTYPE><DTPOSTED>20240203</DTPOSTED><TRNAMT>-20.00</TRNAMT><FITID>X1</FITID><PAYEE><NAME>Coffee</NAME></PAYEE><MEMO/></STMTTRN>
+</BANKTRANLIST>
+<LEDGERBAL><BALAMT>-20.00</BALAMT><DTASOF>20240229</DTASOF></LEDGERBAL>
+</CCSTMTRS></CCSTMTTRNRS></CREDITCARDMSGSRSV1></OFX>"#;
+
+    #[test]
+    fn reads_an_ofx_1_statement() {
+        let statements = parse_ofx(SGML, None).unwrap();
+        assert_eq!(statements.len(), 1);
+        let s = &statements[0];
+        assert_eq!(s.account.account_id, "001234567890");
+        assert_eq!(s.account.bank_id.as_deref(), Some("012"));
+        assert_eq!(s.account.currency.as_deref(), Some("MXN"));
+        assert_eq!(s.ledger_balance.as_ref().unwrap().amount, 10500.25);
+        assert_eq!(s.available_balance.as_ref().unwrap().amount, 10000.0);
+        assert_eq!(s.start_date.as_deref(), Some("2024-01-01"));
+        assert_eq!(s.transactions.len(), 3);
+        let t = &s.transactions[0];
+        assert_eq!(t.date, "2024-01-15");
+        assert_eq!(t.amount, -1234.5);
+        assert_eq!(t.outflow, 1234.5);
+        assert_eq!(t.description, "OXXO & CO");
+        assert_eq!(t.original_memo, "Compra tarjeta");
+        assert_eq!(t.memo, "Compra tarjeta");
+        assert_eq!(t.reference, "F1");
+        assert_eq!(t.check_number.as_deref(), Some("101"));
+        assert_eq!(t.account_name, "001234567890");
+        let t = &s.transactions[1];
+        assert_eq!(t.amount, 5000.0);
+        assert_eq!(t.description, "Nomina");
+        let json = serde_json::to_value(t).unwrap();
+        assert_eq!(json["isMSI"], false);
+        // An empty MEMO does not swallow the NAME and TRNAMT after it
+        let t = &s.transactions[2];
+        assert_eq!(
+            (t.description.as_str(), t.amount, t.original_memo.as_str()),
+            ("OXXO", -45.0, "")
+        );
+        assert_eq!(t.status, "pending");
+        assert_eq!(s.errors.len(), 1);
+        assert!(s.errors[0].contains("F3"));
+    }
+
+    #[test]
+    fn reads_an_ofx_2_card_statement() {
+        let mut budget = Budget::load(&fixtures::budget_folder("ofx-card")).unwrap();
+        let idx = budget
+            .data
+            .accounts
+            .iter()
+            .position(|a| a.entity_id == "ACC-CC")
+            .unwrap();
+        budget.data.accounts[idx].account_name = "Visa 4321".to_string();
+        let cc = "ACC-CC".to_string();
+        let statements = parse_ofx(XML, Some(&budget.data)).unwrap();
+        let s = &statements[0];
+        assert_eq!(s.account.account_type, "CREDITCARD");
+        assert_eq!(s.matched_account_id.as_deref(), Some(cc.as_str()));
+        assert_eq!(s.transactions[0].account_id.as_deref(), Some(cc.as_str()));
+        assert_eq!(s.transactions[0].account_name, "Visa 4321");
+        assert_eq!(s.transactions[0].description, "Coffee");
+        assert_eq!(s.transactions[0].original_memo, "");
+        assert_eq!(
+            s.ledger_balance.as_ref().unwrap().date.as_deref(),
+            Some("2024-02-29")
+        );
+        assert!(parse_ofx("hello", None).is_err());
+    }
+}
@@ -0,0 +1,421 @@
+//! Saved import profiles for bank files.
+//!
+//! A profile remembers how one bank's export is read: which column holds
+//! what, the date and amount formats, whether charges come as positive
+//! numbers, the account the rows go to, the title rows above the header and a
+//! pattern that cleans the payee out of the bank's description. A profile is
+//! found again by the fingerprint of the file's header row, by a file name
+//! pattern such as `movimientos_*.csv`, or both. Profiles live in a sidecar
+//! file in the budget folder so every device that opens the budget has them.
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+use super::formats::{DateFormat, NumberFormat};
+use super::transaction::ImportTransaction;
+use crate::ynab4::budget::new_entity_id;
+use crate::ynab4::entities::BudgetData;
+use crate::ynab4::local;
+
+/// Sidecar with the import profiles, next to `Budget.ymeta`
+pub const PROFILES_SIDECAR_FILE: &str = "importProfiles.ynab4client.json";
+
+/// Column of each field, as in the frontend's `ColumnMapping`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnMapping {
+    pub date: usize,
+    pub description: usize,
+    /// None when the file has separate debit and credit columns
+    pub amount: Option<usize>,
+    pub debit: Option<usize>,
+    pub credit: Option<usize>,
+    pub balance: Option<usize>,
+    pub memo: Option<usize>,
+    pub original_memo: Option<usize>,
+    pub reference: Option<usize>,
+    pub payee: Option<usize>,
+    pub suggested_payee: Option<usize>,
+    pub category: Option<usize>,
+    pub suggested_category: Option<usize>,
+    pub flag: Option<usize>,
+    pub account: Option<usize>,
+    pub installments: Option<usize>,
+    pub num_installments: Option<usize>,
+}
+
+/// How the file signs its amounts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SignConvention {
+    /// Outflows are negative, like in the budget
+    #[default]
+    AsIs,
+    /// Charges are positive and payments negative, as on most card statements
+    Negate,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ImportProfile {
+    pub id: String,
+    pub name: String,
+    /// Hash of the header row; see `header_fingerprint`
+    pub header_fingerprint: Option<String>,
+    /// File name pattern with `*` and `?`, compared without case
+    pub filename_pattern: Option<String>,
+    pub mapping: ColumnMapping,
+    /// Overrides the detected date format
+    pub date_format: Option<DateFormat>,
+    /// Overrides the detected amount format
+    pub number_format: Option<NumberFormat>,
+    pub sign: SignConvention,
+    pub account_id: Option<String>,
+    /// Rows above the header to drop (titles, account details)
+    pub skip_rows: usize,
+    /// Regex applied to the payee: its `payee` or first group is kept when it
+    /// has one, otherwise whatever it matches is removed
+    pub payee_cleanup: Option<String>,
+    /// When the profile was last applied (RFC 3339)
+    pub last_used: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfilesSidecar {
+    #[serde(default)]
+    pub profiles: Vec<ImportProfile>,
+}
+
+pub fn load_sidecar(budget_path: &Path) -> Result<ProfilesSidecar, String> {
+    local::read_or_default(&budget_path.join(PROFILES_SIDECAR_FILE))
+}
+
+/// Fingerprint of a header row: case, spacing and empty trailing columns
+/// don't change it
+pub fn header_fingerprint(headers: &[String]) -> String {
+    let mut cells: Vec<String> = headers
+        .iter()
+        .map(|h| {
+            h.split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase()
+        })
+        .collect();
+    while cells.last().is_some_and(|c| c.is_empty()) {
+        cells.pop();
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(cells.join("\u{1f}").as_bytes());
+    hex::encode(hasher.finalize())[..16].to_string()
+}
+
+fn compile_filename_pattern(pattern: &str) -> Result<Regex, String> {
+    let mut source = String::from("^");
+    for c in pattern.trim().chars() {
+        match c {
+            '*' => source.push_str(".*"),
+            '?' => source.push('.'),
+            c => source.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    source.push('$');
+    RegexBuilder::new(&source)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| format!("Invalid file name pattern {}: {}", pattern, e))
+}
+
+fn compile_cleanup(pattern: &str) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| format!("Invalid regular expression {}: {}", pattern, e))
+}
+
+/// File name without its folder
+fn base_name(file_name: &str) -> &str {
+    file_name.rsplit(['/', '\\']).next().unwrap_or(file_name)
+}
+
+impl ImportProfile {
+    fn check(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("The import profile needs a name".to_string());
+        }
+        if self.header_fingerprint.is_none() && self.filename_pattern.is_none() {
+            return Err(format!(
+                "Import profile {} has neither a header nor a file name pattern",
+                self.name
+            ));
+        }
+        if let Some(pattern) = &self.filename_pattern {
+            compile_filename_pattern(pattern)?;
+        }
+        if let Some(pattern) = &self.payee_cleanup {
+            compile_cleanup(pattern)?;
+        }
+        Ok(())
+    }
+
+    /// How many of the profile's keys the file matches; None when any of them
+    /// does not match or the profile has no keys
+    fn match_keys(&self, file_name: &str, fingerprint: Option<&str>) -> Option<usize> {
+        let mut matched = 0;
+        if let Some(expected) = &self.header_fingerprint {
+            if fingerprint != Some(expected.as_str()) {
+                return None;
+            }
+            matched += 1;
+        }
+        if let Some(pattern) = &self.filename_pattern {
+            let regex = compile_filename_pattern(pattern).ok()?;
+            if !regex.is_match(base_name(file_name)) {
+                return None;
+            }
+            matched += 1;
+        }
+        (matched > 0).then_some(matched)
+    }
+
+    /// Payee text after the cleanup pattern
+    fn clean_payee(regex: &Regex, text: &str) -> String {
+        let kept = match regex.captures(text) {
+            Some(caps) if caps.len() > 1 => caps
+                .name("payee")
+                .or_else(|| caps.get(1))
+                .map_or(text.to_string(), |m| m.as_str().to_string()),
+            _ => regex.replace_all(text, "").into_owned(),
+        };
+        let cleaned = kept.split_whitespace().collect::<Vec<_>>().join(" ");
+        // A pattern that eats the whole name was not meant for this row
+        if cleaned.is_empty() {
+            text.to_string()
+        } else {
+            cleaned
+        }
+    }
+
+    /// Apply the sign convention, target account and payee cleanup to rows
+    /// read with this profile's mapping
+    pub fn apply(
+        &self,
+        data: Option<&BudgetData>,
+        rows: &mut [ImportTransaction],
+    ) -> Result<(), String> {
+        let cleanup = self
+            .payee_cleanup
+            .as_deref()
+            .map(compile_cleanup)
+            .transpose()?;
+        let account = self.account_id.as_deref().and_then(|id| {
+            data.and_then(|d| {
+                d.accounts
+                    .iter()
+                    .find(|a| a.entity_id == id && !a.is_tombstone)
+            })
+        });
+        for row in rows.iter_mut() {
+            if self.sign == SignConvention::Negate {
+                row.amount = -row.amount;
+                std::mem::swap(&mut row.inflow, &mut row.outflow);
+                if row.is_msi {
+                    row.msi_original_amount = -row.msi_original_amount;
+                }
+            }
+            if let Some(account) = account {
+                row.account_id = Some(account.entity_id.clone());
+                row.account_name = account.account_name.clone();
+            }
+            if let Some(regex) = &cleanup {
+                for payee in [&mut row.payee_name, &mut row.suggested_payee] {
+                    if !payee.is_empty() {
+                        *payee = Self::clean_payee(regex, payee);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Profile for a file: the one matching the most of its keys; among equals,
+/// the one used last
+pub fn find_profile<'a>(
+    profiles: &'a [ImportProfile],
+    file_name: &str,
+    headers: Option<&[String]>,
+) -> Option<&'a ImportProfile> {
+    let fingerprint = headers.map(header_fingerprint);
+    profiles
+        .iter()
+        .filter_map(|p| Some((p.match_keys(file_name, fingerprint.as_deref())?, p)))
+        .max_by(|a, b| {
+            a.0.cmp(&b.0)
+                .then_with(|| a.1.last_used.cmp(&b.1.last_used))
+        })
+        .map(|(_, p)| p)
+}
+
+/// Add or replace a profile, fingerprinting `headers` when given. Returns the
+/// saved profile.
+pub fn save_profile(
+    budget_path: &Path,
+    mut profile: ImportProfile,
+    headers: Option<&[String]>,
+) -> Result<ImportProfile, String> {
+    if let Some(headers) = headers {
+        profile.header_fingerprint = Some(header_fingerprint(headers));
+    }
+    profile.filename_pattern = profile.filename_pattern.filter(|p| !p.trim().is_empty());
+    profile.payee_cleanup = profile.payee_cleanup.filter(|p| !p.trim().is_empty());
+    profile.check()?;
+    if profile.id.is_empty() {
+        profile.id = new_entity_id();
+    }
+    let mut sidecar = load_sidecar(budget_path)?;
+    match sidecar.profiles.iter_mut().find(|p| p.id == profile.id) {
+        Some(existing) => *existing = profile.clone(),
+        None => sidecar.profiles.push(profile.clone()),
+    }
+    local::write_json(&budget_path.join(PROFILES_SIDECAR_FILE), &sidecar)?;
+    Ok(profile)
+}
+
+pub fn delete_profile(budget_path: &Path, profile_id: &str) -> Result<(), String> {
+    let mut sidecar = load_sidecar(budget_path)?;
+    let before = sidecar.profiles.len();
+    sidecar.profiles.retain(|p| p.id != profile_id);
+    if sidecar.profiles.len() == before {
+        return Err(format!("Import profile not found: {}", profile_id));
+    }
+    local::write_json(&budget_path.join(PROFILES_SIDECAR_FILE), &sidecar)
+}
+
+/// Record that a profile was applied
+pub fn touch_profile(budget_path: &Path, profile_id: &str) -> Result<ImportProfile, String> {
+    let mut sidecar = load_sidecar(budget_path)?;
+    let profile = sidecar
+        .profiles
+        .iter_mut()
+        .find(|p| p.id == profile_id)
+        .ok_or_else(|| format!("Import profile not found: {}", profile_id))?;
+    profile.last_used = Some(chrono::Utc::now().to_rfc3339());
+    let profile = profile.clone();
+    local::write_json(&budget_path.join(PROFILES_SIDECAR_FILE), &sidecar)?;
+    Ok(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::budget::Budget;
+    use crate::ynab4::fixtures;
+
+    fn headers(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn saves_finds_and_applies_profiles() {
+        let path = fixtures::budget_folder("profiles-sidecar");
+        let header = headers(&["Fecha", "Concepto", "Importe"]);
+        let saved = save_profile(
+            &path,
+            ImportProfile {
+                name: "Visa".to_string(),
+                filename_pattern: Some("visa_*.csv".to_string()),
+                sign: SignConvention::Negate,
+                account_id: Some("ACC-CC".to_string()),
+                payee_cleanup: Some(r"^(?:COMPRA\s+)?(?P<payee>.+?)\s+SUC\b".to_string()),
+                ..Default::default()
+            },
+            Some(&header),
+        )
+        .unwrap();
+        assert!(!saved.id.is_empty());
+        assert_eq!(saved.header_fingerprint, Some(header_fingerprint(&header)));
+
+        let generic = save_profile(
+            &path,
+            ImportProfile {
+                name: "Any CSV".to_string(),
+                filename_pattern: Some("*.CSV".to_string()),
+                ..Default::default()
+            },
+            None,
+        )
+        .unwrap();
+
+        let list = load_sidecar(&path).unwrap().profiles;
+        assert_eq!(list.len(), 2);
+        // Both keys beat the file name alone; case and spacing of the header don't matter
+        let spaced = headers(&[" fecha ", "CONCEPTO", "importe", ""]);
+        let found = find_profile(&list, "/tmp/VISA_2024-01.csv", Some(&spaced)).unwrap();
+        assert_eq!(found.id, saved.id);
+        let found = find_profile(
+            &list,
+            "visa_2024-01.csv",
+            Some(&headers(&["Date", "Amount"])),
+        )
+        .unwrap();
+        assert_eq!(found.id, generic.id);
+        assert!(find_profile(&list, "visa.ofx", None).is_none());
+
+        let budget = Budget::load(&path).unwrap();
+        let mut rows = vec![
+            ImportTransaction::new(
+                1,
+                0,
+                "2024-01-03".into(),
+                "COMPRA OXXO SUC 123".into(),
+                80.0,
+            ),
+            ImportTransaction::new(1, 1, "2024-01-04".into(), "PAGO".into(), -500.0),
+        ];
+        rows[0].outflow = 0.0;
+        rows[0].inflow = 80.0;
+        found_profile(&list, &saved.id)
+            .apply(Some(&budget.data), &mut rows)
+            .unwrap();
+        assert_eq!(rows[0].amount, -80.0);
+        assert_eq!(rows[0].outflow, 80.0);
+        assert_eq!(rows[0].account_id.as_deref(), Some("ACC-CC"));
+        assert_eq!(rows[0].account_name, "Visa");
+        assert_eq!(rows[0].suggested_payee, "OXXO");
+        assert_eq!(rows[0].description, "COMPRA OXXO SUC 123");
+        // A pattern that doesn't match leaves the name alone
+        assert_eq!(rows[1].suggested_payee, "PAGO");
+        assert_eq!(rows[1].amount, 500.0);
+
+        let used = touch_profile(&path, &saved.id).unwrap();
+        assert!(used.last_used.is_some());
+        delete_profile(&path, &generic.id).unwrap();
+        assert!(delete_profile(&path, &generic.id).is_err());
+        assert_eq!(load_sidecar(&path).unwrap().profiles.len(), 1);
+        assert!(!path
+            .join(PROFILES_SIDECAR_FILE)
+            .with_extension("tmp")
+            .exists());
+
+        let invalid = ImportProfile {
+            name: "Bad".to_string(),
+            filename_pattern: Some("*.csv".to_string()),
+            payee_cleanup: Some("(".to_string()),
+            ..Default::default()
+        };
+        assert!(save_profile(&path, invalid, None).is_err());
+        let keyless = ImportProfile {
+            name: "Nothing".to_string(),
+            ..Default::default()
+        };
+        assert!(save_profile(&path, keyless, None).is_err());
+    }
+
+    fn found_profile<'a>(list: &'a [ImportProfile], id: &str) -> &'a ImportProfile {
+        list.iter().find(|p| p.id == id).unwrap()
+    }
+}
@@ -0,0 +1,569 @@
+//! QIF import and export.
+//!
+//! QIF is a list of records, one field per line, each record ending with `^`.
+//! `!Type:` lines start a list of transactions and `!Account` records say
+//! which account the following transactions belong to. Dates have no fixed
+//! format, so the user can pick one; otherwise it is inferred from all the
+//! dates in the file at once.
+
+use serde::Serialize;
+
+use super::formats::{self, ColumnKind, DateFormat, NumberFormat};
+use super::transaction::{self, ImportSplit, ImportTransaction};
+use crate::ynab4::entities::{BudgetData, Transaction, CLEARED, RECONCILED, TRANSFER_PAYEE_PREFIX};
+
+/// Transaction list types this reader understands; investment lists are skipped
+const TRANSACTION_TYPES: [&str; 5] = ["Bank", "Cash", "CCard", "Oth A", "Oth L"];
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QifAccount {
+    /// None for files with a single unnamed transaction list
+    pub name: Option<String>,
+    /// Bank, Cash, CCard, Oth A or Oth L
+    pub account_type: String,
+    pub description: Option<String>,
+    pub transactions: Vec<ImportTransaction>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QifFile {
+    pub accounts: Vec<QifAccount>,
+    pub date_format: DateFormat,
+    /// Why the date format was picked
+    pub date_evidence: String,
+    /// Records that could not be read, and skipped sections
+    pub errors: Vec<String>,
+}
+
+#[derive(Clone)]
+enum Section {
+    Account,
+    Transactions(String),
+    Skip(String),
+}
+
+struct Record {
+    line: usize,
+    fields: Vec<(char, String)>,
+}
+
+impl Record {
+    fn get(&self, code: char) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// QIF years may be written as 1/15'24 or with padding spaces
+fn normalize_date(value: &str) -> String {
+    value.replace('\'', "/").replace(' ', "")
+}
+
+/// `[Account]` names a transfer; anything after `/` is a QIF class
+fn read_category(value: &str) -> (String, Option<String>) {
+    let value = value.split('/').next().unwrap_or_default().trim();
+    match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        Some(account) => (String::new(), Some(account.to_string())),
+        None => (value.to_string(), None),
+    }
+}
+
+fn read_transaction(
+    record: &Record,
+    date_format: DateFormat,
+    data: Option<&BudgetData>,
+    batch: i64,
+    index: usize,
+) -> Result<ImportTransaction, String> {
+    let raw_date = record
+        .get('D')
+        .ok_or_else(|| format!("Line {}: missing date", record.line))?;
+    let date = formats::parse_date(&normalize_date(raw_date), date_format)
+        .ok_or_else(|| format!("Line {}: invalid date '{}'", record.line, raw_date))?;
+    let raw_amount = record
+        .get('T')
+        .or_else(|| record.get('U'))
+        .ok_or_else(|| format!("Line {}: missing amount", record.line))?;
+    let number_format = NumberFormat::default();
+    let amount = formats::parse_amount(raw_amount, &number_format)
+        .ok_or_else(|| format!("Line {}: invalid amount '{}'", record.line, raw_amount))?;
+
+    let payee = record.get('P').unwrap_or_default().to_string();
+    let mut row = ImportTransaction::new(
+        batch,
+        index,
+        date.format("%Y-%m-%d").to_string(),
+        payee,
+        amount,
+    );
+    row.original_memo = record.get('M').unwrap_or_default().to_string();
+    row.memo = row.original_memo.clone();
+    row.check_number = record.get('N').map(str::to_string);
+    row.cleared = matches!(record.get('C'), Some("*" | "c" | "X" | "R"));
+    row.reconciled = matches!(record.get('C'), Some("X" | "R"));
+    if let Some(category) = record.get('L') {
+        let (name, transfer) = read_category(category);
+        row.category_id = data.and_then(|d| transaction::find_category(d, &name));
+        row.suggested_category = name.clone();
+        row.category_name = name;
+        row.transfer_account_name = transfer;
+    }
+
+    // Split lines: S starts a line, E and $ fill it in
+    for (code, value) in &record.fields {
+        match code {
+            'S' => {
+                let (name, transfer) = read_category(value);
+                row.splits.push(ImportSplit {
+                    category_id: data.and_then(|d| transaction::find_category(d, &name)),
+                    category_name: name,
+                    transfer_account_name: transfer,
+                    ..Default::default()
+                });
+            }
+            'E' => {
+                if let Some(split) = row.splits.last_mut() {
+                    split.memo = value.clone();
+                }
+            }
+            '$' => {
+                let split_amount =
+                    formats::parse_amount(value, &number_format).ok_or_else(|| {
+                        format!("Line {}: invalid split amount '{}'", record.line, value)
+                    })?;
+                if let Some(split) = row.splits.last_mut() {
+                    split.amount = split_amount;
+                }
+            }
+            _ => {}
+        }
+    }
+    if !row.splits.is_empty() {
+        // The L line of a split only repeats the first split's category
+        row.category_id = None;
+        row.category_name.clear();
+        row.suggested_category.clear();
+        row.transfer_account_name = None;
+    }
+    Ok(row)
+}
+
+/// Parse a QIF file. Without `date_format` the format is inferred from the
+/// file's dates; with budget data, categories are matched to the budget's.
+pub fn parse_qif(
+    text: &str,
+    date_format: Option<DateFormat>,
+    data: Option<&BudgetData>,
+) -> Result<QifFile, String> {
+    let mut section = Section::Transactions(String::new());
+    let mut records: Vec<(Section, Record)> = Vec::new();
+    let mut current = Record {
+        line: 1,
+        fields: Vec::new(),
+    };
+    let mut errors = Vec::new();
+
+    for (idx, raw) in text.lines().enumerate() {
+        let line = raw.trim_end();
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('!') {
+            if header.starts_with("Option") || header.starts_with("Clear") {
+                continue;
+            }
+            section = match header.split_once(':') {
+                _ if header.eq_ignore_ascii_case("Account") => Section::Account,
+                Some(("Type", kind)) if TRANSACTION_TYPES.contains(&kind.trim()) => {
+                    Section::Transactions(kind.trim().to_string())
+                }
+                _ => Section::Skip(header.to_string()),
+            };
+            continue;
+        }
+        if current.fields.is_empty() {
+            current.line = idx + 1;
+        }
+        if line.starts_with('^') {
+            let record = std::mem::replace(
+                &mut current,
+                Record {
+                    line: idx + 2,
+                    fields: Vec::new(),
+                },
+            );
+            records.push((section.clone(), record));
+            continue;
+        }
+        let mut chars = line.chars();
+        if let Some(code) = chars.next() {
+            current
+                .fields
+                .push((code, chars.as_str().trim().to_string()));
+        }
+    }
+    if records.is_empty() {
+        return Err("Not a QIF file: no records".to_string());
+    }
+
+    let (date_format, date_evidence) = match date_format {
+        Some(format) => (format, "chosen by the user".to_string()),
+        None => {
+            let dates: Vec<String> = records
+                .iter()
+                .filter(|(s, _)| matches!(s, Section::Transactions(_)))
+                .filter_map(|(_, r)| r.get('D'))
+                .map(normalize_date)
+                .collect();
+            let cells: Vec<&str> = dates.iter().map(String::as_str).collect();
+            let column = formats::infer_column(0, &cells);
+            match column.kind {
+                ColumnKind::Date { format } => (format, column.evidence),
+                _ => (
+                    DateFormat::Mdy,
+                    "no format fits the dates; assuming MM/dd/yyyy".to_string(),
+                ),
+            }
+        }
+    };
+
+    let batch = transaction::batch_id();
+    let mut accounts: Vec<QifAccount> = Vec::new();
+    let mut current_account: Option<usize> = None;
+    let mut skipped: Vec<(String, usize)> = Vec::new();
+    for (index, (section, record)) in records.iter().enumerate() {
+        match section {
+            Section::Account => {
+                let name = record.get('N').map(str::to_string);
+                let idx = match accounts
+                    .iter()
+                    .position(|a| a.name.is_some() && a.name == name)
+                {
+                    Some(idx) => idx,
+                    None => {
+                        accounts.push(QifAccount {
+                            name,
+                            ..Default::default()
+                        });
+                        accounts.len() - 1
+                    }
+                };
+                // The same account can be listed more than once; keep what each record adds
+                let account = &mut accounts[idx];
+                if let Some(kind) = record.get('T') {
+                    account.account_type = kind.to_string();
+                }
+                if let Some(description) = record.get('D') {
+                    account.description = Some(description.to_string());
+                }
+                current_account = Some(idx);
+            }
+            Section::Transactions(kind) => {
+                let account_idx = *current_account.get_or_insert_with(|| {
+                    accounts.push(QifAccount::default());
+                    accounts.len() - 1
+                });
+                let account = &mut accounts[account_idx];
+                if account.account_type.is_empty() {
+                    account.account_type = kind.clone();
+                }
+                match read_transaction(record, date_format, data, batch, index) {
+                    Ok(mut row) => {
+                        row.account_name = account.name.clone().unwrap_or_default();
+                        row.account_id = data
+                            .zip(account.name.as_deref())
+                            .and_then(|(d, name)| {
+                                d.accounts
+                                    .iter()
+                                    .find(|a| !a.is_tombstone && a.account_name == name)
+                            })
+                            .map(|a| a.entity_id.clone());
+                        account.transactions.push(row);
+                    }
+                    Err(e) => errors.push(e),
+                }
+            }
+            Section::Skip(kind) => match skipped.iter_mut().find(|(k, _)| k == kind) {
+                Some((_, count)) => *count += 1,
+                None => skipped.push((kind.clone(), 1)),
+            },
+        }
+    }
+    for (kind, count) in skipped {
+        errors.push(format!("Skipped {} records of type {}", count, kind));
+    }
+    // Account lists (!Option:AutoSwitch) name accounts that have no transactions here
+    accounts.retain(|a| !a.transactions.is_empty());
+
+    Ok(QifFile {
+        accounts,
+        date_format,
+        date_evidence,
+        errors,
+    })
+}
+
+/// QIF list type for a YNAB4 account type
+fn qif_type(account_type: &str) -> &'static str {
+    match account_type {
+        "CreditCard" | "LineOfCredit" => "CCard",
+        "Cash" => "Cash",
+        "InvestmentAccount" | "OtherAsset" => "Oth A",
+        "Mortgage" | "OtherLiability" => "Oth L",
+        _ => "Bank",
+    }
+}
+
+fn account_name(data: &BudgetData, account_id: &str) -> String {
+    data.accounts
+        .iter()
+        .find(|a| a.entity_id == account_id)
+        .map(|a| a.account_name.clone())
+        .unwrap_or_else(|| account_id.to_string())
+}
+
+/// `L`/`S` value for a category or a transfer
+fn category_field(
+    data: &BudgetData,
+    category_id: Option<&str>,
+    transfer_account: Option<&str>,
+) -> Option<String> {
+    match transfer_account {
+        Some(account) => Some(format!("[{}]", account_name(data, account))),
+        None => category_id.and_then(|id| transaction::category_path(data, id)),
+    }
+}
+
+fn write_transaction(
+    out: &mut String,
+    data: &BudgetData,
+    t: &Transaction,
+    date_format: DateFormat,
+) {
+    let push = |out: &mut String, code: char, value: &str| {
+        // A record field is one line
+        out.push(code);
+        out.push_str(&value.replace(['\r', '\n'], " "));
+        out.push('\n');
+    };
+
+    let date = chrono::NaiveDate::parse_from_str(t.date.get(..10).unwrap_or(&t.date), "%Y-%m-%d")
+        .map(|d| formats::format_date(d, date_format))
+        .unwrap_or_else(|_| t.date.clone());
+    push(out, 'D', &date);
+    push(out, 'T', &format!("{:.2}", t.amount));
+    match t.cleared.as_str() {
+        RECONCILED => push(out, 'C', "X"),
+        CLEARED => push(out, 'C', "*"),
+        _ => {}
+    }
+    if let Some(number) = t.check_number.as_deref().filter(|n| !n.is_empty()) {
+        push(out, 'N', number);
+    }
+    let transfer = t.transfer_account_id();
+    if transfer.is_none() {
+        let payee = t
+            .payee_id
+            .as_deref()
+            .and_then(|id| data.payees.iter().find(|p| p.entity_id == id));
+        if let Some(payee) = payee {
+            push(out, 'P', &payee.name);
+        }
+    }
+    if let Some(memo) = t.memo.as_deref().filter(|m| !m.is_empty()) {
+        push(out, 'M', memo);
+    }
+    if t.is_split() {
+        for sub in t.live_sub_transactions() {
+            let sub_transfer = sub.transfer_transaction_id.as_ref().and_then(|_| {
+                sub.target_account_id
+                    .as_deref()
+                    .or_else(|| sub.payee_id.as_deref()?.strip_prefix(TRANSFER_PAYEE_PREFIX))
+            });
+            push(
+                out,
+                'S',
+                &category_field(data, sub.category_id.as_deref(), sub_transfer).unwrap_or_default(),
+            );
+            if let Some(memo) = sub.memo.as_deref().filter(|m| !m.is_empty()) {
+                push(out, 'E', memo);
+            }
+            push(out, '$', &format!("{:.2}", sub.amount));
+        }
+    } else if let Some(category) = category_field(data, t.category_id.as_deref(), transfer) {
+        push(out, 'L', &category);
+    }
+    out.push_str("^\n");
+}
+
+/// Write an account's register as QIF. Returns the text and the number of
+/// transactions written.
+pub fn write_qif(
+    data: &BudgetData,
+    account_id: &str,
+    date_format: DateFormat,
+) -> Result<(String, usize), String> {
+    let account = data
+        .accounts
+        .iter()
+        .find(|a| a.entity_id == account_id && !a.is_tombstone)
+        .ok_or_else(|| format!("Account not found: {}", account_id))?;
+    let mut transactions: Vec<&Transaction> = data
+        .transactions
+        .iter()
+        .filter(|t| !t.is_tombstone && t.account_id == account_id)
+        .collect();
+    transactions.sort_by(|a, b| {
+        a.date
+            .cmp(&b.date)
+            .then_with(|| a.entity_id.cmp(&b.entity_id))
+    });
+
+    let kind = qif_type(&account.account_type);
+    let mut out = format!(
+        "!Account\nN{}\nT{}\n^\n!Type:{}\n",
+        account.account_name, kind, kind
+    );
+    for t in &transactions {
+        write_transaction(&mut out, data, t, date_format);
+    }
+    Ok((out, transactions.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::budget::Budget;
+    use crate::ynab4::fixtures;
+
+    #[test]
+    fn dates_are_read_in_the_format_the_whole_file_fits() {
+        let text = "!Type:Bank\nD03/04/2024\nT-10.00\nPOXXO\n^\nD04/13/2024\nT25.00\nPPago\n^\n";
+        let file = parse_qif(text, None, None).unwrap();
+        assert_eq!(file.date_format, DateFormat::Mdy);
+        let transactions = &file.accounts[0].transactions;
+        assert_eq!(transactions[0].date, "2024-03-04");
+        assert_eq!(transactions[1].date, "2024-04-13");
+        assert_eq!(transactions[1].amount, 25.0);
+        assert!(file.errors.is_empty());
+    }
+
+    #[test]
+    fn accounts_splits_transfers_and_categories_are_read() {
+        let budget = Budget::load(&fixtures::budget_folder("qif-read")).unwrap();
+        let text = "!Account\nNChecking\nTBank\nDMain account\n^\n\
+            !Type:Bank\nD01/15/2024\nT-300.00\nPLandlord\nCX\nN101\nLEveryday:Rent\n^\n\
+            D01/20/2024\nT-100.00\nL[Visa]\nMPay card\n^\n\
+            D01/25/2024\nT-250.00\nPSoriana\nC*\nLEveryday:Food\n\
+            SEveryday:Food\n$-200.00\nEgroceries\nS[Visa]\n$-50.00\n^\n\
+            !Account\nNVisa\nTCCard\n^\n!Type:CCard\nD01/20/2024\nT100.00\nL[Checking]\n^\n";
+        let file = parse_qif(text, None, Some(&budget.data)).unwrap();
+        assert!(file.errors.is_empty(), "{:?}", file.errors);
+        assert_eq!(file.accounts.len(), 2);
+
+        let checking = &file.accounts[0];
+        assert_eq!(checking.name.as_deref(), Some("Checking"));
+        assert_eq!(checking.account_type, "Bank");
+        assert_eq!(checking.description.as_deref(), Some("Main account"));
+        let rent = &checking.transactions[0];
+        assert_eq!(rent.account_id.as_deref(), Some("ACC-CHK"));
+        assert_eq!(rent.category_id.as_deref(), Some("CAT-RENT"));
+        assert_eq!(rent.category_name, "Everyday:Rent");
+        assert_eq!(rent.check_number.as_deref(), Some("101"));
+        assert!(rent.cleared && rent.reconciled);
+
+        let payment = &checking.transactions[1];
+        assert_eq!(payment.transfer_account_name.as_deref(), Some("Visa"));
+        assert!(payment.category_name.is_empty());
+        assert_eq!(payment.original_memo, "Pay card");
+        assert_eq!(payment.memo, "Pay card");
+
+        let groceries = &checking.transactions[2];
+        assert!(groceries.cleared && !groceries.reconciled);
+        // The L line of a split only repeats the first line
+        assert_eq!(groceries.category_id, None);
+        assert_eq!(groceries.splits.len(), 2);
+        assert_eq!(groceries.splits[0].category_id.as_deref(), Some("CAT-FOOD"));
+        assert_eq!(groceries.splits[0].amount, -200.0);
+        assert_eq!(groceries.splits[0].memo, "groceries");
+        assert_eq!(
+            groceries.splits[1].transfer_account_name.as_deref(),
+            Some("Visa")
+        );
+        assert_eq!(groceries.splits[1].amount, -50.0);
+
+        let visa = &file.accounts[1];
+        assert_eq!(visa.account_type, "CCard");
+        assert_eq!(visa.transactions[0].account_id.as_deref(), Some("ACC-CC"));
+        assert_eq!(
+            visa.transactions[0].transfer_account_name.as_deref(),
+            Some("Checking")
+        );
+    }
+
+    #[test]
+    fn exported_register_reads_back() {
+        let mut budget = Budget::load(&fixtures::budget_folder("qif-round-trip")).unwrap();
+        if let Some(t) = budget
+            .data
+            .transactions
+            .iter_mut()
+            .find(|t| t.entity_id == "T-1")
+        {
+            t.cleared = RECONCILED.to_string();
+        }
+        let (text, count) = write_qif(&budget.data, "ACC-CHK", DateFormat::Dmy).unwrap();
+        assert_eq!(count, 5);
+
+        let file = parse_qif(&text, None, Some(&budget.data)).unwrap();
+        assert!(file.errors.is_empty(), "{:?}", file.errors);
+        assert_eq!(file.date_format, DateFormat::Dmy);
+        assert_eq!(file.accounts.len(), 1);
+        let account = &file.accounts[0];
+        assert_eq!(account.name.as_deref(), Some("Checking"));
+        assert_eq!(account.account_type, "Bank");
+
+        // Sorted by date: T-1, T-2, T-5, T-4, T-7
+        let rows = &account.transactions;
+        assert_eq!(rows.len(), 5);
+        assert!(rows
+            .iter()
+            .all(|r| r.account_id.as_deref() == Some("ACC-CHK")));
+        assert_eq!(rows[0].date, "2024-01-01");
+        assert_eq!(rows[0].description, "Employer");
+        assert_eq!(
+            rows[0].category_id.as_deref(),
+            Some("Category/__ImmediateIncome__")
+        );
+        assert!(rows[0].cleared && rows[0].reconciled);
+        assert_eq!(rows[1].amount, -250.0);
+        assert_eq!(rows[1].category_id.as_deref(), Some("CAT-FOOD"));
+        assert!(!rows[1].cleared);
+        assert_eq!(rows[2].transfer_account_name.as_deref(), Some("Visa"));
+        assert!(
+            rows[2].description.is_empty(),
+            "a transfer has no payee line"
+        );
+        assert_eq!(
+            rows[3].category_id.as_deref(),
+            Some("Category/__DeferredIncome__")
+        );
+
+        let split = &rows[4];
+        assert_eq!(split.date, "2024-02-03");
+        assert_eq!(split.amount, -300.0);
+        let lines: Vec<(Option<&str>, f64)> = split
+            .splits
+            .iter()
+            .map(|s| (s.category_id.as_deref(), s.amount))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![(Some("CAT-RENT"), -200.0), (Some("CAT-FOOD"), -100.0)]
+        );
+    }
+}
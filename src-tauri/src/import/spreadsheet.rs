@@ -0,0 +1,449 @@
+//! XLSX, XLS and ODS workbooks.
+//!
+//! Rows come back as text in the `{headers, rows}` shape of `parseExcel`, a
+//! page at a time so big workbooks never travel to the webview whole. A sheet
+//! is read in one pass (XLSX cell by cell, XLS and ODS through calamine's
+//! range) and kept, so the following pages don't open the workbook again.
+//!
+//! Banks often split a header over two rows, with a merged "Importe" cell
+//! above "Cargo" and "Abono"; such headers come back as one row, here
+//! "Importe Cargo" and "Importe Abono".
+
+use calamine::{open_workbook_auto, Data, Dimensions, Reader, Sheets};
+use chrono::{Duration, NaiveDate};
+use serde::Serialize;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Rows sent when the caller does not ask for a page size
+const DEFAULT_PAGE: usize = 5000;
+
+/// Sheets kept for paging; the oldest is dropped first
+const KEPT_SHEETS: usize = 2;
+
+/// A sheet read whole, with what it was read from
+struct ReadSheet {
+    path: PathBuf,
+    /// Modification time of the file, so a changed file is read again
+    modified: Option<SystemTime>,
+    sheet_index: usize,
+    skip_rows: usize,
+    sheet: String,
+    headers: Vec<String>,
+    /// Data rows, without the header
+    rows: Vec<Vec<String>>,
+}
+
+static READ_SHEETS: Mutex<Vec<ReadSheet>> = Mutex::new(Vec::new());
+
+/// A page of a sheet's rows
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SheetRows {
+    pub sheet: String,
+    pub headers: Vec<String>,
+    /// Rows from `offset`; as in `parseExcel`, row 0 is the header row
+    pub rows: Vec<Vec<String>>,
+    /// Offset of the next page, when there is one
+    pub next_offset: Option<usize>,
+}
+
+fn open(path: &Path) -> Result<Sheets<BufReader<File>>, String> {
+    open_workbook_auto(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+}
+
+/// Names of the sheets, in workbook order
+pub fn sheet_names(path: &Path) -> Result<Vec<String>, String> {
+    Ok(open(path)?.sheet_names())
+}
+
+/// Date of an Excel serial number (days since 1899-12-30)
+fn serial_date(serial: f64) -> Option<NaiveDate> {
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, 30)?;
+    epoch.checked_add_signed(Duration::days(serial.floor() as i64))
+}
+
+fn number_text(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Text of a cell: dates as YYYY-MM-DD (with the time only when there is one)
+fn cell_text(value: &Data) -> String {
+    match value {
+        Data::Empty | Data::Error(_) => String::new(),
+        Data::String(s) => s.trim().to_string(),
+        Data::Int(i) => i.to_string(),
+        Data::Float(f) => number_text(*f),
+        Data::Bool(b) => b.to_string(),
+        Data::DateTime(dt) if dt.is_datetime() => match dt.as_datetime() {
+            Some(dt) if dt.time() == chrono::NaiveTime::MIN => dt.format("%Y-%m-%d").to_string(),
+            Some(dt) => dt.format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => number_text(dt.as_f64()),
+        },
+        Data::DateTime(dt) => number_text(dt.as_f64()),
+        Data::DateTimeIso(s) => s.strip_suffix("T00:00:00").unwrap_or(s).replace('T', " "),
+        Data::DurationIso(s) => s.clone(),
+    }
+}
+
+/// Read rows in order until `f` returns false. Empty rows are skipped; each
+/// row comes with its sheet row number.
+fn each_row(
+    workbook: &mut Sheets<BufReader<File>>,
+    sheet: &str,
+    mut f: impl FnMut(u32, Vec<String>) -> bool,
+) -> Result<(), String> {
+    let read_error = |e: &dyn std::fmt::Display| format!("Failed to read sheet {}: {}", sheet, e);
+    if let Sheets::Xlsx(xlsx) = workbook {
+        let mut reader = xlsx
+            .worksheet_cells_reader(sheet)
+            .map_err(|e| read_error(&e))?;
+        let mut current: Option<(u32, Vec<String>)> = None;
+        while let Some(cell) = reader.next_cell().map_err(|e| read_error(&e))? {
+            let (row, col) = cell.get_position();
+            let text = cell_text(&Data::from(cell.get_value().clone()));
+            if current.as_ref().is_some_and(|(r, _)| *r != row) {
+                let (r, cells) = current.take().unwrap();
+                if cells.iter().any(|c| !c.is_empty()) && !f(r, cells) {
+                    return Ok(());
+                }
+            }
+            let (_, cells) = current.get_or_insert_with(|| (row, Vec::new()));
+            if cells.len() <= col as usize {
+                cells.resize(col as usize + 1, String::new());
+            }
+            cells[col as usize] = text;
+        }
+        if let Some((r, cells)) = current {
+            if cells.iter().any(|c| !c.is_empty()) {
+                f(r, cells);
+            }
+        }
+        return Ok(());
+    }
+
+    let range = workbook
+        .worksheet_range(sheet)
+        .map_err(|e| read_error(&e))?;
+    let (first_row, first_col) = range.start().unwrap_or((0, 0));
+    for (idx, row) in range.rows().enumerate() {
+        // Keep columns where they are in the sheet, like the XLSX reader does
+        let cells: Vec<String> = std::iter::repeat(String::new())
+            .take(first_col as usize)
+            .chain(row.iter().map(cell_text))
+            .collect();
+        if cells.iter().any(|c| !c.is_empty()) && !f(first_row + idx as u32, cells) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Merged cell ranges of a sheet (none for ODS, which calamine does not report)
+fn merged_cells(workbook: &mut Sheets<BufReader<File>>, sheet: &str) -> Vec<Dimensions> {
+    match workbook {
+        Sheets::Xlsx(xlsx) => xlsx
+            .worksheet_merge_cells(sheet)
+            .and_then(Result::ok)
+            .unwrap_or_default(),
+        Sheets::Xls(xls) => xls.worksheet_merge_cells(sheet).unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+fn merge_at(merges: &[Dimensions], row: u32, col: u32) -> Option<&Dimensions> {
+    merges.iter().find(|m| m.contains(row, col))
+}
+
+/// Header of the first row, using the row below too when merged cells make
+/// it a two-row header. Returns the header and how many rows it takes.
+fn build_header(
+    first: (u32, &[String]),
+    second: Option<(u32, &[String])>,
+    merges: &[Dimensions],
+) -> (Vec<String>, usize) {
+    let (row, cells) = first;
+    let width = cells.len().max(second.map_or(0, |(_, s)| s.len()));
+    // A merged cell shows its value across every column it covers
+    let mut top: Vec<String> = cells.to_vec();
+    top.resize(width, String::new());
+    for m in merges.iter().filter(|m| m.start.0 == row) {
+        let value = top.get(m.start.1 as usize).cloned().unwrap_or_default();
+        for col in m.start.1..=m.end.1.min(width as u32 - 1) {
+            top[col as usize] = value.clone();
+        }
+    }
+
+    // Two rows when a label is merged across columns and the row below labels each of them
+    let spans_columns = merges
+        .iter()
+        .any(|m| m.start.0 == row && m.end.0 == row && m.end.1 > m.start.1);
+    let Some((next_row, below)) = second.filter(|(next_row, below)| {
+        spans_columns
+            && *next_row == row + 1
+            && below.iter().any(|c| !c.is_empty())
+            && below
+                .iter()
+                .all(|c| c.is_empty() || !super::formats::is_value(c))
+    }) else {
+        return (top, 1);
+    };
+    let header = (0..width)
+        .map(|col| {
+            let parent = &top[col];
+            let child = below.get(col).map(String::as_str).unwrap_or_default();
+            // A label merged down over both rows has no child
+            let tall = merge_at(merges, row, col as u32).is_some_and(|m| m.end.0 >= next_row);
+            match (parent.is_empty(), child.is_empty() || tall) {
+                (_, true) => parent.clone(),
+                (true, false) => child.to_string(),
+                (false, false) => format!("{} {}", parent, child),
+            }
+        })
+        .collect();
+    (header, 2)
+}
+
+/// Rows whose header column names a date and whose value is a plain number
+/// hold date serials the workbook did not format as dates
+fn fix_date_serials(headers: &[String], rows: &mut [Vec<String>]) {
+    let date_columns: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .filter(|(_, h)| {
+            let h = h.to_lowercase();
+            h.contains("fecha") || h.contains("date")
+        })
+        .map(|(idx, _)| idx)
+        .collect();
+    for row in rows.iter_mut() {
+        for &col in &date_columns {
+            let Some(cell) = row.get_mut(col) else {
+                continue;
+            };
+            let Ok(serial) = cell.parse::<f64>() else {
+                continue;
+            };
+            // 1955 to 2118; anything else is not a date
+            if !(20000.0..80000.0).contains(&serial) {
+                continue;
+            }
+            if let Some(date) = serial_date(serial) {
+                *cell = date.format("%Y-%m-%d").to_string();
+            }
+        }
+    }
+}
+
+/// Read the whole sheet at `sheet_index`, after dropping `skip_rows`
+/// non-empty rows above the header
+fn read_whole_sheet(
+    path: &Path,
+    sheet_index: usize,
+    skip_rows: usize,
+) -> Result<ReadSheet, String> {
+    let mut workbook = open(path)?;
+    let names = workbook.sheet_names();
+    let sheet = names
+        .get(sheet_index)
+        .cloned()
+        .ok_or_else(|| format!("The workbook has no sheet {}", sheet_index + 1))?;
+    let merges = merged_cells(&mut workbook, &sheet);
+
+    // Sheet rows: the header row or rows, then the data
+    let mut header_rows: Vec<(u32, Vec<String>)> = Vec::new();
+    let mut header: Option<Vec<String>> = None;
+    let mut rows = Vec::new();
+    let mut skipped = 0;
+    each_row(&mut workbook, &sheet, |row, cells| {
+        if skipped < skip_rows {
+            skipped += 1;
+            return true;
+        }
+        if header.is_none() {
+            header_rows.push((row, cells));
+            if header_rows.len() < 2 {
+                return true;
+            }
+            let (first, second) = (&header_rows[0], &header_rows[1]);
+            let (built, used) =
+                build_header((first.0, &first.1), Some((second.0, &second.1)), &merges);
+            header = Some(built);
+            if used == 1 {
+                let (_, cells) = header_rows.pop().unwrap();
+                rows.push(cells);
+            }
+            return true;
+        }
+        rows.push(cells);
+        true
+    })?;
+    // A sheet with a single row
+    if header.is_none() {
+        if let Some((row, cells)) = header_rows.first() {
+            header = Some(build_header((*row, cells), None, &merges).0);
+        }
+    }
+
+    let headers = header.unwrap_or_default();
+    fix_date_serials(&headers, &mut rows);
+    Ok(ReadSheet {
+        path: path.to_path_buf(),
+        modified: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+        sheet_index,
+        skip_rows,
+        sheet,
+        headers,
+        rows,
+    })
+}
+
+/// Read `limit` rows of the sheet at `sheet_index` starting at `offset`,
+/// after dropping `skip_rows` non-empty rows above the header
+pub fn read_sheet(
+    path: &Path,
+    sheet_index: usize,
+    skip_rows: usize,
+    offset: usize,
+    limit: Option<usize>,
+) -> Result<SheetRows, String> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE).max(1);
+    let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut kept = READ_SHEETS.lock().map_err(|e| e.to_string())?;
+    let position = kept.iter().position(|s| {
+        s.path == path
+            && s.sheet_index == sheet_index
+            && s.skip_rows == skip_rows
+            && s.modified == modified
+    });
+    let read = match position {
+        Some(idx) => kept.remove(idx),
+        None => read_whole_sheet(path, sheet_index, skip_rows)?,
+    };
+
+    // Logical row 0 is the header, so data row n is logical row n + 1
+    let end = (offset + limit).min(read.rows.len() + 1);
+    let mut rows: Vec<Vec<String>> = read
+        .rows
+        .get(offset.saturating_sub(1)..end.saturating_sub(1))
+        .unwrap_or_default()
+        .to_vec();
+    if offset == 0 && !read.headers.is_empty() {
+        rows.insert(0, read.headers.clone());
+    }
+    let page = SheetRows {
+        sheet: read.sheet.clone(),
+        headers: read.headers.clone(),
+        rows,
+        next_offset: (end <= read.rows.len()).then_some(end),
+    };
+
+    // The newest sheet goes last
+    kept.push(read);
+    if kept.len() > KEPT_SHEETS {
+        kept.remove(0);
+    }
+    Ok(page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_xlsxwriter::{ExcelDateTime, Format, Workbook};
+    use std::path::PathBuf;
+
+    /// A bank export with a two-row header, and a plain sheet that starts further down
+    fn workbook(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join("ynab4-client-tests");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.xlsx", name));
+        let mut workbook = Workbook::new();
+        let date = Format::new().set_num_format("dd/mm/yyyy");
+        let sheet = workbook.add_worksheet().set_name("Movimientos").unwrap();
+        sheet
+            .merge_range(0, 0, 1, 0, "Fecha", &Format::new())
+            .unwrap();
+        sheet
+            .merge_range(0, 1, 1, 1, "Concepto", &Format::new())
+            .unwrap();
+        sheet
+            .merge_range(0, 2, 0, 3, "Importe", &Format::new())
+            .unwrap();
+        sheet.write(1, 2, "Cargo").unwrap();
+        sheet.write(1, 3, "Abono").unwrap();
+        for i in 0..10u32 {
+            let day = ExcelDateTime::from_ymd(2024, 1, (i + 1) as u8).unwrap();
+            sheet.write_with_format(i + 2, 0, &day, &date).unwrap();
+            sheet.write(i + 2, 1, format!("Pago {}", i)).unwrap();
+            sheet.write(i + 2, 2, 10.5 * i as f64).unwrap();
+        }
+        let plain = workbook.add_worksheet().set_name("Otra").unwrap();
+        plain.write(2, 1, "Date").unwrap();
+        plain.write(2, 2, "Amount").unwrap();
+        plain.write(3, 1, 45292).unwrap();
+        plain.write(3, 2, -12.25).unwrap();
+        workbook.save(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_merged_headers_a_page_at_a_time() {
+        let path = workbook("spreadsheet-pages");
+        assert_eq!(sheet_names(&path).unwrap(), vec!["Movimientos", "Otra"]);
+
+        let page = read_sheet(&path, 0, 0, 0, Some(4)).unwrap();
+        assert_eq!(
+            page.headers,
+            vec!["Fecha", "Concepto", "Importe Cargo", "Importe Abono"]
+        );
+        assert_eq!(page.rows.len(), 4);
+        assert_eq!(page.rows[0], page.headers);
+        assert_eq!(
+            (page.rows[1][0].as_str(), page.rows[2][2].as_str()),
+            ("2024-01-01", "10.5")
+        );
+        assert_eq!(page.next_offset, Some(4));
+
+        let rest = read_sheet(&path, 0, 0, 4, Some(100)).unwrap();
+        assert_eq!(rest.rows.len(), 7);
+        assert_eq!(rest.rows[0][1], "Pago 3");
+        assert_eq!(rest.next_offset, None);
+
+        // A serial number under a "Date" header is a date
+        let other = read_sheet(&path, 1, 0, 0, None).unwrap();
+        assert_eq!(other.headers, vec!["", "Date", "Amount"]);
+        assert_eq!(other.rows[1], vec!["", "2024-01-01", "-12.25"]);
+        assert!(read_sheet(&path, 2, 0, 0, None).is_err());
+    }
+
+    #[test]
+    fn later_pages_come_from_the_sheet_already_read() {
+        let path = workbook("spreadsheet-kept");
+        let first = read_sheet(&path, 0, 0, 0, Some(2)).unwrap();
+        assert_eq!(first.next_offset, Some(2));
+        let kept = |skip_rows: usize| {
+            READ_SHEETS
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|s| s.path == path && s.skip_rows == skip_rows)
+        };
+        assert!(kept(0));
+
+        let second = read_sheet(&path, 0, 0, 2, Some(2)).unwrap();
+        assert_eq!(second.rows[0][1], "Pago 1");
+        assert_eq!(second.next_offset, Some(4));
+
+        // Skipping rows reads the sheet again: the first data row becomes the header
+        let skipped = read_sheet(&path, 0, 2, 0, Some(2)).unwrap();
+        assert_eq!(skipped.headers[1], "Pago 0");
+        assert!(kept(2));
+    }
+}
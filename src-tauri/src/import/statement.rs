@@ -0,0 +1,649 @@
+//! Delimited bank statements (CSV and friends).
+//!
+//! Banks export in whatever their system happens to use: UTF-8, Windows-1252
+//! or UTF-16, with `,`, `;`, tab or `|` between fields, and often a few lines
+//! of account details above the real header. Everything is detected from the
+//! file itself, and the reasons for each choice are returned with the rows so
+//! the user can see why a file was read the way it was.
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::formats::{self, ColumnFormat, ColumnKind, DateFormat, NumberFormat};
+
+const DELIMITERS: [char; 4] = [',', ';', '\t', '|'];
+const QUOTES: [char; 2] = ['"', '\''];
+
+/// Records looked at when detecting the delimiter and the header
+const SAMPLE_RECORDS: usize = 100;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedStatement {
+    /// Header cells, or "Column N" when the file has no header row
+    pub headers: Vec<String>,
+    pub rows: Vec<StatementRow>,
+    /// Format picked for each column
+    pub columns: Vec<ColumnFormat>,
+    /// Cells that don't fit their column's format
+    pub errors: Vec<CellError>,
+    pub detection: Detection,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CellError {
+    pub line: usize,
+    pub column: usize,
+    pub header: String,
+    pub value: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatementRow {
+    /// Line in the file where the row starts (1-based)
+    pub line: usize,
+    /// Cells as text, padded to the header width
+    pub cells: Vec<String>,
+    /// Cells read with their column's format; cells that don't fit stay text
+    pub values: Vec<CellValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum CellValue {
+    Empty,
+    Number(f64),
+    /// ISO date (YYYY-MM-DD)
+    Date(String),
+    Text(String),
+}
+
+/// What was detected, with a short explanation for each choice
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Detection {
+    /// "UTF-8", "windows-1252", "UTF-16LE" or "UTF-16BE"
+    pub encoding: String,
+    pub encoding_evidence: String,
+    /// Some bytes could not be decoded and were replaced
+    pub decode_errors: bool,
+    pub delimiter: String,
+    pub delimiter_evidence: String,
+    /// None when no field is quoted
+    pub quote: Option<String>,
+    pub quote_evidence: String,
+    pub header_line: Option<usize>,
+    pub header_evidence: String,
+    /// Lines above the data that are not the header (account details, titles...)
+    pub skipped_lines: Vec<usize>,
+    /// Rows whose field count differs from the header's
+    pub ragged_lines: Vec<usize>,
+}
+
+/// Choices saved in an import profile that replace detection
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StatementOptions {
+    /// Records above the header to drop; the next record is the header
+    pub skip_rows: usize,
+    pub date_format: Option<DateFormat>,
+    pub number_format: Option<NumberFormat>,
+}
+
+/// One record as split from the text
+#[derive(Debug, Clone)]
+struct Record {
+    line: usize,
+    fields: Vec<String>,
+    /// Fields that were enclosed in quotes
+    quoted: usize,
+}
+
+/// Parse a statement from the raw bytes of the file
+pub fn parse_statement(bytes: &[u8]) -> Result<ParsedStatement, String> {
+    parse_statement_with(bytes, &StatementOptions::default())
+}
+
+/// Parse a statement, with some choices made by an import profile
+pub fn parse_statement_with(
+    bytes: &[u8],
+    options: &StatementOptions,
+) -> Result<ParsedStatement, String> {
+    let (encoding, bom_len, encoding_evidence) = detect_encoding(bytes);
+    let (text, decode_errors) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+    if text.trim().is_empty() {
+        return Err("The file is empty".to_string());
+    }
+
+    let layout = detect_layout(&text);
+    let records = split_records(&text, layout.delimiter, layout.quote, usize::MAX);
+    let (header_idx, header_evidence) =
+        if options.skip_rows > 0 && options.skip_rows < records.len() {
+            let evidence = format!(
+                "first row after the {} skipped by the import profile",
+                options.skip_rows
+            );
+            (Some(options.skip_rows), evidence)
+        } else {
+            detect_header(&records, layout.width)
+        };
+
+    let (headers, data_start) = match header_idx {
+        Some(idx) => (records[idx].fields.clone(), idx + 1),
+        None => {
+            let first = records
+                .iter()
+                .position(|r| r.fields.len() == layout.width)
+                .unwrap_or(0);
+            (
+                (1..=layout.width)
+                    .map(|n| format!("Column {}", n))
+                    .collect(),
+                first,
+            )
+        }
+    };
+    let header_line = header_idx.map(|idx| records[idx].line);
+    let skipped_lines = records[..data_start]
+        .iter()
+        .map(|r| r.line)
+        .filter(|line| Some(*line) != header_line)
+        .collect();
+
+    let width = headers.len();
+    let mut rows = Vec::new();
+    let mut ragged_lines = Vec::new();
+    for record in records.into_iter().skip(data_start) {
+        if record.fields.len() != width {
+            ragged_lines.push(record.line);
+        }
+        let mut cells = record.fields;
+        if cells.len() < width {
+            cells.resize(width, String::new());
+        }
+        rows.push(StatementRow {
+            line: record.line,
+            cells,
+            values: Vec::new(),
+        });
+    }
+
+    let columns: Vec<ColumnFormat> = (0..width)
+        .map(|idx| {
+            let cells: Vec<&str> = rows.iter().map(|r| r.cells[idx].as_str()).collect();
+            let mut column = formats::infer_column(idx, &cells);
+            formats::override_format(
+                &mut column,
+                &cells,
+                options.date_format,
+                options.number_format,
+            );
+            column
+        })
+        .collect();
+    let mut errors = Vec::new();
+    for row in rows.iter_mut() {
+        row.values = row
+            .cells
+            .iter()
+            .enumerate()
+            .map(|(idx, cell)| {
+                let column = columns.get(idx);
+                let value = read_cell(cell, column.map(|c| &c.kind));
+                if let (CellValue::Text(_), Some(column)) = (&value, column) {
+                    if let Some(pattern) = column
+                        .pattern
+                        .as_ref()
+                        .filter(|_| column.kind != ColumnKind::Text)
+                    {
+                        errors.push(CellError {
+                            line: row.line,
+                            column: idx,
+                            header: headers[idx].clone(),
+                            value: cell.clone(),
+                            message: format!("'{}' does not match {}", cell, pattern),
+                        });
+                    }
+                }
+                value
+            })
+            .collect();
+    }
+
+    Ok(ParsedStatement {
+        headers,
+        rows,
+        columns,
+        errors,
+        detection: Detection {
+            encoding: encoding.name().to_string(),
+            encoding_evidence,
+            decode_errors,
+            delimiter: layout.delimiter.to_string(),
+            delimiter_evidence: layout.delimiter_evidence,
+            quote: layout.quote_used.map(|q| q.to_string()),
+            quote_evidence: layout.quote_evidence,
+            header_line,
+            header_evidence,
+            skipped_lines,
+            ragged_lines,
+        },
+    })
+}
+
+/// Read a cell with its column's format; cells that don't fit stay text
+fn read_cell(cell: &str, kind: Option<&ColumnKind>) -> CellValue {
+    if cell.is_empty() {
+        return CellValue::Empty;
+    }
+    let value = match kind {
+        Some(ColumnKind::Date { format }) => formats::parse_date(cell, *format)
+            .map(|date| CellValue::Date(date.format("%Y-%m-%d").to_string())),
+        Some(ColumnKind::Number { format }) => {
+            formats::parse_amount(cell, format).map(CellValue::Number)
+        }
+        _ => None,
+    };
+    value.unwrap_or_else(|| CellValue::Text(cell.to_string()))
+}
+
+/// Decode a text file in whichever encoding it was detected to be in
+pub fn decode_text(bytes: &[u8]) -> String {
+    let (encoding, bom_len, _) = detect_encoding(bytes);
+    encoding
+        .decode_without_bom_handling(&bytes[bom_len..])
+        .0
+        .into_owned()
+}
+
+/// Encoding, BOM length and the reason it was chosen
+fn detect_encoding(bytes: &[u8]) -> (&'static Encoding, usize, String) {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        return (
+            encoding,
+            bom_len,
+            format!("{} byte order mark", encoding.name()),
+        );
+    }
+
+    // UTF-16 without a BOM: text is mostly ASCII, so every other byte is zero
+    let sample = &bytes[..bytes.len().min(4096) & !1];
+    let pairs = sample.len() / 2;
+    if pairs > 0 {
+        let even_zeros = sample.iter().step_by(2).filter(|b| **b == 0).count();
+        let odd_zeros = sample
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .filter(|b| **b == 0)
+            .count();
+        if odd_zeros * 10 >= pairs * 3 && even_zeros * 10 < pairs {
+            return (
+                UTF_16LE,
+                0,
+                format!(
+                    "no byte order mark; {} of {} odd bytes are zero",
+                    odd_zeros, pairs
+                ),
+            );
+        }
+        if even_zeros * 10 >= pairs * 3 && odd_zeros * 10 < pairs {
+            return (
+                UTF_16BE,
+                0,
+                format!(
+                    "no byte order mark; {} of {} even bytes are zero",
+                    even_zeros, pairs
+                ),
+            );
+        }
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) if text.is_ascii() => (UTF_8, 0, "plain ASCII".to_string()),
+        Ok(_) => (
+            UTF_8,
+            0,
+            "valid UTF-8 with non-ASCII characters".to_string(),
+        ),
+        Err(e) => (
+            WINDOWS_1252,
+            0,
+            format!(
+                "not valid UTF-8 (byte {} at offset {})",
+                bytes[e.valid_up_to()],
+                e.valid_up_to()
+            ),
+        ),
+    }
+}
+
+struct Layout {
+    delimiter: char,
+    /// Quote character used for splitting
+    quote: Option<char>,
+    /// Quote character actually found around fields
+    quote_used: Option<char>,
+    /// Most common field count
+    width: usize,
+    delimiter_evidence: String,
+    quote_evidence: String,
+}
+
+/// Most common field count among `records` and how many records have it
+fn modal_width(records: &[Record]) -> (usize, usize) {
+    let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+    for record in records {
+        *counts.entry(record.fields.len()).or_default() += 1;
+    }
+    // Ties go to the wider layout
+    counts
+        .into_iter()
+        .max_by_key(|(width, count)| (*count, *width))
+        .unwrap_or((0, 0))
+}
+
+/// Pick the delimiter and quote character that split the sample most consistently
+fn detect_layout(text: &str) -> Layout {
+    struct Candidate {
+        delimiter: char,
+        quote: char,
+        width: usize,
+        consistent: usize,
+        quoted: usize,
+        total: usize,
+    }
+
+    let mut candidates = Vec::new();
+    for quote in QUOTES {
+        for delimiter in DELIMITERS {
+            let records = split_records(text, delimiter, Some(quote), SAMPLE_RECORDS);
+            let (width, consistent) = modal_width(&records);
+            candidates.push(Candidate {
+                delimiter,
+                quote,
+                width,
+                consistent: if width > 1 { consistent } else { 0 },
+                quoted: records.iter().map(|r| r.quoted).sum(),
+                total: records.len(),
+            });
+        }
+    }
+
+    // Quoted fields break ties; after that the first candidate wins, so `"` and `,` are preferred
+    let mut best = 0;
+    for (idx, candidate) in candidates.iter().enumerate() {
+        let current = &candidates[best];
+        if (candidate.consistent, candidate.quoted) > (current.consistent, current.quoted) {
+            best = idx;
+        }
+    }
+    let chosen = &candidates[best];
+
+    let name = |d: char| match d {
+        '\t' => "tab".to_string(),
+        other => format!("'{}'", other),
+    };
+    let delimiter_evidence = if chosen.consistent == 0 {
+        "no delimiter splits the lines; reading one column".to_string()
+    } else {
+        let mut parts = vec![format!(
+            "{} gives {} fields on {} of {} lines",
+            name(chosen.delimiter),
+            chosen.width,
+            chosen.consistent,
+            chosen.total
+        )];
+        if let Some(runner_up) = candidates
+            .iter()
+            .filter(|c| {
+                c.quote == chosen.quote && c.delimiter != chosen.delimiter && c.consistent > 0
+            })
+            .max_by_key(|c| (c.consistent, c.width))
+        {
+            parts.push(format!(
+                "{} gives {} fields on {} lines",
+                name(runner_up.delimiter),
+                runner_up.width,
+                runner_up.consistent
+            ));
+        }
+        parts.join("; ")
+    };
+
+    let quote_used = (chosen.quoted > 0).then_some(chosen.quote);
+    let quote_evidence = match quote_used {
+        Some(quote) => format!("{} fields enclosed in {}", chosen.quoted, quote),
+        None => "no quoted fields".to_string(),
+    };
+
+    Layout {
+        delimiter: if chosen.consistent == 0 {
+            ','
+        } else {
+            chosen.delimiter
+        },
+        quote: Some(chosen.quote),
+        quote_used,
+        width: chosen.width.max(1),
+        delimiter_evidence,
+        quote_evidence,
+    }
+}
+
+/// Split text into records, keeping delimiters and line breaks inside quotes.
+/// Blank records are dropped and fields are trimmed.
+fn split_records(text: &str, delimiter: char, quote: Option<char>, limit: usize) -> Vec<Record> {
+    let mut records = Vec::new();
+    let mut fields: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut quoted = 0;
+    let mut in_quotes = false;
+    let mut field_was_quoted = false;
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if Some(c) == quote {
+                if chars.peek() == Some(&c) {
+                    field.push(c);
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+            continue;
+        }
+
+        if Some(c) == quote && field.trim().is_empty() && !field_was_quoted {
+            in_quotes = true;
+            field_was_quoted = true;
+            field.clear();
+        } else if c == delimiter {
+            if field_was_quoted {
+                quoted += 1;
+            }
+            fields.push(field.trim().to_string());
+            field.clear();
+            field_was_quoted = false;
+        } else if c == '\n' || c == '\r' {
+            if c == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+            if field_was_quoted {
+                quoted += 1;
+            }
+            fields.push(field.trim().to_string());
+            field.clear();
+            field_was_quoted = false;
+            finish_record(&mut records, &mut fields, &mut quoted, record_line);
+            line += 1;
+            record_line = line;
+            if records.len() >= limit {
+                return records;
+            }
+        } else {
+            field.push(c);
+        }
+    }
+
+    if !field.is_empty() || field_was_quoted || !fields.is_empty() {
+        if field_was_quoted {
+            quoted += 1;
+        }
+        fields.push(field.trim().to_string());
+        finish_record(&mut records, &mut fields, &mut quoted, record_line);
+    }
+    records
+}
+
+fn finish_record(
+    records: &mut Vec<Record>,
+    fields: &mut Vec<String>,
+    quoted: &mut usize,
+    line: usize,
+) {
+    if fields.iter().any(|f| !f.is_empty()) {
+        records.push(Record {
+            line,
+            fields: std::mem::take(fields),
+            quoted: *quoted,
+        });
+    } else {
+        fields.clear();
+    }
+    *quoted = 0;
+}
+
+fn is_data_like(cell: &str) -> bool {
+    formats::is_value(cell)
+}
+
+/// Index of the header record and why it was chosen.
+/// The header is the first full-width record made only of labels, followed
+/// by a full-width record with numbers or dates in it.
+fn detect_header(records: &[Record], width: usize) -> (Option<usize>, String) {
+    let full: Vec<usize> = (0..records.len().min(SAMPLE_RECORDS))
+        .filter(|idx| records[*idx].fields.len() == width)
+        .collect();
+    let Some(&first) = full.first() else {
+        return (
+            None,
+            "no line has the expected number of fields".to_string(),
+        );
+    };
+
+    let record = &records[first];
+    let filled = record.fields.iter().filter(|f| !f.is_empty()).count();
+    if let Some(cell) = record.fields.iter().find(|f| is_data_like(f)) {
+        return (
+            None,
+            format!(
+                "line {} starts the data ('{}' is a value)",
+                record.line, cell
+            ),
+        );
+    }
+    if filled * 2 < width {
+        return (
+            None,
+            format!("line {} has too many empty cells for a header", record.line),
+        );
+    }
+    match full.get(1).map(|idx| &records[*idx]) {
+        Some(next) if next.fields.iter().any(|f| is_data_like(f)) => (
+            Some(first),
+            format!(
+                "line {} has only labels and line {} has values",
+                record.line, next.line
+            ),
+        ),
+        Some(_) => (
+            Some(first),
+            format!(
+                "line {} is the first line with {} fields",
+                record.line, width
+            ),
+        ),
+        None => (
+            None,
+            "only one line with the expected number of fields".to_string(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn semicolons_with_decimal_commas() {
+        let text = "Fecha;Concepto;Importe\n05/01/2024;Café;-12,50\n13/01/2024;Nómina;1.000,00\n";
+        let parsed = parse_statement(text.as_bytes()).unwrap();
+        assert_eq!(parsed.detection.encoding, "UTF-8");
+        assert_eq!(parsed.detection.delimiter, ";");
+        assert_eq!(parsed.detection.header_line, Some(1));
+        assert_eq!(
+            parsed.rows[0].values[0],
+            CellValue::Date("2024-01-05".to_string())
+        );
+        assert_eq!(parsed.rows[0].values[2], CellValue::Number(-12.5));
+        assert_eq!(parsed.rows[1].values[2], CellValue::Number(1000.0));
+        assert!(parsed.errors.is_empty());
+    }
+
+    #[test]
+    fn files_without_a_header_get_column_names() {
+        let parsed = parse_statement(b"2024-01-05,OXXO,-12.50\n2024-01-06,Pago,100.00\n").unwrap();
+        assert_eq!(parsed.detection.header_line, None);
+        assert_eq!(parsed.headers, vec!["Column 1", "Column 2", "Column 3"]);
+        assert_eq!(parsed.rows.len(), 2);
+        assert_eq!(parsed.rows[1].values[2], CellValue::Number(100.0));
+    }
+
+    #[test]
+    fn windows_1252_is_the_fallback_for_invalid_utf8() {
+        let parsed =
+            parse_statement(b"Fecha;Concepto;Importe\n13/01/2024;N\xf3mina;1.000,00\n").unwrap();
+        assert_eq!(parsed.detection.encoding, "windows-1252");
+        assert!(!parsed.detection.decode_errors);
+        assert_eq!(parsed.rows[0].cells[1], "Nómina");
+        assert_eq!(parsed.rows[0].values[2], CellValue::Number(1000.0));
+    }
+
+    #[test]
+    fn utf16_is_read_with_or_without_a_byte_order_mark() {
+        let text = "Fecha;Concepto;Importe\n13/01/2024;Nómina;1.000,00\n05/01/2024;Café;-12,50\n";
+        let le: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let be: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let mut with_bom = vec![0xFF, 0xFE];
+        with_bom.extend(&le);
+
+        for (bytes, encoding) in [
+            (&with_bom, "UTF-16LE"),
+            (&le, "UTF-16LE"),
+            (&be, "UTF-16BE"),
+        ] {
+            let parsed = parse_statement(bytes).unwrap();
+            assert_eq!(parsed.detection.encoding, encoding);
+            assert!(!parsed.detection.decode_errors);
+            assert_eq!(parsed.headers, vec!["Fecha", "Concepto", "Importe"]);
+            assert_eq!(parsed.rows[0].cells[1], "Nómina");
+            assert_eq!(parsed.rows[1].cells[1], "Café");
+            assert_eq!(parsed.rows[1].values[2], CellValue::Number(-12.5));
+        }
+        let evidence = parse_statement(&le).unwrap().detection.encoding_evidence;
+        assert!(evidence.starts_with("no byte order mark"), "{}", evidence);
+    }
+}
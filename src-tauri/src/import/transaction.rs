@@ -0,0 +1,265 @@
+//! The row shape shared by every importer.
+//!
+//! Mirrors `ImportTransaction` in `import-service.ts`, so parsed rows can go
+//! straight into an import file in the UI.
+
+use serde::{Deserialize, Serialize};
+
+use super::matching::ImportMatch;
+use crate::ynab4::entities::{
+    BudgetData, DEFERRED_INCOME_CATEGORY_ID, IMMEDIATE_INCOME_CATEGORY_ID,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ImportTransaction {
+    pub id: String,
+    /// YYYY-MM-DD
+    pub date: String,
+    /// Description from the source
+    pub description: String,
+    /// Memo from the source (bank reference, remittance info...)
+    pub original_memo: String,
+    pub account_id: Option<String>,
+    pub account_name: String,
+    pub payee_id: Option<String>,
+    pub payee_name: String,
+    pub suggested_payee: String,
+    pub category_id: Option<String>,
+    pub category_name: String,
+    pub suggested_category: String,
+    pub amount: f64,
+    pub outflow: f64,
+    pub inflow: f64,
+    pub memo: String,
+    pub reference: String,
+    pub flag: Option<String>,
+    pub cleared: bool,
+    /// Reconciled in the source, as in YNAB4's own register export
+    pub reconciled: bool,
+    #[serde(rename = "isMSI")]
+    pub is_msi: bool,
+    pub msi_months: u32,
+    pub msi_original_amount: f64,
+    /// pending, ready, imported or skipped
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub check_number: Option<String>,
+    /// Date the money moved, when the source has one besides the booking date
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_date: Option<String>,
+    /// IBAN of the other party, when the bank gives it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counterparty_iban: Option<String>,
+    /// Account named by the source as the other side of a transfer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transfer_account_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub splits: Vec<ImportSplit>,
+    /// What duplicate detection found in the register for this row
+    #[serde(default, rename = "match", skip_serializing_if = "Option::is_none")]
+    pub import_match: Option<ImportMatch>,
+}
+
+/// One line of a split row
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSplit {
+    pub amount: f64,
+    pub category_id: Option<String>,
+    pub category_name: String,
+    pub memo: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transfer_account_name: Option<String>,
+}
+
+/// Statement balance on a date, as given to `reconcile_start`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatementBalance {
+    pub amount: f64,
+    pub date: Option<String>,
+}
+
+/// A bank statement with its balances (camt.053, MT940)
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankStatement {
+    /// Statement identifier given by the bank
+    pub statement_id: String,
+    /// IBAN or account number
+    pub account: String,
+    pub currency: Option<String>,
+    /// Budget account that looks like this one, when a budget was given
+    pub matched_account_id: Option<String>,
+    pub opening_balance: Option<StatementBalance>,
+    /// Balance to reconcile against
+    pub closing_balance: Option<StatementBalance>,
+    pub transactions: Vec<ImportTransaction>,
+    /// Entries that could not be read, and balance mismatches
+    pub errors: Vec<String>,
+}
+
+impl BankStatement {
+    /// Match the budget account and label every row with it
+    pub fn assign_account(&mut self, data: Option<&BudgetData>) {
+        self.matched_account_id = data.and_then(|d| match_account(d, &self.account));
+        let name = self
+            .matched_account_id
+            .as_ref()
+            .and_then(|id| data?.accounts.iter().find(|a| &a.entity_id == id))
+            .map(|a| a.account_name.clone())
+            .unwrap_or_else(|| self.account.clone());
+        for row in self.transactions.iter_mut() {
+            row.account_id = self.matched_account_id.clone();
+            row.account_name = name.clone();
+        }
+    }
+
+    /// Note when the rows don't add up from the opening to the closing balance
+    pub fn check_balances(&mut self) {
+        let (Some(opening), Some(closing)) = (&self.opening_balance, &self.closing_balance) else {
+            return;
+        };
+        let total: f64 = self.transactions.iter().map(|t| t.amount).sum();
+        let expected = ((opening.amount + total) * 100.0).round() / 100.0;
+        if (expected - closing.amount).abs() >= 0.005 {
+            self.errors.push(format!(
+                "Opening balance {:.2} plus {} transactions gives {:.2}, but the closing balance is {:.2}",
+                opening.amount,
+                self.transactions.len(),
+                expected,
+                closing.amount
+            ));
+        }
+    }
+}
+
+impl ImportTransaction {
+    /// A pending row; `id` follows the `import-<time>-<index>` scheme of the UI
+    pub fn new(batch: i64, index: usize, date: String, description: String, amount: f64) -> Self {
+        ImportTransaction {
+            id: format!("import-{}-{}", batch, index),
+            date,
+            suggested_payee: description.clone(),
+            description,
+            status: "pending".to_string(),
+            ..Default::default()
+        }
+        .with_amount(amount)
+    }
+
+    /// Set the amount and the matching outflow/inflow split
+    pub fn with_amount(mut self, amount: f64) -> Self {
+        self.amount = amount;
+        self.outflow = if amount < 0.0 { -amount } else { 0.0 };
+        self.inflow = if amount > 0.0 { amount } else { 0.0 };
+        self
+    }
+}
+
+/// Timestamp used to build row ids for one parse
+pub fn batch_id() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// Budget account whose name or note mentions the last four digits of `number`
+pub fn match_account(data: &BudgetData, number: &str) -> Option<String> {
+    let digits: String = number.chars().filter(|c| c.is_ascii_digit()).collect();
+    if digits.len() < 4 {
+        return None;
+    }
+    let last4 = &digits[digits.len() - 4..];
+    let mut matches = data
+        .accounts
+        .iter()
+        .filter(|a| !a.is_tombstone && !a.hidden)
+        .filter(|a| {
+            a.account_name.contains(last4)
+                || a.note.as_deref().is_some_and(|note| note.contains(last4))
+        });
+    let first = matches.next()?;
+    // Two accounts ending in the same digits: let the user pick
+    if matches.next().is_some() {
+        return None;
+    }
+    Some(first.entity_id.clone())
+}
+
+/// Names YNAB4 uses for the income categories in its exports
+const INCOME_NAMES: [(&str, &str); 2] = [
+    (IMMEDIATE_INCOME_CATEGORY_ID, "Income:Available this month"),
+    (DEFERRED_INCOME_CATEGORY_ID, "Income:Available next month"),
+];
+
+/// "Master:Sub" name of a category
+pub fn category_path(data: &BudgetData, category_id: &str) -> Option<String> {
+    if let Some((_, name)) = INCOME_NAMES.iter().find(|(id, _)| *id == category_id) {
+        return Some(name.to_string());
+    }
+    data.master_categories.iter().find_map(|master| {
+        let category = master
+            .sub_categories
+            .iter()
+            .flatten()
+            .find(|c| c.entity_id == category_id)?;
+        Some(format!("{}:{}", master.name, category.name))
+    })
+}
+
+/// Category named "Master:Sub", or just "Sub" when only one category has that name
+pub fn find_category(data: &BudgetData, path: &str) -> Option<String> {
+    let path = path.trim();
+    if let Some((id, _)) = INCOME_NAMES
+        .iter()
+        .find(|(_, name)| name.eq_ignore_ascii_case(path))
+    {
+        return Some(id.to_string());
+    }
+    let (master_name, name) = match path.split_once(':') {
+        Some((master, name)) => (Some(master.trim()), name.trim()),
+        None => (None, path),
+    };
+    let mut found = data
+        .master_categories
+        .iter()
+        .filter(|m| !m.is_tombstone)
+        .filter(|m| master_name.map_or(true, |n| m.name.eq_ignore_ascii_case(n)))
+        .flat_map(|m| m.sub_categories.iter().flatten())
+        .filter(|c| !c.is_tombstone && c.name.eq_ignore_ascii_case(name));
+    let first = found.next()?;
+    if master_name.is_none() && found.next().is_some() {
+        return None;
+    }
+    Some(first.entity_id.clone())
+}
+
+/// Words that say nothing about who a business is
+const NAME_NOISE: [&str; 12] = [
+    "sa", "de", "cv", "s", "a", "c", "v", "rl", "sapi", "sab", "the", "inc",
+];
+
+fn name_tokens(name: &str) -> Vec<String> {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty() && !NAME_NOISE.contains(t))
+        .map(str::to_string)
+        .collect()
+}
+
+/// How alike two payee names are, from 0 to 1: the share of words they have
+/// in common, ignoring company suffixes such as "S.A. de C.V."
+pub fn name_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (name_tokens(a), name_tokens(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    // Bank descriptions cut words short, so a prefix of at least 3 letters counts
+    let alike = |x: &String, y: &String| {
+        x == y
+            || (x.len().min(y.len()) >= 3
+                && (x.starts_with(y.as_str()) || y.starts_with(x.as_str())))
+    };
+    let common = a.iter().filter(|x| b.iter().any(|y| alike(x, y))).count();
+    common as f64 / a.len().max(b.len()) as f64
+}
@@ -0,0 +1,969 @@
+//! YNAB4's own CSV exports: `Register.csv` and `Budget.csv`.
+//!
+//! Both are recognised by their header row. The register has one line per
+//! transaction and one per split line, the latter with `(Split 1/3)` in front
+//! of the memo; transfers show up in both accounts as `Transfer : <account>`
+//! payees, so only one side of each is kept. The budget export has one line
+//! per month and category, which is enough to put every month's budgeted
+//! amounts back into a budget whose folder was lost or broken.
+
+use chrono::{Datelike, NaiveDate};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
+
+use super::formats::{self, ColumnKind, DateFormat, NumberFormat};
+use super::statement::{CellValue, ParsedStatement};
+use super::transaction::{self, ImportSplit, ImportTransaction};
+use crate::ynab4::budget::{new_entity_id, Budget};
+use crate::ynab4::entities::*;
+
+/// Master category YNAB4 lists hidden categories under
+const HIDDEN_MASTER: &str = "Hidden Categories";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Ynab4Export {
+    Register,
+    Budget,
+}
+
+/// A recognised export, read
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Ynab4ExportFile {
+    Register(RegisterExport),
+    Budget(BudgetExport),
+}
+
+/// Column of each header, compared without case
+struct Columns<'a>(&'a [String]);
+
+impl Columns<'_> {
+    fn find(&self, name: &str) -> Option<usize> {
+        self.0.iter().position(|h| {
+            h.trim_start_matches('\u{feff}')
+                .trim()
+                .eq_ignore_ascii_case(name)
+        })
+    }
+
+    fn has_all(&self, names: &[&str]) -> bool {
+        names.iter().all(|n| self.find(n).is_some())
+    }
+}
+
+const REGISTER_COLUMNS: [&str; 6] = ["Account", "Date", "Payee", "Outflow", "Inflow", "Cleared"];
+const BUDGET_COLUMNS: [&str; 3] = ["Month", "Sub Category", "Budgeted"];
+
+/// Which YNAB4 export a header row belongs to, if any
+pub fn detect_export(headers: &[String]) -> Option<Ynab4Export> {
+    let columns = Columns(headers);
+    if columns.has_all(&REGISTER_COLUMNS) {
+        Some(Ynab4Export::Register)
+    } else if columns.has_all(&BUDGET_COLUMNS) && columns.find("Master Category").is_some() {
+        Some(Ynab4Export::Budget)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterExport {
+    pub transactions: Vec<ImportTransaction>,
+    /// Account names in the file, in order of appearance
+    pub accounts: Vec<String>,
+    /// Accounts and categories the budget doesn't have; empty without a budget
+    pub missing_accounts: Vec<String>,
+    pub missing_categories: Vec<String>,
+    /// Transfers listed in both accounts, kept only once
+    pub paired_transfers: usize,
+    pub date_format: DateFormat,
+    /// Lines that could not be read
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetExportRow {
+    /// First day of the month ("2024-01-01")
+    pub month: String,
+    pub master_category: String,
+    pub sub_category: String,
+    pub budgeted: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetExport {
+    pub rows: Vec<BudgetExportRow>,
+    /// Months in the file, "YYYY-MM", oldest first
+    pub months: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetRestoreResult {
+    pub month_count: usize,
+    /// Amounts that changed, including new ones
+    pub updated_count: usize,
+    pub unchanged_count: usize,
+    /// Rows of hidden categories the budget doesn't have, which are not restored
+    pub skipped_count: usize,
+    pub created_master_category_ids: Vec<String>,
+    pub created_category_ids: Vec<String>,
+    pub ydiff_path: Option<String>,
+    /// Why rows were skipped
+    pub errors: Vec<String>,
+}
+
+fn split_marker() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^\(Split (\d+)/(\d+)\)\s*(.*)$").unwrap())
+}
+
+fn transfer_payee() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^Transfer\s*:\s*(.+)$").unwrap())
+}
+
+/// Amounts as YNAB4 writes them, with the budget's currency symbol around them
+fn amount_text(cell: &str) -> String {
+    cell.chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '-' | '(' | ')'))
+        .collect()
+}
+
+/// Number format shared by the amount columns
+fn amount_format(statement: &ParsedStatement, indexes: &[usize]) -> NumberFormat {
+    let texts: Vec<String> = statement
+        .rows
+        .iter()
+        .flat_map(|row| indexes.iter().map(|&idx| amount_text(&row.cells[idx])))
+        .collect();
+    let cells: Vec<&str> = texts.iter().map(String::as_str).collect();
+    match formats::infer_column(0, &cells).kind {
+        ColumnKind::Number { format } => format,
+        _ => NumberFormat::default(),
+    }
+}
+
+fn read_amount(cell: &str, format: &NumberFormat) -> Option<f64> {
+    let text = amount_text(cell);
+    if text.is_empty() {
+        return Some(0.0);
+    }
+    formats::parse_amount(&text, format)
+}
+
+/// "Master:Sub" of a register line, from the split columns when present
+fn category_of(cells: &[String], columns: &Columns) -> String {
+    let cell = |name: &str| columns.find(name).map_or("", |idx| cells[idx].trim());
+    match (cell("Master Category"), cell("Sub Category")) {
+        (master, sub) if !master.is_empty() && !sub.is_empty() => format!("{}:{}", master, sub),
+        _ => cell("Category").to_string(),
+    }
+}
+
+/// One register line before splits are put together
+struct Line {
+    number: usize,
+    account: String,
+    date: String,
+    payee: String,
+    transfer: Option<String>,
+    category: String,
+    memo: String,
+    amount: f64,
+    cleared: String,
+    flag: String,
+    check_number: String,
+    /// (index, count) from a `(Split i/n)` memo
+    split: Option<(usize, usize)>,
+}
+
+fn read_lines(
+    statement: &ParsedStatement,
+    columns: &Columns,
+    errors: &mut Vec<String>,
+) -> Result<(Vec<Line>, DateFormat), String> {
+    let index = |name: &str| {
+        columns
+            .find(name)
+            .ok_or_else(|| format!("Missing column {}", name))
+    };
+    let (account, date, payee, outflow, inflow, cleared) = (
+        index("Account")?,
+        index("Date")?,
+        index("Payee")?,
+        index("Outflow")?,
+        index("Inflow")?,
+        index("Cleared")?,
+    );
+    let date_format = match statement.columns.get(date).map(|c| &c.kind) {
+        Some(ColumnKind::Date { format }) => *format,
+        _ => return Err("The dates of the register could not be read".to_string()),
+    };
+    let number_format = amount_format(statement, &[outflow, inflow]);
+    let optional = |cells: &[String], name: &str| {
+        columns
+            .find(name)
+            .map_or(String::new(), |idx| cells[idx].trim().to_string())
+    };
+
+    let mut lines = Vec::new();
+    for row in &statement.rows {
+        let cells = &row.cells;
+        if cells.iter().all(|c| c.trim().is_empty()) {
+            continue;
+        }
+        let Some(CellValue::Date(iso)) = row.values.get(date) else {
+            errors.push(format!("Line {}: invalid date '{}'", row.line, cells[date]));
+            continue;
+        };
+        let (Some(out), Some(inn)) = (
+            read_amount(&cells[outflow], &number_format),
+            read_amount(&cells[inflow], &number_format),
+        ) else {
+            errors.push(format!(
+                "Line {}: invalid amount '{}' / '{}'",
+                row.line, cells[outflow], cells[inflow]
+            ));
+            continue;
+        };
+        let payee_text = cells[payee].trim().to_string();
+        let transfer = transfer_payee()
+            .captures(&payee_text)
+            .map(|c| c[1].trim().to_string());
+        let memo = optional(cells, "Memo");
+        let (split, memo) = match split_marker().captures(&memo) {
+            Some(caps) => {
+                let index: usize = caps[1].parse().unwrap_or(0);
+                let count: usize = caps[2].parse().unwrap_or(0);
+                (Some((index, count)), caps[3].trim().to_string())
+            }
+            None => (None, memo),
+        };
+        lines.push(Line {
+            number: row.line,
+            account: cells[account].trim().to_string(),
+            date: iso.clone(),
+            payee: if transfer.is_some() {
+                String::new()
+            } else {
+                payee_text
+            },
+            transfer,
+            category: category_of(cells, columns),
+            memo,
+            amount: ((inn - out.abs()) * 100.0).round() / 100.0,
+            cleared: cells[cleared].trim().to_string(),
+            flag: optional(cells, "Flag"),
+            check_number: optional(cells, "Check Number"),
+            split,
+        });
+    }
+    Ok((lines, date_format))
+}
+
+/// Lines of one transaction: a single line, or every line of a split
+fn group_lines(lines: Vec<Line>, errors: &mut Vec<String>) -> Vec<Vec<Line>> {
+    let mut groups: Vec<Vec<Line>> = Vec::new();
+    let mut open = false;
+    for line in lines {
+        let continues = open
+            && groups.last().and_then(|g| g.last()).is_some_and(|prev| {
+                prev.account == line.account
+                    && prev.date == line.date
+                    && matches!((prev.split, line.split), (Some((i, n)), Some((j, m))) if j == i + 1 && m == n)
+            });
+        if continues {
+            let group = groups.last_mut().unwrap();
+            group.push(line);
+        } else {
+            if open {
+                let group = groups.last().unwrap();
+                errors.push(format!("Line {}: split is missing lines", group[0].number));
+            }
+            groups.push(vec![line]);
+        }
+        let last = groups.last().unwrap().last().unwrap();
+        open = matches!(last.split, Some((i, n)) if i < n);
+    }
+    if open {
+        let group = groups.last().unwrap();
+        errors.push(format!("Line {}: split is missing lines", group[0].number));
+    }
+    groups
+}
+
+/// (account, other account, date, amount) of one side of a transfer
+type TransferLeg = (String, String, String, i64);
+
+fn leg(account: &str, other: &str, date: &str, amount: f64) -> TransferLeg {
+    (
+        account.to_lowercase(),
+        other.to_lowercase(),
+        date.to_string(),
+        (amount * 100.0).round() as i64,
+    )
+}
+
+fn counterpart(leg: &TransferLeg) -> TransferLeg {
+    (leg.1.clone(), leg.0.clone(), leg.2.clone(), -leg.3)
+}
+
+/// Drop the second side of transfers listed in both accounts. Split lines
+/// always stay, since a split can't lose one of its lines.
+fn pair_transfers(groups: Vec<Vec<Line>>) -> (Vec<Vec<Line>>, usize) {
+    let mut pool: Vec<TransferLeg> = groups
+        .iter()
+        .filter(|g| g.len() > 1)
+        .flatten()
+        .filter_map(|l| {
+            l.transfer
+                .as_ref()
+                .map(|other| leg(&l.account, other, &l.date, l.amount))
+        })
+        .collect();
+    let mut kept = Vec::new();
+    let mut paired = 0;
+    for group in groups {
+        if let [line] = group.as_slice() {
+            if let Some(other) = &line.transfer {
+                let this = leg(&line.account, other, &line.date, line.amount);
+                if let Some(pos) = pool.iter().position(|l| *l == counterpart(&this)) {
+                    pool.remove(pos);
+                    paired += 1;
+                    continue;
+                }
+                pool.push(this);
+            }
+        }
+        kept.push(group);
+    }
+    (kept, paired)
+}
+
+fn category_id(
+    data: Option<&BudgetData>,
+    path: &str,
+    missing: &mut BTreeSet<String>,
+) -> Option<String> {
+    if path.is_empty() {
+        return None;
+    }
+    let data = data?;
+    let found = transaction::find_category(data, path);
+    if found.is_none() {
+        missing.insert(path.to_string());
+    }
+    found
+}
+
+/// Rebuild the transactions of a `Register.csv`. With budget data, accounts
+/// and categories are matched by name and rows that match fully are ready.
+pub fn parse_register(
+    statement: &ParsedStatement,
+    data: Option<&BudgetData>,
+) -> Result<RegisterExport, String> {
+    let columns = Columns(&statement.headers);
+    if detect_export(&statement.headers) != Some(Ynab4Export::Register) {
+        return Err("Not a YNAB4 register export".to_string());
+    }
+    let mut errors = Vec::new();
+    let (lines, date_format) = read_lines(statement, &columns, &mut errors)?;
+    let (groups, paired_transfers) = pair_transfers(group_lines(lines, &mut errors));
+
+    let account_id = |name: &str| {
+        data.and_then(|d| {
+            d.accounts
+                .iter()
+                .find(|a| !a.is_tombstone && a.account_name.trim().eq_ignore_ascii_case(name))
+                .map(|a| a.entity_id.clone())
+        })
+    };
+    let mut accounts: Vec<String> = Vec::new();
+    let mut missing_accounts = BTreeSet::new();
+    let mut missing_categories = BTreeSet::new();
+    let batch = transaction::batch_id();
+    let mut transactions = Vec::new();
+
+    for (index, group) in groups.into_iter().enumerate() {
+        let first = &group[0];
+        let payee = group
+            .iter()
+            .map(|l| l.payee.as_str())
+            .find(|p| !p.is_empty())
+            .unwrap_or_default();
+        let description = if payee.is_empty() {
+            first
+                .transfer
+                .as_deref()
+                .map_or(String::new(), |t| format!("Transfer : {}", t))
+        } else {
+            payee.to_string()
+        };
+        let amount = (group.iter().map(|l| l.amount).sum::<f64>() * 100.0).round() / 100.0;
+        let mut row = ImportTransaction::new(batch, index, first.date.clone(), description, amount);
+        row.account_name = first.account.clone();
+        row.account_id = account_id(&first.account);
+        row.payee_name = payee.to_string();
+        row.suggested_payee = payee.to_string();
+        row.flag = Some(first.flag.clone()).filter(|f| !f.is_empty());
+        row.check_number = Some(first.check_number.clone()).filter(|n| !n.is_empty());
+        row.cleared = matches!(
+            first.cleared.to_uppercase().as_str(),
+            "C" | "R" | "CLEARED" | "RECONCILED"
+        );
+        row.reconciled = matches!(first.cleared.to_uppercase().as_str(), "R" | "RECONCILED");
+
+        let mut complete = true;
+        if group.len() > 1 {
+            for line in &group {
+                let category_id = category_id(data, &line.category, &mut missing_categories);
+                complete &= line.transfer.is_some() || category_id.is_some();
+                row.splits.push(ImportSplit {
+                    amount: line.amount,
+                    category_id,
+                    category_name: line.category.clone(),
+                    memo: line.memo.clone(),
+                    transfer_account_name: line.transfer.clone(),
+                });
+            }
+        } else {
+            row.memo = first.memo.clone();
+            row.transfer_account_name = first.transfer.clone();
+            row.category_id = category_id(data, &first.category, &mut missing_categories);
+            row.category_name = first.category.clone();
+            row.suggested_category = first.category.clone();
+            complete = first.transfer.is_some() || row.category_id.is_some();
+        }
+
+        for name in group
+            .iter()
+            .flat_map(|l| std::iter::once(&l.account).chain(l.transfer.as_ref()))
+        {
+            if !accounts.iter().any(|a| a.eq_ignore_ascii_case(name)) {
+                accounts.push(name.clone());
+            }
+            if data.is_some() && account_id(name).is_none() {
+                missing_accounts.insert(name.clone());
+            }
+        }
+        let accounts_found = group
+            .iter()
+            .flat_map(|l| std::iter::once(&l.account).chain(l.transfer.as_ref()))
+            .all(|name| account_id(name).is_some());
+        if complete && accounts_found {
+            row.status = "ready".to_string();
+        }
+        transactions.push(row);
+    }
+
+    Ok(RegisterExport {
+        transactions,
+        accounts,
+        missing_accounts: missing_accounts.into_iter().collect(),
+        missing_categories: missing_categories.into_iter().collect(),
+        paired_transfers,
+        date_format,
+        errors,
+    })
+}
+
+/// Read a statement as a YNAB4 export; None when its header is not one
+pub fn read_export(
+    statement: &ParsedStatement,
+    data: Option<&BudgetData>,
+) -> Result<Option<Ynab4ExportFile>, String> {
+    match detect_export(&statement.headers) {
+        Some(Ynab4Export::Register) => {
+            parse_register(statement, data).map(|r| Some(Ynab4ExportFile::Register(r)))
+        }
+        Some(Ynab4Export::Budget) => {
+            parse_budget(statement).map(|b| Some(Ynab4ExportFile::Budget(b)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// First day of a month written as "January 2024", "ene 2024", "01/2024",
+/// "2024-01" or as a full date
+fn parse_month(cell: &str) -> Option<NaiveDate> {
+    let parts: Vec<&str> = cell
+        .split(|c: char| c.is_whitespace() || c == '/' || c == '-' || c == '.')
+        .filter(|p| !p.is_empty())
+        .collect();
+    if let [a, b] = parts.as_slice() {
+        let (year, month) = if a.len() == 4 { (a, b) } else { (b, a) };
+        let year: i32 = year.parse().ok().filter(|_| year.len() == 4)?;
+        let month = month
+            .parse()
+            .ok()
+            .or_else(|| formats::month_from_name(month))?;
+        return NaiveDate::from_ymd_opt(year, month, 1);
+    }
+    [
+        DateFormat::Ymd,
+        DateFormat::Dmy,
+        DateFormat::Mdy,
+        DateFormat::DayMonthName,
+        DateFormat::MonthNameDay,
+    ]
+    .into_iter()
+    .find_map(|format| formats::parse_date(cell, format))
+    .and_then(|date| NaiveDate::from_ymd_opt(date.year(), date.month(), 1))
+}
+
+/// Read the budgeted amounts of a `Budget.csv`
+pub fn parse_budget(statement: &ParsedStatement) -> Result<BudgetExport, String> {
+    let columns = Columns(&statement.headers);
+    if detect_export(&statement.headers) != Some(Ynab4Export::Budget) {
+        return Err("Not a YNAB4 budget export".to_string());
+    }
+    let index = |name: &str| {
+        columns
+            .find(name)
+            .ok_or_else(|| format!("Missing column {}", name))
+    };
+    let (month, master, sub, budgeted) = (
+        index("Month")?,
+        index("Master Category")?,
+        index("Sub Category")?,
+        index("Budgeted")?,
+    );
+    let number_format = amount_format(statement, &[budgeted]);
+
+    let mut rows = Vec::new();
+    let mut months = BTreeSet::new();
+    let mut errors = Vec::new();
+    for row in &statement.rows {
+        let cells = &row.cells;
+        if cells.iter().all(|c| c.trim().is_empty()) {
+            continue;
+        }
+        let Some(date) = parse_month(cells[month].trim()) else {
+            errors.push(format!(
+                "Line {}: invalid month '{}'",
+                row.line, cells[month]
+            ));
+            continue;
+        };
+        let Some(amount) = read_amount(&cells[budgeted], &number_format) else {
+            errors.push(format!(
+                "Line {}: invalid amount '{}'",
+                row.line, cells[budgeted]
+            ));
+            continue;
+        };
+        let (master_category, sub_category) = (
+            cells[master].trim().to_string(),
+            cells[sub].trim().to_string(),
+        );
+        if sub_category.is_empty() {
+            continue;
+        }
+        months.insert(date.format("%Y-%m").to_string());
+        rows.push(BudgetExportRow {
+            month: date.format("%Y-%m-%d").to_string(),
+            master_category,
+            sub_category,
+            budgeted: amount,
+        });
+    }
+    if rows.is_empty() {
+        return Err("The budget export has no amounts".to_string());
+    }
+    Ok(BudgetExport {
+        rows,
+        months: months.into_iter().collect(),
+        errors,
+    })
+}
+
+/// Category of a hidden category row. YNAB4 renames a category it hides to
+/// "Master ` Name ` N" and moves it under the hidden master category, so the
+/// name is looked up there first and then, in case it was shown again, under
+/// its original master category. Hidden categories are never created.
+fn find_hidden_category(data: &BudgetData, row: &BudgetExportRow) -> Option<String> {
+    let hidden = data
+        .master_categories
+        .iter()
+        .filter(|m| !m.is_tombstone && m.name.trim().eq_ignore_ascii_case(HIDDEN_MASTER))
+        .flat_map(|m| m.sub_categories.iter().flatten())
+        .find(|c| !c.is_tombstone && c.name.trim().eq_ignore_ascii_case(&row.sub_category));
+    if let Some(category) = hidden {
+        return Some(category.entity_id.clone());
+    }
+    let mut parts = row.sub_category.split('`').map(str::trim);
+    let (master, name) = (parts.next()?, parts.next()?);
+    transaction::find_category(data, &format!("{}:{}", master, name))
+}
+
+/// Category for a budget row, creating it (and its master category) when the
+/// budget doesn't have it; what it writes is added to `changed`
+fn ensure_category(
+    budget: &mut Budget,
+    row: &BudgetExportRow,
+    result: &mut BudgetRestoreResult,
+    changed: &mut Vec<String>,
+) -> String {
+    let path = format!("{}:{}", row.master_category, row.sub_category);
+    if let Some(id) = transaction::find_category(&budget.data, &path) {
+        return id;
+    }
+    let masters = &mut budget.data.master_categories;
+    let master_idx = match masters
+        .iter()
+        .position(|m| !m.is_tombstone && m.name.trim().eq_ignore_ascii_case(&row.master_category))
+    {
+        Some(idx) => idx,
+        None => {
+            let sortable_index = masters
+                .iter()
+                .filter_map(|m| m.sortable_index)
+                .fold(0.0, f64::max)
+                + 1.0;
+            masters.push(MasterCategory {
+                entity_id: new_entity_id(),
+                entity_type: "masterCategory".to_string(),
+                name: row.master_category.clone(),
+                category_type: Some("OUTFLOW".to_string()),
+                sortable_index: Some(sortable_index),
+                sub_categories: Some(Vec::new()),
+                ..Default::default()
+            });
+            result
+                .created_master_category_ids
+                .push(masters[masters.len() - 1].entity_id.clone());
+            masters.len() - 1
+        }
+    };
+    let master = &mut masters[master_idx];
+    let subs = master.sub_categories.get_or_insert_with(Vec::new);
+    let sortable_index = subs
+        .iter()
+        .filter_map(|c| c.sortable_index)
+        .fold(0.0, f64::max)
+        + 1.0;
+    let category = Category {
+        entity_id: new_entity_id(),
+        entity_type: "category".to_string(),
+        name: row.sub_category.clone(),
+        category_type: Some("OUTFLOW".to_string()),
+        master_category_id: master.entity_id.clone(),
+        sortable_index: Some(sortable_index),
+        ..Default::default()
+    };
+    let id = category.entity_id.clone();
+    subs.push(category);
+    changed.push(master.entity_id.clone());
+    changed.push(id.clone());
+    result.created_category_ids.push(id.clone());
+    id
+}
+
+/// Set every month's budgeted amounts from a budget export, creating missing
+/// categories, and write the changes as one ydiff
+pub fn restore_budgeted(
+    budget: &mut Budget,
+    export: &BudgetExport,
+) -> Result<BudgetRestoreResult, String> {
+    let mut result = BudgetRestoreResult {
+        month_count: export.months.len(),
+        ..Default::default()
+    };
+    let mut changed = Vec::new();
+    for row in &export.rows {
+        let category_id = if row.master_category.eq_ignore_ascii_case(HIDDEN_MASTER) {
+            match find_hidden_category(&budget.data, row) {
+                Some(id) => id,
+                None => {
+                    result.skipped_count += 1;
+                    result.errors.push(format!(
+                        "{}: hidden category '{}' is not in the budget",
+                        &row.month[..7],
+                        row.sub_category
+                    ));
+                    continue;
+                }
+            }
+        } else {
+            ensure_category(budget, row, &mut result, &mut changed)
+        };
+        let month_key = &row.month[..7];
+        let months = &mut budget.data.monthly_budgets;
+        let month_idx = match months
+            .iter()
+            .position(|m| !m.is_tombstone && m.month_key() == month_key)
+        {
+            Some(idx) => idx,
+            None if row.budgeted == 0.0 => {
+                result.unchanged_count += 1;
+                continue;
+            }
+            None => {
+                months.push(MonthlyBudget {
+                    entity_id: format!("MB/{}", month_key),
+                    entity_type: "monthlyBudget".to_string(),
+                    month: row.month.clone(),
+                    ..Default::default()
+                });
+                changed.push(format!("MB/{}", month_key));
+                months.len() - 1
+            }
+        };
+        let month = &mut months[month_idx];
+        let parent_id = month.entity_id.clone();
+        let list = &mut month.monthly_sub_category_budgets;
+        match list.iter_mut().find(|b| b.category_id == category_id) {
+            Some(monthly) if !monthly.is_tombstone && monthly.budgeted == row.budgeted => {
+                result.unchanged_count += 1
+            }
+            Some(monthly) => {
+                monthly.is_tombstone = false;
+                monthly.budgeted = row.budgeted;
+                changed.push(monthly.entity_id.clone());
+                result.updated_count += 1;
+            }
+            None if row.budgeted == 0.0 => result.unchanged_count += 1,
+            None => {
+                let entity_id = format!("MCB/{}/{}", month_key, category_id);
+                list.push(MonthlySubCategoryBudget {
+                    entity_id: entity_id.clone(),
+                    entity_type: "monthlyCategoryBudget".to_string(),
+                    category_id,
+                    budgeted: row.budgeted,
+                    parent_monthly_budget_id: parent_id,
+                    ..Default::default()
+                });
+                changed.push(entity_id);
+                result.updated_count += 1;
+            }
+        }
+    }
+    budget
+        .data
+        .monthly_budgets
+        .sort_by(|a, b| a.month.cmp(&b.month));
+    for id in &changed {
+        budget.mark_dirty(id.clone());
+    }
+    let ydiff_path = budget.save_entities(&changed)?;
+    result.ydiff_path = ydiff_path.map(|p| p.to_string_lossy().to_string());
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::statement::parse_statement;
+    use crate::ynab4::fixtures;
+
+    const REGISTER: &str = "\u{feff}\"Account\",\"Flag\",\"Check Number\",\"Date\",\"Payee\",\"Category\",\"Master Category\",\"Sub Category\",\"Memo\",\"Outflow\",\"Inflow\",\"Cleared\",\"Running Balance\"
+\"Checking\",\"\",\"\",\"01/02/2024\",\"Employer\",\"Income: Available this month\",\"Income\",\"Available this month\",\"\",\"$0.00\",\"$1,000.00\",R,\"$1,000.00\"
+\"Checking\",\"Red\",\"101\",\"01/03/2024\",\"Walmart\",\"Everyday: Food\",\"Everyday\",\"Food\",\"(Split 1/3) groceries\",\"$80.00\",\"$0.00\",C,\"$920.00\"
+\"Checking\",\"Red\",\"101\",\"01/03/2024\",\"Walmart\",\"Everyday: Fun\",\"Everyday\",\"Fun\",\"(Split 2/3) toys\",\"$20.00\",\"$0.00\",C,\"$900.00\"
+\"Checking\",\"Red\",\"101\",\"01/03/2024\",\"Transfer : Visa\",\"\",\"\",\"\",\"(Split 3/3) card\",\"$50.00\",\"$0.00\",C,\"$850.00\"
+\"Checking\",\"\",\"\",\"01/15/2024\",\"Transfer : Visa\",\"\",\"\",\"\",\"payment\",\"$200.00\",\"$0.00\",U,\"$650.00\"
+\"Checking\",\"\",\"\",\"01/20/2024\",\"Cafe Luna\",\"Everyday: Coffee\",\"Everyday\",\"Coffee\",\"\",\"$4.50\",\"$0.00\",U,\"$645.50\"
+\"Visa\",\"\",\"\",\"01/03/2024\",\"Transfer : Checking\",\"\",\"\",\"\",\"card\",\"$0.00\",\"$50.00\",C,\"$50.00\"
+\"Visa\",\"\",\"\",\"01/15/2024\",\"Transfer : Checking\",\"\",\"\",\"\",\"payment\",\"$0.00\",\"$200.00\",U,\"$250.00\"
+\"Savings\",\"\",\"\",\"01/31/2024\",\"Bank\",\"Everyday: Fun\",\"Everyday\",\"Fun\",\"\",\"$1.00\",\"$0.00\",U,\"-$1.00\"
+";
+
+    const BUDGET: &str =
+        "\"Month\",\"Category\",\"Master Category\",\"Sub Category\",\"Budgeted\",\"Outflows\",\"Category Balance\"
+\"January 2024\",\"Everyday: Food\",\"Everyday\",\"Food\",\"$100.00\",\"-$80.00\",\"$20.00\"
+\"January 2024\",\"Everyday: Fun\",\"Everyday\",\"Fun\",\"$75.00\",\"-$20.00\",\"$55.00\"
+\"January 2024\",\"Bills: Phone\",\"Bills\",\"Phone\",\"$30.00\",\"$0.00\",\"$30.00\"
+\"April 2024\",\"Everyday: Food\",\"Everyday\",\"Food\",\"$1,250.50\",\"$0.00\",\"$1,250.50\"
+\"April 2024\",\"Hidden Categories\",\"Hidden Categories\",\"Old ` Stuff ` 3\",\"$10.00\",\"$0.00\",\"$10.00\"
+";
+
+    #[test]
+    fn reads_register_export() {
+        let path = fixtures::budget_folder("ynab4-csv-register");
+        let budget = Budget::load(&path).unwrap();
+        let parsed = parse_statement(REGISTER.as_bytes()).unwrap();
+        assert_eq!(detect_export(&parsed.headers), Some(Ynab4Export::Register));
+        let Some(Ynab4ExportFile::Register(export)) =
+            read_export(&parsed, Some(&budget.data)).unwrap()
+        else {
+            panic!("not read as a register");
+        };
+        assert!(export.errors.is_empty(), "{:?}", export.errors);
+        assert_eq!(export.accounts, vec!["Checking", "Visa", "Savings"]);
+        assert_eq!(export.missing_accounts, vec!["Savings"]);
+        assert_eq!(export.missing_categories, vec!["Everyday:Coffee"]);
+        // The plain Visa side of the payment and of the split's card line
+        assert_eq!(export.paired_transfers, 2);
+        let rows = &export.transactions;
+        assert_eq!(rows.len(), 5);
+
+        let income = &rows[0];
+        assert_eq!(income.date, "2024-01-02");
+        assert_eq!(income.amount, 1000.0);
+        assert_eq!(
+            income.category_id.as_deref(),
+            Some("Category/__ImmediateIncome__")
+        );
+        assert!(income.cleared && income.reconciled);
+        assert_eq!(income.status, "ready");
+
+        let split = &rows[1];
+        assert_eq!(split.amount, -150.0);
+        assert_eq!(split.payee_name, "Walmart");
+        assert_eq!(split.flag.as_deref(), Some("Red"));
+        assert_eq!(split.check_number.as_deref(), Some("101"));
+        assert!(split.cleared && !split.reconciled);
+        assert_eq!(split.splits.len(), 3);
+        assert_eq!(split.splits[0].category_id.as_deref(), Some("CAT-FOOD"));
+        assert_eq!(split.splits[1].memo, "toys");
+        assert_eq!(
+            split.splits[2].transfer_account_name.as_deref(),
+            Some("Visa")
+        );
+        assert_eq!(split.status, "ready");
+
+        let transfer = &rows[2];
+        assert_eq!(transfer.transfer_account_name.as_deref(), Some("Visa"));
+        assert_eq!(transfer.payee_name, "");
+        assert_eq!(transfer.amount, -200.0);
+        assert!(!transfer.cleared);
+        assert_eq!(transfer.status, "ready");
+
+        assert_eq!(rows[3].status, "pending", "unknown category");
+        assert_eq!(rows[4].status, "pending", "unknown account");
+
+        // Without a budget nothing is matched or reported missing
+        let Some(Ynab4ExportFile::Register(plain)) = read_export(&parsed, None).unwrap() else {
+            panic!("not read as a register");
+        };
+        assert!(plain.missing_accounts.is_empty());
+        assert!(plain.transactions.iter().all(|t| t.status == "pending"));
+    }
+
+    #[test]
+    fn restores_budgeted_amounts() {
+        let path = fixtures::budget_folder("ynab4-csv-budget");
+        let mut budget = Budget::load(&path).unwrap();
+        budget.mark_dirty("T-1");
+        let parsed = parse_statement(BUDGET.as_bytes()).unwrap();
+        assert_eq!(detect_export(&parsed.headers), Some(Ynab4Export::Budget));
+        let Some(Ynab4ExportFile::Budget(export)) = read_export(&parsed, None).unwrap() else {
+            panic!("not read as a budget");
+        };
+        assert_eq!(export.months, vec!["2024-01", "2024-04"]);
+        assert_eq!(export.rows[3].budgeted, 1250.5);
+
+        let result = restore_budgeted(&mut budget, &export).unwrap();
+        assert_eq!(result.month_count, 2);
+        assert_eq!(result.unchanged_count, 1, "January food was already 100");
+        assert_eq!(result.updated_count, 3);
+        assert_eq!(result.skipped_count, 1);
+        assert_eq!(
+            result.errors,
+            vec!["2024-04: hidden category 'Old ` Stuff ` 3' is not in the budget"]
+        );
+        assert_eq!(result.created_master_category_ids.len(), 1);
+        assert_eq!(result.created_category_ids.len(), 1);
+        assert!(result.ydiff_path.is_some());
+        assert!(budget.dirty.contains("T-1"));
+
+        let reloaded = Budget::load(&path).unwrap();
+        let month = |key: &str| {
+            reloaded
+                .data
+                .monthly_budgets
+                .iter()
+                .find(|m| m.month_key() == key)
+                .unwrap()
+        };
+        let budgeted = |key: &str, category: &str| {
+            month(key)
+                .monthly_sub_category_budgets
+                .iter()
+                .find(|b| b.category_id == category)
+                .map(|b| b.budgeted)
+        };
+        assert_eq!(budgeted("2024-01", "CAT-FUN"), Some(75.0));
+        assert_eq!(
+            budgeted("2024-01", &result.created_category_ids[0]),
+            Some(30.0)
+        );
+        assert_eq!(budgeted("2024-04", "CAT-FOOD"), Some(1250.5));
+        assert_eq!(month("2024-04").month, "2024-04-01");
+        let phone = transaction::find_category(&reloaded.data, "Bills:Phone");
+        assert_eq!(
+            phone.as_deref(),
+            Some(result.created_category_ids[0].as_str())
+        );
+
+        // Other CSV files are not YNAB4 exports
+        let other = parse_statement(b"Fecha,Concepto,Importe\n01/02/2024,OXXO,-50.00\n").unwrap();
+        assert!(read_export(&other, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn hidden_categories_are_found_or_reported() {
+        let path = fixtures::budget_folder("ynab4-csv-hidden");
+        let mut budget = Budget::load(&path).unwrap();
+        budget.data.master_categories.push(MasterCategory {
+            entity_id: "MC-HIDDEN".to_string(),
+            entity_type: "masterCategory".to_string(),
+            name: HIDDEN_MASTER.to_string(),
+            sub_categories: Some(vec![Category {
+                entity_id: "CAT-OLD".to_string(),
+                entity_type: "category".to_string(),
+                name: "Bills ` Phone ` 4".to_string(),
+                master_category_id: "MC-HIDDEN".to_string(),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        });
+        let row = |sub: &str, budgeted: f64| BudgetExportRow {
+            month: "2024-02-01".to_string(),
+            master_category: HIDDEN_MASTER.to_string(),
+            sub_category: sub.to_string(),
+            budgeted,
+        };
+        let export = BudgetExport {
+            rows: vec![
+                row("Bills ` Phone ` 4", 12.0),
+                row("Everyday ` Fun ` 2", 15.0),
+                row("Gone ` Gym ` 5", 40.0),
+            ],
+            months: vec!["2024-02".to_string()],
+            errors: Vec::new(),
+        };
+
+        let result = restore_budgeted(&mut budget, &export).unwrap();
+        assert_eq!(result.updated_count, 2);
+        assert!(result.created_category_ids.is_empty());
+        assert_eq!(result.skipped_count, 1);
+        assert_eq!(
+            result.errors,
+            vec!["2024-02: hidden category 'Gone ` Gym ` 5' is not in the budget"]
+        );
+        let february = budget
+            .data
+            .monthly_budgets
+            .iter()
+            .find(|m| m.month_key() == "2024-02")
+            .unwrap();
+        let budgeted = |category: &str| {
+            february
+                .monthly_sub_category_budgets
+                .iter()
+                .find(|b| b.category_id == category)
+                .map(|b| b.budgeted)
+        };
+        assert_eq!(budgeted("CAT-OLD"), Some(12.0));
+        assert_eq!(budgeted("CAT-FUN"), Some(15.0));
+    }
+}
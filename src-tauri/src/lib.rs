@@ -8,6 +8,10 @@ use tokio::net::TcpListener;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tauri::Emitter;
 
+pub mod export;
+pub mod import;
+pub mod ynab4;
+
 // Store PKCE verifier between auth steps
 #[allow(dead_code)]
 static CODE_VERIFIER: Mutex<Option<String>> = Mutex::new(None);
@@ -657,7 +661,70 @@ pub fn run() {
             dropbox_exchange_code_android,
             open_url_in_browser,
             get_last_deep_link,
-            clear_deep_link
+            clear_deep_link,
+            ynab4::commands::budget_open,
+            ynab4::commands::budget_close,
+            ynab4::commands::budget_save_changes,
+            ynab4::commands::budget_calculate_months,
+            ynab4::commands::reconcile_start,
+            ynab4::commands::reconcile_toggle_cleared,
+            ynab4::commands::reconcile_status,
+            ynab4::commands::reconcile_finish,
+            ynab4::commands::reconcile_cancel,
+            ynab4::commands::transaction_create,
+            ynab4::commands::transaction_update,
+            ynab4::commands::transaction_delete,
+            ynab4::commands::bulk_update_transactions,
+            ynab4::commands::merge_payees,
+            ynab4::commands::payee_rules_suggest,
+            ynab4::commands::payee_rule_test,
+            ynab4::commands::payee_regex_rules_get,
+            ynab4::commands::payee_regex_rules_save,
+            ynab4::commands::category_merge,
+            ynab4::commands::category_delete,
+            ynab4::commands::account_update,
+            ynab4::commands::account_close,
+            ynab4::commands::account_reopen,
+            ynab4::commands::account_delete,
+            ynab4::commands::account_reorder,
+            ynab4::commands::budget_undo,
+            ynab4::commands::budget_redo,
+            ynab4::commands::budget_history,
+            import::commands::parse_statement_file,
+            import::commands::parse_ofx_file,
+            import::commands::parse_qif_file,
+            import::commands::export_account_qif,
+            import::commands::parse_camt_file,
+            import::commands::parse_mt940_file,
+            import::commands::parse_cfdi_file,
+            import::commands::attach_cfdi,
+            import::commands::cfdi_invoices,
+            import::commands::list_spreadsheet_sheets,
+            import::commands::read_spreadsheet,
+            import::commands::match_import_rows,
+            import::commands::classifier_suggest,
+            import::commands::classifier_retrain,
+            import::commands::list_import_profiles,
+            import::commands::save_import_profile,
+            import::commands::delete_import_profile,
+            import::commands::find_import_profile,
+            import::commands::apply_import_profile,
+            import::commands::list_import_drafts,
+            import::commands::get_import_draft,
+            import::commands::save_import_draft,
+            import::commands::delete_import_draft,
+            import::commands::migrate_import_drafts,
+            import::commands::import_draft_file,
+            import::commands::commit_import,
+            import::commands::parse_ynab4_export,
+            import::commands::restore_ynab4_budget,
+            import::commands::parse_notification_text,
+            import::commands::list_notification_templates,
+            import::commands::save_notification_template,
+            import::commands::delete_notification_template,
+            export::commands::export_transactions,
+            export::commands::export_ledger,
+            export::commands::write_import_template
         ])
     .run(tauri::generate_context!())
     .expect("error while running tauri application");
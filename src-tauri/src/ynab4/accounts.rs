@@ -0,0 +1,700 @@
+//! Account lifecycle: editing, closing, reopening, deleting and reordering.
+//!
+//! YNAB4 shows a closed account as `hidden`. Only the on-budget side of a
+//! transfer leaving or entering the budget has a category, so moving an
+//! account on or off budget also fixes the categories of its transactions and
+//! of the other side of its transfers.
+
+use serde::{Deserialize, Serialize};
+
+use super::budget::Budget;
+use super::calculator::round_amount;
+use super::entities::*;
+
+/// Account types YNAB4 knows
+pub const ACCOUNT_TYPES: &[&str] = &[
+    "Checking",
+    "Savings",
+    "CreditCard",
+    "Cash",
+    "LineOfCredit",
+    "PayPal",
+    "MerchantAccount",
+    "InvestmentAccount",
+    "Mortgage",
+    "OtherAsset",
+    "OtherLiability",
+];
+
+/// Step between the `sortableIndex` of consecutive accounts
+const SORTABLE_INDEX_STEP: f64 = 10.0;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountUpdate {
+    #[serde(default)]
+    pub account_name: Option<String>,
+    #[serde(default)]
+    pub account_type: Option<String>,
+    #[serde(default)]
+    pub on_budget: Option<bool>,
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Category for transactions that need one after moving on or off budget
+    #[serde(default)]
+    pub category_id: Option<String>,
+}
+
+/// What happens to the transactions of a deleted account
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum TransactionPolicy {
+    /// Refuse to delete an account that still has transactions
+    #[default]
+    RequireEmpty,
+    Delete,
+    /// Move them to another account with the same budget status
+    MoveTo {
+        #[serde(rename = "accountId")]
+        account_id: String,
+    },
+}
+
+/// What happens to the other side of transfers with a deleted account
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TransferPolicy {
+    #[default]
+    Delete,
+    /// Keep them as ordinary transactions without a payee
+    Detach,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDeletePolicy {
+    #[serde(default)]
+    pub transactions: TransactionPolicy,
+    #[serde(default)]
+    pub transfers: TransferPolicy,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountChange {
+    pub account_id: String,
+    pub changed_ids: Vec<String>,
+    pub ydiff_path: Option<String>,
+}
+
+/// Result of reordering accounts
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountOrder {
+    /// Every live account, in its new order
+    pub account_ids: Vec<String>,
+    pub ydiff_path: Option<String>,
+}
+
+fn live_account<'a>(budget: &'a Budget, account_id: &str) -> Result<&'a Account, String> {
+    budget
+        .data
+        .account(account_id)
+        .filter(|a| !a.is_tombstone)
+        .ok_or_else(|| format!("Account not found: {}", account_id))
+}
+
+/// Sum of the account's transactions
+pub fn account_balance(data: &BudgetData, account_id: &str) -> f64 {
+    round_amount(
+        data.transactions
+            .iter()
+            .filter(|t| !t.is_tombstone && t.account_id == account_id)
+            .map(|t| t.amount)
+            .sum(),
+    )
+}
+
+/// Mark the changes dirty and write them as one ydiff
+fn commit(
+    budget: &mut Budget,
+    account_id: &str,
+    changed: Vec<String>,
+) -> Result<AccountChange, String> {
+    for id in &changed {
+        budget.mark_dirty(id.clone());
+    }
+    let ydiff_path = budget.save_entities(&changed)?;
+    Ok(AccountChange {
+        account_id: account_id.to_string(),
+        changed_ids: changed,
+        ydiff_path: ydiff_path.map(|p| p.to_string_lossy().to_string()),
+    })
+}
+
+fn require_category(
+    budget: &Budget,
+    category_id: Option<&str>,
+    reason: &str,
+) -> Result<String, String> {
+    let category_id =
+        category_id.ok_or_else(|| format!("{}; choose a category for them", reason))?;
+    let known = category_id == IMMEDIATE_INCOME_CATEGORY_ID
+        || category_id == DEFERRED_INCOME_CATEGORY_ID
+        || budget
+            .data
+            .live_categories()
+            .iter()
+            .any(|c| c.entity_id == category_id);
+    if known {
+        Ok(category_id.to_string())
+    } else {
+        Err(format!("Category not found: {}", category_id))
+    }
+}
+
+/// Recategorize after moving `account_id` on or off budget. Returns the ids changed.
+fn fix_categories(
+    budget: &mut Budget,
+    account_id: &str,
+    on_budget: bool,
+    category_id: Option<&str>,
+) -> Result<Vec<String>, String> {
+    let on_budget_accounts: Vec<String> = budget
+        .data
+        .accounts
+        .iter()
+        .filter(|a| !a.is_tombstone && a.on_budget && a.entity_id != account_id)
+        .map(|a| a.entity_id.clone())
+        .collect();
+    let is_on = |id: Option<&str>| id.is_some_and(|id| on_budget_accounts.iter().any(|a| a == id));
+
+    // (transaction, split line, new category) for every change
+    let mut updates: Vec<(String, Option<String>, Option<String>)> = Vec::new();
+    let mut missing = 0;
+    for transaction in budget.data.transactions.iter().filter(|t| !t.is_tombstone) {
+        let here = transaction.account_id == account_id;
+        let points_here = transaction.transfer_account_id() == Some(account_id);
+        if here && !transaction.is_split() {
+            let transfer_to_on = is_on(transaction.transfer_account_id());
+            // This side keeps a category only when it is on budget and the
+            // transaction is not a transfer to another on-budget account
+            let wants_category = on_budget && !transfer_to_on;
+            if !wants_category && transaction.category_id.is_some() {
+                updates.push((transaction.entity_id.clone(), None, None));
+            } else if wants_category && transaction.category_id.is_none() {
+                missing += 1;
+                updates.push((
+                    transaction.entity_id.clone(),
+                    None,
+                    category_id.map(str::to_string),
+                ));
+            }
+        } else if here {
+            for sub in transaction.live_sub_transactions() {
+                let transfer_to_on = sub.transfer_transaction_id.is_some()
+                    && is_on(sub.target_account_id.as_deref());
+                let wants_category = on_budget && !transfer_to_on;
+                if !wants_category && sub.category_id.is_some() {
+                    updates.push((
+                        transaction.entity_id.clone(),
+                        Some(sub.entity_id.clone()),
+                        None,
+                    ));
+                } else if wants_category && sub.category_id.is_none() {
+                    missing += 1;
+                    updates.push((
+                        transaction.entity_id.clone(),
+                        Some(sub.entity_id.clone()),
+                        category_id.map(str::to_string),
+                    ));
+                }
+            }
+        } else if points_here {
+            // Other side of a transfer: it needs a category only when it is
+            // on budget and this account is not
+            let other_on = on_budget_accounts.contains(&transaction.account_id);
+            let wants_category = other_on && !on_budget;
+            if !wants_category && transaction.category_id.is_some() {
+                updates.push((transaction.entity_id.clone(), None, None));
+            } else if wants_category && transaction.category_id.is_none() {
+                missing += 1;
+                updates.push((
+                    transaction.entity_id.clone(),
+                    None,
+                    category_id.map(str::to_string),
+                ));
+            }
+        }
+    }
+
+    // Split lines elsewhere that transfer to this account
+    for transaction in budget
+        .data
+        .transactions
+        .iter()
+        .filter(|t| !t.is_tombstone && t.account_id != account_id)
+    {
+        let other_on = on_budget_accounts.contains(&transaction.account_id);
+        for sub in transaction.live_sub_transactions().filter(|s| {
+            s.transfer_transaction_id.is_some()
+                && s.target_account_id.as_deref() == Some(account_id)
+        }) {
+            let wants_category = other_on && !on_budget;
+            if !wants_category && sub.category_id.is_some() {
+                updates.push((
+                    transaction.entity_id.clone(),
+                    Some(sub.entity_id.clone()),
+                    None,
+                ));
+            } else if wants_category && sub.category_id.is_none() {
+                missing += 1;
+                updates.push((
+                    transaction.entity_id.clone(),
+                    Some(sub.entity_id.clone()),
+                    category_id.map(str::to_string),
+                ));
+            }
+        }
+    }
+
+    if missing > 0 {
+        require_category(
+            budget,
+            category_id,
+            &format!("{} transactions need a category", missing),
+        )?;
+    }
+
+    let mut changed = Vec::new();
+    for (transaction_id, sub_id, category) in updates {
+        let Some(transaction) = budget
+            .data
+            .transactions
+            .iter_mut()
+            .find(|t| t.entity_id == transaction_id)
+        else {
+            continue;
+        };
+        match sub_id {
+            Some(sub_id) => {
+                if let Some(sub) = transaction
+                    .sub_transactions
+                    .iter_mut()
+                    .flatten()
+                    .find(|s| s.entity_id == sub_id)
+                {
+                    sub.category_id = category;
+                    changed.push(sub_id);
+                }
+            }
+            None => {
+                transaction.category_id = category;
+                changed.push(transaction_id);
+            }
+        }
+    }
+    Ok(changed)
+}
+
+/// Change an account's name, type, note or budget status
+pub fn update_account(
+    budget: &mut Budget,
+    account_id: &str,
+    update: AccountUpdate,
+) -> Result<AccountChange, String> {
+    let account = live_account(budget, account_id)?.clone();
+    if let Some(account_type) = update.account_type.as_deref() {
+        if !ACCOUNT_TYPES.contains(&account_type) {
+            return Err(format!("Unknown account type: {}", account_type));
+        }
+    }
+    if update
+        .account_name
+        .as_deref()
+        .is_some_and(|n| n.trim().is_empty())
+    {
+        return Err("Account name cannot be empty".to_string());
+    }
+
+    let mut changed = Vec::new();
+    if let Some(on_budget) = update.on_budget.filter(|on| *on != account.on_budget) {
+        changed.extend(fix_categories(
+            budget,
+            account_id,
+            on_budget,
+            update.category_id.as_deref(),
+        )?);
+    }
+
+    if let Some(name) = update.account_name.as_deref().map(str::trim) {
+        let transfer_payee = format!("{}{}", TRANSFER_PAYEE_PREFIX, account_id);
+        if let Some(payee) = budget
+            .data
+            .payees
+            .iter_mut()
+            .find(|p| p.entity_id == transfer_payee && !p.is_tombstone)
+        {
+            payee.name = format!("Transfer : {}", name);
+            changed.push(payee.entity_id.clone());
+        }
+    }
+
+    let target = budget
+        .data
+        .accounts
+        .iter_mut()
+        .find(|a| a.entity_id == account_id)
+        .ok_or_else(|| format!("Account not found: {}", account_id))?;
+    if let Some(name) = update.account_name {
+        target.account_name = name.trim().to_string();
+    }
+    if let Some(account_type) = update.account_type {
+        target.account_type = account_type;
+    }
+    if let Some(on_budget) = update.on_budget {
+        target.on_budget = on_budget;
+    }
+    if update.note.is_some() {
+        target.note = update.note;
+    }
+    changed.push(account_id.to_string());
+    commit(budget, account_id, changed)
+}
+
+/// Close (hide) an account; its balance must be zero
+pub fn close_account(budget: &mut Budget, account_id: &str) -> Result<AccountChange, String> {
+    live_account(budget, account_id)?;
+    let balance = account_balance(&budget.data, account_id);
+    if balance != 0.0 {
+        return Err(format!(
+            "Only accounts with a zero balance can be closed (balance: {})",
+            balance
+        ));
+    }
+    set_hidden(budget, account_id, true)
+}
+
+/// Show a closed account again
+pub fn reopen_account(budget: &mut Budget, account_id: &str) -> Result<AccountChange, String> {
+    live_account(budget, account_id)?;
+    set_hidden(budget, account_id, false)
+}
+
+fn set_hidden(
+    budget: &mut Budget,
+    account_id: &str,
+    hidden: bool,
+) -> Result<AccountChange, String> {
+    if let Some(account) = budget
+        .data
+        .accounts
+        .iter_mut()
+        .find(|a| a.entity_id == account_id)
+    {
+        account.hidden = hidden;
+    }
+    commit(budget, account_id, vec![account_id.to_string()])
+}
+
+/// Give accounts increasing `sortableIndex` values in the given order.
+/// Accounts left out keep their relative order after the listed ones.
+pub fn reorder_accounts(
+    budget: &mut Budget,
+    account_ids: &[String],
+) -> Result<AccountOrder, String> {
+    for id in account_ids {
+        live_account(budget, id)?;
+    }
+    let mut rest: Vec<&Account> = budget
+        .data
+        .accounts
+        .iter()
+        .filter(|a| !a.is_tombstone && !account_ids.contains(&a.entity_id))
+        .collect();
+    rest.sort_by(|a, b| {
+        a.sortable_index
+            .unwrap_or(0.0)
+            .total_cmp(&b.sortable_index.unwrap_or(0.0))
+    });
+    let order: Vec<String> = account_ids
+        .iter()
+        .cloned()
+        .chain(rest.iter().map(|a| a.entity_id.clone()))
+        .collect();
+
+    let mut changed = Vec::new();
+    for (position, id) in order.iter().enumerate() {
+        let index = position as f64 * SORTABLE_INDEX_STEP;
+        if let Some(account) = budget.data.accounts.iter_mut().find(|a| &a.entity_id == id) {
+            if account.sortable_index != Some(index) {
+                account.sortable_index = Some(index);
+                changed.push(id.clone());
+            }
+        }
+    }
+    for id in &changed {
+        budget.mark_dirty(id.clone());
+    }
+    let ydiff_path = budget.save_entities(&changed)?;
+    Ok(AccountOrder {
+        account_ids: order,
+        ydiff_path: ydiff_path.map(|p| p.to_string_lossy().to_string()),
+    })
+}
+
+/// Turn the other side of a transfer into an ordinary transaction
+fn detach(transaction: &mut Transaction) {
+    transaction.transfer_transaction_id = None;
+    transaction.target_account_id = None;
+    transaction.payee_id = None;
+}
+
+fn detach_sub(sub: &mut SubTransaction) {
+    sub.transfer_transaction_id = None;
+    sub.target_account_id = None;
+    sub.payee_id = None;
+}
+
+/// Delete an account, handling its transactions and transfers per `policy`
+pub fn delete_account(
+    budget: &mut Budget,
+    account_id: &str,
+    policy: AccountDeletePolicy,
+) -> Result<AccountChange, String> {
+    let account = live_account(budget, account_id)?.clone();
+    let own: Vec<String> = budget
+        .data
+        .transactions
+        .iter()
+        .filter(|t| !t.is_tombstone && t.account_id == account_id)
+        .map(|t| t.entity_id.clone())
+        .collect();
+
+    let move_to = match &policy.transactions {
+        TransactionPolicy::RequireEmpty if !own.is_empty() => {
+            return Err(format!("The account still has {} transactions", own.len()));
+        }
+        TransactionPolicy::MoveTo { account_id: target } => {
+            let target_account = live_account(budget, target)?;
+            if target == account_id {
+                return Err("Cannot move transactions to the account being deleted".to_string());
+            }
+            if target_account.on_budget != account.on_budget {
+                return Err(
+                    "Transactions can only move to an account with the same budget status"
+                        .to_string(),
+                );
+            }
+            Some(target.clone())
+        }
+        _ => None,
+    };
+
+    let mut changed: Vec<String> = Vec::new();
+    let transfer_payee = format!("{}{}", TRANSFER_PAYEE_PREFIX, account_id);
+    let data = &mut budget.data;
+
+    match &move_to {
+        Some(target) => {
+            let target_payee = format!("{}{}", TRANSFER_PAYEE_PREFIX, target);
+            // Split lines between the two accounts would transfer to their own
+            // account too, but cannot go without changing their parent's
+            // amount; they and their other sides are detached instead
+            let mut split_sides: Vec<String> = Vec::new();
+            for transaction in data.transactions.iter().filter(|t| !t.is_tombstone) {
+                let other = if own.contains(&transaction.entity_id) {
+                    target.as_str()
+                } else if &transaction.account_id == target {
+                    account_id
+                } else {
+                    continue;
+                };
+                for sub in transaction.live_sub_transactions() {
+                    if sub.target_account_id.as_deref() == Some(other) {
+                        split_sides.push(sub.entity_id.clone());
+                        split_sides.extend(sub.transfer_transaction_id.clone());
+                    }
+                }
+            }
+            let own_transfer_ids: Vec<String> = data
+                .transactions
+                .iter()
+                .filter(|t| own.contains(&t.entity_id) && !split_sides.contains(&t.entity_id))
+                .filter(|t| t.transfer_account_id() == Some(target.as_str()))
+                .filter_map(|t| t.transfer_transaction_id.clone())
+                .collect();
+            for transaction in data.transactions.iter_mut().filter(|t| !t.is_tombstone) {
+                if split_sides.contains(&transaction.entity_id) {
+                    detach(transaction);
+                    if own.contains(&transaction.entity_id) {
+                        transaction.account_id = target.clone();
+                    }
+                    changed.push(transaction.entity_id.clone());
+                } else if own.contains(&transaction.entity_id) {
+                    if transaction.transfer_account_id() == Some(target.as_str()) {
+                        // A transfer with the target would become a transfer
+                        // to itself; both sides cancel out
+                        transaction.is_tombstone = true;
+                    } else {
+                        transaction.account_id = target.clone();
+                    }
+                    changed.push(transaction.entity_id.clone());
+                } else if own_transfer_ids.contains(&transaction.entity_id) {
+                    transaction.is_tombstone = true;
+                    changed.push(transaction.entity_id.clone());
+                } else if transaction.transfer_account_id() == Some(account_id) {
+                    transaction.target_account_id = Some(target.clone());
+                    transaction.payee_id = Some(target_payee.clone());
+                    changed.push(transaction.entity_id.clone());
+                }
+                for sub in transaction.sub_transactions.iter_mut().flatten() {
+                    if sub.is_tombstone {
+                        continue;
+                    }
+                    if split_sides.contains(&sub.entity_id) {
+                        detach_sub(sub);
+                        changed.push(sub.entity_id.clone());
+                    } else if sub.target_account_id.as_deref() == Some(account_id) {
+                        sub.target_account_id = Some(target.clone());
+                        sub.payee_id = Some(target_payee.clone());
+                        changed.push(sub.entity_id.clone());
+                    }
+                }
+            }
+        }
+        None => {
+            // Transactions (and split line counterparts) on the other side
+            let mut counterparts: Vec<String> = Vec::new();
+            for transaction in data
+                .transactions
+                .iter()
+                .filter(|t| own.contains(&t.entity_id))
+            {
+                counterparts.extend(transaction.transfer_transaction_id.clone());
+                for sub in transaction.live_sub_transactions() {
+                    counterparts.extend(sub.transfer_transaction_id.clone());
+                }
+            }
+            for transaction in data.transactions.iter_mut().filter(|t| !t.is_tombstone) {
+                if own.contains(&transaction.entity_id) {
+                    transaction.is_tombstone = true;
+                    changed.push(transaction.entity_id.clone());
+                    for sub in transaction.sub_transactions.iter_mut().flatten() {
+                        if !sub.is_tombstone {
+                            sub.is_tombstone = true;
+                            changed.push(sub.entity_id.clone());
+                        }
+                    }
+                    continue;
+                }
+                if counterparts.contains(&transaction.entity_id) {
+                    match policy.transfers {
+                        TransferPolicy::Delete => transaction.is_tombstone = true,
+                        TransferPolicy::Detach => detach(transaction),
+                    }
+                    changed.push(transaction.entity_id.clone());
+                }
+                // A split line cannot go without changing its parent's
+                // amount, so it is always detached
+                for sub in transaction.sub_transactions.iter_mut().flatten() {
+                    if !sub.is_tombstone && sub.target_account_id.as_deref() == Some(account_id) {
+                        detach_sub(sub);
+                        changed.push(sub.entity_id.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    // Scheduled transactions in or to the account go with it
+    let new_home = move_to.clone();
+    for scheduled in data
+        .scheduled_transactions
+        .iter_mut()
+        .filter(|s| !s.is_tombstone)
+    {
+        if scheduled.account_id == account_id {
+            match &new_home {
+                Some(target) => scheduled.account_id = target.clone(),
+                None => scheduled.is_tombstone = true,
+            }
+            changed.push(scheduled.entity_id.clone());
+        } else if scheduled.target_account_id.as_deref() == Some(account_id) {
+            scheduled.is_tombstone = true;
+            changed.push(scheduled.entity_id.clone());
+        }
+    }
+
+    if let Some(payee) = data
+        .payees
+        .iter_mut()
+        .find(|p| p.entity_id == transfer_payee && !p.is_tombstone)
+    {
+        payee.is_tombstone = true;
+        changed.push(payee.entity_id.clone());
+    }
+    if let Some(target) = data.accounts.iter_mut().find(|a| a.entity_id == account_id) {
+        target.is_tombstone = true;
+        changed.push(account_id.to_string());
+    }
+    commit(budget, account_id, changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::fixtures;
+
+    #[test]
+    fn account_edits_keep_transfers_consistent() {
+        let path = fixtures::budget_folder("accounts-lifecycle");
+        let mut budget = Budget::load(&path).unwrap();
+        assert!(close_account(&mut budget, "ACC-CHK").is_err());
+
+        // T-5 on checking is a transfer to the card and needs a category once the card is off budget
+        let update = AccountUpdate {
+            on_budget: Some(false),
+            ..Default::default()
+        };
+        assert!(update_account(&mut budget, "ACC-CC", update).is_err());
+        let update = AccountUpdate {
+            on_budget: Some(false),
+            category_id: Some("CAT-FUN".to_string()),
+            ..Default::default()
+        };
+        update_account(&mut budget, "ACC-CC", update).unwrap();
+        assert_eq!(
+            budget
+                .data
+                .transaction("T-5")
+                .unwrap()
+                .category_id
+                .as_deref(),
+            Some("CAT-FUN")
+        );
+        assert_eq!(budget.data.transaction("T-3").unwrap().category_id, None);
+
+        budget.mark_dirty("T-1");
+        let order = reorder_accounts(&mut budget, &["ACC-INV".to_string()]).unwrap();
+        assert_eq!(order.account_ids[0], "ACC-INV");
+        assert!(order.ydiff_path.is_some());
+        assert!(budget.dirty.contains("T-1"));
+
+        let policy: AccountDeletePolicy =
+            serde_json::from_str(r#"{"transactions":{"mode":"delete"},"transfers":"detach"}"#)
+                .unwrap();
+        assert!(delete_account(&mut budget, "ACC-CC", AccountDeletePolicy::default()).is_err());
+        delete_account(&mut budget, "ACC-CC", policy).unwrap();
+
+        let reloaded = Budget::load(&path).unwrap();
+        assert_eq!(
+            reloaded.data.account("ACC-INV").unwrap().sortable_index,
+            Some(0.0)
+        );
+        assert!(reloaded.data.account("ACC-CC").unwrap().is_tombstone);
+        assert!(reloaded.data.transaction("T-6").unwrap().is_tombstone);
+        let t5 = reloaded.data.transaction("T-5").unwrap();
+        assert!(!t5.is_tombstone && t5.transfer_transaction_id.is_none() && t5.payee_id.is_none());
+    }
+}
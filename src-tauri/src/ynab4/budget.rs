@@ -0,0 +1,728 @@
+//! Loading a `.ynab4` budget folder into memory.
+//!
+//! A budget folder contains `Budget.ymeta`, which points at the data folder.
+//! The data folder has one `.ydevice` file per device, one sub folder per
+//! device with that device's `.ydiff` files and, for devices with full
+//! knowledge, a `Budget.yfull` snapshot. Loading reads the freshest snapshot
+//! and replays every diff that is newer than it.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::entities::*;
+use super::knowledge::{compare_versions, Knowledge};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetMeta {
+    pub relative_data_folder_name: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Contents of a `devices/<shortDeviceId>.ydevice` file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Device {
+    #[serde(rename = "deviceGUID")]
+    pub device_guid: String,
+    pub short_device_id: String,
+    #[serde(default)]
+    pub friendly_name: String,
+    #[serde(default)]
+    pub knowledge: String,
+    #[serde(default)]
+    pub knowledge_in_full_budget_file: Option<String>,
+    #[serde(default)]
+    pub has_full_knowledge: bool,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Contents of a `.ydiff` file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diff {
+    pub start_version: String,
+    pub end_version: String,
+    #[serde(default)]
+    pub short_device_id: String,
+    #[serde(default)]
+    pub items: Vec<Value>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Entities that carry an id and a version
+pub trait Entity {
+    fn id(&self) -> &str;
+    fn version(&self) -> Option<&str>;
+    fn set_version(&mut self, version: String);
+}
+
+macro_rules! impl_entity {
+    ($($ty:ty),*) => {
+        $(impl Entity for $ty {
+            fn id(&self) -> &str {
+                &self.entity_id
+            }
+            fn version(&self) -> Option<&str> {
+                self.entity_version.as_deref()
+            }
+            fn set_version(&mut self, version: String) {
+                self.entity_version = Some(version);
+            }
+        })*
+    };
+}
+
+impl_entity!(
+    Account,
+    Payee,
+    PayeeRenameCondition,
+    MasterCategory,
+    Category,
+    Transaction,
+    SubTransaction,
+    ScheduledTransaction,
+    MonthlyBudget,
+    MonthlySubCategoryBudget
+);
+
+/// Insert `item`, or replace the existing entity with the same id unless the
+/// existing one has a newer version. Returns the index when the item was kept.
+pub fn upsert<T: Entity>(list: &mut Vec<T>, item: T) -> Option<usize> {
+    match list.iter().position(|e| e.id() == item.id()) {
+        Some(idx) => {
+            if compare_versions(item.version(), list[idx].version()) == Ordering::Less {
+                return None;
+            }
+            list[idx] = item;
+            Some(idx)
+        }
+        None => {
+            list.push(item);
+            Some(list.len() - 1)
+        }
+    }
+}
+
+/// Merge incoming nested children into the ones already known
+fn merge_children<T: Entity>(existing: Option<Vec<T>>, incoming: Option<Vec<T>>) -> Option<Vec<T>> {
+    match (existing, incoming) {
+        (Some(mut existing), Some(incoming)) => {
+            for child in incoming {
+                upsert(&mut existing, child);
+            }
+            Some(existing)
+        }
+        (existing, None) => existing,
+        (None, incoming) => incoming,
+    }
+}
+
+/// An open budget
+#[derive(Debug, Clone)]
+pub struct Budget {
+    /// Path of the `.ynab4` folder
+    pub path: PathBuf,
+    /// Path of the `data1~XXXX` folder
+    pub data_folder: PathBuf,
+    /// Everything known after replaying all diffs
+    pub knowledge: Knowledge,
+    pub data: BudgetData,
+    /// Device this client writes as, once attached
+    pub device: Option<Device>,
+    /// Ids of entities changed since the last ydiff was written
+    pub dirty: BTreeSet<String>,
+    /// Ids of entities changed since the caller last cleared it; unlike
+    /// `dirty` it survives writing a ydiff
+    pub touched: BTreeSet<String>,
+}
+
+pub(crate) fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    // YNAB4 writes some files with a UTF-8 BOM
+    serde_json::from_str(content.trim_start_matches('\u{feff}'))
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+}
+
+impl Budget {
+    /// Load a budget from its `.ynab4` folder
+    pub fn load(path: &Path) -> Result<Budget, String> {
+        let meta: BudgetMeta = read_json(&path.join("Budget.ymeta"))?;
+        let data_folder = path.join(&meta.relative_data_folder_name);
+        let devices = Self::read_devices(&data_folder)?;
+
+        // Start from the snapshot that includes the most knowledge
+        let mut snapshot: Option<(Knowledge, PathBuf)> = None;
+        for device in devices.iter().filter(|d| d.has_full_knowledge) {
+            let yfull = data_folder.join(&device.device_guid).join("Budget.yfull");
+            if !yfull.exists() {
+                continue;
+            }
+            let knowledge = Knowledge::parse(
+                device
+                    .knowledge_in_full_budget_file
+                    .as_deref()
+                    .unwrap_or(&device.knowledge),
+            );
+            let better = match &snapshot {
+                Some((best, _)) => knowledge.max_counter() > best.max_counter(),
+                None => true,
+            };
+            if better {
+                snapshot = Some((knowledge, yfull));
+            }
+        }
+        let (knowledge, yfull) = snapshot
+            .ok_or_else(|| format!("No Budget.yfull found in {}", data_folder.display()))?;
+
+        let mut budget = Budget {
+            path: path.to_path_buf(),
+            data_folder: data_folder.clone(),
+            knowledge,
+            data: read_json(&yfull)?,
+            device: None,
+            dirty: BTreeSet::new(),
+            touched: BTreeSet::new(),
+        };
+
+        // Replay every diff that brings something new, oldest first
+        let mut diffs = Vec::new();
+        for device in &devices {
+            let folder = data_folder.join(&device.device_guid);
+            let Ok(entries) = fs::read_dir(&folder) else {
+                continue;
+            };
+            for entry in entries.filter_map(|e| e.ok()) {
+                let diff_path = entry.path();
+                if diff_path.extension().is_some_and(|ext| ext == "ydiff") {
+                    let diff: Diff = read_json(&diff_path)?;
+                    diffs.push(diff);
+                }
+            }
+        }
+        diffs.sort_by_key(|d| Knowledge::parse(&d.end_version).max_counter());
+        for diff in diffs {
+            let end = Knowledge::parse(&diff.end_version);
+            if budget.knowledge.contains(&end) {
+                continue;
+            }
+            for item in diff.items {
+                budget.apply_item(item)?;
+            }
+            budget.knowledge.merge(&end);
+        }
+
+        Ok(budget)
+    }
+
+    pub fn read_devices(data_folder: &Path) -> Result<Vec<Device>, String> {
+        let devices_folder = data_folder.join("devices");
+        let entries = fs::read_dir(&devices_folder)
+            .map_err(|e| format!("Failed to read {}: {}", devices_folder.display(), e))?;
+
+        let mut devices = Vec::new();
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "ydevice") {
+                devices.push(read_json::<Device>(&path)?);
+            }
+        }
+        Ok(devices)
+    }
+
+    /// Apply a single entity from a diff, keeping whichever version is newer
+    pub fn apply_item(&mut self, item: Value) -> Result<(), String> {
+        let entity_type = item
+            .get("entityType")
+            .and_then(|t| t.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let parse_err = |e: serde_json::Error| format!("Invalid {} entity: {}", entity_type, e);
+        let data = &mut self.data;
+
+        match entity_type.as_str() {
+            "account" => {
+                upsert(
+                    &mut data.accounts,
+                    serde_json::from_value(item).map_err(parse_err)?,
+                );
+            }
+            "payee" => {
+                let mut payee: Payee = serde_json::from_value(item).map_err(parse_err)?;
+                if let Some(existing) = data.payees.iter().find(|p| p.entity_id == payee.entity_id)
+                {
+                    payee.rename_conditions =
+                        merge_children(existing.rename_conditions.clone(), payee.rename_conditions);
+                }
+                upsert(&mut data.payees, payee);
+            }
+            "payeeRenameCondition" => {
+                let condition: PayeeRenameCondition =
+                    serde_json::from_value(item).map_err(parse_err)?;
+                if let Some(payee) = data
+                    .payees
+                    .iter_mut()
+                    .find(|p| p.entity_id == condition.parent_payee_id)
+                {
+                    upsert(
+                        payee.rename_conditions.get_or_insert_with(Vec::new),
+                        condition,
+                    );
+                }
+            }
+            "masterCategory" => {
+                let mut master: MasterCategory = serde_json::from_value(item).map_err(parse_err)?;
+                if let Some(existing) = data
+                    .master_categories
+                    .iter()
+                    .find(|m| m.entity_id == master.entity_id)
+                {
+                    master.sub_categories =
+                        merge_children(existing.sub_categories.clone(), master.sub_categories);
+                }
+                upsert(&mut data.master_categories, master);
+            }
+            "category" => {
+                let category: Category = serde_json::from_value(item).map_err(parse_err)?;
+                // A category can move to another master category
+                for master in data.master_categories.iter_mut() {
+                    if master.entity_id == category.master_category_id {
+                        continue;
+                    }
+                    if let Some(subs) = master.sub_categories.as_mut() {
+                        if let Some(idx) =
+                            subs.iter().position(|c| c.entity_id == category.entity_id)
+                        {
+                            if compare_versions(category.version(), subs[idx].version())
+                                != Ordering::Less
+                            {
+                                subs.remove(idx);
+                            }
+                        }
+                    }
+                }
+                if let Some(master) = data
+                    .master_categories
+                    .iter_mut()
+                    .find(|m| m.entity_id == category.master_category_id)
+                {
+                    upsert(master.sub_categories.get_or_insert_with(Vec::new), category);
+                }
+            }
+            "transaction" => {
+                let mut transaction: Transaction =
+                    serde_json::from_value(item).map_err(parse_err)?;
+                if let Some(existing) = data
+                    .transactions
+                    .iter()
+                    .find(|t| t.entity_id == transaction.entity_id)
+                {
+                    transaction.sub_transactions = merge_children(
+                        existing.sub_transactions.clone(),
+                        transaction.sub_transactions,
+                    );
+                }
+                upsert(&mut data.transactions, transaction);
+            }
+            "subTransaction" => {
+                let sub: SubTransaction = serde_json::from_value(item).map_err(parse_err)?;
+                if let Some(parent) = data
+                    .transactions
+                    .iter_mut()
+                    .find(|t| t.entity_id == sub.parent_transaction_id)
+                {
+                    upsert(parent.sub_transactions.get_or_insert_with(Vec::new), sub);
+                }
+            }
+            "scheduledTransaction" => {
+                let mut scheduled: ScheduledTransaction =
+                    serde_json::from_value(item).map_err(parse_err)?;
+                if let Some(existing) = data
+                    .scheduled_transactions
+                    .iter()
+                    .find(|t| t.entity_id == scheduled.entity_id)
+                {
+                    scheduled.sub_transactions = merge_children(
+                        existing.sub_transactions.clone(),
+                        scheduled.sub_transactions,
+                    );
+                }
+                upsert(&mut data.scheduled_transactions, scheduled);
+            }
+            "scheduledSubTransaction" => {
+                let sub: SubTransaction = serde_json::from_value(item).map_err(parse_err)?;
+                if let Some(parent) = data
+                    .scheduled_transactions
+                    .iter_mut()
+                    .find(|t| t.entity_id == sub.parent_transaction_id)
+                {
+                    upsert(parent.sub_transactions.get_or_insert_with(Vec::new), sub);
+                }
+            }
+            "monthlyBudget" => {
+                let mut month: MonthlyBudget = serde_json::from_value(item).map_err(parse_err)?;
+                if let Some(existing) = data
+                    .monthly_budgets
+                    .iter()
+                    .find(|m| m.entity_id == month.entity_id)
+                {
+                    let merged = merge_children(
+                        Some(existing.monthly_sub_category_budgets.clone()),
+                        Some(month.monthly_sub_category_budgets),
+                    );
+                    month.monthly_sub_category_budgets = merged.unwrap_or_default();
+                }
+                upsert(&mut data.monthly_budgets, month);
+            }
+            "monthlyCategoryBudget" => {
+                let budget: MonthlySubCategoryBudget =
+                    serde_json::from_value(item).map_err(parse_err)?;
+                if let Some(month) = data
+                    .monthly_budgets
+                    .iter_mut()
+                    .find(|m| m.entity_id == budget.parent_monthly_budget_id)
+                {
+                    upsert(&mut month.monthly_sub_category_budgets, budget);
+                }
+            }
+            "budgetMetaData" => {
+                data.budget_meta_data = item;
+            }
+            // Payee locations, account mappings and other entities are not used
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Friendly name this client registers its device with
+pub const DEVICE_FRIENDLY_NAME: &str = "YNAB4 Client";
+
+/// New entity id in YNAB4's uppercase GUID form
+pub fn new_entity_id() -> String {
+    let bytes: [u8; 16] = rand::random();
+    let hex = hex::encode_upper(bytes);
+    format!(
+        "{}-{}-4{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[13..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Short device ids go A..Z, then AA, AB, ...
+fn next_short_device_id(devices: &[Device]) -> String {
+    let taken: BTreeSet<&str> = devices.iter().map(|d| d.short_device_id.as_str()).collect();
+    (0usize..)
+        .map(|mut n| {
+            let mut id = String::new();
+            loop {
+                id.insert(0, (b'A' + (n % 26) as u8) as char);
+                if n < 26 {
+                    break;
+                }
+                n = n / 26 - 1;
+            }
+            id
+        })
+        .find(|id| !taken.contains(id.as_str()))
+        .unwrap_or_default()
+}
+
+/// Stamp `entity` with a new version and serialize it as a flat ydiff item
+fn diff_item<T: Serialize + Entity>(
+    entity: &mut T,
+    entity_type: &str,
+    next_version: &mut impl FnMut() -> String,
+) -> Result<Value, String> {
+    entity.set_version(next_version());
+    let mut value = serde_json::to_value(&*entity).map_err(|e| e.to_string())?;
+    if let Some(object) = value.as_object_mut() {
+        // Nested children are written as their own items
+        for key in [
+            "subTransactions",
+            "subCategories",
+            "renameConditions",
+            "monthlySubCategoryBudgets",
+        ] {
+            object.remove(key);
+        }
+        let has_type = object
+            .get("entityType")
+            .and_then(|t| t.as_str())
+            .is_some_and(|t| !t.is_empty());
+        if !has_type {
+            object.insert("entityType".to_string(), Value::from(entity_type));
+        }
+    }
+    Ok(value)
+}
+
+impl Budget {
+    /// Use an existing device of this budget, or register a new one
+    pub fn attach_device(&mut self, device_guid: Option<&str>) -> Result<&Device, String> {
+        let devices = Self::read_devices(&self.data_folder)?;
+
+        let device = match device_guid {
+            Some(guid) => devices
+                .iter()
+                .find(|d| d.device_guid == guid)
+                .cloned()
+                .ok_or_else(|| format!("Device {} is not registered in this budget", guid))?,
+            None => {
+                let mut extra = Map::new();
+                extra.insert(
+                    "YNABVersion".to_string(),
+                    Value::from(format!(
+                        "{} {}",
+                        DEVICE_FRIENDLY_NAME,
+                        env!("CARGO_PKG_VERSION")
+                    )),
+                );
+                extra.insert("formatVersion".to_string(), Value::from("1.2"));
+                extra.insert("lastDataVersionFullyKnown".to_string(), Value::from("4.2"));
+                extra.insert("deviceType".to_string(), Value::from(DEVICE_FRIENDLY_NAME));
+                extra.insert("highestDataVersionImported".to_string(), Value::Null);
+                let device = Device {
+                    device_guid: new_entity_id(),
+                    short_device_id: next_short_device_id(&devices),
+                    friendly_name: DEVICE_FRIENDLY_NAME.to_string(),
+                    knowledge: self.knowledge.to_string(),
+                    knowledge_in_full_budget_file: None,
+                    has_full_knowledge: false,
+                    extra,
+                };
+                fs::create_dir_all(self.data_folder.join(&device.device_guid))
+                    .map_err(|e| format!("Failed to create device folder: {}", e))?;
+                self.write_device(&device)?;
+                device
+            }
+        };
+
+        Ok(self.device.insert(device))
+    }
+
+    fn write_device(&self, device: &Device) -> Result<(), String> {
+        let path = self
+            .data_folder
+            .join("devices")
+            .join(format!("{}.ydevice", device.short_device_id));
+        let json = serde_json::to_string_pretty(device).map_err(|e| e.to_string())?;
+        fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Remember that an entity changed so the next ydiff includes it
+    pub fn mark_dirty(&mut self, entity_id: impl Into<String>) {
+        let entity_id = entity_id.into();
+        self.touched.insert(entity_id.clone());
+        self.dirty.insert(entity_id);
+    }
+
+    /// Write every dirty entity to a single ydiff in this device's folder.
+    /// Returns the path of the new file, or `None` when nothing changed.
+    pub fn save_changes(&mut self) -> Result<Option<PathBuf>, String> {
+        if self.dirty.is_empty() {
+            return Ok(None);
+        }
+        if self.device.is_none() {
+            self.attach_device(None)?;
+        }
+        let device = self.device.clone().ok_or("No device attached")?;
+
+        let start_version = self.knowledge.to_string();
+        let mut counter = self.knowledge.max_counter();
+        let short_id = device.short_device_id.clone();
+        let mut next_version = || {
+            counter += 1;
+            format!("{}-{}", short_id, counter)
+        };
+
+        let dirty = self.dirty.clone();
+        let is_dirty = |id: &str| dirty.contains(id);
+        let data = &mut self.data;
+        let mut items = Vec::new();
+
+        for account in data.accounts.iter_mut().filter(|a| is_dirty(&a.entity_id)) {
+            items.push(diff_item(account, "account", &mut next_version)?);
+        }
+        for payee in data.payees.iter_mut() {
+            if is_dirty(&payee.entity_id) {
+                items.push(diff_item(payee, "payee", &mut next_version)?);
+            }
+            for condition in payee.rename_conditions.iter_mut().flatten() {
+                if is_dirty(&condition.entity_id) {
+                    items.push(diff_item(
+                        condition,
+                        "payeeRenameCondition",
+                        &mut next_version,
+                    )?);
+                }
+            }
+        }
+        for master in data.master_categories.iter_mut() {
+            if is_dirty(&master.entity_id) {
+                items.push(diff_item(master, "masterCategory", &mut next_version)?);
+            }
+            for category in master.sub_categories.iter_mut().flatten() {
+                if is_dirty(&category.entity_id) {
+                    items.push(diff_item(category, "category", &mut next_version)?);
+                }
+            }
+        }
+        for transaction in data.transactions.iter_mut() {
+            if is_dirty(&transaction.entity_id) {
+                items.push(diff_item(transaction, "transaction", &mut next_version)?);
+            }
+            for sub in transaction.sub_transactions.iter_mut().flatten() {
+                if is_dirty(&sub.entity_id) {
+                    items.push(diff_item(sub, "subTransaction", &mut next_version)?);
+                }
+            }
+        }
+        for scheduled in data.scheduled_transactions.iter_mut() {
+            if is_dirty(&scheduled.entity_id) {
+                items.push(diff_item(
+                    scheduled,
+                    "scheduledTransaction",
+                    &mut next_version,
+                )?);
+            }
+            for sub in scheduled.sub_transactions.iter_mut().flatten() {
+                if is_dirty(&sub.entity_id) {
+                    items.push(diff_item(
+                        sub,
+                        "scheduledSubTransaction",
+                        &mut next_version,
+                    )?);
+                }
+            }
+        }
+        for month in data.monthly_budgets.iter_mut() {
+            if is_dirty(&month.entity_id) {
+                items.push(diff_item(month, "monthlyBudget", &mut next_version)?);
+            }
+            for budget in month.monthly_sub_category_budgets.iter_mut() {
+                if is_dirty(&budget.entity_id) {
+                    items.push(diff_item(
+                        budget,
+                        "monthlyCategoryBudget",
+                        &mut next_version,
+                    )?);
+                }
+            }
+        }
+
+        if items.is_empty() {
+            self.dirty.clear();
+            return Ok(None);
+        }
+        // Only claimed once the ydiff is on disk
+        let mut knowledge = self.knowledge.clone();
+        knowledge.set(&device.short_device_id, counter);
+        let end_version = knowledge.to_string();
+
+        let budget_guid = self
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let budget_data_guid = self
+            .data_folder
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let diff = serde_json::json!({
+            "budgetDataGUID": budget_data_guid,
+            "budgetGUID": budget_guid,
+            "dataVersion": "4.2",
+            "deviceGUID": device.device_guid,
+            "shortDeviceId": device.short_device_id,
+            "publishTime": chrono::Local::now().format("%a %b %d %H:%M:%S GMT%z %Y").to_string(),
+            "startVersion": start_version,
+            "endVersion": end_version,
+            "items": items,
+        });
+
+        let device_folder = self.data_folder.join(&device.device_guid);
+        fs::create_dir_all(&device_folder)
+            .map_err(|e| format!("Failed to create {}: {}", device_folder.display(), e))?;
+        let diff_path = device_folder.join(format!("{}_{}.ydiff", start_version, end_version));
+        let json = serde_json::to_string_pretty(&diff).map_err(|e| e.to_string())?;
+        fs::write(&diff_path, json)
+            .map_err(|e| format!("Failed to write {}: {}", diff_path.display(), e))?;
+        self.knowledge = knowledge;
+
+        let mut device = device;
+        device.knowledge = end_version;
+        self.write_device(&device)?;
+        self.device = Some(device);
+        self.dirty.clear();
+
+        Ok(Some(diff_path))
+    }
+
+    /// Write only `entity_ids` to a ydiff. Other pending changes stay dirty for
+    /// a later save, so a command never commits edits it did not make.
+    pub fn save_entities(&mut self, entity_ids: &[String]) -> Result<Option<PathBuf>, String> {
+        let own: BTreeSet<String> = entity_ids.iter().cloned().collect();
+        let pending: BTreeSet<String> = self.dirty.difference(&own).cloned().collect();
+        self.dirty = own;
+        let saved = self.save_changes();
+        // On failure the command's own ids are still dirty next to the others
+        self.dirty.extend(pending);
+        saved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::fixtures;
+
+    #[test]
+    fn knowledge_only_advances_when_the_ydiff_is_written() {
+        let path = fixtures::budget_folder("budget-failed-write");
+        let mut budget = Budget::load(&path).unwrap();
+        let device = budget.attach_device(None).unwrap().clone();
+        let before = budget.knowledge.clone();
+        let mut after = before.clone();
+        after.set(&device.short_device_id, before.max_counter() + 1);
+
+        // A folder where the ydiff should go makes the write fail
+        let ydiff = budget
+            .data_folder
+            .join(&device.device_guid)
+            .join(format!("{}_{}.ydiff", before, after));
+        fs::create_dir_all(&ydiff).unwrap();
+        budget.mark_dirty("T-1");
+        assert!(budget.save_changes().is_err());
+        assert_eq!(budget.knowledge, before);
+
+        fs::remove_dir(&ydiff).unwrap();
+        assert_eq!(budget.save_changes().unwrap(), Some(ydiff));
+        assert_eq!(budget.knowledge, after);
+        assert!(budget.dirty.is_empty());
+    }
+
+    #[test]
+    fn saving_some_entities_leaves_the_others_pending() {
+        let path = fixtures::budget_folder("budget-save-entities");
+        let mut budget = Budget::load(&path).unwrap();
+        budget.mark_dirty("T-1");
+        budget.mark_dirty("T-3");
+        let ydiff = budget.save_entities(&["T-1".to_string()]).unwrap().unwrap();
+        let text = fs::read_to_string(ydiff).unwrap();
+        assert!(text.contains("\"T-1\"") && !text.contains("\"T-3\""));
+        assert_eq!(budget.dirty, BTreeSet::from(["T-3".to_string()]));
+    }
+}
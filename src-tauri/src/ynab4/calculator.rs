@@ -0,0 +1,494 @@
+//! Monthly budget calculation following YNAB4's rules.
+//!
+//! For every month and sub category:
+//!
+//! `balance = carryover + budgeted + outflows`
+//!
+//! When a balance ends negative, what happens next month depends on the
+//! category's overspending handling, which sticks from the month it was set
+//! until it is changed again:
+//!
+//! - `Confined` ("carry over negative balance"): the negative balance carries
+//!   over into the category.
+//! - Default: the category starts next month at zero. The part of the
+//!   overspending paid from credit cards becomes card debt; the cash part is
+//!   taken from next month's Available to Budget ("Overspent in <month>").
+//!
+//! Available to Budget for a month is
+//!
+//! `not budgeted last month + overspent last month + income this month - budgeted this month - budgeted in future`
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use super::entities::*;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryMonth {
+    pub category_id: String,
+    pub master_category_id: String,
+    pub budgeted: f64,
+    /// Net activity of the month (outflows are negative)
+    pub outflows: f64,
+    /// Balance carried in from last month
+    pub carryover: f64,
+    pub balance: f64,
+    /// Effective overspending handling ("AffectsBuffer" or "Confined")
+    pub overspending_handling: String,
+    /// Overspending taken from next month's Available to Budget
+    pub cash_overspending: f64,
+    /// Overspending that became credit card debt
+    pub credit_overspending: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonthSummary {
+    /// "YYYY-MM"
+    pub month: String,
+    /// Available to Budget left in the previous month (negative when overbudgeted)
+    pub not_budgeted_last_month: f64,
+    /// Cash overspending of the previous month, as a negative amount
+    pub overspent_last_month: f64,
+    /// Immediate income of this month plus deferred income of last month
+    pub income_this_month: f64,
+    pub budgeted_this_month: f64,
+    /// Money that later months have budgeted beyond their own funds
+    pub budgeted_in_future: f64,
+    pub available_to_budget: f64,
+    pub total_outflows: f64,
+    pub total_balance: f64,
+    pub categories: Vec<CategoryMonth>,
+}
+
+/// Round to milliunits to hide floating point noise
+pub fn round_amount(value: f64) -> f64 {
+    let rounded = (value * 1000.0).round() / 1000.0;
+    if rounded == 0.0 {
+        0.0
+    } else {
+        rounded
+    }
+}
+
+/// "2024-01-15" or "2024-01" -> "2024-01"
+pub fn month_of(date: &str) -> String {
+    date.get(..7).unwrap_or(date).to_string()
+}
+
+/// "2024-12" -> "2025-01"
+pub fn next_month(month: &str) -> String {
+    let year: i32 = month.get(..4).and_then(|y| y.parse().ok()).unwrap_or(1970);
+    let m: u32 = month.get(5..7).and_then(|m| m.parse().ok()).unwrap_or(1);
+    if m >= 12 {
+        format!("{:04}-01", year + 1)
+    } else {
+        format!("{:04}-{:02}", year, m + 1)
+    }
+}
+
+#[derive(Default)]
+struct Activity {
+    /// (month, category) -> net amount
+    outflows: HashMap<(String, String), f64>,
+    /// (month, category) -> net amount on credit accounts
+    credit: HashMap<(String, String), f64>,
+    /// month -> income available that month
+    income: HashMap<String, f64>,
+    months: BTreeSet<String>,
+}
+
+impl Activity {
+    fn add(&mut self, month: &str, category_id: &str, amount: f64, credit: bool) {
+        match category_id {
+            IMMEDIATE_INCOME_CATEGORY_ID => {
+                *self.income.entry(month.to_string()).or_default() += amount;
+            }
+            DEFERRED_INCOME_CATEGORY_ID => {
+                let next = next_month(month);
+                *self.income.entry(next.clone()).or_default() += amount;
+                self.months.insert(next);
+            }
+            _ => {
+                let key = (month.to_string(), category_id.to_string());
+                *self.outflows.entry(key.clone()).or_default() += amount;
+                if credit {
+                    *self.credit.entry(key).or_default() += amount;
+                }
+            }
+        }
+        self.months.insert(month.to_string());
+    }
+}
+
+fn collect_activity(data: &BudgetData) -> Activity {
+    let mut activity = Activity::default();
+    let on_budget: HashMap<&str, &Account> = data
+        .accounts
+        .iter()
+        .filter(|a| a.on_budget && !a.is_tombstone)
+        .map(|a| (a.entity_id.as_str(), a))
+        .collect();
+
+    for transaction in data.transactions.iter().filter(|t| !t.is_tombstone) {
+        let Some(account) = on_budget.get(transaction.account_id.as_str()) else {
+            continue;
+        };
+        let month = month_of(&transaction.date);
+        let credit = account.is_credit();
+
+        if transaction.is_split() {
+            for sub in transaction.live_sub_transactions() {
+                if let Some(category_id) = sub.category_id.as_deref() {
+                    activity.add(&month, category_id, sub.amount, credit);
+                }
+            }
+        } else if let Some(category_id) = transaction.category_id.as_deref() {
+            activity.add(&month, category_id, transaction.amount, credit);
+        }
+    }
+    activity
+}
+
+/// Calculate every month from the first month with data up to `until`
+/// (or the last month with data, whichever is later)
+pub fn calculate_months(data: &BudgetData, until: Option<&str>) -> Vec<MonthSummary> {
+    let activity = collect_activity(data);
+
+    // (month, category) -> monthly sub category budget
+    let mut budgets: HashMap<(String, String), &MonthlySubCategoryBudget> = HashMap::new();
+    let mut months = activity.months.clone();
+    for monthly in data.monthly_budgets.iter().filter(|m| !m.is_tombstone) {
+        let month = monthly.month_key().to_string();
+        for budget in monthly
+            .monthly_sub_category_budgets
+            .iter()
+            .filter(|b| !b.is_tombstone)
+        {
+            budgets.insert((month.clone(), budget.category_id.clone()), budget);
+            if budget.budgeted != 0.0 || budget.overspending_handling.is_some() {
+                months.insert(month.clone());
+            }
+        }
+    }
+
+    let (Some(first), Some(last_with_data)) = (months.first().cloned(), months.last().cloned())
+    else {
+        return Vec::new();
+    };
+    let last = match until {
+        Some(until) if month_of(until) > last_with_data => month_of(until),
+        _ => last_with_data,
+    };
+
+    let categories = data.live_categories();
+    let mut carryover: BTreeMap<String, f64> = BTreeMap::new();
+    let mut handling: HashMap<String, String> = HashMap::new();
+    let mut summaries: Vec<MonthSummary> = Vec::new();
+    let mut not_budgeted_last_month = 0.0;
+    let mut overspent_last_month = 0.0;
+
+    let mut month = first;
+    while month <= last {
+        let mut category_months = Vec::with_capacity(categories.len());
+        let mut budgeted_this_month = 0.0;
+        let mut cash_overspent_this_month = 0.0;
+        let mut total_outflows = 0.0;
+        let mut total_balance = 0.0;
+
+        for category in &categories {
+            let key = (month.clone(), category.entity_id.clone());
+            let budget = budgets.get(&key);
+            let budgeted = budget.map_or(0.0, |b| b.budgeted);
+            let outflows = activity.outflows.get(&key).copied().unwrap_or(0.0);
+            let credit_outflows = activity.credit.get(&key).copied().unwrap_or(0.0);
+            let carry_in = carryover.get(&category.entity_id).copied().unwrap_or(0.0);
+
+            if let Some(value) = budget.and_then(|b| b.overspending_handling.clone()) {
+                handling.insert(category.entity_id.clone(), value);
+            }
+            let effective_handling = handling
+                .get(&category.entity_id)
+                .cloned()
+                .unwrap_or_else(|| OVERSPENDING_AFFECTS_BUFFER.to_string());
+
+            let balance = round_amount(carry_in + budgeted + outflows);
+            let mut cash_overspending = 0.0;
+            let mut credit_overspending = 0.0;
+            let carry_out = if balance >= 0.0 || effective_handling == OVERSPENDING_CONFINED {
+                balance
+            } else {
+                // Card spending counts as overspent first, the rest was paid in cash
+                let overspent = -balance;
+                credit_overspending = overspent.min((-credit_outflows).max(0.0));
+                cash_overspending = round_amount(overspent - credit_overspending);
+                0.0
+            };
+            carryover.insert(category.entity_id.clone(), carry_out);
+
+            budgeted_this_month += budgeted;
+            cash_overspent_this_month += cash_overspending;
+            total_outflows += outflows;
+            total_balance += balance;
+
+            category_months.push(CategoryMonth {
+                category_id: category.entity_id.clone(),
+                master_category_id: category.master_category_id.clone(),
+                budgeted: round_amount(budgeted),
+                outflows: round_amount(outflows),
+                carryover: round_amount(carry_in),
+                balance,
+                overspending_handling: effective_handling,
+                cash_overspending,
+                credit_overspending: round_amount(credit_overspending),
+            });
+        }
+
+        let income_this_month = activity.income.get(&month).copied().unwrap_or(0.0);
+        let available = not_budgeted_last_month + overspent_last_month + income_this_month
+            - budgeted_this_month;
+
+        summaries.push(MonthSummary {
+            month: month.clone(),
+            not_budgeted_last_month: round_amount(not_budgeted_last_month),
+            overspent_last_month: round_amount(overspent_last_month),
+            income_this_month: round_amount(income_this_month),
+            budgeted_this_month: round_amount(budgeted_this_month),
+            budgeted_in_future: 0.0,
+            available_to_budget: round_amount(available),
+            total_outflows: round_amount(total_outflows),
+            total_balance: round_amount(total_balance),
+            categories: category_months,
+        });
+
+        not_budgeted_last_month = available;
+        overspent_last_month = -cash_overspent_this_month;
+        month = next_month(&month);
+    }
+
+    // Money budgeted in later months beyond what those months have comes out
+    // of the current month, but never pushes it below zero
+    let mut lowest_future = f64::INFINITY;
+    for summary in summaries.iter_mut().rev() {
+        let available = summary.available_to_budget;
+        if available > 0.0 && lowest_future < available {
+            let in_future = (available - lowest_future.max(0.0)).min(available);
+            summary.budgeted_in_future = round_amount(in_future);
+            summary.available_to_budget = round_amount(available - in_future);
+        }
+        lowest_future = lowest_future.min(available);
+    }
+
+    summaries
+}
+
+/// Calculate the months between `from` and `to` (inclusive, "YYYY-MM")
+pub fn calculate_range(
+    data: &BudgetData,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Vec<MonthSummary> {
+    let from = from.map(month_of);
+    let to = to.map(month_of);
+    calculate_months(data, to.as_deref())
+        .into_iter()
+        .filter(|m| from.as_ref().map_or(true, |from| &m.month >= from))
+        .filter(|m| to.as_ref().map_or(true, |to| &m.month <= to))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checking and a credit card, Food and Rent, income in checking on January 1st
+    fn budget(
+        transactions: &[(&str, &str, &str, f64)],
+        budgets: &[(&str, &str, f64, Option<&str>)],
+    ) -> BudgetData {
+        let account = |id: &str, account_type: &str| Account {
+            entity_id: id.to_string(),
+            account_type: account_type.to_string(),
+            on_budget: true,
+            ..Default::default()
+        };
+        let category = |id: &str, index: f64| Category {
+            entity_id: id.to_string(),
+            master_category_id: "MC".to_string(),
+            sortable_index: Some(index),
+            ..Default::default()
+        };
+        let income = ("CHK", "2024-01-01", IMMEDIATE_INCOME_CATEGORY_ID, 1000.0);
+        let transactions = std::iter::once(&income)
+            .chain(transactions)
+            .enumerate()
+            .map(|(i, (account_id, date, category_id, amount))| Transaction {
+                entity_id: format!("T-{}", i),
+                account_id: account_id.to_string(),
+                date: date.to_string(),
+                category_id: Some(category_id.to_string()),
+                amount: *amount,
+                ..Default::default()
+            })
+            .collect();
+        let mut monthly_budgets: Vec<MonthlyBudget> = Vec::new();
+        for (month, category_id, budgeted, handling) in budgets {
+            let month = format!("{}-01", month);
+            if !monthly_budgets.iter().any(|m| m.month == month) {
+                monthly_budgets.push(MonthlyBudget {
+                    entity_id: format!("MB/{}", month),
+                    month: month.clone(),
+                    ..Default::default()
+                });
+            }
+            let monthly = monthly_budgets
+                .iter_mut()
+                .find(|m| m.month == month)
+                .unwrap();
+            monthly
+                .monthly_sub_category_budgets
+                .push(MonthlySubCategoryBudget {
+                    entity_id: format!("MCB/{}/{}", month, category_id),
+                    category_id: category_id.to_string(),
+                    budgeted: *budgeted,
+                    overspending_handling: handling.map(str::to_string),
+                    ..Default::default()
+                });
+        }
+        BudgetData {
+            accounts: vec![account("CHK", "Checking"), account("CC", "CreditCard")],
+            master_categories: vec![MasterCategory {
+                entity_id: "MC".to_string(),
+                sub_categories: Some(vec![category("FOOD", 0.0), category("RENT", 1.0)]),
+                ..Default::default()
+            }],
+            transactions,
+            monthly_budgets,
+            ..Default::default()
+        }
+    }
+
+    fn category<'a>(summary: &'a MonthSummary, category_id: &str) -> &'a CategoryMonth {
+        summary
+            .categories
+            .iter()
+            .find(|c| c.category_id == category_id)
+            .unwrap()
+    }
+
+    #[test]
+    fn positive_balances_carry_over() {
+        let data = budget(
+            &[
+                ("CHK", "2024-01-10", "FOOD", -30.0),
+                ("CHK", "2024-02-10", "FOOD", -20.0),
+            ],
+            &[("2024-01", "FOOD", 100.0, None)],
+        );
+        let months = calculate_months(&data, None);
+        assert_eq!(months.len(), 2);
+        let february = category(&months[1], "FOOD");
+        assert_eq!(february.carryover, 70.0);
+        assert_eq!(february.balance, 50.0);
+        assert_eq!(months[0].available_to_budget, 900.0);
+        assert_eq!(months[1].not_budgeted_last_month, 900.0);
+        assert_eq!(months[1].available_to_budget, 900.0);
+    }
+
+    #[test]
+    fn card_overspending_becomes_debt_and_cash_overspending_leaves_the_buffer() {
+        let data = budget(
+            &[
+                ("CHK", "2024-01-05", "FOOD", -40.0),
+                ("CC", "2024-01-06", "FOOD", -30.0),
+                ("CHK", "2024-01-07", "RENT", -60.0),
+                ("CC", "2024-01-08", "RENT", -10.0),
+            ],
+            &[("2024-01", "FOOD", 50.0, None)],
+        );
+        let months = calculate_months(&data, Some("2024-02"));
+        let food = category(&months[0], "FOOD");
+        assert_eq!(
+            (
+                food.balance,
+                food.credit_overspending,
+                food.cash_overspending
+            ),
+            (-20.0, 20.0, 0.0)
+        );
+        let rent = category(&months[0], "RENT");
+        assert_eq!(
+            (
+                rent.balance,
+                rent.credit_overspending,
+                rent.cash_overspending
+            ),
+            (-70.0, 10.0, 60.0)
+        );
+
+        assert_eq!(category(&months[1], "FOOD").carryover, 0.0);
+        assert_eq!(category(&months[1], "RENT").carryover, 0.0);
+        assert_eq!(months[1].overspent_last_month, -60.0);
+        assert_eq!(months[1].available_to_budget, 890.0);
+    }
+
+    #[test]
+    fn overspending_handling_sticks_until_changed() {
+        let data = budget(
+            &[("CHK", "2024-01-05", "RENT", -50.0)],
+            &[
+                ("2024-01", "RENT", 0.0, Some(OVERSPENDING_CONFINED)),
+                ("2024-03", "RENT", 0.0, Some(OVERSPENDING_AFFECTS_BUFFER)),
+            ],
+        );
+        let months = calculate_months(&data, Some("2024-04"));
+        assert_eq!(months.len(), 4);
+        // Confined in January and, without a new setting, in February
+        for summary in &months[..2] {
+            let rent = category(summary, "RENT");
+            assert_eq!(rent.overspending_handling, OVERSPENDING_CONFINED);
+            assert_eq!((rent.balance, rent.cash_overspending), (-50.0, 0.0));
+        }
+        assert_eq!(category(&months[1], "RENT").carryover, -50.0);
+        assert_eq!(months[1].overspent_last_month, 0.0);
+
+        let march = category(&months[2], "RENT");
+        assert_eq!(march.overspending_handling, OVERSPENDING_AFFECTS_BUFFER);
+        assert_eq!((march.carryover, march.cash_overspending), (-50.0, 50.0));
+        assert_eq!(category(&months[3], "RENT").carryover, 0.0);
+        assert_eq!(months[3].overspent_last_month, -50.0);
+        assert_eq!(months[3].available_to_budget, 950.0);
+    }
+
+    #[test]
+    fn budgeting_in_future_months_comes_out_of_earlier_ones() {
+        let data = budget(
+            &[],
+            &[
+                ("2024-01", "FOOD", 200.0, None),
+                ("2024-02", "FOOD", 300.0, None),
+            ],
+        );
+        let months = calculate_months(&data, None);
+        assert_eq!(months[0].budgeted_in_future, 300.0);
+        assert_eq!(months[0].available_to_budget, 500.0);
+        assert_eq!(
+            (months[1].budgeted_in_future, months[1].available_to_budget),
+            (0.0, 500.0)
+        );
+
+        // Overbudgeting February takes all of January's money, but no more
+        let data = budget(
+            &[],
+            &[
+                ("2024-01", "FOOD", 200.0, None),
+                ("2024-02", "FOOD", 1000.0, None),
+            ],
+        );
+        let months = calculate_months(&data, None);
+        assert_eq!(months[0].budgeted_in_future, 800.0);
+        assert_eq!(months[0].available_to_budget, 0.0);
+        assert_eq!(months[1].available_to_budget, -200.0);
+    }
+}
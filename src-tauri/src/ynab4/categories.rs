@@ -0,0 +1,326 @@
+//! Deleting and merging categories.
+//!
+//! Removing a sub category (or a master category with all of its sub
+//! categories) first moves everything that points at it to a target category:
+//! transactions, split lines, scheduled transactions and their split lines,
+//! and payee auto-fill categories.
+//! Each month's budgeted amount is added to the target's amount for that
+//! month. A category with history can only be removed with a target, so no
+//! reference is ever left pointing at a deleted category.
+
+use serde::Serialize;
+
+use super::budget::Budget;
+use super::entities::*;
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryRemovalResult {
+    /// Sub categories removed, including those of removed master categories
+    pub removed_category_ids: Vec<String>,
+    pub removed_master_category_ids: Vec<String>,
+    pub target_category_id: Option<String>,
+    pub transaction_count: usize,
+    pub sub_transaction_count: usize,
+    pub scheduled_transaction_count: usize,
+    /// Months whose budgeted amounts were folded into the target
+    pub month_count: usize,
+    /// False for a preview; nothing was written
+    pub committed: bool,
+    pub ydiff_path: Option<String>,
+}
+
+impl CategoryRemovalResult {
+    fn has_history(&self) -> bool {
+        self.transaction_count
+            + self.sub_transaction_count
+            + self.scheduled_transaction_count
+            + self.month_count
+            > 0
+    }
+}
+
+/// Sub and master categories named by `ids`, expanding master categories
+fn resolve(budget: &Budget, ids: &[String]) -> Result<(Vec<String>, Vec<String>), String> {
+    let mut categories = Vec::new();
+    let mut masters = Vec::new();
+    for id in ids {
+        if let Some(master) = budget
+            .data
+            .master_categories
+            .iter()
+            .find(|m| &m.entity_id == id && !m.is_tombstone)
+        {
+            masters.push(master.entity_id.clone());
+            for sub in master
+                .sub_categories
+                .iter()
+                .flatten()
+                .filter(|c| !c.is_tombstone)
+            {
+                if !categories.contains(&sub.entity_id) {
+                    categories.push(sub.entity_id.clone());
+                }
+            }
+        } else if budget
+            .data
+            .live_categories()
+            .iter()
+            .any(|c| &c.entity_id == id)
+        {
+            if !categories.contains(id) {
+                categories.push(id.clone());
+            }
+        } else {
+            return Err(format!("Category not found: {}", id));
+        }
+    }
+    Ok((categories, masters))
+}
+
+fn points_at(category_id: &Option<String>, sources: &[String]) -> bool {
+    category_id.as_ref().is_some_and(|id| sources.contains(id))
+}
+
+/// Remove categories, moving their history to `target_id`.
+/// With `dry_run` only the counts are returned; otherwise the changes are
+/// written as one ydiff.
+pub fn remove_categories(
+    budget: &mut Budget,
+    category_ids: &[String],
+    target_id: Option<&str>,
+    dry_run: bool,
+) -> Result<CategoryRemovalResult, String> {
+    let (sources, masters) = resolve(budget, category_ids)?;
+    if sources.is_empty() && masters.is_empty() {
+        return Err("No categories to remove".to_string());
+    }
+    if let Some(target) = target_id {
+        if sources.iter().any(|id| id == target) {
+            return Err("The target category is being removed".to_string());
+        }
+        if !budget
+            .data
+            .live_categories()
+            .iter()
+            .any(|c| c.entity_id == target)
+        {
+            return Err(format!("Target category not found: {}", target));
+        }
+    }
+
+    let mut result = CategoryRemovalResult {
+        removed_category_ids: sources.clone(),
+        removed_master_category_ids: masters.clone(),
+        target_category_id: target_id.map(str::to_string),
+        ..Default::default()
+    };
+    let data = &budget.data;
+    for transaction in data.transactions.iter().filter(|t| !t.is_tombstone) {
+        if points_at(&transaction.category_id, &sources) {
+            result.transaction_count += 1;
+        }
+        result.sub_transaction_count += transaction
+            .live_sub_transactions()
+            .filter(|s| points_at(&s.category_id, &sources))
+            .count();
+    }
+    for scheduled in data
+        .scheduled_transactions
+        .iter()
+        .filter(|t| !t.is_tombstone)
+    {
+        let in_subs = scheduled
+            .sub_transactions
+            .iter()
+            .flatten()
+            .any(|s| !s.is_tombstone && points_at(&s.category_id, &sources));
+        if in_subs || points_at(&scheduled.category_id, &sources) {
+            result.scheduled_transaction_count += 1;
+        }
+    }
+    result.month_count = data
+        .monthly_budgets
+        .iter()
+        .filter(|m| !m.is_tombstone)
+        .filter(|m| {
+            m.monthly_sub_category_budgets
+                .iter()
+                .any(|b| !b.is_tombstone && sources.contains(&b.category_id) && b.budgeted != 0.0)
+        })
+        .count();
+
+    if result.has_history() && target_id.is_none() {
+        return Err(format!(
+            "These categories are used by {} transactions, {} split lines, {} scheduled transactions and {} months; choose a category to move them to",
+            result.transaction_count,
+            result.sub_transaction_count,
+            result.scheduled_transaction_count,
+            result.month_count
+        ));
+    }
+    if dry_run {
+        return Ok(result);
+    }
+
+    let target = target_id.map(str::to_string);
+    let mut changed: Vec<String> = Vec::new();
+    let data = &mut budget.data;
+
+    for transaction in data.transactions.iter_mut().filter(|t| !t.is_tombstone) {
+        if points_at(&transaction.category_id, &sources) {
+            transaction.category_id = target.clone();
+            changed.push(transaction.entity_id.clone());
+        }
+        for sub in transaction.sub_transactions.iter_mut().flatten() {
+            if !sub.is_tombstone && points_at(&sub.category_id, &sources) {
+                sub.category_id = target.clone();
+                changed.push(sub.entity_id.clone());
+            }
+        }
+    }
+    for scheduled in data
+        .scheduled_transactions
+        .iter_mut()
+        .filter(|t| !t.is_tombstone)
+    {
+        if points_at(&scheduled.category_id, &sources) {
+            scheduled.category_id = target.clone();
+            changed.push(scheduled.entity_id.clone());
+        }
+        for sub in scheduled.sub_transactions.iter_mut().flatten() {
+            if !sub.is_tombstone && points_at(&sub.category_id, &sources) {
+                sub.category_id = target.clone();
+                changed.push(sub.entity_id.clone());
+            }
+        }
+    }
+
+    // Auto-fill categories follow the target, or are cleared without one
+    for payee in data.payees.iter_mut().filter(|p| !p.is_tombstone) {
+        if points_at(&payee.auto_fill_category_id, &sources) {
+            payee.auto_fill_category_id = target.clone();
+            changed.push(payee.entity_id.clone());
+        }
+    }
+
+    for month in data.monthly_budgets.iter_mut().filter(|m| !m.is_tombstone) {
+        let mut folded = 0.0;
+        for monthly in month.monthly_sub_category_budgets.iter_mut() {
+            if !monthly.is_tombstone && sources.contains(&monthly.category_id) {
+                folded += monthly.budgeted;
+                monthly.budgeted = 0.0;
+                monthly.is_tombstone = true;
+                changed.push(monthly.entity_id.clone());
+            }
+        }
+        let Some(target) = target_id.filter(|_| folded != 0.0) else {
+            continue;
+        };
+        let month_key = month.month_key().to_string();
+        let parent_id = month.entity_id.clone();
+        let list = &mut month.monthly_sub_category_budgets;
+        let idx = match list.iter().position(|b| b.category_id == target) {
+            Some(idx) => idx,
+            None => {
+                list.push(MonthlySubCategoryBudget {
+                    entity_id: format!("MCB/{}/{}", month_key, target),
+                    entity_type: "monthlyCategoryBudget".to_string(),
+                    category_id: target.to_string(),
+                    parent_monthly_budget_id: parent_id,
+                    ..Default::default()
+                });
+                list.len() - 1
+            }
+        };
+        let monthly = &mut list[idx];
+        if monthly.is_tombstone {
+            monthly.is_tombstone = false;
+            monthly.budgeted = 0.0;
+        }
+        monthly.budgeted += folded;
+        changed.push(monthly.entity_id.clone());
+    }
+
+    for master in data.master_categories.iter_mut() {
+        let master_removed = masters.contains(&master.entity_id);
+        if master_removed && !master.is_tombstone {
+            master.is_tombstone = true;
+            changed.push(master.entity_id.clone());
+        }
+        for category in master.sub_categories.iter_mut().flatten() {
+            if !category.is_tombstone && sources.contains(&category.entity_id) {
+                category.is_tombstone = true;
+                changed.push(category.entity_id.clone());
+            }
+        }
+    }
+
+    for id in &changed {
+        budget.mark_dirty(id.clone());
+    }
+    let ydiff_path = budget.save_entities(&changed)?;
+    result.committed = true;
+    result.ydiff_path = ydiff_path.map(|p| p.to_string_lossy().to_string());
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::fixtures;
+
+    #[test]
+    fn removing_a_category_folds_it_into_the_target() {
+        let path = fixtures::budget_folder("categories-remove");
+        let mut budget = Budget::load(&path).unwrap();
+        let fun = vec!["CAT-FUN".to_string()];
+        assert!(remove_categories(&mut budget, &fun, None, false).is_err());
+        let preview = remove_categories(&mut budget, &fun, Some("CAT-FOOD"), true).unwrap();
+        assert_eq!(
+            (
+                preview.transaction_count,
+                preview.scheduled_transaction_count,
+                preview.month_count
+            ),
+            (1, 1, 2)
+        );
+        assert!(budget.dirty.is_empty());
+
+        budget.mark_dirty("T-1");
+        let result = remove_categories(&mut budget, &fun, Some("CAT-FOOD"), false).unwrap();
+        assert!(result.committed);
+        assert!(budget.dirty.contains("T-1"));
+
+        let reloaded = Budget::load(&path).unwrap();
+        assert_eq!(
+            reloaded
+                .data
+                .transaction("T-3")
+                .unwrap()
+                .category_id
+                .as_deref(),
+            Some("CAT-FOOD")
+        );
+        let budgeted = |month: &str| {
+            let month = reloaded
+                .data
+                .monthly_budgets
+                .iter()
+                .find(|m| m.entity_id == month)
+                .unwrap();
+            let food = month.monthly_sub_category_budgets.iter();
+            food.filter(|b| b.category_id == "CAT-FOOD" && !b.is_tombstone)
+                .map(|b| b.budgeted)
+                .sum::<f64>()
+        };
+        assert_eq!(budgeted("MB/2024-01"), 150.0);
+        assert_eq!(budgeted("MB/2024-03"), 2000.0);
+        assert!(!reloaded
+            .data
+            .live_categories()
+            .iter()
+            .any(|c| c.entity_id == "CAT-FUN"));
+        assert!(remove_categories(&mut budget, &["MC-1".to_string()], None, true).is_err());
+    }
+}
@@ -0,0 +1,531 @@
+//! Tauri commands working on budgets opened by the Rust backend.
+//!
+//! Budgets are kept in memory by path so that repeated calls do not re-read
+//! the whole folder. `budget_open` always reloads from disk. Commands that
+//! change the budget go through `with_budget_edit`, which records an undo step.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use super::accounts::{self, AccountChange, AccountDeletePolicy, AccountOrder, AccountUpdate};
+use super::budget::Budget;
+use super::calculator::{self, MonthSummary};
+use super::categories::{self, CategoryRemovalResult};
+use super::journal::{Journal, JournalEntry, JournalHistory};
+use super::local;
+use super::payees::{self, PayeeMergeResult};
+use super::reconcile::{ReconcileResult, ReconcileStatus, ReconciliationSession};
+use super::rules::{self, RuleSet, RuleSuggestion, RulesSidecar};
+use super::transactions::{
+    self, BulkUpdateResult, TransactionChange, TransactionInput, TransactionPatch,
+};
+
+// Budgets opened by the backend, keyed by budget folder path
+static OPEN_BUDGETS: Mutex<BTreeMap<String, Budget>> = Mutex::new(BTreeMap::new());
+
+// Undo/redo journals, keyed by budget folder path
+static JOURNALS: Mutex<BTreeMap<String, Journal>> = Mutex::new(BTreeMap::new());
+
+// Reconciliations in progress, keyed by budget folder path
+static RECONCILIATIONS: Mutex<BTreeMap<String, ReconciliationSession>> =
+    Mutex::new(BTreeMap::new());
+
+/// Run `f` on an open budget, loading it first if needed
+pub fn with_budget<T>(
+    budget_path: &str,
+    f: impl FnOnce(&mut Budget) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut open = OPEN_BUDGETS.lock().map_err(|e| e.to_string())?;
+    if !open.contains_key(budget_path) {
+        let budget = load_budget(budget_path)?;
+        open.insert(budget_path.to_string(), budget);
+    }
+    let budget = open
+        .get_mut(budget_path)
+        .ok_or_else(|| format!("Budget not open: {}", budget_path))?;
+    let result = f(budget);
+    remember_device(budget_path, budget)?;
+    result
+}
+
+/// File in the budget's local folder naming the device this client writes as
+const DEVICE_FILE: &str = "device.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LocalDevice {
+    device_guid: Option<String>,
+}
+
+fn remembered_device(budget_path: &str) -> Result<Option<String>, String> {
+    let device: LocalDevice =
+        local::read_or_default(&local::budget_dir(budget_path)?.join(DEVICE_FILE))?;
+    Ok(device.device_guid)
+}
+
+/// Keep the attached device for next time, so each run does not register a new one
+fn remember_device(budget_path: &str, budget: &Budget) -> Result<(), String> {
+    let Some(device) = &budget.device else {
+        return Ok(());
+    };
+    if remembered_device(budget_path)?.as_deref() == Some(device.device_guid.as_str()) {
+        return Ok(());
+    }
+    let local = LocalDevice {
+        device_guid: Some(device.device_guid.clone()),
+    };
+    local::write_json(&local::budget_dir(budget_path)?.join(DEVICE_FILE), &local)
+}
+
+/// Run `f` on the budget's journal, loading it first if needed
+fn with_journal<T>(
+    budget_path: &str,
+    f: impl FnOnce(&mut Journal) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut journals = JOURNALS.lock().map_err(|e| e.to_string())?;
+    if !journals.contains_key(budget_path) {
+        journals.insert(budget_path.to_string(), Journal::load(budget_path)?);
+    }
+    let journal = journals
+        .get_mut(budget_path)
+        .ok_or_else(|| format!("No journal for {}", budget_path))?;
+    f(journal)
+}
+
+/// Load a budget from disk with the edits that were not written yet, writing
+/// as the device this client used before when it is still registered
+fn load_budget(budget_path: &str) -> Result<Budget, String> {
+    let mut budget = Budget::load(Path::new(budget_path))?;
+    if let Some(guid) = remembered_device(budget_path)? {
+        let devices = Budget::read_devices(&budget.data_folder)?;
+        if devices.iter().any(|d| d.device_guid == guid) {
+            budget.attach_device(Some(&guid))?;
+        }
+    }
+    let skipped = with_journal(budget_path, |journal| journal.restore_pending(&mut budget))?;
+    if !skipped.is_empty() {
+        log::warn!(
+            "Dropped unsaved edits written elsewhere since: {}",
+            skipped.join(", ")
+        );
+    }
+    Ok(budget)
+}
+
+/// Like `with_budget`, recording what `f` changed as one undo step named `label`;
+/// when `f` fails the budget is left as it was before the call
+pub fn with_budget_edit<T>(
+    budget_path: &str,
+    label: &str,
+    f: impl FnOnce(&mut Budget) -> Result<T, String>,
+) -> Result<T, String> {
+    with_budget(budget_path, |budget| {
+        let before = budget.data.clone();
+        let dirty_before = budget.dirty.clone();
+        budget.touched.clear();
+        let result = f(budget);
+        if result.is_err() {
+            // A failed edit leaves nothing behind, not even the part done before the error
+            budget.data = before;
+            budget.dirty = dirty_before;
+            budget.touched.clear();
+            return result;
+        }
+        if !budget.touched.is_empty() {
+            with_journal(budget_path, |journal| {
+                journal.record(budget, &before, label);
+                journal.sync_pending(budget);
+                journal.save(budget_path)
+            })?;
+        }
+        result
+    })
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenBudgetInfo {
+    pub path: String,
+    pub knowledge: String,
+    pub device_guid: Option<String>,
+    pub short_device_id: Option<String>,
+    pub account_count: usize,
+    pub transaction_count: usize,
+}
+
+/// Load (or reload) a budget folder into the backend.
+/// Edits are written as `device_guid`, or else as the device used last time;
+/// a new device is only registered when the first ydiff is written.
+#[tauri::command]
+pub fn budget_open(
+    budget_path: String,
+    device_guid: Option<String>,
+) -> Result<OpenBudgetInfo, String> {
+    let mut budget = load_budget(&budget_path)?;
+    if let Some(guid) = device_guid.as_deref() {
+        budget.attach_device(Some(guid))?;
+        remember_device(&budget_path, &budget)?;
+    }
+    let device = budget.device.as_ref();
+    let info = OpenBudgetInfo {
+        path: budget_path.clone(),
+        knowledge: budget.knowledge.to_string(),
+        device_guid: device.map(|d| d.device_guid.clone()),
+        short_device_id: device.map(|d| d.short_device_id.clone()),
+        account_count: budget
+            .data
+            .accounts
+            .iter()
+            .filter(|a| !a.is_tombstone)
+            .count(),
+        transaction_count: budget
+            .data
+            .transactions
+            .iter()
+            .filter(|t| !t.is_tombstone)
+            .count(),
+    };
+    OPEN_BUDGETS
+        .lock()
+        .map_err(|e| e.to_string())?
+        .insert(budget_path, budget);
+    Ok(info)
+}
+
+/// Forget a budget opened by the backend
+#[tauri::command]
+pub fn budget_close(budget_path: String) -> Result<(), String> {
+    OPEN_BUDGETS
+        .lock()
+        .map_err(|e| e.to_string())?
+        .remove(&budget_path);
+    Ok(())
+}
+
+/// Write the edits made since the last save as one ydiff.
+/// Returns the ydiff path, or nothing when there was nothing to write.
+#[tauri::command]
+pub fn budget_save_changes(budget_path: String) -> Result<Option<String>, String> {
+    with_budget(&budget_path, |budget| {
+        let path = budget.save_changes()?;
+        with_journal(&budget_path, |journal| {
+            journal.sync_pending(budget);
+            journal.save(&budget_path)
+        })?;
+        Ok(path.map(|p| p.to_string_lossy().to_string()))
+    })
+}
+
+/// Monthly budget figures (budgeted, outflows, balance, carryover and
+/// Available to Budget) for the months between `from_month` and `to_month`
+#[tauri::command]
+pub fn budget_calculate_months(
+    budget_path: String,
+    from_month: Option<String>,
+    to_month: Option<String>,
+) -> Result<Vec<MonthSummary>, String> {
+    with_budget(&budget_path, |budget| {
+        Ok(calculator::calculate_range(
+            &budget.data,
+            from_month.as_deref(),
+            to_month.as_deref(),
+        ))
+    })
+}
+
+/// Start reconciling an account against a bank statement
+#[tauri::command]
+pub fn reconcile_start(
+    budget_path: String,
+    account_id: String,
+    statement_date: String,
+    statement_balance: f64,
+) -> Result<ReconcileStatus, String> {
+    with_budget(&budget_path, |budget| {
+        let session =
+            ReconciliationSession::new(budget, &account_id, &statement_date, statement_balance)?;
+        let status = session.status(budget);
+        RECONCILIATIONS
+            .lock()
+            .map_err(|e| e.to_string())?
+            .insert(budget_path.clone(), session);
+        Ok(status)
+    })
+}
+
+/// Toggle the cleared state of transactions in the running reconciliation
+#[tauri::command]
+pub fn reconcile_toggle_cleared(
+    budget_path: String,
+    transaction_ids: Vec<String>,
+) -> Result<ReconcileStatus, String> {
+    with_budget(&budget_path, |budget| {
+        let mut sessions = RECONCILIATIONS.lock().map_err(|e| e.to_string())?;
+        let session = sessions
+            .get_mut(&budget_path)
+            .ok_or("No reconciliation in progress")?;
+        session.toggle(budget, &transaction_ids)?;
+        Ok(session.status(budget))
+    })
+}
+
+/// Cleared balance and running difference of the reconciliation in progress
+#[tauri::command]
+pub fn reconcile_status(budget_path: String) -> Result<ReconcileStatus, String> {
+    with_budget(&budget_path, |budget| {
+        let sessions = RECONCILIATIONS.lock().map_err(|e| e.to_string())?;
+        let session = sessions
+            .get(&budget_path)
+            .ok_or("No reconciliation in progress")?;
+        Ok(session.status(budget))
+    })
+}
+
+/// Mark cleared transactions as reconciled, adding an adjustment transaction
+/// for any remaining difference when `create_adjustment` is set
+#[tauri::command]
+pub fn reconcile_finish(
+    budget_path: String,
+    create_adjustment: bool,
+) -> Result<ReconcileResult, String> {
+    with_budget_edit(&budget_path, "Reconcile account", |budget| {
+        let mut sessions = RECONCILIATIONS.lock().map_err(|e| e.to_string())?;
+        let session = sessions
+            .get(&budget_path)
+            .ok_or("No reconciliation in progress")?;
+        let result = session.finish(budget, create_adjustment)?;
+        sessions.remove(&budget_path);
+        Ok(result)
+    })
+}
+
+/// Drop the reconciliation in progress without changing anything
+#[tauri::command]
+pub fn reconcile_cancel(budget_path: String) -> Result<(), String> {
+    RECONCILIATIONS
+        .lock()
+        .map_err(|e| e.to_string())?
+        .remove(&budget_path);
+    Ok(())
+}
+
+/// Create a transaction; transfers and split transfers get their other side
+#[tauri::command]
+pub fn transaction_create(
+    budget_path: String,
+    input: TransactionInput,
+) -> Result<TransactionChange, String> {
+    with_budget_edit(&budget_path, "Create transaction", |budget| {
+        transactions::create_transaction(budget, input)
+    })
+}
+
+/// Replace a transaction's details, keeping the other side of transfers in sync
+#[tauri::command]
+pub fn transaction_update(
+    budget_path: String,
+    transaction_id: String,
+    input: TransactionInput,
+) -> Result<TransactionChange, String> {
+    with_budget_edit(&budget_path, "Edit transaction", |budget| {
+        transactions::update_transaction(budget, &transaction_id, input)
+    })
+}
+
+/// Delete a transaction together with the other side of its transfers
+#[tauri::command]
+pub fn transaction_delete(
+    budget_path: String,
+    transaction_id: String,
+) -> Result<TransactionChange, String> {
+    with_budget_edit(&budget_path, "Delete transaction", |budget| {
+        transactions::delete_transaction(budget, &transaction_id)
+    })
+}
+
+/// Apply the same change to many transactions in one undo step. Rows that
+/// fail validation are reported and left untouched.
+#[tauri::command]
+pub fn bulk_update_transactions(
+    budget_path: String,
+    transaction_ids: Vec<String>,
+    patch: TransactionPatch,
+) -> Result<BulkUpdateResult, String> {
+    with_budget_edit(&budget_path, "Edit transactions", |budget| {
+        Ok(transactions::bulk_update_transactions(
+            budget,
+            &transaction_ids,
+            &patch,
+        ))
+    })
+}
+
+/// Combine payees into `target_payee_id`. With `dry_run` set, only report how
+/// many transactions would change.
+#[tauri::command]
+pub fn merge_payees(
+    budget_path: String,
+    target_payee_id: String,
+    source_payee_ids: Vec<String>,
+    dry_run: bool,
+) -> Result<PayeeMergeResult, String> {
+    with_budget_edit(&budget_path, "Combine payees", |budget| {
+        payees::merge_payees(budget, &target_payee_id, &source_payee_ids, dry_run)
+    })
+}
+
+/// Payee and category suggested by the budget's rules for each description
+#[tauri::command]
+pub fn payee_rules_suggest(
+    budget_path: String,
+    descriptions: Vec<String>,
+) -> Result<Vec<Option<RuleSuggestion>>, String> {
+    let sidecar = rules::load_sidecar(Path::new(&budget_path))?;
+    with_budget(&budget_path, |budget| {
+        let rule_set = RuleSet::new(&budget.data, &sidecar);
+        Ok(descriptions
+            .iter()
+            .map(|d| rule_set.suggest(&budget.data, d))
+            .collect())
+    })
+}
+
+/// Check which sample descriptions a rule matches
+#[tauri::command]
+pub fn payee_rule_test(
+    operator: String,
+    operand: String,
+    samples: Vec<String>,
+) -> Result<Vec<bool>, String> {
+    rules::test_rule(&operator, &operand, &samples)
+}
+
+/// Regex payee rules stored beside the budget
+#[tauri::command]
+pub fn payee_regex_rules_get(budget_path: String) -> Result<RulesSidecar, String> {
+    rules::load_sidecar(Path::new(&budget_path))
+}
+
+/// Replace the regex payee rules stored beside the budget
+#[tauri::command]
+pub fn payee_regex_rules_save(budget_path: String, sidecar: RulesSidecar) -> Result<(), String> {
+    rules::save_sidecar(Path::new(&budget_path), &sidecar)
+}
+
+/// Merge categories (sub or master) into `target_category_id`, moving their
+/// transactions and budgeted amounts. With `dry_run` set, only report counts.
+#[tauri::command]
+pub fn category_merge(
+    budget_path: String,
+    source_category_ids: Vec<String>,
+    target_category_id: String,
+    dry_run: bool,
+) -> Result<CategoryRemovalResult, String> {
+    with_budget_edit(&budget_path, "Merge categories", |budget| {
+        categories::remove_categories(
+            budget,
+            &source_category_ids,
+            Some(&target_category_id),
+            dry_run,
+        )
+    })
+}
+
+/// Delete categories (sub or master). Categories with history need
+/// `reassign_to`, which receives their transactions and budgeted amounts.
+#[tauri::command]
+pub fn category_delete(
+    budget_path: String,
+    category_ids: Vec<String>,
+    reassign_to: Option<String>,
+    dry_run: bool,
+) -> Result<CategoryRemovalResult, String> {
+    with_budget_edit(&budget_path, "Delete categories", |budget| {
+        categories::remove_categories(budget, &category_ids, reassign_to.as_deref(), dry_run)
+    })
+}
+
+/// Change an account's name, type, note or on/off-budget status.
+/// Moving on or off budget may need `categoryId` for transactions that
+/// must gain a category.
+#[tauri::command]
+pub fn account_update(
+    budget_path: String,
+    account_id: String,
+    update: AccountUpdate,
+) -> Result<AccountChange, String> {
+    with_budget_edit(&budget_path, "Edit account", |budget| {
+        accounts::update_account(budget, &account_id, update)
+    })
+}
+
+/// Close an account whose balance is zero
+#[tauri::command]
+pub fn account_close(budget_path: String, account_id: String) -> Result<AccountChange, String> {
+    with_budget_edit(&budget_path, "Close account", |budget| {
+        accounts::close_account(budget, &account_id)
+    })
+}
+
+/// Show a closed account again in the account list
+#[tauri::command]
+pub fn account_reopen(budget_path: String, account_id: String) -> Result<AccountChange, String> {
+    with_budget_edit(&budget_path, "Reopen account", |budget| {
+        accounts::reopen_account(budget, &account_id)
+    })
+}
+
+/// Delete an account; `policy` says what happens to its transactions and transfers
+#[tauri::command]
+pub fn account_delete(
+    budget_path: String,
+    account_id: String,
+    policy: AccountDeletePolicy,
+) -> Result<AccountChange, String> {
+    with_budget_edit(&budget_path, "Delete account", |budget| {
+        accounts::delete_account(budget, &account_id, policy)
+    })
+}
+
+/// Put accounts in the given order; returns the full resulting order and the ydiff
+#[tauri::command]
+pub fn account_reorder(
+    budget_path: String,
+    account_ids: Vec<String>,
+) -> Result<AccountOrder, String> {
+    with_budget_edit(&budget_path, "Reorder accounts", |budget| {
+        accounts::reorder_accounts(budget, &account_ids)
+    })
+}
+
+/// Undo the latest edit; returns it, or nothing when there is nothing to undo
+#[tauri::command]
+pub fn budget_undo(budget_path: String) -> Result<Option<JournalEntry>, String> {
+    with_budget(&budget_path, |budget| {
+        with_journal(&budget_path, |journal| {
+            let entry = journal.undo(budget)?;
+            journal.save(&budget_path)?;
+            Ok(entry)
+        })
+    })
+}
+
+/// Redo the latest undone edit
+#[tauri::command]
+pub fn budget_redo(budget_path: String) -> Result<Option<JournalEntry>, String> {
+    with_budget(&budget_path, |budget| {
+        with_journal(&budget_path, |journal| {
+            let entry = journal.redo(budget)?;
+            journal.save(&budget_path)?;
+            Ok(entry)
+        })
+    })
+}
+
+/// Edits that can be undone and redone, newest first
+#[tauri::command]
+pub fn budget_history(budget_path: String) -> Result<JournalHistory, String> {
+    with_journal(&budget_path, |journal| Ok(journal.history()))
+}
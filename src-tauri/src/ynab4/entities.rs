@@ -0,0 +1,411 @@
+//! YNAB4 entity types as stored in `Budget.yfull` and `.ydiff` files.
+//!
+//! Every entity keeps the fields it does not know about in `extra`, so a
+//! budget can be read and written back without losing data.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Category used by the parent of a split transaction
+pub const SPLIT_CATEGORY_ID: &str = "Category/__Split__";
+/// Income available this month
+pub const IMMEDIATE_INCOME_CATEGORY_ID: &str = "Category/__ImmediateIncome__";
+/// Income available next month
+pub const DEFERRED_INCOME_CATEGORY_ID: &str = "Category/__DeferredIncome__";
+/// Prefix of the built-in payee used by transfers ("Payee/Transfer:<accountId>")
+pub const TRANSFER_PAYEE_PREFIX: &str = "Payee/Transfer:";
+
+pub const CLEARED: &str = "Cleared";
+pub const UNCLEARED: &str = "Uncleared";
+pub const RECONCILED: &str = "Reconciled";
+
+/// Overspending stays in the category and carries over as a negative balance
+pub const OVERSPENDING_CONFINED: &str = "Confined";
+/// Overspending is taken from next month's Available to Budget (the default)
+pub const OVERSPENDING_AFFECTS_BUFFER: &str = "AffectsBuffer";
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Account {
+    pub entity_id: String,
+    #[serde(default)]
+    pub entity_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_version: Option<String>,
+    #[serde(default)]
+    pub account_name: String,
+    #[serde(default)]
+    pub account_type: String,
+    #[serde(default)]
+    pub on_budget: bool,
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sortable_index: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_reconciled_date: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_reconciled_balance: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub is_tombstone: bool,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Account {
+    /// Credit cards and lines of credit: overspending on them becomes debt
+    pub fn is_credit(&self) -> bool {
+        matches!(self.account_type.as_str(), "CreditCard" | "LineOfCredit")
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayeeRenameCondition {
+    pub entity_id: String,
+    #[serde(default)]
+    pub entity_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_version: Option<String>,
+    #[serde(default)]
+    pub parent_payee_id: String,
+    #[serde(default)]
+    pub operator: String,
+    #[serde(default)]
+    pub operand: String,
+    #[serde(default)]
+    pub is_tombstone: bool,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payee {
+    pub entity_id: String,
+    #[serde(default)]
+    pub entity_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_version: Option<String>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_fill_category_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_fill_memo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_fill_amount: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rename_conditions: Option<Vec<PayeeRenameCondition>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_account_id: Option<String>,
+    #[serde(default)]
+    pub is_tombstone: bool,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Category {
+    pub entity_id: String,
+    #[serde(default)]
+    pub entity_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_version: Option<String>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub category_type: Option<String>,
+    #[serde(default)]
+    pub master_category_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sortable_index: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub is_tombstone: bool,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MasterCategory {
+    pub entity_id: String,
+    #[serde(default)]
+    pub entity_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_version: Option<String>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub category_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sortable_index: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub_categories: Option<Vec<Category>>,
+    #[serde(default)]
+    pub is_tombstone: bool,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Split line of a transaction or of a scheduled transaction
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubTransaction {
+    pub entity_id: String,
+    #[serde(default)]
+    pub entity_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_version: Option<String>,
+    #[serde(default)]
+    pub parent_transaction_id: String,
+    #[serde(default)]
+    pub amount: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payee_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_account_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transfer_transaction_id: Option<String>,
+    #[serde(default)]
+    pub is_tombstone: bool,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transaction {
+    pub entity_id: String,
+    #[serde(default)]
+    pub entity_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_version: Option<String>,
+    #[serde(default)]
+    pub account_id: String,
+    #[serde(default)]
+    pub date: String,
+    #[serde(default)]
+    pub amount: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payee_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    #[serde(default)]
+    pub cleared: String,
+    #[serde(default = "default_true")]
+    pub accepted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub check_number: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_account_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transfer_transaction_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub_transactions: Option<Vec<SubTransaction>>,
+    #[serde(default)]
+    pub is_tombstone: bool,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Transaction {
+    pub fn is_split(&self) -> bool {
+        self.category_id.as_deref() == Some(SPLIT_CATEGORY_ID)
+    }
+
+    /// Account on the other side when this is a transfer. Older files may
+    /// only have the transfer payee, so fall back to it.
+    pub fn transfer_account_id(&self) -> Option<&str> {
+        self.transfer_transaction_id.as_ref()?;
+        self.target_account_id.as_deref().or_else(|| {
+            self.payee_id
+                .as_deref()?
+                .strip_prefix(TRANSFER_PAYEE_PREFIX)
+        })
+    }
+
+    /// Split lines that have not been deleted
+    pub fn live_sub_transactions(&self) -> impl Iterator<Item = &SubTransaction> {
+        self.sub_transactions
+            .iter()
+            .flatten()
+            .filter(|s| !s.is_tombstone)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledTransaction {
+    pub entity_id: String,
+    #[serde(default)]
+    pub entity_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_version: Option<String>,
+    #[serde(default)]
+    pub account_id: String,
+    #[serde(default)]
+    pub date: String,
+    #[serde(default)]
+    pub frequency: String,
+    #[serde(default)]
+    pub amount: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payee_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_account_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transfer_transaction_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub_transactions: Option<Vec<SubTransaction>>,
+    #[serde(default)]
+    pub is_tombstone: bool,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonthlySubCategoryBudget {
+    pub entity_id: String,
+    #[serde(default)]
+    pub entity_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_version: Option<String>,
+    #[serde(default)]
+    pub category_id: String,
+    #[serde(default)]
+    pub budgeted: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overspending_handling: Option<String>,
+    #[serde(default)]
+    pub parent_monthly_budget_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub is_tombstone: bool,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonthlyBudget {
+    pub entity_id: String,
+    #[serde(default)]
+    pub entity_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_version: Option<String>,
+    /// First day of the month ("2024-01-01")
+    #[serde(default)]
+    pub month: String,
+    #[serde(default)]
+    pub monthly_sub_category_budgets: Vec<MonthlySubCategoryBudget>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub is_tombstone: bool,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl MonthlyBudget {
+    /// Month key in "YYYY-MM" form
+    pub fn month_key(&self) -> &str {
+        self.month.get(..7).unwrap_or(&self.month)
+    }
+}
+
+/// Contents of a `Budget.yfull` file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetData {
+    #[serde(default)]
+    pub accounts: Vec<Account>,
+    #[serde(default)]
+    pub payees: Vec<Payee>,
+    #[serde(default)]
+    pub master_categories: Vec<MasterCategory>,
+    #[serde(default)]
+    pub transactions: Vec<Transaction>,
+    #[serde(default)]
+    pub scheduled_transactions: Vec<ScheduledTransaction>,
+    #[serde(default)]
+    pub monthly_budgets: Vec<MonthlyBudget>,
+    #[serde(default)]
+    pub budget_meta_data: Value,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl BudgetData {
+    pub fn account(&self, id: &str) -> Option<&Account> {
+        self.accounts.iter().find(|a| a.entity_id == id)
+    }
+
+    pub fn payee(&self, id: &str) -> Option<&Payee> {
+        self.payees.iter().find(|p| p.entity_id == id)
+    }
+
+    pub fn transaction(&self, id: &str) -> Option<&Transaction> {
+        self.transactions.iter().find(|t| t.entity_id == id)
+    }
+
+    /// All sub categories that have not been deleted, in master category order
+    pub fn live_categories(&self) -> Vec<&Category> {
+        let mut masters: Vec<&MasterCategory> = self
+            .master_categories
+            .iter()
+            .filter(|m| !m.is_tombstone)
+            .collect();
+        masters.sort_by(|a, b| {
+            a.sortable_index
+                .unwrap_or(0.0)
+                .total_cmp(&b.sortable_index.unwrap_or(0.0))
+        });
+
+        let mut categories = Vec::new();
+        for master in masters {
+            let mut subs: Vec<&Category> = master
+                .sub_categories
+                .iter()
+                .flatten()
+                .filter(|c| !c.is_tombstone)
+                .collect();
+            subs.sort_by(|a, b| {
+                a.sortable_index
+                    .unwrap_or(0.0)
+                    .total_cmp(&b.sortable_index.unwrap_or(0.0))
+            });
+            categories.extend(subs);
+        }
+        categories
+    }
+}
@@ -0,0 +1,89 @@
+//! A small budget folder written to disk for tests.
+//!
+//! Device A owns the full budget (knowledge A-10) and one later ydiff (A-11)
+//! that edits T-2: checking, a credit card and an off budget broker account,
+//! a split, a transfer and three months of budgeted amounts.
+
+use serde_json::json;
+use std::fs;
+use std::path::PathBuf;
+
+/// Write the budget under the temp dir, replacing an earlier copy, and
+/// return the path of its `.ynab4` folder
+pub fn budget_folder(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join("ynab4-client-tests").join(name);
+    let _ = fs::remove_dir_all(&root);
+    let budget = root.join("Test~ABC.ynab4");
+    let data = budget.join("data1~1234");
+    fs::create_dir_all(data.join("devices")).unwrap();
+    fs::create_dir_all(data.join("GUID-A")).unwrap();
+    fs::write(
+        budget.join("Budget.ymeta"),
+        json!({"formatVersion":"1.2","relativeDataFolderName":"data1~1234","TED":1}).to_string(),
+    )
+    .unwrap();
+    fs::write(data.join("devices/A.ydevice"), json!({"deviceGUID":"GUID-A","shortDeviceId":"A","friendlyName":"Mac","knowledge":"A-10","knowledgeInFullBudgetFile":"A-10","hasFullKnowledge":true,"YNABVersion":"Desktop version: YNAB 4 v4.3.857","formatVersion":"1.2","lastDataVersionFullyKnown":"4.2","deviceType":"Desktop (AIR), OS:Mac","highestDataVersionImported":null}).to_string()).unwrap();
+    let yfull = json!({
+      "accounts":[
+        {"entityType":"account","entityId":"ACC-CHK","entityVersion":"A-1","accountName":"Checking","accountType":"Checking","onBudget":true,"hidden":false,"sortableIndex":0,"isTombstone":false},
+        {"entityType":"account","entityId":"ACC-CC","entityVersion":"A-1","accountName":"Visa","accountType":"CreditCard","onBudget":true,"hidden":false,"sortableIndex":1,"isTombstone":false},
+        {"entityType":"account","entityId":"ACC-INV","entityVersion":"A-1","accountName":"Broker","accountType":"InvestmentAccount","onBudget":false,"hidden":false,"sortableIndex":2,"isTombstone":false}
+      ],
+      "payees":[
+        {"entityType":"payee","entityId":"P-1","entityVersion":"A-1","name":"OXXO","enabled":true,"isTombstone":false,
+         "renameConditions":[{"entityType":"payeeRenameCondition","entityId":"RC-1","entityVersion":"A-1","parentPayeeId":"P-1","operator":"Contains","operand":"OXXO","isTombstone":false}]},
+        {"entityType":"payee","entityId":"P-2","entityVersion":"A-1","name":"Oxxo Store","enabled":true,"autoFillCategoryId":"CAT-FOOD","autoFillMemo":"snacks","isTombstone":false},
+        {"entityType":"payee","entityId":"P-EMP","entityVersion":"A-1","name":"Employer","enabled":true,"isTombstone":false},
+        {"entityType":"payee","entityId":"Payee/Transfer:ACC-CHK","entityVersion":"A-1","name":"Transfer : Checking","targetAccountId":"ACC-CHK","enabled":true,"isTombstone":false},
+        {"entityType":"payee","entityId":"Payee/Transfer:ACC-CC","entityVersion":"A-1","name":"Transfer : Visa","targetAccountId":"ACC-CC","enabled":true,"isTombstone":false},
+        {"entityType":"payee","entityId":"Payee/Transfer:ACC-INV","entityVersion":"A-1","name":"Transfer : Broker","targetAccountId":"ACC-INV","enabled":true,"isTombstone":false}
+      ],
+      "masterCategories":[
+        {"entityType":"masterCategory","entityId":"MC-1","entityVersion":"A-1","name":"Everyday","type":"OUTFLOW","sortableIndex":0,"isTombstone":false,"subCategories":[
+          {"entityType":"category","entityId":"CAT-FOOD","entityVersion":"A-1","name":"Food","type":"OUTFLOW","masterCategoryId":"MC-1","sortableIndex":0,"isTombstone":false},
+          {"entityType":"category","entityId":"CAT-FUN","entityVersion":"A-1","name":"Fun","type":"OUTFLOW","masterCategoryId":"MC-1","sortableIndex":1,"isTombstone":false},
+          {"entityType":"category","entityId":"CAT-RENT","entityVersion":"A-1","name":"Rent","type":"OUTFLOW","masterCategoryId":"MC-1","sortableIndex":2,"isTombstone":false}
+        ]}
+      ],
+      "transactions":[
+        {"entityType":"transaction","entityId":"T-1","entityVersion":"A-2","accountId":"ACC-CHK","date":"2024-01-01","amount":1000,"payeeId":"P-EMP","categoryId":"Category/__ImmediateIncome__","cleared":"Cleared","accepted":true,"isTombstone":false},
+        {"entityType":"transaction","entityId":"T-2","entityVersion":"A-2","accountId":"ACC-CHK","date":"2024-01-05","amount":-150,"payeeId":"P-1","categoryId":"CAT-FOOD","cleared":"Uncleared","accepted":true,"isTombstone":false},
+        {"entityType":"transaction","entityId":"T-3","entityVersion":"A-2","accountId":"ACC-CC","date":"2024-01-06","amount":-80,"payeeId":"P-2","categoryId":"CAT-FUN","cleared":"Uncleared","accepted":true,"isTombstone":false},
+        {"entityType":"transaction","entityId":"T-4","entityVersion":"A-2","accountId":"ACC-CHK","date":"2024-01-20","amount":500,"payeeId":"P-EMP","categoryId":"Category/__DeferredIncome__","cleared":"Cleared","accepted":true,"isTombstone":false},
+        {"entityType":"transaction","entityId":"T-5","entityVersion":"A-3","accountId":"ACC-CHK","date":"2024-01-10","amount":-100,"payeeId":"Payee/Transfer:ACC-CC","transferTransactionId":"T-6","cleared":"Cleared","accepted":true,"isTombstone":false},
+        {"entityType":"transaction","entityId":"T-6","entityVersion":"A-3","accountId":"ACC-CC","date":"2024-01-10","amount":100,"payeeId":"Payee/Transfer:ACC-CHK","transferTransactionId":"T-5","cleared":"Cleared","accepted":true,"isTombstone":false},
+        {"entityType":"transaction","entityId":"T-7","entityVersion":"A-3","accountId":"ACC-CHK","date":"2024-02-03","amount":-300,"payeeId":"P-1","categoryId":"Category/__Split__","cleared":"Uncleared","accepted":true,"isTombstone":false,
+         "subTransactions":[
+           {"entityType":"subTransaction","entityId":"S-1","entityVersion":"A-3","parentTransactionId":"T-7","amount":-200,"categoryId":"CAT-RENT","isTombstone":false},
+           {"entityType":"subTransaction","entityId":"S-2","entityVersion":"A-3","parentTransactionId":"T-7","amount":-100,"categoryId":"CAT-FOOD","isTombstone":false}
+         ]}
+      ],
+      "scheduledTransactions":[
+        {"entityType":"scheduledTransaction","entityId":"ST-1","entityVersion":"A-1","accountId":"ACC-CHK","date":"2024-03-01","frequency":"Monthly","amount":-50,"payeeId":"P-2","categoryId":"CAT-FUN","isTombstone":false}
+      ],
+      "monthlyBudgets":[
+        {"entityType":"monthlyBudget","entityId":"MB/2024-01","entityVersion":"A-1","month":"2024-01-01","monthlySubCategoryBudgets":[
+          {"entityType":"monthlyCategoryBudget","entityId":"MCB/2024-01/CAT-FOOD","entityVersion":"A-1","categoryId":"CAT-FOOD","budgeted":100,"overspendingHandling":null,"parentMonthlyBudgetId":"MB/2024-01","isTombstone":false},
+          {"entityType":"monthlyCategoryBudget","entityId":"MCB/2024-01/CAT-FUN","entityVersion":"A-1","categoryId":"CAT-FUN","budgeted":50,"parentMonthlyBudgetId":"MB/2024-01","isTombstone":false},
+          {"entityType":"monthlyCategoryBudget","entityId":"MCB/2024-01/CAT-RENT","entityVersion":"A-1","categoryId":"CAT-RENT","budgeted":100,"overspendingHandling":"Confined","parentMonthlyBudgetId":"MB/2024-01","isTombstone":false}
+        ]},
+        {"entityType":"monthlyBudget","entityId":"MB/2024-02","entityVersion":"A-1","month":"2024-02-01","monthlySubCategoryBudgets":[
+          {"entityType":"monthlyCategoryBudget","entityId":"MCB/2024-02/CAT-FOOD","entityVersion":"A-1","categoryId":"CAT-FOOD","budgeted":100,"parentMonthlyBudgetId":"MB/2024-02","isTombstone":false}
+        ]},
+        {"entityType":"monthlyBudget","entityId":"MB/2024-03","entityVersion":"A-1","month":"2024-03-01","monthlySubCategoryBudgets":[
+          {"entityType":"monthlyCategoryBudget","entityId":"MCB/2024-03/CAT-FUN","entityVersion":"A-1","categoryId":"CAT-FUN","budgeted":2000,"parentMonthlyBudgetId":"MB/2024-03","isTombstone":false}
+        ]}
+      ],
+      "budgetMetaData":{"entityType":"budgetMetaData","entityId":"BMD","currencyLocale":"es_MX","dateLocale":"es_MX","budgetType":"Personal","strictBudget":"","isTombstone":false},
+      "fileMetaData":{"entityType":"fileMetaData","budgetDataVersion":"4.2","currentKnowledge":"A-10"}
+    });
+    fs::write(
+        data.join("GUID-A/Budget.yfull"),
+        serde_json::to_string_pretty(&yfull).unwrap(),
+    )
+    .unwrap();
+    // A later diff from device A that edits T-2
+    fs::write(data.join("GUID-A/A-10_A-11.ydiff"), json!({"budgetDataGUID":"data1~1234","budgetGUID":"Test~ABC","dataVersion":"4.2","deviceGUID":"GUID-A","shortDeviceId":"A","startVersion":"A-10","endVersion":"A-11","publishTime":"x",
+      "items":[{"entityType":"transaction","entityId":"T-2","entityVersion":"A-11","accountId":"ACC-CHK","date":"2024-01-05","amount":-250,"payeeId":"P-1","categoryId":"CAT-FOOD","cleared":"Uncleared","accepted":true,"isTombstone":false}]}).to_string()).unwrap();
+    budget
+}
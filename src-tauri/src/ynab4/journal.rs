@@ -0,0 +1,624 @@
+//! Undo/redo journal of backend edits.
+//!
+//! Every edit command records one step holding, for each top-level entity it
+//! changed, the entity before and after the edit (split lines, rename
+//! conditions, sub categories and monthly category budgets travel with their
+//! parent). Undo writes the "before" side back, redo the "after" side. The
+//! journal also keeps the current state of entities with changes not written
+//! to a ydiff yet, so that those edits survive an app restart.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use super::budget::{Budget, Entity};
+use super::entities::*;
+use super::knowledge::compare_versions;
+use super::local;
+
+/// Steps kept for undo; older ones are dropped
+pub const MAX_STEPS: usize = 200;
+
+const JOURNAL_FILE: &str = "journal.json";
+
+/// Budget collection holding a top-level entity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Collection {
+    Accounts,
+    Payees,
+    MasterCategories,
+    Transactions,
+    ScheduledTransactions,
+    MonthlyBudgets,
+}
+
+impl Collection {
+    /// Key of the nested children in the entity's JSON
+    fn children_key(self) -> Option<&'static str> {
+        match self {
+            Collection::Accounts => None,
+            Collection::Payees => Some("renameConditions"),
+            Collection::MasterCategories => Some("subCategories"),
+            Collection::Transactions | Collection::ScheduledTransactions => Some("subTransactions"),
+            Collection::MonthlyBudgets => Some("monthlySubCategoryBudgets"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityChange {
+    pub collection: Collection,
+    pub entity_id: String,
+    /// `None` when the step created the entity
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalStep {
+    pub id: u64,
+    pub label: String,
+    pub time: String,
+    /// The edit wrote its own ydiff, so undoing it writes one too
+    pub saved: bool,
+    pub changes: Vec<EntityChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingEntity {
+    pub collection: Collection,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Journal {
+    #[serde(default)]
+    pub next_id: u64,
+    #[serde(default)]
+    pub undo: Vec<JournalStep>,
+    #[serde(default)]
+    pub redo: Vec<JournalStep>,
+    /// Entities with changes not written to a ydiff yet
+    #[serde(default)]
+    pub pending: Vec<PendingEntity>,
+    /// Dirty ids at the time `pending` was taken
+    #[serde(default)]
+    pub pending_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    pub id: u64,
+    pub label: String,
+    pub time: String,
+    pub change_count: usize,
+    pub saved: bool,
+}
+
+impl From<&JournalStep> for JournalEntry {
+    fn from(step: &JournalStep) -> Self {
+        JournalEntry {
+            id: step.id,
+            label: step.label.clone(),
+            time: step.time.clone(),
+            change_count: step.changes.len(),
+            saved: step.saved,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalHistory {
+    /// Steps that can be undone, newest first
+    pub undo: Vec<JournalEntry>,
+    /// Steps that can be redone, next one first
+    pub redo: Vec<JournalEntry>,
+    /// Entities with changes not written to a ydiff yet
+    pub pending_count: usize,
+}
+
+/// Map every entity id, nested ones included, to its top-level entity
+fn index(data: &BudgetData) -> HashMap<String, (Collection, String)> {
+    let mut map = HashMap::new();
+    let mut add = |collection: Collection, top: &str, ids: Vec<&str>| {
+        for id in ids {
+            map.insert(id.to_string(), (collection, top.to_string()));
+        }
+    };
+    for account in &data.accounts {
+        add(
+            Collection::Accounts,
+            &account.entity_id,
+            vec![&account.entity_id],
+        );
+    }
+    for payee in &data.payees {
+        let mut ids = vec![payee.entity_id.as_str()];
+        ids.extend(
+            payee
+                .rename_conditions
+                .iter()
+                .flatten()
+                .map(|c| c.entity_id.as_str()),
+        );
+        add(Collection::Payees, &payee.entity_id, ids);
+    }
+    for master in &data.master_categories {
+        let mut ids = vec![master.entity_id.as_str()];
+        ids.extend(
+            master
+                .sub_categories
+                .iter()
+                .flatten()
+                .map(|c| c.entity_id.as_str()),
+        );
+        add(Collection::MasterCategories, &master.entity_id, ids);
+    }
+    for transaction in &data.transactions {
+        let mut ids = vec![transaction.entity_id.as_str()];
+        ids.extend(
+            transaction
+                .sub_transactions
+                .iter()
+                .flatten()
+                .map(|s| s.entity_id.as_str()),
+        );
+        add(Collection::Transactions, &transaction.entity_id, ids);
+    }
+    for scheduled in &data.scheduled_transactions {
+        let mut ids = vec![scheduled.entity_id.as_str()];
+        ids.extend(
+            scheduled
+                .sub_transactions
+                .iter()
+                .flatten()
+                .map(|s| s.entity_id.as_str()),
+        );
+        add(Collection::ScheduledTransactions, &scheduled.entity_id, ids);
+    }
+    for month in &data.monthly_budgets {
+        let mut ids = vec![month.entity_id.as_str()];
+        ids.extend(
+            month
+                .monthly_sub_category_budgets
+                .iter()
+                .map(|b| b.entity_id.as_str()),
+        );
+        add(Collection::MonthlyBudgets, &month.entity_id, ids);
+    }
+    map
+}
+
+fn find<T: Entity + Serialize>(list: &[T], id: &str) -> Option<Value> {
+    list.iter()
+        .find(|e| e.id() == id)
+        .and_then(|e| serde_json::to_value(e).ok())
+}
+
+fn snapshot(data: &BudgetData, collection: Collection, id: &str) -> Option<Value> {
+    match collection {
+        Collection::Accounts => find(&data.accounts, id),
+        Collection::Payees => find(&data.payees, id),
+        Collection::MasterCategories => find(&data.master_categories, id),
+        Collection::Transactions => find(&data.transactions, id),
+        Collection::ScheduledTransactions => find(&data.scheduled_transactions, id),
+        Collection::MonthlyBudgets => find(&data.monthly_budgets, id),
+    }
+}
+
+fn put<T: Entity + DeserializeOwned>(list: &mut Vec<T>, value: Value) -> Result<(), String> {
+    let item: T =
+        serde_json::from_value(value).map_err(|e| format!("Invalid journal entry: {}", e))?;
+    match list.iter().position(|e| e.id() == item.id()) {
+        Some(idx) => list[idx] = item,
+        None => list.push(item),
+    }
+    Ok(())
+}
+
+fn put_entity(data: &mut BudgetData, collection: Collection, value: Value) -> Result<(), String> {
+    match collection {
+        Collection::Accounts => put(&mut data.accounts, value),
+        Collection::Payees => put(&mut data.payees, value),
+        Collection::MasterCategories => put(&mut data.master_categories, value),
+        Collection::Transactions => put(&mut data.transactions, value),
+        Collection::ScheduledTransactions => put(&mut data.scheduled_transactions, value),
+        Collection::MonthlyBudgets => put(&mut data.monthly_budgets, value),
+    }
+}
+
+fn entity_id(value: &Value) -> Option<&str> {
+    value.get("entityId").and_then(Value::as_str)
+}
+
+fn tombstone(value: &mut Value) {
+    if let Some(object) = value.as_object_mut() {
+        object.insert("isTombstone".to_string(), Value::Bool(true));
+    }
+}
+
+/// `loaded` or one of its children has a newer version than in `recorded`
+fn written_since(collection: Collection, recorded: &Value, loaded: &Value) -> bool {
+    let version = |value: &Value| {
+        value
+            .get("entityVersion")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    };
+    let children = |value: &Value| -> Vec<Value> {
+        collection
+            .children_key()
+            .and_then(|key| value.get(key))
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default()
+    };
+    let newer = |loaded: &Value, recorded: Option<&Value>| {
+        let recorded = recorded.and_then(version);
+        compare_versions(version(loaded).as_deref(), recorded.as_deref()) == Ordering::Greater
+    };
+    let recorded_children = children(recorded);
+    newer(loaded, Some(recorded))
+        || children(loaded).iter().any(|child| {
+            let recorded = recorded_children
+                .iter()
+                .find(|c| entity_id(c) == entity_id(child));
+            newer(child, recorded)
+        })
+}
+
+/// Ids of an entity and its nested children
+fn ids_of(collection: Collection, value: &Value) -> Vec<String> {
+    let mut ids: Vec<String> = entity_id(value).map(str::to_string).into_iter().collect();
+    if let Some(children) = collection
+        .children_key()
+        .and_then(|key| value.get(key))
+        .and_then(Value::as_array)
+    {
+        ids.extend(children.iter().filter_map(entity_id).map(str::to_string));
+    }
+    ids
+}
+
+/// What to write to bring `current` back to `target`. Children and entities
+/// that did not exist in `target` are kept as tombstones so the ydiff
+/// deletes them everywhere.
+fn restoration(
+    collection: Collection,
+    target: Option<&Value>,
+    current: Option<&Value>,
+) -> Option<Value> {
+    let mut value = match (target, current) {
+        (Some(target), _) => target.clone(),
+        (None, Some(current)) => {
+            let mut value = current.clone();
+            tombstone(&mut value);
+            value
+        }
+        (None, None) => return None,
+    };
+    let Some(key) = collection.children_key() else {
+        return Some(value);
+    };
+
+    let current_children: Vec<Value> = current
+        .and_then(|c| c.get(key))
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    let deleting = target.is_none();
+    if let Some(object) = value.as_object_mut() {
+        let children = object
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let Some(children) = children.as_array_mut() {
+            if deleting {
+                children.iter_mut().for_each(tombstone);
+            }
+            let known: BTreeSet<String> = children
+                .iter()
+                .filter_map(entity_id)
+                .map(str::to_string)
+                .collect();
+            for mut child in current_children {
+                if entity_id(&child).is_some_and(|id| !known.contains(id)) {
+                    tombstone(&mut child);
+                    children.push(child);
+                }
+            }
+        }
+    }
+    Some(value)
+}
+
+fn journal_path(budget_path: &str) -> Result<PathBuf, String> {
+    Ok(local::budget_dir(budget_path)?.join(JOURNAL_FILE))
+}
+
+impl Journal {
+    pub fn load(budget_path: &str) -> Result<Journal, String> {
+        local::read_or_default(&journal_path(budget_path)?)
+    }
+
+    pub fn save(&self, budget_path: &str) -> Result<(), String> {
+        local::write_json(&journal_path(budget_path)?, self)
+    }
+
+    /// Record the edit that turned `before` into the budget's current data.
+    /// The entities changed are those in `budget.touched`.
+    pub fn record(&mut self, budget: &Budget, before: &BudgetData, label: &str) {
+        let after_index = index(&budget.data);
+        let before_index = index(before);
+        let mut tops: Vec<(Collection, String)> = Vec::new();
+        for id in &budget.touched {
+            if let Some(top) = after_index.get(id).or_else(|| before_index.get(id)) {
+                if !tops.contains(top) {
+                    tops.push(top.clone());
+                }
+            }
+        }
+
+        let changes: Vec<EntityChange> = tops
+            .into_iter()
+            .filter_map(|(collection, id)| {
+                let old = snapshot(before, collection, &id);
+                let new = snapshot(&budget.data, collection, &id);
+                (old != new).then_some(EntityChange {
+                    collection,
+                    entity_id: id,
+                    before: old,
+                    after: new,
+                })
+            })
+            .collect();
+        if changes.is_empty() {
+            return;
+        }
+
+        self.next_id += 1;
+        self.undo.push(JournalStep {
+            id: self.next_id,
+            label: label.to_string(),
+            time: chrono::Local::now().to_rfc3339(),
+            saved: budget.touched.iter().all(|id| !budget.dirty.contains(id)),
+            changes,
+        });
+        if self.undo.len() > MAX_STEPS {
+            let excess = self.undo.len() - MAX_STEPS;
+            self.undo.drain(..excess);
+        }
+        self.redo.clear();
+    }
+
+    /// Remember the entities with changes not written to a ydiff yet
+    pub fn sync_pending(&mut self, budget: &Budget) {
+        let idx = index(&budget.data);
+        let mut tops: Vec<(Collection, String)> = Vec::new();
+        for id in &budget.dirty {
+            if let Some(top) = idx.get(id) {
+                if !tops.contains(top) {
+                    tops.push(top.clone());
+                }
+            }
+        }
+        self.pending = tops
+            .into_iter()
+            .filter_map(|(collection, id)| {
+                snapshot(&budget.data, collection, &id)
+                    .map(|value| PendingEntity { collection, value })
+            })
+            .collect();
+        self.pending_ids = budget.dirty.iter().cloned().collect();
+    }
+
+    /// Put edits that were pending when the app closed back into a freshly
+    /// loaded budget. Entities written since (by another device, say) keep
+    /// their loaded state; their ids are returned.
+    pub fn restore_pending(&self, budget: &mut Budget) -> Result<Vec<String>, String> {
+        let mut skipped = Vec::new();
+        let mut skipped_ids = BTreeSet::new();
+        for entity in &self.pending {
+            let id = entity_id(&entity.value).unwrap_or_default();
+            let loaded = snapshot(&budget.data, entity.collection, id);
+            if loaded.is_some_and(|loaded| written_since(entity.collection, &entity.value, &loaded))
+            {
+                skipped_ids.extend(ids_of(entity.collection, &entity.value));
+                skipped.push(id.to_string());
+                continue;
+            }
+            put_entity(&mut budget.data, entity.collection, entity.value.clone())?;
+        }
+        budget.dirty.extend(
+            self.pending_ids
+                .iter()
+                .filter(|id| !skipped_ids.contains(*id))
+                .cloned(),
+        );
+        Ok(skipped)
+    }
+
+    /// Write one side of a step back into the budget; on failure the budget
+    /// is left as it was
+    fn apply(budget: &mut Budget, step: &JournalStep, use_before: bool) -> Result<(), String> {
+        let before = budget.data.clone();
+        let dirty_before = budget.dirty.clone();
+        let result = Journal::write_side(budget, step, use_before);
+        if result.is_err() {
+            budget.data = before;
+            budget.dirty = dirty_before;
+        }
+        result
+    }
+
+    fn write_side(budget: &mut Budget, step: &JournalStep, use_before: bool) -> Result<(), String> {
+        let changes: Box<dyn Iterator<Item = &EntityChange>> = if use_before {
+            Box::new(step.changes.iter().rev())
+        } else {
+            Box::new(step.changes.iter())
+        };
+        let mut changed = Vec::new();
+        for change in changes {
+            let target = if use_before {
+                &change.before
+            } else {
+                &change.after
+            };
+            let current = snapshot(&budget.data, change.collection, &change.entity_id);
+            let Some(value) = restoration(change.collection, target.as_ref(), current.as_ref())
+            else {
+                continue;
+            };
+            for id in ids_of(change.collection, &value) {
+                budget.mark_dirty(id.clone());
+                changed.push(id);
+            }
+            put_entity(&mut budget.data, change.collection, value)?;
+        }
+        if step.saved {
+            budget.save_entities(&changed)?;
+        }
+        Ok(())
+    }
+
+    /// Undo the latest step; returns it, or `None` when there is nothing to undo
+    pub fn undo(&mut self, budget: &mut Budget) -> Result<Option<JournalEntry>, String> {
+        let Some(step) = self.undo.pop() else {
+            return Ok(None);
+        };
+        if let Err(e) = Journal::apply(budget, &step, true) {
+            self.undo.push(step);
+            return Err(e);
+        }
+        let entry = JournalEntry::from(&step);
+        self.redo.push(step);
+        self.sync_pending(budget);
+        Ok(Some(entry))
+    }
+
+    /// Redo the latest undone step; returns it, or `None` when there is nothing to redo
+    pub fn redo(&mut self, budget: &mut Budget) -> Result<Option<JournalEntry>, String> {
+        let Some(step) = self.redo.pop() else {
+            return Ok(None);
+        };
+        if let Err(e) = Journal::apply(budget, &step, false) {
+            self.redo.push(step);
+            return Err(e);
+        }
+        let entry = JournalEntry::from(&step);
+        self.undo.push(step);
+        self.sync_pending(budget);
+        Ok(Some(entry))
+    }
+
+    pub fn history(&self) -> JournalHistory {
+        JournalHistory {
+            undo: self.undo.iter().rev().map(JournalEntry::from).collect(),
+            redo: self.redo.iter().rev().map(JournalEntry::from).collect(),
+            pending_count: self.pending.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::fixtures;
+
+    fn set_memo(budget: &mut Budget, id: &str, memo: &str) {
+        let transaction = budget
+            .data
+            .transactions
+            .iter_mut()
+            .find(|t| t.entity_id == id)
+            .unwrap();
+        transaction.memo = Some(memo.to_string());
+        budget.mark_dirty(id);
+    }
+
+    #[test]
+    fn pending_edits_yield_to_newer_versions_on_disk() {
+        let path = fixtures::budget_folder("journal-pending");
+        let mut budget = Budget::load(&path).unwrap();
+        set_memo(&mut budget, "T-2", "unsaved");
+        set_memo(&mut budget, "T-3", "unsaved");
+        let mut journal = Journal::default();
+        journal.sync_pending(&budget);
+
+        // Another device wrote T-3 while the app was closed
+        let mut reloaded = Budget::load(&path).unwrap();
+        let t3 = reloaded
+            .data
+            .transactions
+            .iter_mut()
+            .find(|t| t.entity_id == "T-3");
+        t3.unwrap().entity_version = Some("B-20".to_string());
+        let skipped = journal.restore_pending(&mut reloaded).unwrap();
+        assert_eq!(skipped, vec!["T-3".to_string()]);
+        let t2 = reloaded.data.transaction("T-2").unwrap();
+        assert_eq!(t2.memo.as_deref(), Some("unsaved"));
+        assert_eq!(reloaded.data.transaction("T-3").unwrap().memo, None);
+        assert!(reloaded.dirty.contains("T-2") && !reloaded.dirty.contains("T-3"));
+    }
+
+    #[test]
+    fn a_failed_redo_leaves_the_budget_untouched() {
+        let path = fixtures::budget_folder("journal-rollback");
+        let mut budget = Budget::load(&path).unwrap();
+        let mut t1 = snapshot(&budget.data, Collection::Transactions, "T-1").unwrap();
+        t1["memo"] = Value::from("redone");
+        let mut journal = Journal::default();
+        journal.redo.push(JournalStep {
+            id: 1,
+            label: "Edit".to_string(),
+            time: String::new(),
+            saved: false,
+            changes: vec![
+                EntityChange {
+                    collection: Collection::Transactions,
+                    entity_id: "T-1".to_string(),
+                    before: None,
+                    after: Some(t1),
+                },
+                EntityChange {
+                    collection: Collection::Transactions,
+                    entity_id: "T-3".to_string(),
+                    before: None,
+                    after: Some(serde_json::json!({"entityId": "T-3", "amount": "not a number"})),
+                },
+            ],
+        });
+        assert!(journal.redo(&mut budget).is_err());
+        assert_eq!(budget.data.transaction("T-1").unwrap().memo, None);
+        assert!(budget.dirty.is_empty());
+        assert_eq!(journal.redo.len(), 1);
+    }
+
+    #[test]
+    fn undoing_a_saved_step_writes_only_its_own_entities() {
+        let path = fixtures::budget_folder("journal-undo");
+        let mut budget = Budget::load(&path).unwrap();
+        let before = budget.data.clone();
+        set_memo(&mut budget, "T-3", "saved");
+        budget.save_entities(&["T-3".to_string()]).unwrap();
+        let mut journal = Journal::default();
+        journal.record(&budget, &before, "Edit memo");
+
+        set_memo(&mut budget, "T-1", "unsaved");
+        let entry = journal.undo(&mut budget).unwrap().unwrap();
+        assert!(entry.saved);
+        assert!(budget.dirty.contains("T-1"));
+        let reloaded = Budget::load(&path).unwrap();
+        assert_eq!(reloaded.data.transaction("T-3").unwrap().memo, None);
+        assert_eq!(reloaded.data.transaction("T-1").unwrap().memo, None);
+    }
+}
@@ -0,0 +1,103 @@
+//! YNAB4 version vectors.
+//!
+//! Each device stamps the entities it writes with "<shortDeviceId>-<counter>"
+//! (e.g. "A-42"). A device's knowledge is the highest counter it has seen from
+//! every device, written as "A-42,B-7".
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A single entity version ("A-42")
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub device: String,
+    pub counter: u64,
+}
+
+impl Version {
+    pub fn parse(value: &str) -> Option<Version> {
+        let (device, counter) = value.trim().rsplit_once('-')?;
+        Some(Version {
+            device: device.to_string(),
+            counter: counter.parse().ok()?,
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.device, self.counter)
+    }
+}
+
+/// Compare two entity versions to decide which write wins.
+/// Higher counters win; ties are broken by device id, like YNAB4 does.
+pub fn compare_versions(a: Option<&str>, b: Option<&str>) -> Ordering {
+    match (a.and_then(Version::parse), b.and_then(Version::parse)) {
+        (Some(a), Some(b)) => a
+            .counter
+            .cmp(&b.counter)
+            .then_with(|| a.device.cmp(&b.device)),
+        (Some(_), None) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Version vector ("A-42,B-7")
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Knowledge(BTreeMap<String, u64>);
+
+impl Knowledge {
+    pub fn parse(value: &str) -> Knowledge {
+        Knowledge(
+            value
+                .split(',')
+                .filter_map(Version::parse)
+                .map(|v| (v.device, v.counter))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, device: &str) -> u64 {
+        self.0.get(device).copied().unwrap_or(0)
+    }
+
+    pub fn set(&mut self, device: &str, counter: u64) {
+        self.0.insert(device.to_string(), counter);
+    }
+
+    /// Take the highest counter of each device from both vectors
+    pub fn merge(&mut self, other: &Knowledge) {
+        for (device, counter) in &other.0 {
+            if *counter > self.get(device) {
+                self.set(device, *counter);
+            }
+        }
+    }
+
+    /// True if every counter in `other` is already known here
+    pub fn contains(&self, other: &Knowledge) -> bool {
+        other
+            .0
+            .iter()
+            .all(|(device, counter)| self.get(device) >= *counter)
+    }
+
+    /// Highest counter known from any device
+    pub fn max_counter(&self) -> u64 {
+        self.0.values().copied().max().unwrap_or(0)
+    }
+}
+
+impl fmt::Display for Knowledge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .0
+            .iter()
+            .map(|(device, counter)| format!("{}-{}", device, counter))
+            .collect();
+        write!(f, "{}", parts.join(","))
+    }
+}
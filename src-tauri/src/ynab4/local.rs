@@ -0,0 +1,47 @@
+//! Files the app keeps for itself, outside the budget folder.
+//!
+//! They live in the app's local data directory, one folder per budget named
+//! after a hash of the budget path.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::budget::read_json;
+
+/// Same identifier as in `tauri.conf.json`, so files land in the app's folder
+const APP_IDENTIFIER: &str = "com.ynab4.client";
+
+pub fn app_data_dir() -> Result<PathBuf, String> {
+    dirs::data_local_dir()
+        .map(|dir| dir.join(APP_IDENTIFIER))
+        .ok_or_else(|| "Could not find the local data directory".to_string())
+}
+
+/// Folder for one budget's local files, created if needed
+pub fn budget_dir(budget_path: &str) -> Result<PathBuf, String> {
+    let mut hasher = Sha256::new();
+    hasher.update(budget_path.as_bytes());
+    let key = hex::encode(hasher.finalize());
+    let dir = app_data_dir()?.join("budgets").join(&key[..16]);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    Ok(dir)
+}
+
+/// Read a JSON file, or the default value when it does not exist yet
+pub fn read_or_default<T: for<'de> Deserialize<'de> + Default>(path: &Path) -> Result<T, String> {
+    if path.exists() {
+        read_json(path)
+    } else {
+        Ok(T::default())
+    }
+}
+
+/// Write JSON through a temporary file so a crash never leaves half a file
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, json).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
@@ -0,0 +1,17 @@
+//! Native reading (and editing) of YNAB4 budget folders
+
+pub mod accounts;
+pub mod budget;
+pub mod calculator;
+pub mod categories;
+pub mod commands;
+pub mod entities;
+#[cfg(test)]
+pub(crate) mod fixtures;
+pub mod journal;
+pub mod knowledge;
+pub mod local;
+pub mod payees;
+pub mod reconcile;
+pub mod rules;
+pub mod transactions;
@@ -0,0 +1,333 @@
+//! Payee maintenance.
+//!
+//! Combining payees moves every reference from the source payees to the
+//! target, hands their rename conditions and missing auto-fill values to the
+//! target and deletes the sources, all in one ydiff. Regex rules in the
+//! rules sidecar that give a source payee are pointed at the target.
+
+use serde::Serialize;
+
+use super::budget::{new_entity_id, Budget};
+use super::entities::*;
+use super::rules;
+
+/// Operator YNAB4 uses for exact payee rename conditions
+pub const RENAME_OPERATOR_IS: &str = "Is";
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayeeMergeResult {
+    pub target_payee_id: String,
+    pub source_payee_ids: Vec<String>,
+    pub transaction_count: usize,
+    pub sub_transaction_count: usize,
+    pub scheduled_transaction_count: usize,
+    /// Rename conditions added to the target
+    pub rename_condition_count: usize,
+    /// Regex rules pointed at the target
+    pub regex_rule_count: usize,
+    /// False for a preview; nothing was written
+    pub committed: bool,
+    pub ydiff_path: Option<String>,
+}
+
+fn live_payee<'a>(budget: &'a Budget, payee_id: &str) -> Result<&'a Payee, String> {
+    let payee = budget
+        .data
+        .payee(payee_id)
+        .filter(|p| !p.is_tombstone)
+        .ok_or_else(|| format!("Payee not found: {}", payee_id))?;
+    if payee.entity_id.starts_with(TRANSFER_PAYEE_PREFIX) {
+        return Err(format!(
+            "Transfer payees cannot be combined: {}",
+            payee.name
+        ));
+    }
+    Ok(payee)
+}
+
+fn points_at(payee_id: &Option<String>, sources: &[String]) -> bool {
+    payee_id.as_ref().is_some_and(|id| sources.contains(id))
+}
+
+/// Rename conditions the target gets: those of the sources plus an exact
+/// match on each source name, without repeating what the target already has
+fn merged_conditions(target: &Payee, sources: &[&Payee]) -> Vec<(String, String)> {
+    let mut known: Vec<(String, String)> = target
+        .rename_conditions
+        .iter()
+        .flatten()
+        .filter(|c| !c.is_tombstone)
+        .map(|c| (c.operator.clone(), c.operand.to_lowercase()))
+        .collect();
+    let mut added = Vec::new();
+    for source in sources {
+        let conditions = source
+            .rename_conditions
+            .iter()
+            .flatten()
+            .filter(|c| !c.is_tombstone)
+            .map(|c| (c.operator.clone(), c.operand.clone()))
+            .chain(std::iter::once((
+                RENAME_OPERATOR_IS.to_string(),
+                source.name.clone(),
+            )));
+        for (operator, operand) in conditions {
+            if operand.trim().is_empty() || operand.eq_ignore_ascii_case(&target.name) {
+                continue;
+            }
+            let key = (operator.clone(), operand.to_lowercase());
+            if !known.contains(&key) {
+                known.push(key);
+                added.push((operator, operand));
+            }
+        }
+    }
+    added
+}
+
+/// Combine `source_ids` into `target_id`. With `dry_run` only the counts are
+/// returned; otherwise the changes are written as one ydiff.
+pub fn merge_payees(
+    budget: &mut Budget,
+    target_id: &str,
+    source_ids: &[String],
+    dry_run: bool,
+) -> Result<PayeeMergeResult, String> {
+    let target = live_payee(budget, target_id)?.clone();
+    let mut sources: Vec<String> = Vec::new();
+    for id in source_ids {
+        if id == target_id {
+            return Err("A payee cannot be combined with itself".to_string());
+        }
+        live_payee(budget, id)?;
+        if !sources.contains(id) {
+            sources.push(id.clone());
+        }
+    }
+    if sources.is_empty() {
+        return Err("No payees to combine".to_string());
+    }
+
+    let source_payees: Vec<Payee> = sources
+        .iter()
+        .filter_map(|id| budget.data.payee(id).cloned())
+        .collect();
+    let conditions = merged_conditions(&target, &source_payees.iter().collect::<Vec<_>>());
+    let mut sidecar = rules::load_sidecar(&budget.path)?;
+    let regex_rule_count = sidecar
+        .regex_rules
+        .iter()
+        .filter(|r| sources.contains(&r.payee_id))
+        .count();
+
+    let mut result = PayeeMergeResult {
+        target_payee_id: target_id.to_string(),
+        source_payee_ids: sources.clone(),
+        rename_condition_count: conditions.len(),
+        regex_rule_count,
+        ..Default::default()
+    };
+    let mut changed: Vec<String> = Vec::new();
+    let target_ref = Some(target_id.to_string());
+
+    for transaction in budget
+        .data
+        .transactions
+        .iter_mut()
+        .filter(|t| !t.is_tombstone)
+    {
+        if points_at(&transaction.payee_id, &sources) {
+            result.transaction_count += 1;
+            if !dry_run {
+                transaction.payee_id = target_ref.clone();
+                changed.push(transaction.entity_id.clone());
+            }
+        }
+        for sub in transaction.sub_transactions.iter_mut().flatten() {
+            if !sub.is_tombstone && points_at(&sub.payee_id, &sources) {
+                result.sub_transaction_count += 1;
+                if !dry_run {
+                    sub.payee_id = target_ref.clone();
+                    changed.push(sub.entity_id.clone());
+                }
+            }
+        }
+    }
+    for scheduled in budget
+        .data
+        .scheduled_transactions
+        .iter_mut()
+        .filter(|t| !t.is_tombstone)
+    {
+        let mut touched = points_at(&scheduled.payee_id, &sources);
+        if touched && !dry_run {
+            scheduled.payee_id = target_ref.clone();
+            changed.push(scheduled.entity_id.clone());
+        }
+        for sub in scheduled.sub_transactions.iter_mut().flatten() {
+            if !sub.is_tombstone && points_at(&sub.payee_id, &sources) {
+                touched = true;
+                if !dry_run {
+                    sub.payee_id = target_ref.clone();
+                    changed.push(sub.entity_id.clone());
+                }
+            }
+        }
+        if touched {
+            result.scheduled_transaction_count += 1;
+        }
+    }
+    if dry_run {
+        return Ok(result);
+    }
+
+    for payee in budget.data.payees.iter_mut() {
+        if sources.contains(&payee.entity_id) {
+            payee.is_tombstone = true;
+            changed.push(payee.entity_id.clone());
+            for condition in payee.rename_conditions.iter_mut().flatten() {
+                if !condition.is_tombstone {
+                    condition.is_tombstone = true;
+                    changed.push(condition.entity_id.clone());
+                }
+            }
+        } else if payee.entity_id == target_id {
+            // The target's own auto-fill values win; gaps come from the sources
+            for source in &source_payees {
+                if payee.auto_fill_category_id.is_none() {
+                    payee.auto_fill_category_id = source.auto_fill_category_id.clone();
+                }
+                if payee.auto_fill_memo.is_none() {
+                    payee.auto_fill_memo = source.auto_fill_memo.clone();
+                }
+                if payee.auto_fill_amount.is_none() {
+                    payee.auto_fill_amount = source.auto_fill_amount;
+                }
+            }
+            let list = payee.rename_conditions.get_or_insert_with(Vec::new);
+            for (operator, operand) in &conditions {
+                let condition = PayeeRenameCondition {
+                    entity_id: new_entity_id(),
+                    entity_type: "payeeRenameCondition".to_string(),
+                    parent_payee_id: target_id.to_string(),
+                    operator: operator.clone(),
+                    operand: operand.clone(),
+                    ..Default::default()
+                };
+                changed.push(condition.entity_id.clone());
+                list.push(condition);
+            }
+            changed.push(payee.entity_id.clone());
+        }
+    }
+    for id in &changed {
+        budget.mark_dirty(id.clone());
+    }
+
+    let ydiff_path = budget.save_entities(&changed)?;
+    if regex_rule_count > 0 {
+        for rule in sidecar.regex_rules.iter_mut() {
+            if sources.contains(&rule.payee_id) {
+                rule.payee_id = target_id.to_string();
+            }
+        }
+        rules::save_sidecar(&budget.path, &sidecar)?;
+    }
+    result.committed = true;
+    result.ydiff_path = ydiff_path.map(|p| p.to_string_lossy().to_string());
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::fixtures;
+
+    #[test]
+    fn merging_moves_references_and_keeps_other_edits_pending() {
+        let path = fixtures::budget_folder("payees-merge");
+        let mut budget = Budget::load(&path).unwrap();
+        let sources = vec!["P-2".to_string()];
+        let preview = merge_payees(&mut budget, "P-1", &sources, true).unwrap();
+        assert_eq!(
+            (
+                preview.transaction_count,
+                preview.scheduled_transaction_count
+            ),
+            (1, 1)
+        );
+        assert!(!preview.committed && budget.dirty.is_empty());
+        assert!(merge_payees(
+            &mut budget,
+            "P-1",
+            &["Payee/Transfer:ACC-CC".to_string()],
+            true
+        )
+        .is_err());
+
+        // An unrelated edit that was not saved yet
+        budget
+            .data
+            .transactions
+            .iter_mut()
+            .find(|t| t.entity_id == "T-1")
+            .unwrap()
+            .memo = Some("draft".to_string());
+        budget.mark_dirty("T-1");
+        let result = merge_payees(&mut budget, "P-1", &sources, false).unwrap();
+        assert_eq!(result.rename_condition_count, 1);
+        assert!(budget.dirty.contains("T-1"));
+
+        let reloaded = Budget::load(&path).unwrap();
+        assert_eq!(
+            reloaded
+                .data
+                .transaction("T-3")
+                .unwrap()
+                .payee_id
+                .as_deref(),
+            Some("P-1")
+        );
+        assert_eq!(reloaded.data.transaction("T-1").unwrap().memo, None);
+        assert!(reloaded.data.payee("P-2").unwrap().is_tombstone);
+        let target = reloaded.data.payee("P-1").unwrap();
+        assert_eq!(target.auto_fill_memo.as_deref(), Some("snacks"));
+        assert_eq!(target.rename_conditions.as_ref().unwrap().len(), 2);
+        assert_eq!(
+            reloaded.data.scheduled_transactions[0].payee_id.as_deref(),
+            Some("P-1")
+        );
+    }
+
+    #[test]
+    fn merging_points_regex_rules_at_the_target() {
+        let path = fixtures::budget_folder("payees-merge-rules");
+        let rule = |id: &str, payee_id: &str| rules::RegexRule {
+            id: id.to_string(),
+            pattern: "^oxxo".to_string(),
+            payee_id: payee_id.to_string(),
+            category_id: None,
+            enabled: true,
+        };
+        let sidecar = rules::RulesSidecar {
+            regex_rules: vec![rule("R-1", "P-2"), rule("R-2", "P-EMP")],
+        };
+        rules::save_sidecar(&path, &sidecar).unwrap();
+
+        let mut budget = Budget::load(&path).unwrap();
+        let sources = vec!["P-2".to_string()];
+        let preview = merge_payees(&mut budget, "P-1", &sources, true).unwrap();
+        assert_eq!(preview.regex_rule_count, 1);
+        assert_eq!(
+            rules::load_sidecar(&path).unwrap().regex_rules[0].payee_id,
+            "P-2"
+        );
+
+        let result = merge_payees(&mut budget, "P-1", &sources, false).unwrap();
+        assert_eq!(result.regex_rule_count, 1);
+        let saved = rules::load_sidecar(&path).unwrap().regex_rules;
+        assert_eq!(saved[0].payee_id, "P-1");
+        assert_eq!(saved[1].payee_id, "P-EMP");
+    }
+}
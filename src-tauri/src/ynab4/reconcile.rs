@@ -0,0 +1,385 @@
+//! Interactive account reconciliation.
+//!
+//! A session holds the statement date and balance and the cleared state the
+//! user toggled. Nothing touches the budget until the session is finished;
+//! then the toggles, the "Reconciled" marks, the optional adjustment
+//! transaction and the account's last reconciliation are written together.
+
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+use super::budget::{new_entity_id, Budget};
+use super::calculator::round_amount;
+use super::entities::*;
+
+/// Payee YNAB4 uses for reconciliation adjustments
+pub const ADJUSTMENT_PAYEE_NAME: &str = "Reconciliation Balance Adjustment";
+
+#[derive(Debug, Clone)]
+pub struct ReconciliationSession {
+    pub account_id: String,
+    pub statement_date: String,
+    pub statement_balance: f64,
+    /// Transactions whose cleared state was toggled during the session
+    pub toggled: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconcileRow {
+    pub transaction_id: String,
+    pub date: String,
+    pub amount: f64,
+    pub payee_id: Option<String>,
+    pub memo: Option<String>,
+    pub cleared: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconcileStatus {
+    pub account_id: String,
+    pub statement_date: String,
+    pub statement_balance: f64,
+    pub cleared_balance: f64,
+    /// Statement balance minus cleared balance; zero when reconciled
+    pub difference: f64,
+    /// Transactions not reconciled yet, oldest first
+    pub transactions: Vec<ReconcileRow>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconcileResult {
+    pub reconciled_count: usize,
+    pub adjustment_transaction_id: Option<String>,
+    pub ydiff_path: Option<String>,
+}
+
+impl ReconciliationSession {
+    pub fn new(
+        budget: &Budget,
+        account_id: &str,
+        statement_date: &str,
+        statement_balance: f64,
+    ) -> Result<ReconciliationSession, String> {
+        let account = budget
+            .data
+            .account(account_id)
+            .filter(|a| !a.is_tombstone)
+            .ok_or_else(|| format!("Account not found: {}", account_id))?;
+        Ok(ReconciliationSession {
+            account_id: account.entity_id.clone(),
+            statement_date: statement_date.to_string(),
+            statement_balance,
+            toggled: BTreeSet::new(),
+        })
+    }
+
+    /// Cleared state of a transaction with the session's toggles applied
+    fn is_cleared(&self, transaction: &Transaction) -> bool {
+        let cleared = transaction.cleared != UNCLEARED && !transaction.cleared.is_empty();
+        cleared != self.toggled.contains(&transaction.entity_id)
+    }
+
+    /// Whether a transaction is one the statement can cover
+    fn on_statement(&self, transaction: &Transaction) -> bool {
+        !transaction.is_tombstone
+            && transaction.account_id == self.account_id
+            && transaction.date.as_str() <= self.statement_date.as_str()
+    }
+
+    fn account_transactions<'a>(
+        &'a self,
+        budget: &'a Budget,
+    ) -> impl Iterator<Item = &'a Transaction> {
+        budget
+            .data
+            .transactions
+            .iter()
+            .filter(move |t| self.on_statement(t))
+    }
+
+    /// Flip the cleared state of transactions; reconciled ones cannot change
+    pub fn toggle(&mut self, budget: &Budget, transaction_ids: &[String]) -> Result<(), String> {
+        for id in transaction_ids {
+            let transaction = self
+                .account_transactions(budget)
+                .find(|t| &t.entity_id == id)
+                .ok_or_else(|| format!("Transaction {} is not on this statement", id))?;
+            if transaction.cleared == RECONCILED {
+                return Err(format!("Transaction {} is already reconciled", id));
+            }
+            if !self.toggled.remove(id) {
+                self.toggled.insert(id.clone());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn status(&self, budget: &Budget) -> ReconcileStatus {
+        let mut cleared_balance = 0.0;
+        let mut rows = Vec::new();
+        for transaction in self.account_transactions(budget) {
+            let cleared = self.is_cleared(transaction);
+            if cleared {
+                cleared_balance += transaction.amount;
+            }
+            if transaction.cleared != RECONCILED {
+                rows.push(ReconcileRow {
+                    transaction_id: transaction.entity_id.clone(),
+                    date: transaction.date.clone(),
+                    amount: transaction.amount,
+                    payee_id: transaction.payee_id.clone(),
+                    memo: transaction.memo.clone(),
+                    cleared,
+                });
+            }
+        }
+        rows.sort_by(|a, b| a.date.cmp(&b.date));
+
+        let cleared_balance = round_amount(cleared_balance);
+        ReconcileStatus {
+            account_id: self.account_id.clone(),
+            statement_date: self.statement_date.clone(),
+            statement_balance: self.statement_balance,
+            cleared_balance,
+            difference: round_amount(self.statement_balance - cleared_balance),
+            transactions: rows,
+        }
+    }
+
+    /// Apply the session to the budget and write it as one ydiff. Other edits
+    /// that were not saved yet stay pending and are not part of it.
+    pub fn finish(
+        &self,
+        budget: &mut Budget,
+        create_adjustment: bool,
+    ) -> Result<ReconcileResult, String> {
+        let difference = self.status(budget).difference;
+        if difference != 0.0 && !create_adjustment {
+            return Err(format!(
+                "Cleared balance differs from the statement by {}",
+                difference
+            ));
+        }
+
+        let pending = std::mem::take(&mut budget.dirty);
+        let mut changed = Vec::new();
+        let mut reconciled_count = 0;
+        for transaction in budget
+            .data
+            .transactions
+            .iter_mut()
+            .filter(|t| self.on_statement(t))
+        {
+            let cleared = self.is_cleared(transaction);
+            if cleared && transaction.cleared != RECONCILED {
+                transaction.cleared = RECONCILED.to_string();
+                changed.push(transaction.entity_id.clone());
+                reconciled_count += 1;
+            } else if !cleared && transaction.cleared != UNCLEARED {
+                transaction.cleared = UNCLEARED.to_string();
+                changed.push(transaction.entity_id.clone());
+            }
+        }
+        for id in changed {
+            budget.mark_dirty(id);
+        }
+
+        let adjustment_transaction_id = if difference != 0.0 {
+            Some(self.create_adjustment(budget, difference))
+        } else {
+            None
+        };
+
+        if let Some(account) = budget
+            .data
+            .accounts
+            .iter_mut()
+            .find(|a| a.entity_id == self.account_id)
+        {
+            account.last_reconciled_date = Some(self.statement_date.clone());
+            account.last_reconciled_balance = Some(self.statement_balance);
+            let id = account.entity_id.clone();
+            budget.mark_dirty(id);
+        }
+
+        let own: Vec<String> = budget.dirty.iter().cloned().collect();
+        budget.dirty.extend(pending);
+        let ydiff_path = budget.save_entities(&own)?;
+        Ok(ReconcileResult {
+            reconciled_count,
+            adjustment_transaction_id,
+            ydiff_path: ydiff_path.map(|p| p.to_string_lossy().to_string()),
+        })
+    }
+
+    fn create_adjustment(&self, budget: &mut Budget, difference: f64) -> String {
+        let payee_id = match budget
+            .data
+            .payees
+            .iter()
+            .find(|p| !p.is_tombstone && p.name == ADJUSTMENT_PAYEE_NAME)
+        {
+            Some(payee) => payee.entity_id.clone(),
+            None => {
+                let payee = Payee {
+                    entity_id: new_entity_id(),
+                    entity_type: "payee".to_string(),
+                    name: ADJUSTMENT_PAYEE_NAME.to_string(),
+                    enabled: true,
+                    ..Default::default()
+                };
+                let id = payee.entity_id.clone();
+                budget.data.payees.push(payee);
+                budget.mark_dirty(id.clone());
+                id
+            }
+        };
+
+        // On budget adjustments are income (or negative income) of the month
+        let on_budget = budget
+            .data
+            .account(&self.account_id)
+            .is_some_and(|a| a.on_budget);
+        let transaction = Transaction {
+            entity_id: new_entity_id(),
+            entity_type: "transaction".to_string(),
+            account_id: self.account_id.clone(),
+            date: self.statement_date.clone(),
+            amount: difference,
+            payee_id: Some(payee_id),
+            category_id: on_budget.then(|| IMMEDIATE_INCOME_CATEGORY_ID.to_string()),
+            cleared: RECONCILED.to_string(),
+            accepted: true,
+            ..Default::default()
+        };
+        let id = transaction.entity_id.clone();
+        budget.data.transactions.push(transaction);
+        budget.mark_dirty(id.clone());
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::fixtures;
+
+    #[test]
+    fn finishing_writes_the_toggles_and_an_adjustment() {
+        let path = fixtures::budget_folder("reconcile-finish");
+        let mut budget = Budget::load(&path).unwrap();
+        let mut session =
+            ReconciliationSession::new(&budget, "ACC-CHK", "2024-01-31", 1140.0).unwrap();
+        assert_eq!(session.status(&budget).cleared_balance, 1400.0);
+        session.toggle(&budget, &["T-2".to_string()]).unwrap();
+        let status = session.status(&budget);
+        assert_eq!((status.cleared_balance, status.difference), (1150.0, -10.0));
+        assert!(session.finish(&mut budget, false).is_err());
+
+        let result = session.finish(&mut budget, true).unwrap();
+        assert_eq!(result.reconciled_count, 4);
+        let reloaded = Budget::load(&path).unwrap();
+        assert_eq!(
+            reloaded.data.transaction("T-2").unwrap().cleared,
+            RECONCILED
+        );
+        let adjustment = result.adjustment_transaction_id.unwrap();
+        assert_eq!(
+            reloaded.data.transaction(&adjustment).unwrap().amount,
+            -10.0
+        );
+        let account = reloaded.data.account("ACC-CHK").unwrap();
+        assert_eq!(account.last_reconciled_balance, Some(1140.0));
+        assert_eq!(account.last_reconciled_date.as_deref(), Some("2024-01-31"));
+    }
+
+    #[test]
+    fn only_the_statement_period_and_the_reconciliation_are_written() {
+        let path = fixtures::budget_folder("reconcile-scoped");
+        let mut budget = Budget::load(&path).unwrap();
+        budget
+            .data
+            .transactions
+            .iter_mut()
+            .find(|t| t.entity_id == "T-3")
+            .unwrap()
+            .memo = Some("later".to_string());
+        budget.mark_dirty("T-3");
+
+        let mut session =
+            ReconciliationSession::new(&budget, "ACC-CHK", "2024-01-15", 900.0).unwrap();
+        let status = session.status(&budget);
+        // T-4 on 2024-01-20 and T-7 in February are after the statement
+        assert_eq!(status.cleared_balance, 900.0);
+        assert!(status
+            .transactions
+            .iter()
+            .all(|t| t.date.as_str() <= "2024-01-15"));
+        assert!(session.toggle(&budget, &["T-4".to_string()]).is_err());
+
+        let result = session.finish(&mut budget, false).unwrap();
+        assert_eq!(result.reconciled_count, 2);
+        let ydiff = std::fs::read_to_string(result.ydiff_path.unwrap()).unwrap();
+        assert!(!ydiff.contains("\"T-3\"") && !ydiff.contains("\"T-4\""));
+        assert_eq!(budget.data.transaction("T-4").unwrap().cleared, CLEARED);
+        assert_eq!(budget.dirty, BTreeSet::from(["T-3".to_string()]));
+    }
+
+    #[test]
+    fn off_budget_adjustments_have_no_category_and_share_the_payee() {
+        let path = fixtures::budget_folder("reconcile-off-budget");
+        let mut budget = Budget::load(&path).unwrap();
+        assert!(ReconciliationSession::new(&budget, "ACC-GONE", "2024-01-31", 0.0).is_err());
+
+        let first = ReconciliationSession::new(&budget, "ACC-INV", "2024-01-31", 25.0).unwrap();
+        let adjustment = first
+            .finish(&mut budget, true)
+            .unwrap()
+            .adjustment_transaction_id
+            .unwrap();
+        let transaction = budget.data.transaction(&adjustment).unwrap().clone();
+        assert_eq!((transaction.amount, transaction.category_id), (25.0, None));
+
+        let mut second =
+            ReconciliationSession::new(&budget, "ACC-INV", "2024-02-29", 30.0).unwrap();
+        assert!(second.toggle(&budget, &[adjustment.clone()]).is_err());
+        let result = second.finish(&mut budget, true).unwrap();
+        assert_eq!(result.reconciled_count, 0);
+        let later = budget
+            .data
+            .transaction(&result.adjustment_transaction_id.unwrap())
+            .unwrap();
+        assert_eq!(later.amount, 5.0);
+        assert_eq!(later.payee_id, transaction.payee_id);
+        let payees = budget
+            .data
+            .payees
+            .iter()
+            .filter(|p| p.name == ADJUSTMENT_PAYEE_NAME);
+        assert_eq!(payees.count(), 1);
+    }
+
+    #[test]
+    fn toggling_twice_restores_the_cleared_state() {
+        let path = fixtures::budget_folder("reconcile-toggle");
+        let budget = Budget::load(&path).unwrap();
+        let mut session =
+            ReconciliationSession::new(&budget, "ACC-CHK", "2024-01-31", 0.0).unwrap();
+        let before = session.status(&budget).cleared_balance;
+        session
+            .toggle(&budget, &["T-1".to_string(), "T-2".to_string()])
+            .unwrap();
+        assert_eq!(
+            session.status(&budget).cleared_balance,
+            before - 1000.0 - 150.0
+        );
+        session
+            .toggle(&budget, &["T-1".to_string(), "T-2".to_string()])
+            .unwrap();
+        assert!(session.toggled.is_empty());
+        assert_eq!(session.status(&budget).cleared_balance, before);
+    }
+}
@@ -0,0 +1,302 @@
+//! Payee rename and auto-category rules.
+//!
+//! YNAB4 payees carry rename conditions (`Is`, `Contains`, `StartsWith`,
+//! `EndsWith`, compared without case) that turn a bank description into the
+//! payee, whose `autoFillCategoryId` gives the category. Regular expression
+//! rules are our own extension; YNAB4 would not understand them, so they live
+//! in a sidecar file in the budget folder instead of the budget data.
+
+use regex::RegexBuilder;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use super::entities::*;
+use super::local;
+
+/// Sidecar with the regex rules, next to `Budget.ymeta`
+pub const RULES_SIDECAR_FILE: &str = "payeeRules.ynab4client.json";
+
+pub const OPERATOR_IS: &str = "Is";
+pub const OPERATOR_CONTAINS: &str = "Contains";
+pub const OPERATOR_STARTS_WITH: &str = "StartsWith";
+pub const OPERATOR_ENDS_WITH: &str = "EndsWith";
+pub const OPERATOR_REGEX: &str = "Regex";
+
+/// Regex rule kept in the sidecar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegexRule {
+    pub id: String,
+    pub pattern: String,
+    pub payee_id: String,
+    /// Overrides the payee's auto-fill category
+    #[serde(default)]
+    pub category_id: Option<String>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RulesSidecar {
+    #[serde(default)]
+    pub regex_rules: Vec<RegexRule>,
+}
+
+fn sidecar_path(budget_path: &Path) -> PathBuf {
+    budget_path.join(RULES_SIDECAR_FILE)
+}
+
+pub fn load_sidecar(budget_path: &Path) -> Result<RulesSidecar, String> {
+    local::read_or_default(&sidecar_path(budget_path))
+}
+
+/// Save the sidecar after checking every pattern compiles
+pub fn save_sidecar(budget_path: &Path, sidecar: &RulesSidecar) -> Result<(), String> {
+    for rule in &sidecar.regex_rules {
+        compile_regex(&rule.pattern)?;
+    }
+    local::write_json(&sidecar_path(budget_path), sidecar)
+}
+
+fn compile_regex(pattern: &str) -> Result<regex::Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| format!("Invalid regular expression {}: {}", pattern, e))
+}
+
+/// A rule ready to be evaluated
+enum Matcher {
+    Text { operator: String, operand: String },
+    Regex(regex::Regex),
+}
+
+impl Matcher {
+    fn new(operator: &str, operand: &str) -> Result<Matcher, String> {
+        match operator {
+            OPERATOR_REGEX => Ok(Matcher::Regex(compile_regex(operand)?)),
+            OPERATOR_IS | OPERATOR_CONTAINS | OPERATOR_STARTS_WITH | OPERATOR_ENDS_WITH => {
+                Ok(Matcher::Text {
+                    operator: operator.to_string(),
+                    operand: normalize(operand),
+                })
+            }
+            _ => Err(format!("Unknown rule operator: {}", operator)),
+        }
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            Matcher::Regex(regex) => regex.is_match(text),
+            Matcher::Text { operand, .. } if operand.is_empty() => false,
+            Matcher::Text { operator, operand } => {
+                let text = normalize(text);
+                match operator.as_str() {
+                    OPERATOR_IS => &text == operand,
+                    OPERATOR_STARTS_WITH => text.starts_with(operand.as_str()),
+                    OPERATOR_ENDS_WITH => text.ends_with(operand.as_str()),
+                    _ => text.contains(operand.as_str()),
+                }
+            }
+        }
+    }
+
+    /// Exact matches beat patterns, which beat prefixes, suffixes and
+    /// substrings; longer operands beat shorter ones
+    fn rank(&self) -> (u8, usize) {
+        match self {
+            Matcher::Regex(regex) => (3, regex.as_str().len()),
+            Matcher::Text { operator, operand } => {
+                let kind = match operator.as_str() {
+                    OPERATOR_IS => 4,
+                    OPERATOR_STARTS_WITH => 2,
+                    OPERATOR_ENDS_WITH => 1,
+                    _ => 0,
+                };
+                (kind, operand.chars().count())
+            }
+        }
+    }
+}
+
+/// Lowercase and collapse whitespace, like YNAB4 compares payee text
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+struct Rule {
+    matcher: Matcher,
+    payee_id: String,
+    category_id: Option<String>,
+    description: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleSuggestion {
+    pub payee_id: String,
+    pub payee_name: String,
+    pub category_id: Option<String>,
+    pub category_name: Option<String>,
+    /// The rule that matched, e.g. "Contains OXXO"
+    pub rule: String,
+}
+
+/// Every rename condition, payee name and sidecar rule of a budget
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    pub fn new(data: &BudgetData, sidecar: &RulesSidecar) -> RuleSet {
+        let mut rules = Vec::new();
+        let live_payees = data.payees.iter().filter(|p| {
+            !p.is_tombstone && p.enabled && !p.entity_id.starts_with(TRANSFER_PAYEE_PREFIX)
+        });
+
+        for payee in live_payees {
+            let conditions = payee
+                .rename_conditions
+                .iter()
+                .flatten()
+                .filter(|c| !c.is_tombstone)
+                .map(|c| (c.operator.as_str(), c.operand.as_str()))
+                .chain(std::iter::once((OPERATOR_IS, payee.name.as_str())));
+            for (operator, operand) in conditions {
+                // Conditions written by newer clients may use operators we skip
+                if let Ok(matcher) = Matcher::new(operator, operand) {
+                    rules.push(Rule {
+                        matcher,
+                        payee_id: payee.entity_id.clone(),
+                        category_id: None,
+                        description: format!("{} {}", operator, operand),
+                    });
+                }
+            }
+        }
+
+        for rule in sidecar.regex_rules.iter().filter(|r| r.enabled) {
+            match Matcher::new(OPERATOR_REGEX, &rule.pattern) {
+                Ok(matcher) => rules.push(Rule {
+                    matcher,
+                    payee_id: rule.payee_id.clone(),
+                    category_id: rule.category_id.clone(),
+                    description: format!("{} {}", OPERATOR_REGEX, rule.pattern),
+                }),
+                Err(e) => log::warn!("Skipping payee rule {}: {}", rule.id, e),
+            }
+        }
+
+        rules.sort_by_key(|rule| std::cmp::Reverse(rule.matcher.rank()));
+        RuleSet { rules }
+    }
+
+    /// Payee and category suggested for a bank description
+    pub fn suggest(&self, data: &BudgetData, description: &str) -> Option<RuleSuggestion> {
+        let categories = data.live_categories();
+        self.rules
+            .iter()
+            .filter(|rule| rule.matcher.matches(description))
+            .find_map(|rule| {
+                let payee = data.payee(&rule.payee_id).filter(|p| !p.is_tombstone)?;
+                let category_id = rule
+                    .category_id
+                    .clone()
+                    .or_else(|| payee.auto_fill_category_id.clone());
+                let category_name = category_id.as_deref().and_then(|id| {
+                    categories
+                        .iter()
+                        .find(|c| c.entity_id == id)
+                        .map(|c| c.name.clone())
+                });
+                Some(RuleSuggestion {
+                    payee_id: payee.entity_id.clone(),
+                    payee_name: payee.name.clone(),
+                    category_id,
+                    category_name,
+                    rule: rule.description.clone(),
+                })
+            })
+    }
+}
+
+/// Which samples a single rule matches
+pub fn test_rule(operator: &str, operand: &str, samples: &[String]) -> Result<Vec<bool>, String> {
+    let matcher = Matcher::new(operator, operand)?;
+    Ok(samples.iter().map(|s| matcher.matches(s)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::budget::Budget;
+    use crate::ynab4::fixtures;
+
+    #[test]
+    fn suggests_payees_from_rename_conditions_and_regex_rules() {
+        let path = fixtures::budget_folder("rules-suggest");
+        let budget = Budget::load(&path).unwrap();
+        let sidecar = RulesSidecar {
+            regex_rules: vec![RegexRule {
+                id: "R-1".to_string(),
+                pattern: r"^SPEI\s+\d+ EMPRESA".to_string(),
+                payee_id: "P-EMP".to_string(),
+                category_id: Some(IMMEDIATE_INCOME_CATEGORY_ID.to_string()),
+                enabled: true,
+            }],
+        };
+        save_sidecar(&path, &sidecar).unwrap();
+        let rules = RuleSet::new(&budget.data, &load_sidecar(&path).unwrap());
+
+        // The payee's own name, then its rename conditions
+        let suggestion = rules.suggest(&budget.data, "oxxo store").unwrap();
+        assert_eq!(suggestion.payee_id, "P-2");
+        assert_eq!(suggestion.category_name.as_deref(), Some("Food"));
+        assert_eq!(
+            rules
+                .suggest(&budget.data, "COMPRA OXXO 123")
+                .unwrap()
+                .payee_id,
+            "P-1"
+        );
+        let regex = rules.suggest(&budget.data, "spei 42 empresa sa").unwrap();
+        assert_eq!(
+            (regex.payee_id.as_str(), regex.rule.as_str()),
+            ("P-EMP", r"Regex ^SPEI\s+\d+ EMPRESA")
+        );
+        assert!(rules.suggest(&budget.data, "walmart").is_none());
+
+        let samples = ["UBER EATS".to_string(), "x uber".to_string()];
+        assert_eq!(
+            test_rule("StartsWith", "uber", &samples).unwrap(),
+            vec![true, false]
+        );
+        assert!(test_rule("Regex", "(", &[]).is_err());
+    }
+
+    #[test]
+    fn sidecar_defaults_when_missing_and_rejects_bad_patterns() {
+        let path = fixtures::budget_folder("rules-sidecar");
+        assert!(load_sidecar(&path).unwrap().regex_rules.is_empty());
+
+        let sidecar = RulesSidecar {
+            regex_rules: vec![RegexRule {
+                id: "R-1".to_string(),
+                pattern: "(oxxo".to_string(),
+                payee_id: "P-1".to_string(),
+                category_id: None,
+                enabled: true,
+            }],
+        };
+        assert!(save_sidecar(&path, &sidecar).is_err());
+        assert!(!path.join(RULES_SIDECAR_FILE).exists());
+    }
+}
@@ -0,0 +1,1093 @@
+//! Creating, editing and deleting transactions.
+//!
+//! YNAB4 stores a transfer as two transactions pointing at each other through
+//! `transferTransactionId`; a split line that is a transfer points at a whole
+//! transaction in the other account. These helpers keep both sides in sync:
+//! opposite amounts, the same date and memo, and the transfer payees. Only the
+//! on-budget side of a transfer to an off-budget account has a category.
+
+use serde::{Deserialize, Serialize};
+
+use super::budget::{new_entity_id, Budget};
+use super::calculator::round_amount;
+use super::entities::*;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubTransactionInput {
+    /// Existing split line to update; a new one is created when missing
+    #[serde(default)]
+    pub entity_id: Option<String>,
+    pub amount: f64,
+    #[serde(default)]
+    pub category_id: Option<String>,
+    #[serde(default)]
+    pub memo: Option<String>,
+    /// Makes this split line a transfer to that account
+    #[serde(default)]
+    pub transfer_account_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionInput {
+    pub account_id: String,
+    pub date: String,
+    pub amount: f64,
+    #[serde(default)]
+    pub payee_id: Option<String>,
+    #[serde(default)]
+    pub category_id: Option<String>,
+    #[serde(default)]
+    pub memo: Option<String>,
+    /// "Cleared", "Uncleared" or "Reconciled"; defaults to "Uncleared"
+    #[serde(default)]
+    pub cleared: Option<String>,
+    #[serde(default)]
+    pub flag: Option<String>,
+    #[serde(default)]
+    pub check_number: Option<String>,
+    /// Makes this transaction a transfer to that account
+    #[serde(default)]
+    pub transfer_account_id: Option<String>,
+    /// Makes this a split transaction; amounts must add up to `amount`
+    #[serde(default)]
+    pub sub_transactions: Option<Vec<SubTransactionInput>>,
+}
+
+impl TransactionInput {
+    /// Input that reproduces an existing transaction
+    pub fn from_transaction(transaction: &Transaction) -> TransactionInput {
+        let transfer_account_id = transaction
+            .transfer_transaction_id
+            .as_ref()
+            .and(transaction.target_account_id.clone());
+        TransactionInput {
+            account_id: transaction.account_id.clone(),
+            date: transaction.date.clone(),
+            amount: transaction.amount,
+            payee_id: transaction.payee_id.clone(),
+            category_id: transaction
+                .category_id
+                .clone()
+                .filter(|_| !transaction.is_split()),
+            memo: transaction.memo.clone(),
+            cleared: Some(transaction.cleared.clone()),
+            flag: transaction.flag.clone(),
+            check_number: transaction.check_number.clone(),
+            transfer_account_id,
+            sub_transactions: transaction.is_split().then(|| {
+                transaction
+                    .live_sub_transactions()
+                    .map(|sub| SubTransactionInput {
+                        entity_id: Some(sub.entity_id.clone()),
+                        amount: sub.amount,
+                        category_id: sub.category_id.clone(),
+                        memo: sub.memo.clone(),
+                        transfer_account_id: sub
+                            .transfer_transaction_id
+                            .as_ref()
+                            .and(sub.target_account_id.clone()),
+                    })
+                    .collect()
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionChange {
+    pub transaction_id: String,
+    /// Every entity touched, including the other side of transfers
+    pub changed_ids: Vec<String>,
+}
+
+/// Where a transaction sits in a transfer
+enum TransferRole {
+    None,
+    /// Transfer with another whole transaction
+    Pair(String),
+    /// Other side of a split line of this parent transaction
+    SplitCounterpart(String),
+}
+
+fn transfer_role(budget: &Budget, transaction: &Transaction) -> TransferRole {
+    let Some(other_id) = transaction.transfer_transaction_id.as_deref() else {
+        return TransferRole::None;
+    };
+    if budget
+        .data
+        .transactions
+        .iter()
+        .any(|t| t.entity_id == other_id && !t.is_tombstone)
+    {
+        return TransferRole::Pair(other_id.to_string());
+    }
+    for parent in budget.data.transactions.iter().filter(|t| !t.is_tombstone) {
+        if parent
+            .live_sub_transactions()
+            .any(|s| s.entity_id == other_id)
+        {
+            return TransferRole::SplitCounterpart(parent.entity_id.clone());
+        }
+    }
+    TransferRole::None
+}
+
+fn live_account<'a>(budget: &'a Budget, account_id: &str) -> Result<&'a Account, String> {
+    budget
+        .data
+        .account(account_id)
+        .filter(|a| !a.is_tombstone)
+        .ok_or_else(|| format!("Account not found: {}", account_id))
+}
+
+fn validate_category(budget: &Budget, category_id: Option<&str>) -> Result<(), String> {
+    let Some(category_id) = category_id else {
+        return Ok(());
+    };
+    if category_id == IMMEDIATE_INCOME_CATEGORY_ID || category_id == DEFERRED_INCOME_CATEGORY_ID {
+        return Ok(());
+    }
+    if budget
+        .data
+        .live_categories()
+        .iter()
+        .any(|c| c.entity_id == category_id)
+    {
+        Ok(())
+    } else {
+        Err(format!("Category not found: {}", category_id))
+    }
+}
+
+/// Categories for (this side, other side) of a transfer. Only a transfer that
+/// leaves or enters the budget has a category, on its on-budget side.
+pub fn transfer_categories(
+    budget: &Budget,
+    account_id: &str,
+    other_account_id: &str,
+    category_id: Option<String>,
+) -> Result<(Option<String>, Option<String>), String> {
+    if account_id == other_account_id {
+        return Err("A transfer needs two different accounts".to_string());
+    }
+    let this_on = live_account(budget, account_id)?.on_budget;
+    let other_on = live_account(budget, other_account_id)?.on_budget;
+    validate_category(budget, category_id.as_deref())?;
+
+    match (this_on, other_on) {
+        (true, false) | (false, true) => {
+            let category_id = category_id.ok_or(
+                "A transfer between an on-budget and an off-budget account needs a category",
+            )?;
+            Ok(if this_on {
+                (Some(category_id), None)
+            } else {
+                (None, Some(category_id))
+            })
+        }
+        _ if category_id.is_some() => Err(
+            "Only a transfer between an on-budget and an off-budget account can have a category"
+                .to_string(),
+        ),
+        _ => Ok((None, None)),
+    }
+}
+
+/// Id of the built-in payee for transfers to `account_id`, creating it if needed
+pub fn transfer_payee_id(budget: &mut Budget, account_id: &str) -> String {
+    let payee_id = format!("{}{}", TRANSFER_PAYEE_PREFIX, account_id);
+    let exists = budget.data.payees.iter().any(|p| p.entity_id == payee_id);
+    if !exists {
+        let name = budget
+            .data
+            .account(account_id)
+            .map(|a| a.account_name.clone())
+            .unwrap_or_default();
+        budget.data.payees.push(Payee {
+            entity_id: payee_id.clone(),
+            entity_type: "payee".to_string(),
+            name: format!("Transfer : {}", name),
+            enabled: true,
+            target_account_id: Some(account_id.to_string()),
+            ..Default::default()
+        });
+        budget.mark_dirty(payee_id.clone());
+    }
+    payee_id
+}
+
+/// The other side of a transfer as it should look
+struct Counterpart {
+    /// Existing counterpart to update
+    existing_id: Option<String>,
+    account_id: String,
+    /// Account of the side this counterpart mirrors
+    source_account_id: String,
+    /// Transaction or split line this counterpart mirrors
+    source_id: String,
+    date: String,
+    amount: f64,
+    memo: Option<String>,
+    category_id: Option<String>,
+}
+
+/// Create or update the other side of a transfer; returns its id
+fn write_counterpart(budget: &mut Budget, spec: Counterpart) -> String {
+    let payee_id = transfer_payee_id(budget, &spec.source_account_id);
+    let existing = spec.existing_id.as_deref().and_then(|id| {
+        budget
+            .data
+            .transactions
+            .iter()
+            .position(|t| t.entity_id == id && !t.is_tombstone)
+    });
+
+    let id = match existing {
+        Some(idx) => {
+            let transaction = &mut budget.data.transactions[idx];
+            if transaction.account_id != spec.account_id {
+                transaction.cleared = UNCLEARED.to_string();
+            }
+            transaction.account_id = spec.account_id.clone();
+            transaction.date = spec.date;
+            transaction.amount = spec.amount;
+            transaction.memo = spec.memo;
+            transaction.category_id = spec.category_id;
+            transaction.payee_id = Some(payee_id);
+            transaction.target_account_id = Some(spec.source_account_id);
+            transaction.transfer_transaction_id = Some(spec.source_id);
+            transaction.entity_id.clone()
+        }
+        None => {
+            let transaction = Transaction {
+                entity_id: new_entity_id(),
+                entity_type: "transaction".to_string(),
+                account_id: spec.account_id,
+                date: spec.date,
+                amount: spec.amount,
+                payee_id: Some(payee_id),
+                category_id: spec.category_id,
+                memo: spec.memo,
+                cleared: UNCLEARED.to_string(),
+                accepted: true,
+                target_account_id: Some(spec.source_account_id),
+                transfer_transaction_id: Some(spec.source_id),
+                ..Default::default()
+            };
+            let id = transaction.entity_id.clone();
+            budget.data.transactions.push(transaction);
+            id
+        }
+    };
+    budget.mark_dirty(id.clone());
+    id
+}
+
+/// Tombstone a transaction and its split lines
+fn tombstone_transaction(budget: &mut Budget, transaction_id: &str) {
+    let mut dirty = Vec::new();
+    if let Some(transaction) = budget
+        .data
+        .transactions
+        .iter_mut()
+        .find(|t| t.entity_id == transaction_id && !t.is_tombstone)
+    {
+        transaction.is_tombstone = true;
+        dirty.push(transaction.entity_id.clone());
+        for sub in transaction.sub_transactions.iter_mut().flatten() {
+            if !sub.is_tombstone {
+                sub.is_tombstone = true;
+                dirty.push(sub.entity_id.clone());
+            }
+        }
+    }
+    for id in dirty {
+        budget.mark_dirty(id);
+    }
+}
+
+fn check_cleared(cleared: &str) -> Result<(), String> {
+    if [CLEARED, UNCLEARED, RECONCILED].contains(&cleared) {
+        Ok(())
+    } else {
+        Err(format!("Unknown cleared state: {}", cleared))
+    }
+}
+
+/// Write `input` into the transaction with `transaction_id`, which may be new
+fn apply_input(
+    budget: &mut Budget,
+    transaction_id: &str,
+    input: TransactionInput,
+) -> Result<(), String> {
+    let idx = budget
+        .data
+        .transactions
+        .iter()
+        .position(|t| t.entity_id == transaction_id)
+        .ok_or_else(|| format!("Transaction not found: {}", transaction_id))?;
+    if let Some(cleared) = input.cleared.as_deref() {
+        check_cleared(cleared)?;
+    }
+    let before = budget.data.transactions[idx].clone();
+    let account = live_account(budget, &input.account_id)?.clone();
+
+    let old_pair = match transfer_role(budget, &before) {
+        TransferRole::Pair(id) => Some(id),
+        _ => None,
+    };
+    let mut transaction = before.clone();
+    transaction.account_id = account.entity_id.clone();
+    transaction.date = input.date.clone();
+    transaction.amount = input.amount;
+    transaction.memo = input.memo.clone();
+    transaction.flag = input.flag.clone();
+    transaction.check_number = input.check_number.clone();
+    if let Some(cleared) = input.cleared.clone() {
+        transaction.cleared = cleared;
+    } else if transaction.cleared.is_empty() {
+        transaction.cleared = UNCLEARED.to_string();
+    }
+
+    if let Some(sub_inputs) = input.sub_transactions {
+        let total: f64 = sub_inputs.iter().map(|s| s.amount).sum();
+        if round_amount(total) != round_amount(input.amount) {
+            return Err(format!(
+                "Split amounts add up to {} instead of {}",
+                round_amount(total),
+                input.amount
+            ));
+        }
+        if let Some(pair) = old_pair {
+            tombstone_transaction(budget, &pair);
+        }
+        transaction.payee_id = input.payee_id.clone();
+        transaction.category_id = Some(SPLIT_CATEGORY_ID.to_string());
+        transaction.transfer_transaction_id = None;
+        transaction.target_account_id = None;
+
+        let mut old_subs: Vec<SubTransaction> = before.sub_transactions.clone().unwrap_or_default();
+        let mut subs = Vec::new();
+        for sub_input in sub_inputs {
+            let existing = sub_input.entity_id.as_deref().and_then(|id| {
+                old_subs
+                    .iter()
+                    .position(|s| s.entity_id == id && !s.is_tombstone)
+            });
+            let mut sub = match existing {
+                Some(i) => old_subs.remove(i),
+                None => SubTransaction {
+                    entity_id: new_entity_id(),
+                    entity_type: "subTransaction".to_string(),
+                    parent_transaction_id: transaction.entity_id.clone(),
+                    ..Default::default()
+                },
+            };
+            sub.amount = sub_input.amount;
+            sub.memo = sub_input.memo;
+
+            match sub_input.transfer_account_id {
+                Some(other_account_id) => {
+                    let (this_category, other_category) = transfer_categories(
+                        budget,
+                        &account.entity_id,
+                        &other_account_id,
+                        sub_input.category_id,
+                    )?;
+                    let counterpart_id = write_counterpart(
+                        budget,
+                        Counterpart {
+                            existing_id: sub.transfer_transaction_id.clone(),
+                            account_id: other_account_id.clone(),
+                            source_account_id: account.entity_id.clone(),
+                            source_id: sub.entity_id.clone(),
+                            date: transaction.date.clone(),
+                            amount: -sub.amount,
+                            memo: sub.memo.clone(),
+                            category_id: other_category,
+                        },
+                    );
+                    sub.category_id = this_category;
+                    sub.payee_id = Some(transfer_payee_id(budget, &other_account_id));
+                    sub.target_account_id = Some(other_account_id);
+                    sub.transfer_transaction_id = Some(counterpart_id);
+                }
+                None => {
+                    validate_category(budget, sub_input.category_id.as_deref())?;
+                    if let Some(old) = sub.transfer_transaction_id.take() {
+                        tombstone_transaction(budget, &old);
+                    }
+                    sub.category_id = sub_input.category_id;
+                    sub.target_account_id = None;
+                    if sub
+                        .payee_id
+                        .as_deref()
+                        .is_some_and(|p| p.starts_with(TRANSFER_PAYEE_PREFIX))
+                    {
+                        sub.payee_id = None;
+                    }
+                }
+            }
+            budget.mark_dirty(sub.entity_id.clone());
+            subs.push(sub);
+        }
+        // Split lines left out of the input are deleted with their transfers
+        for mut removed in old_subs.into_iter().filter(|s| !s.is_tombstone) {
+            if let Some(counterpart) = removed.transfer_transaction_id.as_deref() {
+                tombstone_transaction(budget, counterpart);
+            }
+            removed.is_tombstone = true;
+            budget.mark_dirty(removed.entity_id.clone());
+            subs.push(removed);
+        }
+        subs.extend(
+            before
+                .sub_transactions
+                .iter()
+                .flatten()
+                .filter(|s| s.is_tombstone)
+                .cloned(),
+        );
+        transaction.sub_transactions = Some(subs);
+    } else {
+        // No longer a split: drop the split lines and their transfers
+        if let Some(subs) = transaction.sub_transactions.as_mut() {
+            for sub in subs.iter_mut().filter(|s| !s.is_tombstone) {
+                sub.is_tombstone = true;
+                budget.mark_dirty(sub.entity_id.clone());
+                if let Some(counterpart) = sub.transfer_transaction_id.clone() {
+                    tombstone_transaction(budget, &counterpart);
+                }
+            }
+        }
+
+        match input.transfer_account_id {
+            Some(other_account_id) => {
+                let (this_category, other_category) = transfer_categories(
+                    budget,
+                    &account.entity_id,
+                    &other_account_id,
+                    input.category_id,
+                )?;
+                let counterpart_id = write_counterpart(
+                    budget,
+                    Counterpart {
+                        existing_id: old_pair,
+                        account_id: other_account_id.clone(),
+                        source_account_id: account.entity_id.clone(),
+                        source_id: transaction.entity_id.clone(),
+                        date: transaction.date.clone(),
+                        amount: -transaction.amount,
+                        memo: transaction.memo.clone(),
+                        category_id: other_category,
+                    },
+                );
+                transaction.category_id = this_category;
+                transaction.payee_id = Some(transfer_payee_id(budget, &other_account_id));
+                transaction.target_account_id = Some(other_account_id);
+                transaction.transfer_transaction_id = Some(counterpart_id);
+            }
+            None => {
+                if input.category_id.as_deref() == Some(SPLIT_CATEGORY_ID) {
+                    return Err("A split transaction needs its split lines".to_string());
+                }
+                validate_category(budget, input.category_id.as_deref())?;
+                if input
+                    .payee_id
+                    .as_deref()
+                    .is_some_and(|p| p.starts_with(TRANSFER_PAYEE_PREFIX))
+                {
+                    return Err("Use transferAccountId to make a transfer".to_string());
+                }
+                if let Some(pair) = old_pair {
+                    tombstone_transaction(budget, &pair);
+                }
+                transaction.category_id = input.category_id;
+                transaction.payee_id = input.payee_id;
+                transaction.target_account_id = None;
+                transaction.transfer_transaction_id = None;
+            }
+        }
+    }
+
+    budget.mark_dirty(transaction.entity_id.clone());
+    budget.data.transactions[idx] = transaction;
+    Ok(())
+}
+
+/// Run `edit` and report every entity it touched
+fn tracked(
+    budget: &mut Budget,
+    edit: impl FnOnce(&mut Budget) -> Result<String, String>,
+) -> Result<TransactionChange, String> {
+    let dirty_before = std::mem::take(&mut budget.dirty);
+    let snapshot = budget.data.clone();
+    match edit(budget) {
+        Ok(transaction_id) => {
+            let changed_ids = budget.dirty.iter().cloned().collect();
+            budget.dirty.extend(dirty_before);
+            Ok(TransactionChange {
+                transaction_id,
+                changed_ids,
+            })
+        }
+        Err(e) => {
+            // Leave the budget as it was when validation fails half way
+            budget.data = snapshot;
+            budget.dirty = dirty_before;
+            Err(e)
+        }
+    }
+}
+
+pub fn create_transaction(
+    budget: &mut Budget,
+    input: TransactionInput,
+) -> Result<TransactionChange, String> {
+    tracked(budget, |budget| {
+        let transaction = Transaction {
+            entity_id: new_entity_id(),
+            entity_type: "transaction".to_string(),
+            cleared: UNCLEARED.to_string(),
+            accepted: true,
+            ..Default::default()
+        };
+        let id = transaction.entity_id.clone();
+        budget.data.transactions.push(transaction);
+        apply_input(budget, &id, input)?;
+        Ok(id)
+    })
+}
+
+pub fn update_transaction(
+    budget: &mut Budget,
+    transaction_id: &str,
+    input: TransactionInput,
+) -> Result<TransactionChange, String> {
+    tracked(budget, |budget| {
+        let transaction = budget
+            .data
+            .transaction(transaction_id)
+            .filter(|t| !t.is_tombstone)
+            .cloned()
+            .ok_or_else(|| format!("Transaction not found: {}", transaction_id))?;
+
+        if let TransferRole::SplitCounterpart(parent_id) = transfer_role(budget, &transaction) {
+            // Amount, date and account belong to the split line; only this
+            // side's own details can change here
+            let locked = input.account_id != transaction.account_id
+                || input.date != transaction.date
+                || round_amount(input.amount) != round_amount(transaction.amount)
+                || input.sub_transactions.is_some()
+                || input.transfer_account_id.as_deref() != transaction.target_account_id.as_deref();
+            if locked {
+                return Err(format!(
+                    "This transfer comes from a split line; edit transaction {} instead",
+                    parent_id
+                ));
+            }
+            if let Some(cleared) = input.cleared.as_deref() {
+                check_cleared(cleared)?;
+            }
+            let target = budget
+                .data
+                .transactions
+                .iter_mut()
+                .find(|t| t.entity_id == transaction_id)
+                .ok_or("Transaction disappeared")?;
+            target.memo = input.memo;
+            target.flag = input.flag;
+            target.check_number = input.check_number;
+            if let Some(cleared) = input.cleared {
+                target.cleared = cleared;
+            }
+            budget.mark_dirty(transaction_id);
+            return Ok(transaction_id.to_string());
+        }
+
+        apply_input(budget, transaction_id, input)?;
+        Ok(transaction_id.to_string())
+    })
+}
+
+pub fn delete_transaction(
+    budget: &mut Budget,
+    transaction_id: &str,
+) -> Result<TransactionChange, String> {
+    tracked(budget, |budget| {
+        let transaction = budget
+            .data
+            .transaction(transaction_id)
+            .filter(|t| !t.is_tombstone)
+            .cloned()
+            .ok_or_else(|| format!("Transaction not found: {}", transaction_id))?;
+
+        match transfer_role(budget, &transaction) {
+            TransferRole::SplitCounterpart(parent_id) => {
+                return Err(format!(
+                    "This transfer comes from a split line; edit transaction {} instead",
+                    parent_id
+                ));
+            }
+            TransferRole::Pair(pair) => tombstone_transaction(budget, &pair),
+            TransferRole::None => {}
+        }
+        for sub in transaction.live_sub_transactions() {
+            if let Some(counterpart) = sub.transfer_transaction_id.as_deref() {
+                tombstone_transaction(budget, counterpart);
+            }
+        }
+        tombstone_transaction(budget, transaction_id);
+        Ok(transaction_id.to_string())
+    })
+}
+
+/// How a bulk edit changes memos
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "mode", content = "text", rename_all = "camelCase")]
+pub enum MemoPatch {
+    Append(String),
+    Replace(String),
+}
+
+/// Changes applied to every transaction of a bulk edit; missing fields stay as they are
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionPatch {
+    #[serde(default)]
+    pub category_id: Option<String>,
+    #[serde(default)]
+    pub payee_id: Option<String>,
+    /// Flag color; an empty string removes the flag
+    #[serde(default)]
+    pub flag: Option<String>,
+    #[serde(default)]
+    pub cleared: Option<String>,
+    #[serde(default)]
+    pub memo: Option<MemoPatch>,
+    /// Days to move the date by (negative moves it back)
+    #[serde(default)]
+    pub date_shift_days: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkRowResult {
+    pub transaction_id: String,
+    pub ok: bool,
+    pub error: Option<String>,
+    pub changed_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkUpdateResult {
+    pub updated_count: usize,
+    pub failed_count: usize,
+    pub rows: Vec<BulkRowResult>,
+}
+
+fn shift_date(date: &str, days: i64) -> Result<String, String> {
+    let parsed = chrono::NaiveDate::parse_from_str(date.get(..10).unwrap_or(date), "%Y-%m-%d")
+        .map_err(|e| format!("Invalid date {}: {}", date, e))?;
+    parsed
+        .checked_add_signed(chrono::Duration::days(days))
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .ok_or_else(|| format!("Cannot move {} by {} days", date, days))
+}
+
+/// Check that `patch` can be applied to the transaction; returns the other
+/// transactions whose date must follow a date shift
+fn validate_patch(
+    budget: &Budget,
+    transaction: &Transaction,
+    patch: &TransactionPatch,
+) -> Result<Vec<String>, String> {
+    let role = transfer_role(budget, transaction);
+    let account = live_account(budget, &transaction.account_id)?;
+
+    if let Some(category_id) = patch.category_id.as_deref() {
+        if transaction.is_split() {
+            return Err("Split transactions keep their categories on the split lines".to_string());
+        }
+        if category_id == SPLIT_CATEGORY_ID {
+            return Err("Use transaction editing to split a transaction".to_string());
+        }
+        if !account.on_budget {
+            return Err("Off-budget transactions have no category".to_string());
+        }
+        if !matches!(role, TransferRole::None) {
+            let other_on = transaction
+                .transfer_account_id()
+                .and_then(|id| budget.data.account(id))
+                .is_some_and(|a| a.on_budget);
+            if other_on {
+                return Err(
+                    "A transfer between on-budget accounts cannot take a category".to_string(),
+                );
+            }
+        }
+        validate_category(budget, Some(category_id))?;
+    }
+
+    if let Some(payee_id) = patch.payee_id.as_deref() {
+        if !matches!(role, TransferRole::None) {
+            return Err("The payee of a transfer is its other account".to_string());
+        }
+        if payee_id.starts_with(TRANSFER_PAYEE_PREFIX) {
+            return Err("Use transaction editing to make a transfer".to_string());
+        }
+        budget
+            .data
+            .payee(payee_id)
+            .filter(|p| !p.is_tombstone)
+            .ok_or_else(|| format!("Payee not found: {}", payee_id))?;
+    }
+
+    if let Some(cleared) = patch.cleared.as_deref() {
+        check_cleared(cleared)?;
+    }
+
+    let mut followers = Vec::new();
+    if let Some(days) = patch.date_shift_days.filter(|d| *d != 0) {
+        shift_date(&transaction.date, days)?;
+        match role {
+            TransferRole::SplitCounterpart(parent_id) => {
+                return Err(format!(
+                    "This transfer comes from a split line; move transaction {} instead",
+                    parent_id
+                ));
+            }
+            TransferRole::Pair(pair) => followers.push(pair),
+            TransferRole::None => {}
+        }
+        followers.extend(
+            transaction
+                .live_sub_transactions()
+                .filter_map(|s| s.transfer_transaction_id.clone()),
+        );
+    }
+    Ok(followers)
+}
+
+/// Apply one patch to many transactions. Each row is validated on its own;
+/// rows that fail are left untouched and reported. Date shifts and memos
+/// carry over to transfer counterparts, which are not changed twice when they
+/// are in the list as well. Changes are only marked dirty, so the next save
+/// writes them all as one ydiff.
+pub fn bulk_update_transactions(
+    budget: &mut Budget,
+    transaction_ids: &[String],
+    patch: &TransactionPatch,
+) -> BulkUpdateResult {
+    // A split counterpart can only move with its parent, so when both are
+    // listed it waits until the parent has run, whatever the order of the ids
+    let shifting = patch.date_shift_days.is_some_and(|d| d != 0);
+    let (deferred, first): (Vec<usize>, Vec<usize>) = (0..transaction_ids.len()).partition(|&i| {
+        shifting
+            && budget
+                .data
+                .transaction(&transaction_ids[i])
+                .filter(|t| !t.is_tombstone)
+                .is_some_and(|t| match transfer_role(budget, t) {
+                    TransferRole::SplitCounterpart(parent_id) => {
+                        transaction_ids.contains(&parent_id)
+                    }
+                    _ => false,
+                })
+    });
+
+    let mut results: Vec<Option<BulkRowResult>> = vec![None; transaction_ids.len()];
+    // Transfers whose date or memo already changed along with their other side
+    let mut shifted: Vec<String> = Vec::new();
+    let mut memo_followed: Vec<String> = Vec::new();
+    for i in first.into_iter().chain(deferred) {
+        results[i] = Some(apply_patch(
+            budget,
+            &transaction_ids[i],
+            patch,
+            &mut shifted,
+            &mut memo_followed,
+        ));
+    }
+
+    let rows: Vec<BulkRowResult> = results.into_iter().flatten().collect();
+    let updated_count = rows.iter().filter(|r| r.ok).count();
+    BulkUpdateResult {
+        updated_count,
+        failed_count: rows.len() - updated_count,
+        rows,
+    }
+}
+
+/// Apply the patch to one row of a bulk edit, skipping what already followed
+/// the other side of a transfer
+fn apply_patch(
+    budget: &mut Budget,
+    id: &str,
+    patch: &TransactionPatch,
+    shifted: &mut Vec<String>,
+    memo_followed: &mut Vec<String>,
+) -> BulkRowResult {
+    // What already followed the other side is not applied twice
+    let mut own = patch.clone();
+    if shifted.iter().any(|s| s == id) {
+        own.date_shift_days = None;
+    }
+    if memo_followed.iter().any(|s| s == id) {
+        own.memo = None;
+    }
+    let checked = budget
+        .data
+        .transaction(id)
+        .filter(|t| !t.is_tombstone)
+        .ok_or_else(|| format!("Transaction not found: {}", id))
+        .and_then(|t| Ok((validate_patch(budget, t, &own)?, transfer_role(budget, t))));
+    let (followers, role) = match checked {
+        Ok(checked) => checked,
+        Err(e) => {
+            return BulkRowResult {
+                transaction_id: id.to_string(),
+                ok: false,
+                error: Some(e),
+                changed_ids: Vec::new(),
+            };
+        }
+    };
+
+    let mut changed = vec![id.to_string()];
+    let shift = own.date_shift_days.filter(|d| *d != 0);
+    let mut memo = None;
+    if let Some(transaction) = budget
+        .data
+        .transactions
+        .iter_mut()
+        .find(|t| t.entity_id == id)
+    {
+        if let Some(category_id) = &own.category_id {
+            transaction.category_id = Some(category_id.clone());
+        }
+        if let Some(payee_id) = &own.payee_id {
+            transaction.payee_id = Some(payee_id.clone());
+        }
+        if let Some(flag) = &own.flag {
+            transaction.flag = (!flag.is_empty()).then(|| flag.clone());
+        }
+        if let Some(cleared) = &own.cleared {
+            transaction.cleared = cleared.clone();
+        }
+        match &own.memo {
+            Some(MemoPatch::Replace(text)) => {
+                transaction.memo = (!text.is_empty()).then(|| text.clone());
+                memo = Some(transaction.memo.clone());
+            }
+            Some(MemoPatch::Append(text)) if !text.is_empty() => {
+                transaction.memo = Some(match transaction.memo.as_deref() {
+                    Some(memo) if !memo.is_empty() => format!("{} {}", memo, text),
+                    _ => text.clone(),
+                });
+                memo = Some(transaction.memo.clone());
+            }
+            _ => {}
+        }
+        if let Some(days) = shift {
+            // Validated above, so the shift cannot fail here
+            if let Ok(date) = shift_date(&transaction.date, days) {
+                transaction.date = date;
+            }
+        }
+    }
+    // Both sides of a transfer keep the same memo
+    if let (Some(memo), TransferRole::Pair(pair)) = (memo, &role) {
+        if let Some(other) = budget
+            .data
+            .transactions
+            .iter_mut()
+            .find(|t| &t.entity_id == pair)
+        {
+            other.memo = memo;
+            if !changed.contains(pair) {
+                changed.push(pair.clone());
+            }
+            memo_followed.push(pair.clone());
+        }
+    }
+    if let Some(days) = shift {
+        for follower in followers {
+            if let Some(other) = budget
+                .data
+                .transactions
+                .iter_mut()
+                .find(|t| t.entity_id == follower && !t.is_tombstone)
+            {
+                if let Ok(date) = shift_date(&other.date, days) {
+                    other.date = date;
+                }
+                if !changed.contains(&follower) {
+                    changed.push(follower.clone());
+                }
+                shifted.push(follower);
+            }
+        }
+    }
+    for changed_id in &changed {
+        budget.mark_dirty(changed_id.clone());
+    }
+    BulkRowResult {
+        transaction_id: id.to_string(),
+        ok: true,
+        error: None,
+        changed_ids: changed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ynab4::fixtures;
+
+    #[test]
+    fn transfers_keep_both_sides_in_sync() {
+        let path = fixtures::budget_folder("transactions-transfers");
+        let mut budget = Budget::load(&path).unwrap();
+        budget.attach_device(None).unwrap();
+        let input = TransactionInput {
+            account_id: "ACC-CHK".to_string(),
+            date: "2024-02-03".to_string(),
+            amount: -100.0,
+            transfer_account_id: Some("ACC-CC".to_string()),
+            ..Default::default()
+        };
+        let change = create_transaction(&mut budget, input.clone()).unwrap();
+        let transaction = budget
+            .data
+            .transaction(&change.transaction_id)
+            .unwrap()
+            .clone();
+        let other_id = transaction.transfer_transaction_id.clone().unwrap();
+        let other = budget.data.transaction(&other_id).unwrap();
+        assert_eq!((other.account_id.as_str(), other.amount), ("ACC-CC", 100.0));
+        assert_eq!(other.payee_id.as_deref(), Some("Payee/Transfer:ACC-CHK"));
+
+        // Between on-budget accounts there is no category
+        let mut categorized = input.clone();
+        categorized.category_id = Some("CAT-FOOD".to_string());
+        let count = budget.data.transactions.len();
+        assert!(create_transaction(&mut budget, categorized).is_err());
+        assert_eq!(budget.data.transactions.len(), count);
+
+        // Moving it off budget needs a category on the on-budget side
+        let mut off_budget = input;
+        off_budget.transfer_account_id = Some("ACC-INV".to_string());
+        assert!(
+            update_transaction(&mut budget, &transaction.entity_id, off_budget.clone()).is_err()
+        );
+        off_budget.category_id = Some("CAT-FUN".to_string());
+        off_budget.amount = -80.0;
+        update_transaction(&mut budget, &transaction.entity_id, off_budget).unwrap();
+        let other = budget.data.transaction(&other_id).unwrap();
+        assert_eq!((other.account_id.as_str(), other.amount), ("ACC-INV", 80.0));
+        assert_eq!(other.category_id, None);
+
+        let deleted = delete_transaction(&mut budget, &transaction.entity_id).unwrap();
+        assert!(deleted.changed_ids.contains(&other_id));
+        budget.save_changes().unwrap().unwrap();
+        let reloaded = Budget::load(&path).unwrap();
+        assert!(reloaded.data.transaction(&other_id).unwrap().is_tombstone);
+    }
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn bulk_edits_carry_over_to_transfer_counterparts() {
+        let mut budget = Budget::load(&fixtures::budget_folder("transactions-bulk")).unwrap();
+        let patch: TransactionPatch = serde_json::from_str(
+            r##"{"memo":{"mode":"append","text":"#trip"},"dateShiftDays":2}"##,
+        )
+        .unwrap();
+        let result = bulk_update_transactions(&mut budget, &ids(&["T-5", "T-6"]), &patch);
+        assert_eq!((result.updated_count, result.failed_count), (2, 0));
+        for id in ["T-5", "T-6"] {
+            let transaction = budget.data.transaction(id).unwrap();
+            assert_eq!(
+                (transaction.date.as_str(), transaction.memo.as_deref()),
+                ("2024-01-12", Some("#trip"))
+            );
+        }
+
+        // Only one side selected: the memo still follows
+        let patch: TransactionPatch =
+            serde_json::from_str(r#"{"memo":{"mode":"replace","text":"card"}}"#).unwrap();
+        bulk_update_transactions(&mut budget, &ids(&["T-6"]), &patch);
+        assert_eq!(
+            budget.data.transaction("T-5").unwrap().memo.as_deref(),
+            Some("card")
+        );
+        assert!(budget.dirty.contains("T-5"));
+    }
+
+    #[test]
+    fn bulk_date_shift_skips_split_counterparts_already_moved() {
+        let mut budget = Budget::load(&fixtures::budget_folder("transactions-bulk-split")).unwrap();
+        let mut input = TransactionInput::from_transaction(budget.data.transaction("T-7").unwrap());
+        input.sub_transactions = Some(vec![
+            SubTransactionInput {
+                amount: -200.0,
+                category_id: Some("CAT-RENT".to_string()),
+                ..Default::default()
+            },
+            SubTransactionInput {
+                amount: -100.0,
+                transfer_account_id: Some("ACC-CC".to_string()),
+                ..Default::default()
+            },
+        ]);
+        update_transaction(&mut budget, "T-7", input).unwrap();
+        let counterpart = budget
+            .data
+            .transaction("T-7")
+            .unwrap()
+            .live_sub_transactions()
+            .find_map(|s| s.transfer_transaction_id.clone())
+            .unwrap();
+
+        let patch: TransactionPatch = serde_json::from_str(r#"{"dateShiftDays":-1}"#).unwrap();
+        let result =
+            bulk_update_transactions(&mut budget, std::slice::from_ref(&counterpart), &patch);
+        assert!(
+            !result.rows[0].ok,
+            "a split counterpart cannot move on its own"
+        );
+        let result = bulk_update_transactions(
+            &mut budget,
+            &["T-7".to_string(), counterpart.clone()],
+            &patch,
+        );
+        assert_eq!((result.updated_count, result.failed_count), (2, 0));
+        assert_eq!(budget.data.transaction("T-7").unwrap().date, "2024-02-02");
+        assert_eq!(
+            budget.data.transaction(&counterpart).unwrap().date,
+            "2024-02-02"
+        );
+
+        // Listed before its parent, the counterpart still moves once and takes the other fields
+        let patch: TransactionPatch =
+            serde_json::from_str(r#"{"dateShiftDays":-1,"flag":"Red"}"#).unwrap();
+        let result = bulk_update_transactions(
+            &mut budget,
+            &[counterpart.clone(), "T-7".to_string()],
+            &patch,
+        );
+        assert_eq!((result.updated_count, result.failed_count), (2, 0));
+        assert_eq!(result.rows[0].transaction_id, counterpart);
+        assert_eq!(budget.data.transaction("T-7").unwrap().date, "2024-02-01");
+        let moved = budget.data.transaction(&counterpart).unwrap();
+        assert_eq!(moved.date, "2024-02-01");
+        assert_eq!(moved.flag.as_deref(), Some("Red"));
+    }
+}
@@ -3,8 +3,6 @@
  * Handles CSV and Excel parsing with extended fields
  */
 
-import * as XLSX from 'xlsx';
-
 export interface ImportTransaction {
   id: string;
   date: string;
@@ -33,6 +31,31 @@ export interface ImportTransaction {
   msiOriginalAmount: number;
   // Status
   status: 'pending' | 'ready' | 'imported' | 'skipped';
+  // Only set by some importers
+  checkNumber?: string;
+  reconciled?: boolean; // Reconciled in the source, as in a YNAB4 register export
+  valueDate?: string; // Date the money moved, if different from the booking date
+  transferAccountName?: string; // Other account of a transfer, as named in the file
+  splits?: ImportSplit[];
+  match?: ImportMatch; // Set by duplicate detection
+}
+
+/**
+ * What duplicate detection found in the register for a row
+ */
+export interface ImportMatch {
+  kind: 'new' | 'duplicate' | 'probable'; // probable: a transaction to clear instead, once the user confirms
+  transactionId: string | null;
+  confidence: number; // 0 to 1
+  reason: string;
+}
+
+export interface ImportSplit {
+  amount: number;
+  categoryId: string | null;
+  categoryName: string;
+  memo: string;
+  transferAccountName?: string;
 }
 
 export interface ImportFile {
@@ -82,6 +105,560 @@ export function parseCSV(content: string, delimiter = ','): string[][] {
   return rows;
 }
 
+/**
+ * Cell typed by the Rust statement parser
+ */
+export type StatementCell =
+  | { type: 'empty' }
+  | { type: 'number'; value: number }
+  | { type: 'date'; value: string }
+  | { type: 'text'; value: string };
+
+export interface StatementRow {
+  line: number; // Line in the file where the row starts
+  cells: string[];
+  values: StatementCell[];
+}
+
+/**
+ * What the Rust parser detected about a statement file, with the reasons
+ */
+export interface StatementDetection {
+  encoding: string;
+  encodingEvidence: string;
+  decodeErrors: boolean;
+  delimiter: string;
+  delimiterEvidence: string;
+  quote: string | null;
+  quoteEvidence: string;
+  headerLine: number | null;
+  headerEvidence: string;
+  skippedLines: number[];
+  raggedLines: number[];
+}
+
+/**
+ * Format the Rust parser picked for a whole column
+ */
+export interface StatementColumn {
+  index: number;
+  kind: 'empty' | 'text' | 'date' | 'number';
+  format?: unknown; // DateFormat name or { decimal, thousands }
+  pattern: string | null; // e.g. "dd-MMM-yy" or "1.234,56"
+  matched: number;
+  total: number;
+  evidence: string;
+}
+
+/**
+ * Cell that doesn't fit its column's format
+ */
+export interface StatementError {
+  line: number;
+  column: number;
+  header: string;
+  value: string;
+  message: string;
+}
+
+export interface ParsedStatement {
+  headers: string[];
+  rows: StatementRow[];
+  columns: StatementColumn[];
+  errors: StatementError[];
+  detection: StatementDetection;
+}
+
+/**
+ * Parse a CSV/TSV statement with the Rust backend, which detects the encoding
+ * (UTF-8, Windows-1252, UTF-16), delimiter, quotes and header row.
+ * Only works in Tauri.
+ */
+export async function parseStatementFile(path: string, options?: StatementOptions): Promise<ParsedStatement> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<ParsedStatement>('parse_statement_file', { path, options: options ?? null });
+}
+
+/**
+ * Choices from an import profile that replace what the parser detects
+ */
+export interface StatementOptions {
+  skipRows?: number;
+  dateFormat?: DateFormat | null;
+  numberFormat?: NumberFormatSpec | null;
+}
+
+export interface NumberFormatSpec {
+  decimal: string;
+  thousands: string | null;
+}
+
+/**
+ * A number format written out as 1,234.56, the way the profile form shows it
+ */
+export function numberFormatExample(format: NumberFormatSpec): string {
+  return `1${format.thousands ?? ''}234${format.decimal}56`;
+}
+
+/**
+ * Read back a number format written as 1,234.56; null when it doesn't look like one
+ */
+export function parseNumberFormatExample(example: string): NumberFormatSpec | null {
+  const match = /^1(\D?)234(\D)56$/.exec(example.trim());
+  if (!match || match[1] === match[2]) return null;
+  return { decimal: match[2], thousands: match[1] || null };
+}
+
+/**
+ * Header plus data rows, in the shape returned by parseCSV.
+ * Dates and amounts come already normalized (YYYY-MM-DD, 1234.56) so the
+ * per-cell guessing in parseDate/parseAmount never kicks in; rows with cells
+ * that don't fit their column's format are left out (see statement.errors).
+ */
+export function statementToRows(statement: ParsedStatement): string[][] {
+  const badLines = new Set(statement.errors.map((error) => error.line));
+  const rows = statement.rows
+    .filter((row) => !badLines.has(row.line))
+    .map((row) =>
+      row.values.map((value, index) => {
+        if (value.type === 'date') return value.value;
+        if (value.type === 'number') return String(value.value);
+        return row.cells[index];
+      })
+    );
+  return [statement.headers, ...rows];
+}
+
+/**
+ * Short message listing the lines that couldn't be read, or null if none
+ */
+export function describeStatementErrors(statement: ParsedStatement): string | null {
+  if (statement.errors.length === 0) return null;
+  const lines = [...new Set(statement.errors.map((error) => error.line))];
+  const shown = lines.slice(0, 5).join(', ');
+  const more = lines.length > 5 ? ` y ${lines.length - 5} más` : '';
+  return `${lines.length} filas no coinciden con el formato de fecha o monto (líneas ${shown}${more})`;
+}
+
+export interface StatementBalance {
+  amount: number;
+  date: string | null;
+}
+
+/**
+ * One account's statement from an OFX/QFX file
+ */
+export interface OfxStatement {
+  account: {
+    bankId: string | null;
+    accountId: string;
+    accountType: string; // CHECKING, SAVINGS, CREDITCARD...
+    currency: string | null;
+  };
+  matchedAccountId: string | null; // Budget account with the same last digits
+  startDate: string | null;
+  endDate: string | null;
+  ledgerBalance: StatementBalance | null;
+  availableBalance: StatementBalance | null;
+  transactions: ImportTransaction[];
+  errors: string[];
+}
+
+/**
+ * Parse an OFX 1.x/2.x (or QFX) file with the Rust backend.
+ * With a local budget path, statements are matched to budget accounts.
+ * Only works in Tauri.
+ */
+export async function parseOfxFile(path: string, budgetPath: string | null): Promise<OfxStatement[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<OfxStatement[]>('parse_ofx_file', { path, budgetPath });
+}
+
+/**
+ * Regex template for bank SMS/email notifications. Named groups: amount
+ * (required), merchant, card, date, reference.
+ */
+export interface NotificationTemplate {
+  id: string;
+  name: string;
+  pattern: string;
+  inflow: boolean;
+  dateFormat: DateFormat | null;
+  accountId: string | null;
+  builtIn: boolean;
+}
+
+export interface NotificationParse {
+  matches: Array<{
+    templateId: string;
+    templateName: string;
+    card: string | null;
+    transaction: ImportTransaction;
+  }>;
+  unmatched: string[]; // Pieces of text no template recognised
+}
+
+/**
+ * Read pasted bank notifications into draft rows. Only works in Tauri.
+ */
+export async function parseNotificationText(text: string, budgetPath: string | null): Promise<NotificationParse> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<NotificationParse>('parse_notification_text', { text, budgetPath });
+}
+
+export async function listNotificationTemplates(budgetPath: string): Promise<NotificationTemplate[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<NotificationTemplate[]>('list_notification_templates', { budgetPath });
+}
+
+export async function saveNotificationTemplate(
+  budgetPath: string,
+  template: Partial<NotificationTemplate>
+): Promise<NotificationTemplate> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<NotificationTemplate>('save_notification_template', { budgetPath, template });
+}
+
+export async function deleteNotificationTemplate(budgetPath: string, templateId: string): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('delete_notification_template', { budgetPath, templateId });
+}
+
+/**
+ * Date orders understood by the Rust parsers
+ */
+export type DateFormat = 'ymd' | 'dmy' | 'mdy' | 'dayMonthName' | 'monthNameDay';
+
+export interface QifFile {
+  accounts: Array<{
+    name: string | null;
+    accountType: string; // Bank, Cash, CCard, Oth A, Oth L
+    description: string | null;
+    transactions: ImportTransaction[];
+  }>;
+  dateFormat: DateFormat;
+  dateEvidence: string;
+  errors: string[];
+}
+
+/**
+ * Parse a QIF file with the Rust backend. The date format is inferred unless
+ * given. Only works in Tauri.
+ */
+export async function parseQifFile(
+  path: string,
+  budgetPath: string | null,
+  dateFormat: DateFormat | null = null
+): Promise<QifFile> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<QifFile>('parse_qif_file', { path, dateFormat, budgetPath });
+}
+
+/**
+ * YNAB4's own Register.csv or Budget.csv, as read by the backend
+ */
+export type Ynab4ExportFile =
+  | {
+      kind: 'register';
+      transactions: ImportTransaction[];
+      accounts: string[];
+      missingAccounts: string[];
+      missingCategories: string[];
+      pairedTransfers: number; // Transfers listed in both accounts, kept once
+      dateFormat: DateFormat;
+      errors: string[];
+    }
+  | {
+      kind: 'budget';
+      rows: Array<{ month: string; masterCategory: string; subCategory: string; budgeted: number }>;
+      months: string[]; // YYYY-MM
+      errors: string[];
+    };
+
+export interface BudgetRestoreResult {
+  monthCount: number;
+  updatedCount: number;
+  unchangedCount: number;
+  skippedCount: number; // Hidden categories the budget doesn't have
+  createdMasterCategoryIds: string[];
+  createdCategoryIds: string[];
+  ydiffPath: string | null;
+  errors: string[];
+}
+
+/**
+ * Read a CSV as a YNAB4 export; null when it is some other CSV. Only works
+ * in Tauri.
+ */
+export async function parseYnab4Export(path: string, budgetPath: string | null): Promise<Ynab4ExportFile | null> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<Ynab4ExportFile | null>('parse_ynab4_export', { path, budgetPath });
+}
+
+/**
+ * Put the budgeted amounts of a YNAB4 Budget.csv back into the budget
+ */
+export async function restoreYnab4Budget(budgetPath: string, path: string): Promise<BudgetRestoreResult> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<BudgetRestoreResult>('restore_ynab4_budget', { budgetPath, path });
+}
+
+/**
+ * Export an account's register to a QIF file chosen by the user.
+ * Returns the number of transactions written, or null if cancelled.
+ */
+export async function exportAccountQif(
+  budgetPath: string,
+  accountId: string,
+  accountName: string,
+  dateFormat: DateFormat = 'mdy'
+): Promise<number | null> {
+  const { save } = await import('@tauri-apps/plugin-dialog');
+  const path = await save({
+    defaultPath: `${accountName}.qif`,
+    filters: [{ name: 'QIF', extensions: ['qif'] }],
+  });
+  if (!path) return null;
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<number>('export_account_qif', { budgetPath, accountId, path, dateFormat });
+}
+
+export type ExportColumn =
+  | 'account'
+  | 'flag'
+  | 'checkNumber'
+  | 'date'
+  | 'payee'
+  | 'category'
+  | 'masterCategory'
+  | 'subCategory'
+  | 'memo'
+  | 'outflow'
+  | 'inflow'
+  | 'amount'
+  | 'cleared'
+  | 'runningBalance';
+
+/**
+ * What `exportTransactions` writes. Empty lists don't filter; number and
+ * date formats default to the budget's locale.
+ */
+export interface ExportOptions {
+  format?: 'csv' | 'xlsx'; // Taken from the chosen file name when missing
+  filter?: {
+    accountIds?: string[];
+    from?: string | null; // YYYY-MM-DD, included
+    to?: string | null;
+    categoryIds?: string[];
+    payeeIds?: string[];
+  };
+  columns?: ExportColumn[]; // Empty for date, account, payee, category, memo, amount, cleared, balance
+  ynab4Order?: boolean; // YNAB4's own Register.csv layout, splits expanded
+  splitMode?: 'combined' | 'expanded';
+  numberFormat?: NumberFormatSpec | null;
+  dateFormat?: DateFormat | null;
+}
+
+export interface ExportResult {
+  path: string;
+  rowCount: number;
+  transactionCount: number;
+}
+
+/**
+ * Export transactions to a CSV or XLSX file chosen by the user.
+ * Returns null if cancelled.
+ */
+export async function exportTransactions(
+  budgetPath: string,
+  options: ExportOptions,
+  fileName = 'transacciones'
+): Promise<ExportResult | null> {
+  const { save } = await import('@tauri-apps/plugin-dialog');
+  const path = await save({
+    defaultPath: `${fileName}.${options.format ?? 'csv'}`,
+    filters: [
+      { name: 'CSV', extensions: ['csv'] },
+      { name: 'Excel', extensions: ['xlsx'] },
+    ],
+  });
+  if (!path) return null;
+  const format = options.format ?? (path.toLowerCase().endsWith('.xlsx') ? 'xlsx' : 'csv');
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<ExportResult>('export_transactions', { budgetPath, path, options: { ...options, format } });
+}
+
+export type LedgerDialect = 'ledger' | 'hledger' | 'beancount';
+
+export interface LedgerOptions {
+  dialect: LedgerDialect;
+  commodity?: string | null; // Currency code; from the budget's locale when missing
+  includeBudget?: boolean; // Monthly budgeted amounts as budget entries
+}
+
+export interface LedgerExportResult {
+  path: string;
+  transactionCount: number;
+  accountCount: number;
+  assertionCount: number; // Balance assertions at reconciliation points
+  mismatchCount: number; // Reconciliations whose recorded statement balance the journal does not reach
+}
+
+const LEDGER_EXTENSIONS: Record<LedgerDialect, string> = {
+  ledger: 'ledger',
+  hledger: 'journal',
+  beancount: 'beancount',
+};
+
+/**
+ * Export the whole budget as a ledger, hledger or beancount journal chosen
+ * by the user. Returns null if cancelled.
+ */
+export async function exportLedger(
+  budgetPath: string,
+  options: LedgerOptions,
+  fileName = 'presupuesto'
+): Promise<LedgerExportResult | null> {
+  const extension = LEDGER_EXTENSIONS[options.dialect];
+  const { save } = await import('@tauri-apps/plugin-dialog');
+  const path = await save({
+    defaultPath: `${fileName}.${extension}`,
+    filters: [{ name: options.dialect, extensions: [extension] }],
+  });
+  if (!path) return null;
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<LedgerExportResult>('export_ledger', { budgetPath, path, options });
+}
+
+/**
+ * A camt.053 or MT940 statement with its balances
+ */
+export interface BankStatement {
+  statementId: string;
+  account: string; // IBAN or account number
+  currency: string | null;
+  matchedAccountId: string | null; // Budget account with the same last digits
+  openingBalance: StatementBalance | null;
+  closingBalance: StatementBalance | null;
+  transactions: ImportTransaction[];
+  errors: string[]; // Unreadable entries and balance mismatches
+}
+
+/**
+ * Parse a camt.053 XML statement with the Rust backend. Only works in Tauri.
+ */
+export async function parseCamtFile(path: string, budgetPath: string | null): Promise<BankStatement[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<BankStatement[]>('parse_camt_file', { path, budgetPath });
+}
+
+/**
+ * Parse an MT940 statement with the Rust backend. Only works in Tauri.
+ */
+export async function parseMt940File(path: string, budgetPath: string | null): Promise<BankStatement[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<BankStatement[]>('parse_mt940_file', { path, budgetPath });
+}
+
+/**
+ * Start reconciling an account against a statement's closing balance.
+ * Returns false when the statement has no dated closing balance.
+ */
+export async function reconcileFromStatement(
+  budgetPath: string,
+  accountId: string,
+  statement: BankStatement
+): Promise<boolean> {
+  const balance = statement.closingBalance;
+  if (!balance?.date) return false;
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('reconcile_start', {
+    budgetPath,
+    accountId,
+    statementDate: balance.date,
+    statementBalance: balance.amount,
+  });
+  return true;
+}
+
+export interface CfdiInvoice {
+  uuid: string;
+  version: string;
+  invoiceType: string; // I income, E credit note, P payment, T transfer
+  issuerRfc: string;
+  issuerName: string;
+  receiverRfc: string;
+  receiverName: string;
+  date: string;
+  subtotal: number;
+  total: number;
+  currency: string;
+  exchangeRate: number | null;
+  paymentMethod: string | null; // PUE or PPD
+  paymentForm: string | null; // SAT code: 01 cash, 03 transfer, 04 credit card...
+  series: string | null;
+  folio: string | null;
+  concepts: Array<{
+    description: string;
+    quantity: number;
+    unitPrice: number;
+    amount: number;
+    productCode: string | null;
+  }>;
+}
+
+export interface CfdiMatch {
+  transactionId: string;
+  accountId: string;
+  date: string;
+  amount: number;
+  payeeName: string | null;
+  memo: string | null;
+  daysApart: number;
+  score: number; // 0 to 1
+}
+
+export interface CfdiImport {
+  invoice: CfdiInvoice;
+  row: ImportTransaction; // For invoices with no transaction yet
+  matches: CfdiMatch[]; // Best first; empty without a budget path
+}
+
+/**
+ * Parse a CFDI 3.3/4.0 invoice with the Rust backend and, with a local budget
+ * path, suggest the transactions that paid it. Only works in Tauri.
+ */
+export async function parseCfdiFile(path: string, budgetPath: string | null): Promise<CfdiImport> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<CfdiImport>('parse_cfdi_file', { path, budgetPath });
+}
+
+/**
+ * Attach an invoice to a transaction; with updateMemo its UUID goes in the memo
+ */
+export async function attachCfdi(
+  budgetPath: string,
+  transactionId: string,
+  invoice: CfdiInvoice,
+  updateMemo = true
+): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('attach_cfdi', { budgetPath, transactionId, invoice, updateMemo });
+}
+
+/**
+ * Compare rows with the budget's register. Exact duplicates come back skipped;
+ * every row gets a `match`. Only works in Tauri.
+ */
+export async function matchImportRows(
+  budgetPath: string,
+  rows: ImportTransaction[],
+  options?: { dateWindowDays?: number; minConfidence?: number }
+): Promise<ImportTransaction[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<ImportTransaction[]>('match_import_rows', { budgetPath, rows, options });
+}
+
 /**
  * Extended column mapping for CSV/Excel
  */
@@ -299,63 +876,324 @@ export function csvToTransactions(
 }
 
 /**
- * Parse Excel file to rows
+ * Payee and category suggested by the budget's rename rules
  */
-export function parseExcel(data: ArrayBuffer, sheetIndex = 0): { headers: string[]; rows: string[][] } {
-  const workbook = XLSX.read(data, { type: 'array' });
-  const sheetName = workbook.SheetNames[sheetIndex];
-  const worksheet = workbook.Sheets[sheetName];
-  
-  // Convert to array of arrays
-  const rawData: unknown[][] = XLSX.utils.sheet_to_json(worksheet, { header: 1 });
-  
-  // Convert all values to strings
-  const rows: string[][] = rawData.map(row => 
-    (row as unknown[]).map(cell => {
-      if (cell === null || cell === undefined) return '';
-      if (cell instanceof Date) {
-        return cell.toISOString().split('T')[0];
-      }
-      return String(cell).trim();
-    })
-  );
+export interface PayeeRuleSuggestion {
+  payeeId: string;
+  payeeName: string;
+  categoryId: string | null;
+  categoryName: string | null;
+  rule: string; // e.g. "Contains OXXO"
+}
+
+/**
+ * Fill suggestedPayee and suggestedCategory from the budget's payee rules
+ * (YNAB4 rename conditions plus regex rules), evaluated by the Rust backend.
+ * Only works in Tauri with a local budget; otherwise returns the rows unchanged.
+ */
+export async function applyPayeeRules(
+  transactions: ImportTransaction[],
+  budgetPath: string | null
+): Promise<ImportTransaction[]> {
+  if (!budgetPath || !isTauriRuntime() || transactions.length === 0) return transactions;
+
+  try {
+    const { invoke } = await import('@tauri-apps/api/core');
+    const suggestions = await invoke<Array<PayeeRuleSuggestion | null>>('payee_rules_suggest', {
+      budgetPath,
+      descriptions: transactions.map(t => t.description),
+    });
+
+    return transactions.map((tx, index) => {
+      const suggestion = suggestions[index];
+      if (!suggestion) return tx;
+      // Values that came from the file win over the rules
+      const payeeFromFile = tx.suggestedPayee && tx.suggestedPayee !== tx.description;
+      return {
+        ...tx,
+        suggestedPayee: payeeFromFile ? tx.suggestedPayee : suggestion.payeeName,
+        suggestedCategory: tx.suggestedCategory || suggestion.categoryName || '',
+      };
+    });
+  } catch (error) {
+    console.error('[ImportService] Error applying payee rules:', error);
+    return transactions;
+  }
+}
+
+export interface LearnedSuggestion {
+  payeeId: string | null;
+  payeeName: string | null;
+  payeeConfidence: number; // 0 to 1
+  categoryId: string | null;
+  categoryName: string | null;
+  categoryConfidence: number; // 0 to 1
+}
 
-  if (rows.length === 0) {
-    return { headers: [], rows: [] };
+/**
+ * Fill the suggestions the rules left empty from the classifier the backend
+ * learns from the budget's register. Suggestions below `minConfidence` are
+ * ignored. Only works in Tauri with a local budget.
+ */
+export async function applyLearnedSuggestions(
+  transactions: ImportTransaction[],
+  budgetPath: string | null,
+  minConfidence = 0.6
+): Promise<ImportTransaction[]> {
+  if (!budgetPath || !isTauriRuntime() || transactions.length === 0) return transactions;
+
+  try {
+    const { invoke } = await import('@tauri-apps/api/core');
+    const suggestions = await invoke<Array<LearnedSuggestion | null>>('classifier_suggest', {
+      budgetPath,
+      rows: transactions,
+    });
+
+    return transactions.map((tx, index) => {
+      const suggestion = suggestions[index];
+      if (!suggestion) return tx;
+      const payeeUnset = !tx.suggestedPayee || tx.suggestedPayee === tx.description;
+      return {
+        ...tx,
+        suggestedPayee:
+          payeeUnset && suggestion.payeeName && suggestion.payeeConfidence >= minConfidence
+            ? suggestion.payeeName
+            : tx.suggestedPayee,
+        suggestedCategory:
+          tx.suggestedCategory ||
+          (suggestion.categoryConfidence >= minConfidence ? suggestion.categoryName || '' : ''),
+      };
+    });
+  } catch (error) {
+    console.error('[ImportService] Error applying learned suggestions:', error);
+    return transactions;
   }
+}
 
-  return {
-    headers: rows[0],
-    rows: rows,
-  };
+/**
+ * Sheet names of an XLSX, XLS or ODS file, read by the Rust backend.
+ * Only works in Tauri.
+ */
+export async function listSpreadsheetSheets(path: string): Promise<string[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<string[]>('list_spreadsheet_sheets', { path });
 }
 
+type SpreadsheetPage = { sheet: string; headers: string[]; rows: string[][]; nextOffset: number | null };
+
 /**
- * Get sheet names from Excel file
+ * One page of a workbook sheet, read by the Rust backend. Row 0 of the first
+ * page is the header row. Only works in Tauri.
  */
-export function getExcelSheetNames(data: ArrayBuffer): string[] {
-  const workbook = XLSX.read(data, { type: 'array' });
-  return workbook.SheetNames;
+export async function readSpreadsheetPage(
+  path: string,
+  sheetIndex = 0,
+  skipRows = 0,
+  offset = 0,
+  limit: number | null = null
+): Promise<SpreadsheetPage> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<SpreadsheetPage>('read_spreadsheet', { path, sheetIndex, skipRows, offset, limit });
 }
 
 /**
- * Parse Excel file directly to ImportTransactions
+ * Read a workbook sheet with the Rust backend into ImportTransactions, a page
+ * at a time, so the sheet's rows never sit in the webview all at once.
+ * Without a mapping the columns are detected from the header.
  */
-export function excelToTransactions(
-  data: ArrayBuffer,
+export async function readSpreadsheetTransactions(
+  path: string,
   sheetIndex = 0,
-  customMapping?: Partial<ColumnMapping>
-): ImportTransaction[] {
-  const { headers, rows } = parseExcel(data, sheetIndex);
-  
-  if (rows.length < 2) {
-    return [];
+  skipRows = 0,
+  mapping?: ColumnMapping
+): Promise<{ headers: string[]; mapping: ColumnMapping; transactions: ImportTransaction[] }> {
+  let page = await readSpreadsheetPage(path, sheetIndex, skipRows);
+  const headers = page.headers;
+  const columns = mapping ?? detectColumns(headers);
+  const batch = Date.now();
+  const transactions: ImportTransaction[] = [];
+  for (let first = true; ; first = false) {
+    // Each page numbers its rows from 0, so ids are given across pages
+    for (const transaction of rowsToTransactions(page.rows, columns, first)) {
+      transactions.push({ ...transaction, id: `import-${batch}-${transactions.length}` });
+    }
+    if (page.nextOffset === null) break;
+    page = await readSpreadsheetPage(path, sheetIndex, skipRows, page.nextOffset);
   }
+  return { headers, mapping: columns, transactions };
+}
 
-  const detectedMapping = detectColumns(headers);
-  const mapping = { ...detectedMapping, ...customMapping };
-  
-  return rowsToTransactions(rows, mapping, true);
+/**
+ * Column mapping as stored in an import profile (null where TS uses -1/undefined)
+ */
+export type ProfileColumnMapping = {
+  [K in keyof ColumnMapping]: K extends 'date' | 'description' ? number : number | null;
+};
+
+/**
+ * Saved way of reading one bank's files, stored beside the budget
+ */
+export interface ImportProfile {
+  id: string;
+  name: string;
+  headerFingerprint: string | null;
+  filenamePattern: string | null; // e.g. "movimientos_*.csv"
+  mapping: ProfileColumnMapping;
+  dateFormat: DateFormat | null;
+  numberFormat: NumberFormatSpec | null;
+  sign: 'asIs' | 'negate'; // negate: charges come as positive amounts
+  accountId: string | null;
+  skipRows: number;
+  payeeCleanup: string | null; // regex; its "payee" or first group is kept
+  lastUsed: string | null;
+}
+
+export function profileToMapping(mapping: ProfileColumnMapping): ColumnMapping {
+  const result: ColumnMapping = {
+    date: mapping.date,
+    description: mapping.description,
+    amount: mapping.amount ?? -1,
+  };
+  for (const [key, value] of Object.entries(mapping)) {
+    if (key !== 'amount' && value !== null && value !== undefined) {
+      (result as unknown as Record<string, number>)[key] = value;
+    }
+  }
+  return result;
+}
+
+export function mappingToProfile(mapping: ColumnMapping): ProfileColumnMapping {
+  const result = {} as Record<string, number | null>;
+  for (const [key, value] of Object.entries(mapping)) {
+    result[key] = value !== undefined && value >= 0 ? value : null;
+  }
+  return result as ProfileColumnMapping;
+}
+
+export async function listImportProfiles(budgetPath: string): Promise<ImportProfile[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<ImportProfile[]>('list_import_profiles', { budgetPath });
+}
+
+/**
+ * Add or replace a profile; with headers it is keyed by that header row
+ */
+export async function saveImportProfile(
+  budgetPath: string,
+  profile: Partial<ImportProfile> & Pick<ImportProfile, 'name' | 'mapping'>,
+  headers?: string[]
+): Promise<ImportProfile> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<ImportProfile>('save_import_profile', { budgetPath, profile, headers: headers ?? null });
+}
+
+export async function deleteImportProfile(budgetPath: string, profileId: string): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('delete_import_profile', { budgetPath, profileId });
+}
+
+export async function findImportProfile(
+  budgetPath: string,
+  fileName: string,
+  headers?: string[]
+): Promise<ImportProfile | null> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<ImportProfile | null>('find_import_profile', { budgetPath, fileName, headers: headers ?? null });
+}
+
+/**
+ * Apply a profile's sign convention, account and payee cleanup to rows
+ */
+export async function applyImportProfile(
+  budgetPath: string,
+  profileId: string,
+  rows: ImportTransaction[]
+): Promise<ImportTransaction[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<ImportTransaction[]>('apply_import_profile', { budgetPath, profileId, rows });
+}
+
+/**
+ * Header row, mapping and formats a CSV or workbook was read with, kept to save a profile from
+ */
+export interface ImportSource {
+  fileName: string;
+  headers: string[];
+  mapping: ColumnMapping;
+  dateFormat: DateFormat | null;
+  numberFormat: NumberFormatSpec | null;
+  skipRows: number;
+}
+
+/**
+ * The source of a parsed statement: the formats detected for the mapped date
+ * and amount columns and the rows above the header
+ */
+export function statementSource(fileName: string, statement: ParsedStatement, mapping: ColumnMapping): ImportSource {
+  const column = (index?: number) =>
+    index === undefined || index < 0 ? undefined : statement.columns.find((c) => c.index === index);
+  const date = column(mapping.date);
+  const amount = [mapping.amount, mapping.debit, mapping.credit].map(column).find((c) => c?.kind === 'number');
+  return {
+    fileName,
+    headers: statement.headers,
+    mapping,
+    dateFormat: date?.kind === 'date' ? (date.format as DateFormat) : null,
+    numberFormat: amount ? (amount.format as NumberFormatSpec) : null,
+    skipRows: statement.detection.headerLine === null ? 0 : statement.detection.skippedLines.length,
+  };
+}
+
+/**
+ * Read a CSV or workbook with the profile that matches it, if any.
+ * Returns null when no profile matches. Only works in Tauri.
+ */
+export async function readWithImportProfile(
+  budgetPath: string,
+  filePath: string,
+  fileName: string
+): Promise<{ profile: ImportProfile; transactions: ImportTransaction[]; source: ImportSource } | null> {
+  const spreadsheet = /\.(xlsx|xls|ods)$/i.test(fileName);
+  let headers: string[];
+  let statement: ParsedStatement | null = null;
+  if (spreadsheet) {
+    // The header row is enough to find the profile
+    headers = (await readSpreadsheetPage(filePath, 0, 0, 0, 1)).headers;
+  } else {
+    statement = await parseStatementFile(filePath);
+    headers = statement.headers;
+  }
+
+  const profile = await findImportProfile(budgetPath, fileName, headers);
+  if (!profile) return null;
+
+  const mapping = profileToMapping(profile.mapping);
+  let read: ImportTransaction[];
+  if (spreadsheet) {
+    ({ headers, transactions: read } = await readSpreadsheetTransactions(filePath, 0, profile.skipRows, mapping));
+  } else {
+    if (profile.skipRows > 0 || profile.dateFormat || profile.numberFormat) {
+      statement = await parseStatementFile(filePath, {
+        skipRows: profile.skipRows,
+        dateFormat: profile.dateFormat,
+        numberFormat: profile.numberFormat,
+      });
+    }
+    headers = statement!.headers;
+    read = rowsToTransactions(statementToRows(statement!), mapping, true);
+  }
+  if (read.length === 0) return null;
+
+  const transactions = await applyImportProfile(budgetPath, profile.id, read);
+  const source = {
+    fileName,
+    headers,
+    mapping,
+    dateFormat: profile.dateFormat,
+    numberFormat: profile.numberFormat,
+    skipRows: profile.skipRows,
+  };
+  return { profile, transactions, source };
 }
 
 /**
@@ -402,19 +1240,20 @@ export const TEMPLATE_COLUMNS = [
 ] as const;
 
 /**
- * Generate template Excel file with sample data
+ * Check if running in Tauri
  */
-export function generateTemplateExcel(): ArrayBuffer {
-  // Create workbook
-  const wb = XLSX.utils.book_new();
-  
-  // Headers
-  const headers = TEMPLATE_COLUMNS.map(col => col.header);
-  
-  // Sample data rows
-  // Sample data with all columns including Installments
+function isTauriRuntime(): boolean {
+  if (typeof window === 'undefined') return false;
+  const win = window as { __TAURI__?: unknown; __TAURI_INTERNALS__?: unknown };
+  return '__TAURI__' in win || '__TAURI_INTERNALS__' in win;
+}
+
+/**
+ * Sample rows and instructions of the Excel import template
+ */
+function templateContent(): { rows: string[][]; instructions: string[] } {
   // Account, Date, Description, Outflow, Inflow, Reference, Original Memo, Payee, Suggested Payee, Category, Memo, Installments, numInstallments, Flag
-  const sampleData = [
+  const rows = [
     ['Cheques HSBC', '15/01/2025', 'PAGO TARJETA DE CREDITO', '5000.00', '', 'REF123456', 'PAGO TDC BANAMEX', '', 'Pago TDC', 'Deudas: Tarjeta Crédito', 'Pago mensual', '', '', ''],
     ['Cheques HSBC', '16/01/2025', 'TRANSFERENCIA SPEI', '', '25000.00', 'SPEI987654', 'NOMINA EMPRESA SA', '', 'Empresa SA', 'Ingreso: Salario', 'Quincena enero', '', '', 'Green'],
     ['2Now', '17/01/2025', 'COMPRA AMAZON', '1200.00', '', 'AMZ001234', 'AMAZON MEXICO', 'Amazon', 'Amazon', 'Hogar: Varios', 'Compra audífonos', '', '', 'Blue'],
@@ -422,72 +1261,43 @@ export function generateTemplateExcel(): ArrayBuffer {
     ['Platinum', '19/01/2025', 'LIVERPOOL MSI 12', '12000.00', '', 'LIV789012', 'LIVERPOOL TIENDA', 'Liverpool', 'Liverpool', 'Ropa: Varios', 'Ropa invierno', 'TRUE', '12', 'Orange'],
     ['Platinum', '20/01/2025', 'SEARS MSI 6', '6000.00', '', 'SEARS001', 'SEARS TIENDA', 'Sears', 'Sears', 'Hogar: Electrodomésticos', 'Licuadora', 'TRUE', '6', 'Orange'],
   ];
-  
-  // Combine headers and data
-  const wsData = [headers, ...sampleData];
-  
-  // Create worksheet
-  const ws = XLSX.utils.aoa_to_sheet(wsData);
-  
-  // Set column widths
-  ws['!cols'] = TEMPLATE_COLUMNS.map(col => ({ wch: col.width }));
-  
-  // Add worksheet to workbook
-  XLSX.utils.book_append_sheet(wb, ws, 'Transacciones');
-  
-  // Create instructions sheet
-  const instructionsData = [
-    ['INSTRUCTIONS'],
-    [''],
-    ['This is a template for importing transactions to YNAB4.'],
-    [''],
-    ['COLUMNS:'],
-    ...TEMPLATE_COLUMNS.map(col => [`• ${col.header}: ${col.description}`]),
-    [''],
-    ['NOTES:'],
-    ['• You can use separate Outflow/Inflow columns OR a single Amount column (positive=inflow, negative=outflow)'],
-    ['• Dates can be in DD/MM/YYYY, MM/DD/YYYY or YYYY-MM-DD format'],
-    ['• Amounts can include commas as thousands separators'],
-    ['• Categories must match your YNAB budget categories'],
-    ['• For installments (MSI), set Installments=TRUE and numInstallments to the number of months'],
-    [''],
-    ['FLAG COLORS:'],
-    ['Red, Orange, Yellow, Green, Blue, Purple (or empty)'],
-    [''],
-    ['MULTI-LANGUAGE SUPPORT:'],
-    ['Column headers are also detected in Spanish: Cuenta, Fecha, Cargo, Abono, Categoría, Bandera, MSI, Meses'],
+
+  const instructions = [
+    'INSTRUCTIONS',
+    '',
+    'This is a template for importing transactions to YNAB4.',
+    '',
+    'COLUMNS:',
+    ...TEMPLATE_COLUMNS.map(col => `• ${col.header}: ${col.description}`),
+    '',
+    'NOTES:',
+    '• You can use separate Outflow/Inflow columns OR a single Amount column (positive=inflow, negative=outflow)',
+    '• Dates can be in DD/MM/YYYY, MM/DD/YYYY or YYYY-MM-DD format',
+    '• Amounts can include commas as thousands separators',
+    '• Categories must match your YNAB budget categories',
+    '• For installments (MSI), set Installments=TRUE and numInstallments to the number of months',
+    '',
+    'FLAG COLORS:',
+    'Red, Orange, Yellow, Green, Blue, Purple (or empty)',
+    '',
+    'MULTI-LANGUAGE SUPPORT:',
+    'Column headers are also detected in Spanish: Cuenta, Fecha, Cargo, Abono, Categoría, Bandera, MSI, Meses',
   ];
-  
-  const wsInstructions = XLSX.utils.aoa_to_sheet(instructionsData);
-  wsInstructions['!cols'] = [{ wch: 80 }];
-  XLSX.utils.book_append_sheet(wb, wsInstructions, 'Instrucciones');
-  
-  // Write to buffer
-  const buffer = XLSX.write(wb, { type: 'array', bookType: 'xlsx' });
-  return buffer;
-}
 
-/**
- * Check if running in Tauri
- */
-function isTauriRuntime(): boolean {
-  if (typeof window === 'undefined') return false;
-  const win = window as { __TAURI__?: unknown; __TAURI_INTERNALS__?: unknown };
-  return '__TAURI__' in win || '__TAURI_INTERNALS__' in win;
+  return { rows, instructions };
 }
 
 /**
- * Download template Excel file
- * Works in both browser and Tauri
+ * Save the Excel import template, written by the Rust backend. Outside Tauri
+ * the sample CSV is downloaded instead.
  */
 export async function downloadTemplate(): Promise<void> {
-  const buffer = generateTemplateExcel();
   const filename = 'plantilla-importacion-ynab.xlsx';
 
   if (isTauriRuntime()) {
     try {
       const { save } = await import('@tauri-apps/plugin-dialog');
-      const { writeFile } = await import('@tauri-apps/plugin-fs');
+      const { invoke } = await import('@tauri-apps/api/core');
       
       let filePath = await save({
         defaultPath: filename,
@@ -503,13 +1313,8 @@ export async function downloadTemplate(): Promise<void> {
           filePath = filePath + '.xlsx';
         }
         
-        const uint8Array = new Uint8Array(buffer);
-        console.log('[ImportService] Writing to:', filePath, 'Size:', uint8Array.length, 'bytes');
-        
-        // writeFile signature: (path, data, options?)
-        await writeFile(filePath, uint8Array);
-        
-        console.log('[ImportService] Template saved successfully!');
+        const columns = TEMPLATE_COLUMNS.map(col => ({ header: col.header, width: col.width }));
+        await invoke('write_import_template', { path: filePath, columns, ...templateContent() });
         alert(`Plantilla guardada en: ${filePath}`);
       }
     } catch (error) {
@@ -517,7 +1322,7 @@ export async function downloadTemplate(): Promise<void> {
       alert(`Error al guardar: ${error}`);
     }
   } else {
-    downloadViaBrowser(buffer, filename);
+    await downloadSampleCSV();
   }
 }
 
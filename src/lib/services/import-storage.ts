@@ -4,8 +4,9 @@
  */
 
 import type { ImportTransaction, ImportFile } from './import-service';
+import { isTauri } from './tauri-io';
 
-// Storage key for import files in localStorage (web) or budget folder (Tauri)
+// Storage key for import files in localStorage (web only; Tauri keeps them in the app data dir)
 const IMPORT_FILES_KEY = 'ynab-import-files';
 
 export interface StoredImportFile {
@@ -25,16 +26,47 @@ export interface ImportFileWithTransactions extends StoredImportFile {
   transactions: ImportTransaction[];
 }
 
+function readLocal(budgetId: string): ImportFileWithTransactions[] {
+  const stored = localStorage.getItem(`${IMPORT_FILES_KEY}-${budgetId}`);
+  return stored ? JSON.parse(stored) : [];
+}
+
+function writeLocal(budgetId: string, files: ImportFileWithTransactions[]): void {
+  localStorage.setItem(`${IMPORT_FILES_KEY}-${budgetId}`, JSON.stringify(files));
+}
+
+// Budgets whose localStorage drafts were already moved this session
+const migratedBudgets = new Set<string>();
+
+/**
+ * Move drafts kept in localStorage by earlier versions into the backend store,
+ * once per budget. The localStorage entry is removed only after the move.
+ */
+async function migrateLocalDrafts(budgetId: string): Promise<void> {
+  if (migratedBudgets.has(budgetId)) return;
+  const key = `${IMPORT_FILES_KEY}-${budgetId}`;
+  const stored = localStorage.getItem(key);
+  if (stored) {
+    const { invoke } = await import('@tauri-apps/api/core');
+    const drafts: ImportFileWithTransactions[] = JSON.parse(stored);
+    await invoke<number>('migrate_import_drafts', { budgetPath: budgetId, drafts });
+    localStorage.removeItem(key);
+  }
+  migratedBudgets.add(budgetId);
+}
+
 /**
  * Get list of all stored import files (metadata only)
  */
-export function getImportFileList(budgetId: string): StoredImportFile[] {
+export async function getImportFileList(budgetId: string): Promise<StoredImportFile[]> {
   try {
-    const key = `${IMPORT_FILES_KEY}-${budgetId}`;
-    const stored = localStorage.getItem(key);
-    if (!stored) return [];
-    
-    const files: ImportFileWithTransactions[] = JSON.parse(stored);
+    if (isTauri()) {
+      await migrateLocalDrafts(budgetId);
+      const { invoke } = await import('@tauri-apps/api/core');
+      return await invoke<StoredImportFile[]>('list_import_drafts', { budgetPath: budgetId });
+    }
+
+    const files = readLocal(budgetId);
     
     // Return metadata only (no transactions)
     return files.map(f => ({
@@ -58,14 +90,16 @@ export function getImportFileList(budgetId: string): StoredImportFile[] {
 /**
  * Get a specific import file with all transactions
  */
-export function getImportFile(budgetId: string, fileId: string): ImportFileWithTransactions | null {
+export async function getImportFile(budgetId: string, fileId: string): Promise<ImportFileWithTransactions | null> {
   try {
-    const key = `${IMPORT_FILES_KEY}-${budgetId}`;
-    const stored = localStorage.getItem(key);
-    if (!stored) return null;
-    
-    const files: ImportFileWithTransactions[] = JSON.parse(stored);
-    return files.find(f => f.id === fileId) || null;
+    if (isTauri()) {
+      const { invoke } = await import('@tauri-apps/api/core');
+      return await invoke<ImportFileWithTransactions | null>('get_import_draft', {
+        budgetPath: budgetId,
+        draftId: fileId,
+      });
+    }
+    return readLocal(budgetId).find(f => f.id === fileId) || null;
   } catch (e) {
     console.error('Error loading import file:', e);
     return null;
@@ -75,14 +109,18 @@ export function getImportFile(budgetId: string, fileId: string): ImportFileWithT
 /**
  * Save or update an import file
  */
-export function saveImportFile(budgetId: string, file: ImportFileWithTransactions): void {
+export async function saveImportFile(budgetId: string, file: ImportFileWithTransactions): Promise<void> {
   try {
-    const key = `${IMPORT_FILES_KEY}-${budgetId}`;
-    const stored = localStorage.getItem(key);
-    let files: ImportFileWithTransactions[] = stored ? JSON.parse(stored) : [];
-    
     // Update timestamp
     file.updatedAt = new Date().toISOString();
+
+    if (isTauri()) {
+      const { invoke } = await import('@tauri-apps/api/core');
+      await invoke('save_import_draft', { budgetPath: budgetId, draft: file });
+      return;
+    }
+
+    const files = readLocal(budgetId);
     
     // Find and update or add
     const index = files.findIndex(f => f.id === file.id);
@@ -92,7 +130,7 @@ export function saveImportFile(budgetId: string, file: ImportFileWithTransaction
       files.push(file);
     }
     
-    localStorage.setItem(key, JSON.stringify(files));
+    writeLocal(budgetId, files);
   } catch (e) {
     console.error('Error saving import file:', e);
     throw e;
@@ -102,16 +140,14 @@ export function saveImportFile(budgetId: string, file: ImportFileWithTransaction
 /**
  * Delete an import file
  */
-export function deleteImportFile(budgetId: string, fileId: string): void {
+export async function deleteImportFile(budgetId: string, fileId: string): Promise<void> {
   try {
-    const key = `${IMPORT_FILES_KEY}-${budgetId}`;
-    const stored = localStorage.getItem(key);
-    if (!stored) return;
-    
-    let files: ImportFileWithTransactions[] = JSON.parse(stored);
-    files = files.filter(f => f.id !== fileId);
-    
-    localStorage.setItem(key, JSON.stringify(files));
+    if (isTauri()) {
+      const { invoke } = await import('@tauri-apps/api/core');
+      await invoke('delete_import_draft', { budgetPath: budgetId, draftId: fileId });
+      return;
+    }
+    writeLocal(budgetId, readLocal(budgetId).filter(f => f.id !== fileId));
   } catch (e) {
     console.error('Error deleting import file:', e);
     throw e;
@@ -151,6 +187,40 @@ export function exportImportFile(file: ImportFileWithTransactions): string {
   return JSON.stringify(file, null, 2);
 }
 
+/**
+ * Read an exported .ynab-import.json file into a new draft in the backend
+ * store. Only works in Tauri.
+ */
+export async function importDraftFile(
+  budgetId: string,
+  path: string,
+  name?: string
+): Promise<ImportFileWithTransactions> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<ImportFileWithTransactions>('import_draft_file', { budgetPath: budgetId, path, name: name ?? null });
+}
+
+/**
+ * What the backend created when committing a draft
+ */
+export interface CommitReport {
+  draftId: string;
+  rows: Array<{ rowId: string; transactionIds: string[]; clearedTransactionId: string | null }>;
+  transactionIds: string[];
+  payeeIds: string[];
+  changedIds: string[];
+  ydiffPath: string | null;
+}
+
+/**
+ * Create the draft's ready rows in the budget and mark them imported.
+ * Either every ready row is written or none is. Only works in Tauri.
+ */
+export async function commitImport(budgetId: string, draftId: string): Promise<CommitReport> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<CommitReport>('commit_import', { budgetPath: budgetId, draftId });
+}
+
 /**
  * Import file from JSON
  */
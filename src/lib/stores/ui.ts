@@ -167,6 +167,15 @@ export function closeModal() {
   modalData.set(null);
 }
 
+// Closing balance of an imported bank statement the user chose to reconcile
+export interface StatementToReconcile {
+  accountId: string;
+  statementDate: string;
+  statementBalance: number;
+}
+
+export const statementToReconcile = writable<StatementToReconcile | null>(null);
+
 // Toast notifications
 export interface Toast {
   id: string;